@@ -335,6 +335,33 @@ pub fn make_audio_decoder(
     Ok(decoder)
 }
 
+// prefer libopus which runs packet loss concealment for TOC only packets,
+// fallback to ffmpeg's native decoder
+pub fn make_opus_decoder(
+    channels: i32,
+    time_base: ff::Rational,
+) -> Result<ff::codec::decoder::Audio, ff::Error> {
+    let codec = ff::decoder::find_by_name("libopus")
+    .or_else(|| ff::decoder::find(ff::codec::Id::OPUS))
+    .ok_or(ff::Error::DecoderNotFound)?;
+
+    let mut decoder_params = FFParameters::new();
+    decoder_params.set_codec((ff::ffi::AVMediaType::AVMEDIA_TYPE_AUDIO, ff::codec::Id::OPUS.into()));
+    decoder_params.set_samplerate(48000);
+    decoder_params.set_channels(channels);
+
+    println!(
+        "input audio decoder {}, ch {channels}, time_base {time_base:?}",
+        codec.name(),
+    );
+
+    let mut decoder = ff::codec::Context::new();
+    set_decoder_context_time_base(&mut decoder, time_base);
+    decoder.set_parameters(decoder_params)?;
+    let decoder = decoder.decoder().open_as(codec)?.audio()?;
+    Ok(decoder)
+}
+
 pub fn audio_decoder_receive_frame(decoder: &mut ff::codec::decoder::Audio) -> std::result::Result<Option<ff::util::frame::Audio>, ff::util::error::Error> {
     let mut frame = ff::util::frame::Audio::empty();
     let decode_result = decoder.receive_frame(&mut frame);
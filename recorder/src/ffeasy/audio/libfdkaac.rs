@@ -0,0 +1,142 @@
+// libfdk-aac decoder called directly,
+// decoders of ffmpeg can't conceal a lost access unit (AACDEC_CONCEAL of aacDecoder_DecodeFrame)
+
+use std::ptr::NonNull;
+
+use anyhow::{bail, Result};
+
+use crate::rtp::codec::latm::AudioSpecificConfig;
+
+#[repr(C)]
+struct AacDecoderInstance {
+    _private: [u8; 0],
+}
+
+// leading fields of CStreamInfo, the rest are never read
+#[repr(C)]
+struct CStreamInfo {
+    sample_rate: i32,
+    frame_size: i32,
+    num_channels: i32,
+}
+
+#[link(name = "fdk-aac")]
+extern "C" {
+    fn aacDecoder_Open(transport_fmt: i32, nr_of_layers: u32) -> *mut AacDecoderInstance;
+    fn aacDecoder_ConfigRaw(decoder: *mut AacDecoderInstance, conf: *mut *mut u8, length: *const u32) -> i32;
+    fn aacDecoder_Fill(decoder: *mut AacDecoderInstance, buffer: *mut *mut u8, buffer_size: *const u32, bytes_valid: *mut u32) -> i32;
+    fn aacDecoder_DecodeFrame(decoder: *mut AacDecoderInstance, time_data: *mut i16, time_data_size: i32, flags: u32) -> i32;
+    fn aacDecoder_GetStreamInfo(decoder: *mut AacDecoderInstance) -> *mut CStreamInfo;
+    fn aacDecoder_Close(decoder: *mut AacDecoderInstance);
+}
+
+// raw access units, AudioSpecificConfig is given out of band
+const TT_MP4_RAW: i32 = 0;
+
+// conceals a frame instead of decoding the filled input
+const AACDEC_CONCEAL: u32 = 1;
+
+// 2048 samples of 8 channels, the largest frame
+const MAX_FRAME_LEN: usize = 2048 * 8;
+
+// decodes raw access units to interleaved i16
+pub struct LibFdkAacDecoder {
+    handle: NonNull<AacDecoderInstance>,
+    sample_rate: u32,
+    channels: u32,
+}
+
+unsafe impl Send for LibFdkAacDecoder {}
+
+impl LibFdkAacDecoder {
+    pub fn new(asc: &AudioSpecificConfig) -> Result<Self> {
+        let handle = match NonNull::new(unsafe { aacDecoder_Open(TT_MP4_RAW, 1) }) {
+            Some(v) => v,
+            None => bail!("aacDecoder_Open failed"),
+        };
+
+        // closed by drop if config fails
+        let me = Self {
+            handle,
+            sample_rate: asc.ext_sample_rate.unwrap_or(asc.sample_rate),
+            channels: asc.channels,
+        };
+
+        let mut conf = asc.raw.to_vec();
+        let mut ptr = conf.as_mut_ptr();
+        let len = conf.len() as u32;
+        let ret = unsafe { aacDecoder_ConfigRaw(me.handle.as_ptr(), &mut ptr, &len) };
+        if ret != 0 {
+            bail!("aacDecoder_ConfigRaw failed [{ret:#x}], config {:02X?}", &asc.raw[..])
+        }
+        Ok(me)
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u32 {
+        self.channels
+    }
+
+    // samples per channel of a frame, 0 until the first one is decoded
+    pub fn frame_size(&self) -> u32 {
+        match unsafe { aacDecoder_GetStreamInfo(self.handle.as_ptr()).as_ref() } {
+            Some(info) => info.frame_size.max(0) as u32,
+            None => 0,
+        }
+    }
+
+    pub fn decode(&mut self, au: &[u8], pcm: &mut Vec<i16>) -> Result<usize> {
+        let mut ptr = au.as_ptr() as *mut u8;
+        let size = au.len() as u32;
+        let mut valid = size;
+        let ret = unsafe { aacDecoder_Fill(self.handle.as_ptr(), &mut ptr, &size, &mut valid) };
+        if ret != 0 {
+            bail!("aacDecoder_Fill failed [{ret:#x}]")
+        }
+        self.decode_inner(0, pcm)
+    }
+
+    // a frame concealed from the previous ones in place of a lost access unit,
+    // nothing before the first decoded frame
+    pub fn conceal(&mut self, pcm: &mut Vec<i16>) -> Result<usize> {
+        if self.frame_size() == 0 {
+            return Ok(0)
+        }
+        self.decode_inner(AACDEC_CONCEAL, pcm)
+    }
+
+    fn decode_inner(&mut self, flags: u32, pcm: &mut Vec<i16>) -> Result<usize> {
+        let start = pcm.len();
+        pcm.resize(start + MAX_FRAME_LEN, 0);
+
+        let ret = unsafe {
+            aacDecoder_DecodeFrame(
+                self.handle.as_ptr(),
+                pcm[start..].as_mut_ptr(),
+                MAX_FRAME_LEN as i32,
+                flags,
+            )
+        };
+
+        if ret != 0 {
+            pcm.truncate(start);
+            bail!("aacDecoder_DecodeFrame failed [{ret:#x}], flags [{flags}]")
+        }
+
+        let (frame_size, channels) = match unsafe { aacDecoder_GetStreamInfo(self.handle.as_ptr()).as_ref() } {
+            Some(info) => (info.frame_size.max(0) as usize, info.num_channels.max(0) as usize),
+            None => (0, 0),
+        };
+        pcm.truncate(start + frame_size * channels);
+        Ok(frame_size)
+    }
+}
+
+impl Drop for LibFdkAacDecoder {
+    fn drop(&mut self) {
+        unsafe { aacDecoder_Close(self.handle.as_ptr()) }
+    }
+}
@@ -0,0 +1,86 @@
+// libopus decoder called directly,
+// decoders of ffmpeg can't decode in-band FEC (decode_fec of opus_decode)
+
+use std::ptr::NonNull;
+
+use anyhow::{bail, Result};
+
+use crate::rtp::codec::opus::OPUS_CLOCK_RATE;
+
+#[repr(C)]
+struct OpusDecoder {
+    _private: [u8; 0],
+}
+
+#[link(name = "opus")]
+extern "C" {
+    fn opus_decoder_create(fs: i32, channels: i32, error: *mut i32) -> *mut OpusDecoder;
+    fn opus_decode(st: *mut OpusDecoder, data: *const u8, len: i32, pcm: *mut i16, frame_size: i32, decode_fec: i32) -> i32;
+    fn opus_decoder_destroy(st: *mut OpusDecoder);
+}
+
+// 120ms, the longest packet
+const MAX_FRAME_SAMPLES: usize = 5760;
+
+// decodes at 48 kHz to interleaved i16
+pub struct LibOpusDecoder {
+    st: NonNull<OpusDecoder>,
+    channels: u32,
+}
+
+unsafe impl Send for LibOpusDecoder {}
+
+impl LibOpusDecoder {
+    pub fn new(channels: u32) -> Result<Self> {
+        let mut error = 0;
+        let st = unsafe { opus_decoder_create(OPUS_CLOCK_RATE as i32, channels as i32, &mut error) };
+        match NonNull::new(st) {
+            Some(st) if error == 0 => Ok(Self { st, channels }),
+            _ => bail!("opus_decoder_create failed [{error}], channels [{channels}]"),
+        }
+    }
+
+    pub fn channels(&self) -> u32 {
+        self.channels
+    }
+
+    // a TOC only packet (see OpusFrame) runs the concealment
+    pub fn decode(&mut self, packet: &[u8], pcm: &mut Vec<i16>) -> Result<usize> {
+        self.decode_inner(packet, MAX_FRAME_SAMPLES, false, pcm)
+    }
+
+    // the lost samples before the next packet, from its LBRR,
+    // libopus conceals them instead if the packet has no LBRR
+    pub fn decode_fec(&mut self, next: &[u8], samples: u32, pcm: &mut Vec<i16>) -> Result<usize> {
+        self.decode_inner(next, samples as usize, true, pcm)
+    }
+
+    fn decode_inner(&mut self, data: &[u8], frame_size: usize, fec: bool, pcm: &mut Vec<i16>) -> Result<usize> {
+        let start = pcm.len();
+        pcm.resize(start + frame_size * self.channels as usize, 0);
+
+        let ret = unsafe {
+            opus_decode(
+                self.st.as_ptr(),
+                data.as_ptr(),
+                data.len() as i32,
+                pcm[start..].as_mut_ptr(),
+                frame_size as i32,
+                fec as i32,
+            )
+        };
+
+        if ret < 0 {
+            pcm.truncate(start);
+            bail!("opus_decode failed [{ret}], fec [{fec}]")
+        }
+        pcm.truncate(start + ret as usize * self.channels as usize);
+        Ok(ret as usize)
+    }
+}
+
+impl Drop for LibOpusDecoder {
+    fn drop(&mut self) {
+        unsafe { opus_decoder_destroy(self.st.as_ptr()) }
+    }
+}
@@ -1,6 +1,10 @@
 
 pub mod input;
 
+pub mod libfdkaac;
+
+pub mod libopus;
+
 pub mod resampler;
 
 pub mod swr;
@@ -46,6 +46,45 @@ impl FFOutput {
         self.add_audio_track(ff::codec::Id::AAC, samplerate, channels, Some(1024))
     }
 
+    // AudioSpecificConfig as extradata
+    pub fn add_aac_track_with_config<'a>(
+        &'a mut self, 
+        samplerate: i32,
+        channels: i32,
+        config: &[u8],
+    ) -> Result<ff::format::stream::StreamMut<'a> , ff::Error> {
+        let mut o_param = FFParameters::new();
+        o_param.set_audio(ff::codec::Id::AAC.into(), samplerate, channels);
+        o_param.set_frame_size(1024);
+        o_param.set_extra(config);
+
+        let codec = ff::encoder::find(ff::codec::Id::AAC);
+        self.add_track(codec, o_param.into())
+    }
+
+    pub fn add_opus_track<'a>(
+        &'a mut self, 
+        channels: i32,
+    ) -> Result<ff::format::stream::StreamMut<'a> , ff::Error> {
+        let mut o_param = FFParameters::new();
+        o_param.set_audio(ff::codec::Id::OPUS.into(), 48000, channels);
+        o_param.set_frame_size(960);
+
+        // mov muxer requires OpusHead (RFC 7845 section 5.1) as extradata
+        let mut head = Vec::with_capacity(19);
+        head.extend_from_slice(b"OpusHead");
+        head.push(1); // version
+        head.push(channels as u8);
+        head.extend_from_slice(&312_u16.to_le_bytes()); // pre-skip
+        head.extend_from_slice(&48000_u32.to_le_bytes()); // input sample rate
+        head.extend_from_slice(&0_i16.to_le_bytes()); // output gain
+        head.push(0); // channel mapping family
+        o_param.set_extra(&head);
+
+        let codec = ff::encoder::find(ff::codec::Id::OPUS);
+        self.add_track(codec, o_param.into())
+    }
+
     pub fn add_audio_track<'a>(
         &'a mut self, 
         codec_id: ff::codec::Id,
@@ -163,6 +202,33 @@ impl FFWriter {
         Ok(())
     }
 
+    // mov muxer writes metadata into moov at trailer, so it can be set after header
+    pub fn set_metadata(&mut self, key: &str, value: &str) {
+        let mut metadata = self.output.metadata().to_owned();
+        metadata.set(key, value);
+        self.output.set_metadata(metadata);
+    }
+
+    // rotate clockwise then flip horizontally when playing,
+    // mov muxer writes the matrix into tkhd at trailer, so it can be set after header
+    pub fn set_track_display_matrix(&mut self, track: &FFTrack, rotation: u16, flip: bool) -> Result<(), ff::Error> {
+        let matrix = display_matrix(rotation, flip);
+        unsafe {
+            let stream = *(*self.output.as_mut_ptr()).streams.add(track.index);
+            let size = std::mem::size_of_val(&matrix);
+            let data = ff::ffi::av_stream_new_side_data(
+                stream,
+                ff::ffi::AVPacketSideDataType::AV_PKT_DATA_DISPLAYMATRIX,
+                size,
+            );
+            if data.is_null() {
+                return Err(ff::Error::Unknown)
+            }
+            std::ptr::copy_nonoverlapping(matrix.as_ptr() as *const u8, data, size);
+        }
+        Ok(())
+    }
+
     pub fn write_trailer(&mut self) -> Result<(), ff::Error> {
         if !self.wrote_trailer {
             self.wrote_trailer = true;
@@ -187,6 +253,31 @@ impl Drop for FFWriter {
 }
 
 
+// same as av_display_rotation_set(-rotation) and av_display_matrix_flip(hflip),
+// 16.16 fixed point except 2.30 for the last column
+pub fn display_matrix(rotation: u16, flip: bool) -> [i32; 9] {
+    let (sin, cos) = match rotation % 360 {
+        90 => (1, 0),
+        180 => (0, -1),
+        270 => (-1, 0),
+        _ => (0, 1),
+    };
+
+    let mut matrix = [0_i32; 9];
+    matrix[0] = cos << 16;
+    matrix[1] = -sin << 16;
+    matrix[3] = sin << 16;
+    matrix[4] = cos << 16;
+    matrix[8] = 1 << 30;
+
+    if flip {
+        matrix[0] = -matrix[0];
+        matrix[3] = -matrix[3];
+        matrix[6] = -matrix[6];
+    }
+    matrix
+}
+
 pub fn rescal_packet_ts(packet: &mut ff::Packet, source: ff::Rational, destination: ff::Rational) {
     let pts = packet.pts().map(|ts| ts.rescale(source, destination));
     packet.set_pts(pts);
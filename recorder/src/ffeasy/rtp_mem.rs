@@ -14,6 +14,13 @@ use video_rs::RtpBuf;
 use video_rs::RtpMuxer;
 use video_rs::StreamInfo;
 
+use crate::ffeasy::parameters::FFParameters;
+use crate::rtp::codec::aac::{aac_hbr_fmtp, RtpPackerAAC};
+use crate::rtp::codec::h264::{h264_fmtp, AvcConfig, RtpPackerH264};
+use crate::rtp::codec::opus::{RtpPackerOpus, OPUS_CLOCK_RATE};
+use crate::rtp::pack::impair::RtpImpair;
+use crate::rtp::pack::{RtpCodecPacker, RtpPackHeader};
+
 
 // use crate::oddity_rtsp_server as thiz_root;
 // use thiz_root::media::MediaDescriptor;
@@ -21,9 +28,23 @@ use video_rs::StreamInfo;
 // use crate::oddity_rtsp_server::media::video::reader::backend::make_reader_with_sane_settings;
 // pub use oddity_sdp_protocol::Sdp;
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+
+// h264, aac and opus are packed by packers of rtp module, other codecs by rtp muxer of ffmpeg
+#[derive(Clone, Default)]
+pub struct RtpMemOptions {
+    // payload size limit of packers, default of each packer if none
+    pub mtu: Option<usize>,
+
+    // drops and reorders rtp packets, each track gets a copy
+    pub impair: Option<RtpImpair>,
+}
 
 pub fn load_rtp_mem_sync(filename: &Path, max_frames: u64) -> Result<Arc<RtpMemData>> {
+    load_rtp_mem_sync_with(filename, max_frames, &Default::default())
+}
+
+pub fn load_rtp_mem_sync_with(filename: &Path, max_frames: u64, options: &RtpMemOptions) -> Result<Arc<RtpMemData>> {
     // // let filename = "/tmp/sample-data/sample.mp4";
     // let source_name = "MemSource";
     // let source_descriptor = MediaDescriptor::File(filename.into());
@@ -51,7 +72,7 @@ pub fn load_rtp_mem_sync(filename: &Path, max_frames: u64) -> Result<Arc<RtpMemD
         let mut ch_id = 0;
         let video = match best_media_index(&video_reader, ffmpeg_next::media::Type::Video) {
             Some(index) => {
-                let track = load_track(&mut video_reader, index, ch_id, max_frames)?;
+                let track = load_track(&mut video_reader, index, ch_id, max_frames, options)?;
                 ch_id += 2;
                 // pt += 1;
                 Some(track)
@@ -65,7 +86,7 @@ pub fn load_rtp_mem_sync(filename: &Path, max_frames: u64) -> Result<Arc<RtpMemD
                 //   按道理 audio_reader 是新创建的，不需要 seek_to_start
                 //   但实际上，如果不 seek_to_start ， mp4 文件读出来的时间戳是负数
                 // let _r = audio_reader.seek_to_start();
-                let track = load_track(&mut audio_reader, index, ch_id, max_frames)?;
+                let track = load_track(&mut audio_reader, index, ch_id, max_frames, options)?;
                 // ch_id += 2;
                 // pt += 1;
                 Some(track)
@@ -73,10 +94,10 @@ pub fn load_rtp_mem_sync(filename: &Path, max_frames: u64) -> Result<Arc<RtpMemD
             None => None,
         };
     
-        // let sdp = make_sdp(source_name, &video, &audio)?;
+        let sdp = make_sdp(&[video.as_ref(), audio.as_ref()]).unwrap_or_default();
     
         Ok(Arc::new(RtpMemData {
-            sdp: Bytes::new(),
+            sdp: Bytes::from(sdp),
             video,
             audio,
         }))
@@ -135,6 +156,7 @@ impl RtpMemData {
         &self.video
     }
     
+    // empty if any track is not packed natively, see RtpMemOptions
     pub fn sdp(&self) -> &Bytes {
         &self.sdp
     }
@@ -280,11 +302,203 @@ impl TsBase {
     }
 }
 
-fn load_track(reader: &mut Reader, index: usize, ch_id: u64, max_frames: u64) -> Result<MemTrack> {
-    load_track1(reader, index, ch_id, max_frames)
+fn load_track(reader: &mut Reader, index: usize, ch_id: u64, max_frames: u64, options: &RtpMemOptions) -> Result<MemTrack> {
+    let params: FFParameters = reader
+        .input
+        .stream(index)
+        .ok_or_else(||anyhow!("not found stream [{index}]"))?
+        .parameters()
+        .into();
+
+    match NativePacker::new(&params, ch_id, options)? {
+        Some(native) => load_native_track(reader, index, ch_id, max_frames, native, options),
+        None => load_track1(reader, index, ch_id, max_frames, options),
+    }
+}
+
+// dynamic payload type of the first track, the next track goes up by one
+const NATIVE_PAYLOAD_TYPE: u8 = 96;
+
+// fixed so that loading a file twice gives the same packets
+const NATIVE_SSRC_BASE: u32 = 0x1000;
+
+struct NativePacker {
+    packer: Box<dyn RtpCodecPacker>,
+    payload_type: u8,
+    clock_rate: u32,
+
+    // h264 samples of mp4 are length prefixed, annex-b otherwise
+    avc: Option<AvcConfig>,
+
+    sdp_media: String,
+}
+
+impl NativePacker {
+    fn new(params: &FFParameters, ch_id: u64, options: &RtpMemOptions) -> Result<Option<Self>> {
+        let track_index = (ch_id >> 1) as u8;
+        let pt = NATIVE_PAYLOAD_TYPE + track_index;
+        let header = RtpPackHeader::new(pt, NATIVE_SSRC_BASE + ch_id as u32);
+        let extra = params.get_extra();
+
+        let mut native = match params.inner().id() {
+            ffmpeg_next::codec::Id::H264 => {
+                let mut packer = RtpPackerH264::new(header);
+                if let Some(mtu) = options.mtu {
+                    packer = packer.with_mtu(mtu);
+                }
+
+                // raw h264 has parameter sets in band
+                let avc = match extra.first() {
+                    Some(1) => Some(AvcConfig::parse(extra)?),
+                    _ => None,
+                };
+                let fmtp = match avc.as_ref().and_then(|x| x.sps.first().map(|sps| (sps, &x.pps))) {
+                    Some((sps, pps)) => h264_fmtp(1, sps, pps),
+                    None => "packetization-mode=1".to_string(),
+                };
+
+                Self {
+                    packer: packer.into_box(),
+                    payload_type: pt,
+                    clock_rate: 90000,
+                    avc,
+                    sdp_media: format!("m=video 0 RTP/AVP {pt}\r\na=rtpmap:{pt} H264/90000\r\na=fmtp:{pt} {fmtp}\r\n"),
+                }
+            },
+            ffmpeg_next::codec::Id::AAC => {
+                if extra.is_empty() {
+                    bail!("aac without AudioSpecificConfig")
+                }
+
+                let mut packer = RtpPackerAAC::new(header);
+                if let Some(mtu) = options.mtu {
+                    packer = packer.with_mtu(mtu);
+                }
+
+                let samplerate = params.get_samplerate() as u32;
+                let channels = params.get_channels();
+                Self {
+                    packer: packer.into_box(),
+                    payload_type: pt,
+                    clock_rate: samplerate,
+                    avc: None,
+                    sdp_media: format!(
+                        "m=audio 0 RTP/AVP {pt}\r\na=rtpmap:{pt} MPEG4-GENERIC/{samplerate}/{channels}\r\na=fmtp:{pt} {}\r\n",
+                        aac_hbr_fmtp(extra),
+                    ),
+                }
+            },
+            ffmpeg_next::codec::Id::OPUS => {
+                let stereo = (params.get_channels() > 1) as u8;
+                Self {
+                    packer: RtpPackerOpus::new(header).into_box(),
+                    payload_type: pt,
+                    clock_rate: OPUS_CLOCK_RATE,
+                    avc: None,
+                    sdp_media: format!("m=audio 0 RTP/AVP {pt}\r\na=rtpmap:{pt} opus/48000/2\r\na=fmtp:{pt} sprop-stereo={stereo}\r\n"),
+                }
+            },
+            _ => return Ok(None),
+        };
+
+        native.sdp_media.push_str(&format!("a=control:streamid={track_index}\r\n"));
+        Ok(Some(native))
+    }
 }
 
-fn load_track1(reader: &mut Reader, index: usize, ch_id: u64, max_frames: u64) -> Result<MemTrack> {
+fn load_native_track(reader: &mut Reader, index: usize, ch_id: u64, max_frames: u64, mut native: NativePacker, options: &RtpMemOptions) -> Result<MemTrack> {
+    let info = reader.stream_info(index)?;
+    let control = format!("track_id={index}");
+    let mut impair = options.impair.clone();
+
+    let spspps: Vec<(SpsBytes, Vec<PpsBytes>)> = native.avc.as_ref()
+    .and_then(|avc| avc.sps.first().map(|sps| (sps.to_vec(), avc.pps.iter().map(|x| x.to_vec()).collect())))
+    .into_iter()
+    .collect();
+
+    let mut packets = Vec::new();
+    let mut pts_gen = TsGen::new(40);
+    let mut dts_gen = TsGen::new(40);
+    let (mut pts, mut dts) = (0, 0);
+
+    for _num in 0..max_frames {
+        let frame = match reader.read(index){
+            Ok(v) => v,
+            Err(_e) => break,
+        };
+        pts = pts_gen.convert(frame.pts().into_parts());
+        dts = dts_gen.convert(frame.pts().into_parts());
+        let rtp_ts = to_rtp_ts(frame.pts().into_parts(), pts, native.clock_rate);
+
+        let (packet, _time_base) = frame.into_inner_parts();
+        let data = match packet.data() {
+            Some(v) => v,
+            None => continue,
+        };
+
+        match native.avc.as_ref() {
+            Some(avc) => native.packer.push_frame(&avc.sample_to_annexb(data)?, rtp_ts)?,
+            None => native.packer.push_frame(data, rtp_ts)?,
+        }
+
+        while let Some(rtp) = native.packer.pull_rtp() {
+            push_rtp(&mut packets, &mut impair, rtp, ch_id, pts, dts);
+        }
+    }
+    flush_impair(&mut packets, &mut impair, ch_id, pts, dts);
+
+    Ok(MemTrack {
+        ch_id,
+        _payload_type: native.payload_type,
+        _info: info,
+        control,
+        packets,
+        spspps,
+        sdp_media: Some(native.sdp_media),
+    })
+}
+
+// packets delayed by impairment go out along with a later frame
+fn push_rtp(packets: &mut Vec<RtpMemPacket>, impair: &mut Option<RtpImpair>, data: Bytes, ch_id: u64, pts: i64, dts: i64) {
+    match impair {
+        Some(impair) => {
+            impair.push(data);
+            while let Some(data) = impair.pull() {
+                packets.push(RtpMemPacket { pts, dts, ch_id, data });
+            }
+        },
+        None => packets.push(RtpMemPacket { pts, dts, ch_id, data }),
+    }
+}
+
+fn flush_impair(packets: &mut Vec<RtpMemPacket>, impair: &mut Option<RtpImpair>, ch_id: u64, pts: i64, dts: i64) {
+    if let Some(impair) = impair {
+        impair.flush();
+        while let Some(data) = impair.pull() {
+            packets.push(RtpMemPacket { pts, dts, ch_id, data });
+        }
+    }
+}
+
+// by time base of the stream, by milliseconds if the frame has no pts
+fn to_rtp_ts(ts: (Option<i64>, ffmpeg_next::Rational), pts_ms: i64, clock_rate: u32) -> u32 {
+    let ticks = match ts.0 {
+        Some(value) => value as i128 * clock_rate as i128 * ts.1.numerator() as i128 / ts.1.denominator() as i128,
+        None => pts_ms as i128 * clock_rate as i128 / 1000,
+    };
+    ticks as u32
+}
+
+// session of the tracks, none if any of them is packed by ffmpeg
+fn make_sdp(tracks: &[Option<&MemTrack>]) -> Option<String> {
+    let mut sdp = String::from("v=0\r\no=- 0 0 IN IP4 127.0.0.1\r\ns=No Name\r\nc=IN IP4 127.0.0.1\r\nt=0 0\r\n");
+    for track in tracks.iter().flatten() {
+        sdp.push_str(track.sdp_media.as_ref()?);
+    }
+    Some(sdp)
+}
+
+fn load_track1(reader: &mut Reader, index: usize, ch_id: u64, max_frames: u64, options: &RtpMemOptions) -> Result<MemTrack> {
 
     // let val = reader
     // .input
@@ -312,28 +526,34 @@ fn load_track1(reader: &mut Reader, index: usize, ch_id: u64, max_frames: u64) -
 
     let packets = {
         let mut packets = Vec::new();
+        let mut impair = options.impair.clone();
         let mut pts_gen = TsGen::new(40);
         let mut dts_gen = TsGen::new(40);
+        let (mut pts, mut dts) = (0, 0);
 
         for _num in 0..max_frames {
             let frame = match reader.read(index){
                 Ok(v) => v,
                 Err(_e) => break,
             };
-            let pts = pts_gen.convert(frame.pts().into_parts());
-            let dts = dts_gen.convert(frame.pts().into_parts());
+            pts = pts_gen.convert(frame.pts().into_parts());
+            dts = dts_gen.convert(frame.pts().into_parts());
 
             // let pts = Duration::from(frame.pts()).as_millis() as i64;
             // let dts = Duration::from(frame.dts()).as_millis() as i64;
             // println!("rtpmem: ch[{ch_id}] frame {_num}: pts {}, dts {}", pts, dts);
             let rtp_packets = muxer.mux(frame)?;
-            packets.extend(rtp_packets.into_iter().map(|buf| {
-                if let RtpBuf::Rtp(d) = &buf {
-                    pt = d[1] & 0x7F;
+            for buf in rtp_packets {
+                match buf {
+                    RtpBuf::Rtp(d) => {
+                        pt = d[1] & 0x7F;
+                        push_rtp(&mut packets, &mut impair, Bytes::from(d), ch_id, pts, dts);
+                    },
+                    RtpBuf::Rtcp(_) => packets.push(RtpMemPacket::from_rtp_buf(buf, ch_id, pts, dts)),
                 }
-                RtpMemPacket::from_rtp_buf(buf, ch_id, pts, dts)
-            }));
+            }
         }
+        flush_impair(&mut packets, &mut impair, ch_id, pts, dts);
         // println!("rtpmem: ch[{ch_id}] rtp packets {}", packets.len());
         packets
     };
@@ -345,6 +565,7 @@ fn load_track1(reader: &mut Reader, index: usize, ch_id: u64, max_frames: u64) -
         control,
         packets,
         spspps,
+        sdp_media: None,
     })
 }
 
@@ -472,6 +693,9 @@ pub struct MemTrack {
     control: String,
     packets: Vec<RtpMemPacket>,
     spspps: Vec<(SpsBytes, Vec<PpsBytes>)>,
+
+    // media section of sdp for native packed track
+    sdp_media: Option<String>,
 }
 
 impl MemTrack {
@@ -13,6 +13,12 @@ impl YuvColor {
         v: 128,
     };
 
+    pub const GRAY: Self = Self {
+        y: 126,
+        u: 128,
+        v: 128,
+    };
+
     pub const RED: Self = Self {
         y: 81,
         u: 90,
@@ -68,6 +68,46 @@ impl FFYuvImage {
         &self.frame
     }
 
+    // rotate clockwise by 0, 90, 180 or 270 degrees, then flip horizontally,
+    // same order as the display matrix, unchanged if not yuv420p
+    pub fn transformed(&self, rotation: u16, flip: bool) -> FFYuvImage {
+        let planar = matches!(self.frame.format(), ff::util::format::Pixel::YUV420P | ff::util::format::Pixel::YUVJ420P);
+        if !planar || (rotation % 360 == 0 && !flip) {
+            return self.clone()
+        }
+
+        let size = self.size();
+        let osize = if rotation % 180 == 90 {
+            VideoSize { width: size.height, height: size.width }
+        } else {
+            size
+        };
+
+        let mut frame = new_image(osize);
+        frame.set_pts(self.frame.pts());
+
+        for plane in 0..3 {
+            let (w, h) = if plane == 0 {
+                (size.width as usize, size.height as usize)
+            } else {
+                (size.width.div_ceil(2) as usize, size.height.div_ceil(2) as usize)
+            };
+
+            let dst_stride = frame.stride(plane);
+            transform_plane(
+                self.frame.data(plane),
+                self.frame.stride(plane),
+                (w, h),
+                frame.data_mut(plane),
+                dst_stride,
+                rotation,
+                flip,
+            );
+        }
+
+        Self { frame }
+    }
+
 }
 
 impl From<ff::util::frame::Video> for FFYuvImage {
@@ -87,6 +127,31 @@ fn new_image(size: VideoSize) -> ff::util::frame::Video {
     )
 }
 
+fn transform_plane(
+    src: &[u8],
+    src_stride: usize,
+    (w, h): (usize, usize),
+    dst: &mut [u8],
+    dst_stride: usize,
+    rotation: u16,
+    flip: bool,
+) {
+    let dst_w = if rotation % 180 == 90 { h } else { w };
+
+    for y in 0..h {
+        for x in 0..w {
+            let (dx, dy) = match rotation % 360 {
+                90 => (h - 1 - y, x),
+                180 => (w - 1 - x, h - 1 - y),
+                270 => (y, w - 1 - x),
+                _ => (x, y),
+            };
+            let dx = if flip { dst_w - 1 - dx } else { dx };
+            dst[dy * dst_stride + dx] = src[y * src_stride + x];
+        }
+    }
+}
+
 fn fill_frame_color(frame: &mut ff::util::frame::Video, color: &YuvColor) {
     frame.data_mut(0).fill(color.y);
     frame.data_mut(1).fill(color.u);
@@ -177,3 +242,25 @@ fn draw_yuv_frame(
         }
 	}
 }
+
+
+#[test]
+fn test_transform_plane() {
+    // 3x2
+    // 1 2 3
+    // 4 5 6
+    let src = [1, 2, 3, 0, 4, 5, 6, 0];
+
+    let mut dst = [0_u8; 6];
+    transform_plane(&src, 4, (3, 2), &mut dst, 2, 90, false);
+    assert_eq!(dst, [4, 1, 5, 2, 6, 3]);
+
+    transform_plane(&src, 4, (3, 2), &mut dst, 3, 180, false);
+    assert_eq!(dst, [6, 5, 4, 3, 2, 1]);
+
+    transform_plane(&src, 4, (3, 2), &mut dst, 2, 270, false);
+    assert_eq!(dst, [3, 6, 2, 5, 1, 4]);
+
+    transform_plane(&src, 4, (3, 2), &mut dst, 2, 90, true);
+    assert_eq!(dst, [1, 4, 2, 5, 3, 6]);
+}
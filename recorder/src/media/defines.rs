@@ -2,32 +2,43 @@
 
 // pub type CodecId = ff::codec::Id;
 
+use super::codec_registry;
+
 #[allow(non_camel_case_types)]
 #[derive(Eq, PartialEq, Clone, Copy, Debug, Hash)]
 pub enum CodecId {
     H264,
     H265,
+    VP8,
+    VP9,
+    AV1,
     AAC,
+    MP4ALATM,
+    MPA,
+    Opus,
+    PCMU,
+    PCMA,
+    G722,
+    AMR,
+    AMRWB,
+    TelephoneEvent, // https://datatracker.ietf.org/doc/html/rfc4733
+    RED, // https://datatracker.ietf.org/doc/html/rfc2198
     RtpRTX, // https://datatracker.ietf.org/doc/html/rfc4588
+    ULPFEC, // https://datatracker.ietf.org/doc/html/rfc5109
+    FlexFEC, // https://datatracker.ietf.org/doc/html/draft-ietf-payload-flexible-fec-scheme-03
+    Custom(u32), // registered by applications, see register_codec
 }
 
 
 impl CodecId {
+    // by encoding name of rtpmap, see CodecRegistry
     pub fn parse_from_str(name: &str) -> Option<CodecId> {
-        if name.eq_ignore_ascii_case("H264") {
-            Some(CodecId::H264)
-        } else if name.eq_ignore_ascii_case("H265") {
-            Some(CodecId::H265)
-        } else if name.eq_ignore_ascii_case("AAC") {
-            Some(CodecId::AAC)
-        } else if name.eq_ignore_ascii_case("MPEG4-GENERIC") {
-            Some(CodecId::AAC)
-        } else if name.eq_ignore_ascii_case("RTX") {
-            Some(CodecId::RtpRTX)
-        } 
-        else {
-            None
-        }
+        codec_registry().find_by_name(name).map(|x| x.codec_id)
     }
-}
 
+    // static payload types of RFC 3551 section 6, (codec, clock_rate, channels)
+    pub fn from_static_payload_type(payload_type: u8) -> Option<(CodecId, u32, u32)> {
+        codec_registry().find_by_static_payload_type(payload_type)
+        .map(|(desc, x)| (desc.codec_id, x.clock_rate, x.channels))
+    }
+}
@@ -1,3 +1,6 @@
 
 mod defines;
 pub use defines::*;
+
+mod registry;
+pub use registry::*;
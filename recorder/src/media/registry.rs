@@ -0,0 +1,445 @@
+// registry of codec descriptors, the one place to add a codec:
+//   - encoding names of rtpmap and static payload types
+//   - rtp depacker
+//   - ffmpeg decoder and its parameters from fmtp
+//   - track of ffmpeg output
+// applications may add their own codecs by register_codec with CodecId::Custom
+
+use std::{num::NonZeroU16, sync::{RwLock, RwLockReadGuard}};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use ffmpeg_next as ff;
+
+use crate::{
+    ffeasy::output::FFOutput,
+    rtp::{
+        codec::{aac::RtpDepackerAAC, amr::RtpDepackerAMR, av1::RtpDepackerAV1, g711::G711_SAMPLERATE, h264::{H264DepackBackend, RtpDepackerH264, RtpH264Parameters}, h265::{RtpDepackerH265, RtpH265Parameters}, latm::{decode_hex, RtpDepackerLATM, RtpLatmParameters}, mpa::{RtpDepackerMPA, RtpMpaParameters, MPA_CLOCK_RATE}, opus::{RtpDepackerOpus, RtpOpusParameters}, vp8::RtpDepackerVP8, vp9::RtpDepackerVP9},
+        depack::{depack_simple::RtpDepackerSimpleAudio, RtpCodecDepacker},
+    },
+    sdp::sdp::SdpCodec,
+};
+
+use super::CodecId;
+
+// RFC 3551 section 6
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaticPayloadType {
+    pub payload_type: u8,
+    pub clock_rate: u32,
+    pub channels: u32,
+}
+
+// parameters to open ffmpeg decoder, zero if unknown
+#[derive(Debug, Clone)]
+pub struct CodecDecoderArgs {
+    pub codec_id: ff::codec::Id,
+    pub width: i32,
+    pub height: i32,
+    pub sample_rate: i32,
+    pub channels: i32,
+    pub extra: Bytes,
+}
+
+impl CodecDecoderArgs {
+    pub fn video(codec_id: ff::codec::Id, width: i32, height: i32, extra: Bytes) -> Self {
+        Self {
+            codec_id,
+            width,
+            height,
+            sample_rate: 0,
+            channels: 0,
+            extra,
+        }
+    }
+
+    pub fn audio(codec_id: ff::codec::Id, sample_rate: i32, channels: i32) -> Self {
+        Self {
+            codec_id,
+            width: 0,
+            height: 0,
+            sample_rate,
+            channels,
+            extra: Bytes::new(),
+        }
+    }
+}
+
+pub type DepackerFactory = fn(&SdpCodec) -> Result<Box<dyn RtpCodecDepacker>>;
+
+pub type DecoderArgsBuilder = fn(&SdpCodec) -> Result<CodecDecoderArgs>;
+
+// returns the index of added track
+pub type TrackBuilder = fn(&mut FFOutput, &SdpCodec) -> Result<usize>;
+
+#[derive(Clone)]
+pub struct CodecDescriptor {
+    pub codec_id: CodecId,
+
+    // encoding names of rtpmap, case insensitive
+    pub names: &'static [&'static str],
+
+    pub static_payload_types: &'static [StaticPayloadType],
+
+    pub depacker: Option<DepackerFactory>,
+
+    pub decoder: Option<DecoderArgsBuilder>,
+
+    // frames from depacker are written into the track as is
+    pub track: Option<TrackBuilder>,
+}
+
+impl CodecDescriptor {
+    pub fn new(codec_id: CodecId, names: &'static [&'static str]) -> Self {
+        Self {
+            codec_id,
+            names,
+            static_payload_types: &[],
+            depacker: None,
+            decoder: None,
+            track: None,
+        }
+    }
+
+    pub fn with_static_payload_types(mut self, static_payload_types: &'static [StaticPayloadType]) -> Self {
+        self.static_payload_types = static_payload_types;
+        self
+    }
+
+    pub fn with_depacker(mut self, depacker: DepackerFactory) -> Self {
+        self.depacker = Some(depacker);
+        self
+    }
+
+    pub fn with_decoder(mut self, decoder: DecoderArgsBuilder) -> Self {
+        self.decoder = Some(decoder);
+        self
+    }
+
+    pub fn with_track(mut self, track: TrackBuilder) -> Self {
+        self.track = Some(track);
+        self
+    }
+
+    pub fn has_name(&self, name: &str) -> bool {
+        self.names.iter().any(|x| x.eq_ignore_ascii_case(name))
+    }
+
+    pub fn make_depacker(&self, codec: &SdpCodec) -> Result<Option<Box<dyn RtpCodecDepacker>>> {
+        self.depacker.map(|f| f(codec)).transpose()
+    }
+
+    pub fn decoder_args(&self, codec: &SdpCodec) -> Result<Option<CodecDecoderArgs>> {
+        self.decoder.map(|f| f(codec)).transpose()
+    }
+
+    pub fn add_track(&self, output: &mut FFOutput, codec: &SdpCodec) -> Result<Option<usize>> {
+        self.track.map(|f| f(output, codec)).transpose()
+    }
+}
+
+#[derive(Clone)]
+pub struct CodecRegistry {
+    codecs: Vec<CodecDescriptor>,
+}
+
+impl Default for CodecRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl CodecRegistry {
+    pub fn empty() -> Self {
+        Self {
+            codecs: Default::default(),
+        }
+    }
+
+    pub fn builtin() -> Self {
+        let mut registry = Self::empty();
+        for desc in builtin_codecs() {
+            registry.register(desc);
+        }
+        registry
+    }
+
+    // replaces the one with the same codec id
+    pub fn register(&mut self, desc: CodecDescriptor) {
+        match self.codecs.iter_mut().find(|x| x.codec_id == desc.codec_id) {
+            Some(exist) => *exist = desc,
+            None => self.codecs.push(desc),
+        }
+    }
+
+    pub fn get(&self, codec_id: CodecId) -> Option<&CodecDescriptor> {
+        self.codecs.iter().find(|x| x.codec_id == codec_id)
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<&CodecDescriptor> {
+        self.codecs.iter().find(|x| x.has_name(name))
+    }
+
+    pub fn find_by_static_payload_type(&self, payload_type: u8) -> Option<(&CodecDescriptor, &StaticPayloadType)> {
+        self.codecs.iter()
+        .find_map(|desc| {
+            desc.static_payload_types.iter()
+            .find(|x| x.payload_type == payload_type)
+            .map(|x| (desc, x))
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CodecDescriptor> {
+        self.codecs.iter()
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref CODEC_REGISTRY: RwLock<CodecRegistry> = RwLock::new(CodecRegistry::builtin());
+}
+
+// do not register while holding it
+pub fn codec_registry() -> RwLockReadGuard<'static, CodecRegistry> {
+    CODEC_REGISTRY.read().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn register_codec(desc: CodecDescriptor) {
+    CODEC_REGISTRY.write().unwrap_or_else(|e| e.into_inner()).register(desc);
+}
+
+// copy of the descriptor, so that the registry is not locked while using it
+pub fn find_codec(codec_id: CodecId) -> Option<CodecDescriptor> {
+    codec_registry().get(codec_id).cloned()
+}
+
+fn codec_fmtp(codec: &SdpCodec) -> Option<&str> {
+    codec.fmtps.first().map(|x|x.as_str())
+}
+
+// select depack backend by register_codec(h264_codec_descriptor(backend))
+pub fn h264_codec_descriptor(backend: H264DepackBackend) -> CodecDescriptor {
+    let depacker: DepackerFactory = match backend {
+        H264DepackBackend::Retina => |codec| RtpDepackerH264::with_backend(H264DepackBackend::Retina, codec_fmtp(codec)),
+        H264DepackBackend::WebrtcRs => |codec| RtpDepackerH264::with_backend(H264DepackBackend::WebrtcRs, codec_fmtp(codec)),
+    };
+
+    CodecDescriptor::new(CodecId::H264, &["H264"])
+    .with_depacker(depacker)
+    .with_decoder(h264_decoder_args)
+    .with_track(|output, codec| {
+        let args = h264_decoder_args(codec)?;
+        Ok(output.add_h264_track(args.width, args.height, &args.extra)?.index())
+    })
+}
+
+fn builtin_codecs() -> Vec<CodecDescriptor> {
+    vec![
+        h264_codec_descriptor(H264DepackBackend::default()),
+
+        CodecDescriptor::new(CodecId::H265, &["H265"])
+        .with_depacker(|codec| Ok(RtpDepackerH265::new(codec_fmtp(codec))?.into_box()))
+        .with_decoder(h265_decoder_args)
+        .with_track(|output, codec| {
+            let args = h265_decoder_args(codec)?;
+            Ok(output.add_video_track(args.codec_id, args.width, args.height, &args.extra)?.index())
+        }),
+
+        CodecDescriptor::new(CodecId::VP8, &["VP8"])
+        .with_depacker(|_codec| Ok(RtpDepackerVP8::new().into_box()))
+        .with_decoder(|_codec| Ok(CodecDecoderArgs::video(ff::codec::Id::VP8, 0, 0, Bytes::new()))),
+
+        CodecDescriptor::new(CodecId::VP9, &["VP9"])
+        .with_depacker(|_codec| Ok(RtpDepackerVP9::new().into_box()))
+        .with_decoder(|_codec| Ok(CodecDecoderArgs::video(ff::codec::Id::VP9, 0, 0, Bytes::new()))),
+
+        CodecDescriptor::new(CodecId::AV1, &["AV1"])
+        .with_depacker(|_codec| Ok(RtpDepackerAV1::new().into_box()))
+        .with_decoder(|_codec| Ok(CodecDecoderArgs::video(ff::codec::Id::AV1, 0, 0, Bytes::new()))),
+
+        CodecDescriptor::new(CodecId::AAC, &["AAC", "MPEG4-GENERIC"])
+        .with_depacker(|codec| {
+            let channels = codec.channels.and_then(|x| NonZeroU16::new(x as u16));
+            Ok(RtpDepackerAAC::new(codec.clock_rate, channels, codec_fmtp(codec))?.into_box())
+        })
+        .with_decoder(aac_decoder_args)
+        .with_track(|output, codec| {
+            Ok(output.add_aac_track(codec.clock_rate as i32, codec.channels.unwrap_or(1) as i32)?.index())
+        }),
+
+        CodecDescriptor::new(CodecId::MP4ALATM, &["MP4A-LATM"])
+        .with_depacker(|codec| Ok(RtpDepackerLATM::new(codec_fmtp(codec))?.into_box()))
+        .with_decoder(latm_decoder_args)
+        .with_track(|output, codec| {
+            let args = latm_decoder_args(codec)?;
+            Ok(output.add_aac_track_with_config(args.sample_rate, args.channels, &args.extra)?.index())
+        }),
+
+        CodecDescriptor::new(CodecId::MPA, &["MPA"])
+        .with_static_payload_types(&[StaticPayloadType { payload_type: 14, clock_rate: MPA_CLOCK_RATE, channels: 2 }])
+        .with_depacker(|_codec| Ok(RtpDepackerMPA::new().into_box()))
+        .with_decoder(mpa_decoder_args)
+        .with_track(|output, codec| {
+            let args = mpa_decoder_args(codec)?;
+            Ok(output.add_audio_track(args.codec_id, args.sample_rate, args.channels, Some(1152))?.index())
+        }),
+
+        CodecDescriptor::new(CodecId::Opus, &["OPUS"])
+        .with_depacker(|codec| Ok(RtpDepackerOpus::new(codec_fmtp(codec))?.into_box()))
+        .with_decoder(opus_decoder_args)
+        .with_track(|output, codec| {
+            let args = opus_decoder_args(codec)?;
+            Ok(output.add_opus_track(args.channels)?.index())
+        }),
+
+        // payload is raw samples, decoded by G711Decoder instead of ffmpeg
+        CodecDescriptor::new(CodecId::PCMU, &["PCMU"])
+        .with_static_payload_types(&[StaticPayloadType { payload_type: 0, clock_rate: G711_SAMPLERATE, channels: 1 }])
+        .with_depacker(|_codec| Ok(Box::new(RtpDepackerSimpleAudio::default())))
+        .with_decoder(|_codec| Ok(CodecDecoderArgs::audio(ff::codec::Id::PCM_MULAW, G711_SAMPLERATE as i32, 1))),
+
+        CodecDescriptor::new(CodecId::PCMA, &["PCMA"])
+        .with_static_payload_types(&[StaticPayloadType { payload_type: 8, clock_rate: G711_SAMPLERATE, channels: 1 }])
+        .with_depacker(|_codec| Ok(Box::new(RtpDepackerSimpleAudio::default())))
+        .with_decoder(|_codec| Ok(CodecDecoderArgs::audio(ff::codec::Id::PCM_ALAW, G711_SAMPLERATE as i32, 1))),
+
+        CodecDescriptor::new(CodecId::G722, &["G722"])
+        .with_static_payload_types(&[StaticPayloadType { payload_type: 9, clock_rate: 8000, channels: 1 }])
+        .with_depacker(|_codec| Ok(Box::new(RtpDepackerSimpleAudio::default())))
+        .with_decoder(|codec| Ok(CodecDecoderArgs::audio(
+            // real sample rate 16000 instead of rtp clock rate
+            ff::codec::Id::ADPCM_G722,
+            codec.sample_rate() as i32,
+            codec.channels.unwrap_or(1) as i32,
+        ))),
+
+        CodecDescriptor::new(CodecId::AMR, &["AMR"])
+        .with_depacker(|codec| Ok(RtpDepackerAMR::new(false, codec.channels, codec_fmtp(codec))?.into_box()))
+        .with_decoder(|codec| Ok(CodecDecoderArgs::audio(ff::codec::Id::AMR_NB, codec.clock_rate as i32, 1))),
+
+        CodecDescriptor::new(CodecId::AMRWB, &["AMR-WB"])
+        .with_depacker(|codec| Ok(RtpDepackerAMR::new(true, codec.channels, codec_fmtp(codec))?.into_box()))
+        .with_decoder(|codec| Ok(CodecDecoderArgs::audio(ff::codec::Id::AMR_WB, codec.clock_rate as i32, 1))),
+
+        CodecDescriptor::new(CodecId::TelephoneEvent, &["telephone-event"]),
+
+        CodecDescriptor::new(CodecId::RED, &["red"]),
+
+        CodecDescriptor::new(CodecId::RtpRTX, &["RTX"]),
+
+        CodecDescriptor::new(CodecId::ULPFEC, &["ulpfec"]),
+
+        CodecDescriptor::new(CodecId::FlexFEC, &["flexfec-03", "flexfec"]),
+    ]
+}
+
+// sps and pps in annexb as extradata
+fn h264_decoder_args(codec: &SdpCodec) -> Result<CodecDecoderArgs> {
+    let param = RtpH264Parameters::parse_from_str(codec_fmtp(codec).unwrap_or("")).map_err(|e|anyhow!("{e}"))?;
+
+    Ok(CodecDecoderArgs::video(
+        ff::codec::Id::H264,
+        param.generic.pixel_dimensions.0 as i32,
+        param.generic.pixel_dimensions.1 as i32,
+        param.annexb_spspps(),
+    ))
+}
+
+fn h265_decoder_args(codec: &SdpCodec) -> Result<CodecDecoderArgs> {
+    let param = RtpH265Parameters::parse_from_str(codec_fmtp(codec).unwrap_or("")).map_err(|e|anyhow!("{e}"))?;
+
+    Ok(CodecDecoderArgs::video(
+        ff::codec::Id::HEVC,
+        param.generic.pixel_dimensions.0 as i32,
+        param.generic.pixel_dimensions.1 as i32,
+        param.generic.extra_data.clone(),
+    ))
+}
+
+// AudioSpecificConfig of RFC 3640 config as extradata if any
+fn aac_decoder_args(codec: &SdpCodec) -> Result<CodecDecoderArgs> {
+    let config = codec_fmtp(codec).unwrap_or("").split(';')
+        .filter_map(|x| x.split_once('='))
+        .find(|x| x.0.trim().eq_ignore_ascii_case("config"))
+        .map(|x| decode_hex(x.1.trim()).ok_or_else(||anyhow!("invalid aac config [{}]", x.1)))
+        .transpose()?;
+
+    Ok(CodecDecoderArgs {
+        extra: config.map(Bytes::from).unwrap_or_default(),
+        ..CodecDecoderArgs::audio(ff::codec::Id::AAC, codec.clock_rate as i32, codec.channels.unwrap_or(1) as i32)
+    })
+}
+
+// AudioSpecificConfig as extradata,
+// cpresent=1 is rejected since the track and decoder can't be created before the in band config
+fn latm_decoder_args(codec: &SdpCodec) -> Result<CodecDecoderArgs> {
+    let params = RtpLatmParameters::parse_from_str(codec_fmtp(codec).unwrap_or("")).map_err(|e|anyhow!("{e}"))?;
+
+    // rtp clock rate may differ from the sample rate in StreamMuxConfig
+    match params.stream_mux_config().transpose()? {
+        Some(config) => Ok(CodecDecoderArgs {
+            extra: config.asc.raw.clone(),
+            ..CodecDecoderArgs::audio(ff::codec::Id::AAC, config.asc.sample_rate as i32, config.asc.channels as i32)
+        }),
+        None => bail!("unsupported latm config in band"),
+    }
+}
+
+// real sample rate is in frame header, rtp clock rate is always 90000
+fn mpa_decoder_args(codec: &SdpCodec) -> Result<CodecDecoderArgs> {
+    let params = RtpMpaParameters::parse_from_str(codec_fmtp(codec).unwrap_or("")).map_err(|e|anyhow!("{e}"))?;
+    Ok(CodecDecoderArgs::audio(
+        ff::codec::Id::MP3,
+        params.samplerate.unwrap_or(44100) as i32,
+        params.channels() as i32,
+    ))
+}
+
+// always decode at 48 kHz
+fn opus_decoder_args(codec: &SdpCodec) -> Result<CodecDecoderArgs> {
+    let params = RtpOpusParameters::parse_from_str(codec_fmtp(codec).unwrap_or("")).map_err(|e|anyhow!("{e}"))?;
+    Ok(CodecDecoderArgs::audio(ff::codec::Id::OPUS, 48000, params.channels() as i32))
+}
+
+
+#[test]
+fn test_codec_registry() {
+    let mut registry = CodecRegistry::builtin();
+    assert_eq!(registry.find_by_name("mpeg4-generic").map(|x|x.codec_id), Some(CodecId::AAC));
+    assert_eq!(registry.find_by_name("FlexFEC").map(|x|x.codec_id), Some(CodecId::FlexFEC));
+    assert!(registry.find_by_name("x-unknown").is_none());
+
+    let (desc, pt) = registry.find_by_static_payload_type(9).unwrap();
+    assert_eq!((desc.codec_id, pt.clock_rate, pt.channels), (CodecId::G722, 8000, 1));
+    assert!(registry.find_by_static_payload_type(96).is_none());
+
+    // only codecs with depacker and track are written as is
+    assert!(registry.get(CodecId::H264).unwrap().track.is_some());
+    assert!(registry.get(CodecId::PCMU).unwrap().track.is_none());
+
+    // custom codec
+    registry.register(
+        CodecDescriptor::new(CodecId::Custom(1), &["x-raw"])
+        .with_depacker(|_codec| Ok(Box::new(RtpDepackerSimpleAudio::default())))
+    );
+    let desc = registry.find_by_name("X-RAW").unwrap();
+    assert_eq!(desc.codec_id, CodecId::Custom(1));
+    assert!(desc.depacker.is_some() && desc.decoder.is_none());
+
+    // replaces the builtin
+    registry.register(CodecDescriptor::new(CodecId::PCMU, &["PCMU"]));
+    assert!(registry.get(CodecId::PCMU).unwrap().depacker.is_none());
+    assert!(registry.find_by_static_payload_type(0).is_none());
+}
+
+#[test]
+fn test_register_codec() {
+    // a codec id and name no other test uses, removed at the end
+    // since tests running in parallel share the global registry
+    register_codec(CodecDescriptor::new(CodecId::Custom(0x5eed), &["x-test-registry"]));
+    assert_eq!(CodecId::parse_from_str("X-Test-Registry"), Some(CodecId::Custom(0x5eed)));
+    assert_eq!(CodecId::parse_from_str("H264"), Some(CodecId::H264));
+    assert_eq!(CodecId::from_static_payload_type(8), Some((CodecId::PCMA, 8000, 1)));
+
+    CODEC_REGISTRY.write().unwrap_or_else(|e| e.into_inner()).codecs.retain(|x| x.codec_id != CodecId::Custom(0x5eed));
+    assert_eq!(CodecId::parse_from_str("X-Test-Registry"), None);
+}
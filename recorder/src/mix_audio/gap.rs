@@ -0,0 +1,201 @@
+// keeps decoded audio of a channel at the position of its rtp timestamp in the mixed timeline,
+// missing media time (loss, DTX, mute) is filled and overlapped samples are trimmed
+
+// what fills missing media time that decoders didn't conceal,
+// opus (see RtpDepackerOpus) and AAC are concealed by their decoders before reaching here
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AudioGapFill {
+    // low level white noise
+    ComfortNoise,
+
+    #[default]
+    Silence,
+}
+
+// differences within this are resampling jitter, samples are just appended
+const GAP_TOLERANCE_MS: i64 = 3;
+
+// re-anchored if rtp timestamp is this far from arrival, e.g. timestamp jumped or reset
+const MAX_DRIFT_MS: i64 = 1000;
+
+// about -60 dBov
+const COMFORT_NOISE_AMPLITUDE: i32 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioPlace {
+    // right after the end of channel
+    Append,
+
+    // interleaved length missing before the samples
+    Fill(usize),
+
+    // interleaved length of the samples already passed
+    Trim(usize),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioGapStats {
+    pub gaps: u64,
+
+    // interleaved length
+    pub filled: u64,
+    pub trimmed: u64,
+
+    pub resyncs: u64,
+}
+
+// positions are interleaved lengths of the mixed timeline
+pub struct AudioTimeline {
+    clock_rate: u32,
+    rate: u32,
+    channels: u32,
+
+    // unwrapped rtp timestamp and position
+    anchor: Option<(i64, u64)>,
+    last_rtp: Option<(u32, i64)>,
+    stats: AudioGapStats,
+}
+
+impl AudioTimeline {
+    // rate and channels of the mixed output
+    pub fn new(clock_rate: u32, rate: u32, channels: u32) -> Self {
+        Self {
+            clock_rate,
+            rate,
+            channels,
+            anchor: None,
+            last_rtp: None,
+            stats: Default::default(),
+        }
+    }
+
+    // now: position of the arrival, end: position after the last samples of channel
+    pub fn push(&mut self, rtp_ts: u32, now: u64, end: u64) -> AudioPlace {
+        let ext = match self.last_rtp {
+            Some((last, ext)) => ext + rtp_ts.wrapping_sub(last) as i32 as i64,
+            None => rtp_ts as i64,
+        };
+        self.last_rtp = Some((rtp_ts, ext));
+
+        let mut pos = match self.anchor {
+            Some((anchor_rtp, anchor_pos)) => anchor_pos as i64 + self.rtp_to_len(ext - anchor_rtp),
+            None => {
+                self.anchor = Some((ext, now));
+                now as i64
+            },
+        };
+
+        if (pos - now as i64).abs() > self.millis_to_len(MAX_DRIFT_MS) {
+            self.anchor = Some((ext, now));
+            self.stats.resyncs += 1;
+            pos = now as i64;
+        }
+
+        let diff = pos - end as i64;
+        if diff.abs() <= self.millis_to_len(GAP_TOLERANCE_MS) {
+            AudioPlace::Append
+        } else if diff > 0 {
+            self.stats.gaps += 1;
+            self.stats.filled += diff as u64;
+            AudioPlace::Fill(diff as usize)
+        } else {
+            self.stats.trimmed += (-diff) as u64;
+            AudioPlace::Trim((-diff) as usize)
+        }
+    }
+
+    pub fn stats(&self) -> &AudioGapStats {
+        &self.stats
+    }
+
+    fn rtp_to_len(&self, rtp: i64) -> i64 {
+        rtp * self.rate as i64 / self.clock_rate as i64 * self.channels as i64
+    }
+
+    fn millis_to_len(&self, millis: i64) -> i64 {
+        millis * self.rate as i64 / 1000 * self.channels as i64
+    }
+}
+
+pub struct GapFiller {
+    policy: AudioGapFill,
+    noise: u32,
+}
+
+impl GapFiller {
+    pub fn new(policy: AudioGapFill) -> Self {
+        Self {
+            policy,
+            noise: 0x2545_F491,
+        }
+    }
+
+    // interleaved samples of len
+    pub fn fill(&mut self, len: usize) -> Vec<i16> {
+        let mut buf = vec![0_i16; len];
+        match self.policy {
+            AudioGapFill::Silence => {}
+            AudioGapFill::ComfortNoise => {
+                for x in buf.iter_mut() {
+                    *x = self.next_noise();
+                }
+            }
+        }
+        buf
+    }
+
+    // xorshift32
+    fn next_noise(&mut self) -> i16 {
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 17;
+        self.noise ^= self.noise << 5;
+        ((self.noise % (2 * COMFORT_NOISE_AMPLITUDE as u32 + 1)) as i32 - COMFORT_NOISE_AMPLITUDE) as i16
+    }
+}
+
+
+#[test]
+fn test_audio_timeline() {
+    // 8 kHz rtp into 48 kHz stereo, 20 ms is 160 rtp and 1920 interleaved
+    let mut timeline = AudioTimeline::new(8000, 48000, 2);
+    assert_eq!(timeline.push(1000, 9600, 0), AudioPlace::Fill(9600));
+    assert_eq!(timeline.push(1160, 11600, 11520), AudioPlace::Append);
+
+    // one packet lost
+    assert_eq!(timeline.push(1480, 13600, 13440), AudioPlace::Fill(1920));
+
+    // resampler jitter within tolerance
+    assert_eq!(timeline.push(1640, 15600, 17276), AudioPlace::Append);
+
+    // channel ran dry and was mixed as silence past the position
+    assert_eq!(timeline.push(1800, 17600, 19200 + 960), AudioPlace::Trim(960));
+
+    // duplicated packet
+    assert_eq!(timeline.push(1800, 17600, 21120), AudioPlace::Trim(1920));
+
+    // timestamp jumped, placed at arrival
+    assert_eq!(timeline.push(900_000, 21600, 21600), AudioPlace::Append);
+
+    // wraps around
+    let mut timeline = AudioTimeline::new(48000, 48000, 1);
+    assert_eq!(timeline.push(u32::MAX - 479, 0, 0), AudioPlace::Append);
+    assert_eq!(timeline.push(480, 960, 480), AudioPlace::Fill(480));
+
+    assert_eq!(timeline.stats(), &AudioGapStats {
+        gaps: 1,
+        filled: 480,
+        trimmed: 0,
+        resyncs: 0,
+    });
+}
+
+#[test]
+fn test_gap_filler() {
+    let mut filler = GapFiller::new(AudioGapFill::ComfortNoise);
+    let noise = filler.fill(1000);
+    assert!(noise.iter().all(|x| x.abs() <= COMFORT_NOISE_AMPLITUDE as i16));
+    assert!(noise.iter().any(|x| *x != 0));
+
+    let mut filler = GapFiller::new(AudioGapFill::Silence);
+    assert_eq!(filler.fill(4), vec![0; 4]);
+}
@@ -4,6 +4,7 @@
 use std::collections::HashMap;
 use ffmpeg_next as ff;
 use crate::rwbuf::{RBuf, RwBufVec};
+use super::speaker::{pcm_audio_level, SILENCE_LEVEL};
 
 
 
@@ -13,6 +14,9 @@ pub struct PcmMixer {
     next_id: u64,
     sources: HashMap<AChId, PcmChannel>,
     max_len: usize,
+
+    // interleaved length pulled since start, the position of mixing
+    pulled: u64,
 }
 
 impl PcmMixer {
@@ -21,6 +25,7 @@ impl PcmMixer {
             max_len,
             next_id: 0,
             sources: Default::default(),
+            pulled: 0,
         })
     }
 
@@ -30,6 +35,8 @@ impl PcmMixer {
 
         self.sources.insert(ch_id, PcmChannel {
             pcm: RwBufVec::new(self.max_len),
+            level: SILENCE_LEVEL,
+            end: self.pulled,
         });
         
         Ok(ch_id)
@@ -42,11 +49,29 @@ impl PcmMixer {
 
     pub fn update_ch(&mut self, ch_id: &AChId, samples: &[i16]) -> Result<()> {
         if let Some(ch) = self.sources.get_mut(ch_id) {
+            ch.end = ch.end.max(self.pulled) + samples.len() as u64;
             ch.pcm.push_rotate(samples);
+            ch.level = pcm_audio_level(samples);
         }
         Ok(())
     }
 
+    // -dBov of the last updated samples
+    pub fn ch_level(&self, ch_id: &AChId) -> Option<u8> {
+        self.sources.get(ch_id).map(|x| x.level)
+    }
+
+    // position after the last samples of channel,
+    // a channel ran dry has been mixed as silence until the position of mixing
+    pub fn ch_end(&self, ch_id: &AChId) -> Option<u64> {
+        self.sources.get(ch_id).map(|x| x.end.max(self.pulled))
+    }
+
+    // interleaved length pulled since start
+    pub fn position(&self) -> u64 {
+        self.pulled
+    }
+
     pub fn pull_mix(&mut self, buf: &mut [i16]) {
         buf.fill(0);
         for (_id, ch) in self.sources.iter_mut() {
@@ -59,11 +84,16 @@ impl PcmMixer {
             }
             ch.pcm.radvance(len);
         }
+        self.pulled += buf.len() as u64;
     }
 }
 
 struct PcmChannel {
     pcm: RwBufVec<i16>,
+    level: u8,
+
+    // position after the last samples
+    end: u64,
 }
 
 
@@ -128,6 +158,13 @@ impl PcmTimedMixer {
         millis_to_len(self.samplerate, self.channels, millis)
     }
 
+    // position of ts in the mixed timeline of PcmMixer, the first call starts the timeline
+    pub fn position(&mut self, ts: i64) -> u64 {
+        let first = *self.first_mix_ts.get_or_insert(ts);
+        let frames = (ts - first).max(0) as u64 * self.samplerate as u64 / 1000;
+        frames * self.channels as u64
+    }
+
 }
 
 fn millis_to_len(samplerate: u32, channels: u32, millis: i64) -> usize {
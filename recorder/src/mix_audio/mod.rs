@@ -1,2 +1,4 @@
 
 pub mod mixer;
+pub mod speaker;
+pub mod gap;
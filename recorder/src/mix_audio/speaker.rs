@@ -0,0 +1,230 @@
+// active speaker detection by audio levels in -dBov,
+//   - RFC 6464 ssrc-audio-level is preferred, PCM energy of decoded samples is the fallback
+//   - levels are smoothed, a new speaker must be louder than the current one by a margin,
+//     and the current one is held for a minimum time
+
+use std::{collections::{HashMap, VecDeque}, hash::Hash};
+
+pub const SILENCE_LEVEL: u8 = 127;
+
+// -dBov of the samples, same scale as RFC 6464
+pub fn pcm_audio_level(samples: &[i16]) -> u8 {
+    if samples.is_empty() {
+        return SILENCE_LEVEL
+    }
+
+    let sum: f64 = samples.iter().map(|x| *x as f64 * *x as f64).sum();
+    let rms = (sum / samples.len() as f64).sqrt();
+    if rms < 1.0 {
+        return SILENCE_LEVEL
+    }
+
+    let db = 20.0 * (rms / i16::MAX as f64).log10();
+    (-db).round().clamp(0.0, SILENCE_LEVEL as f64) as u8
+}
+
+#[derive(Debug, Clone)]
+pub struct SpeakerConfig {
+    // levels quieter than it are silence, in -dBov
+    pub threshold: u8,
+
+    // in dB
+    pub margin: f64,
+
+    // milliseconds to hold the current speaker
+    pub min_hold: i64,
+
+    // time constant of smoothing in milliseconds
+    pub smooth: i64,
+
+    // milliseconds without levels to treat a channel as silent,
+    // PCM levels are ignored if audio level extension received within it
+    pub timeout: i64,
+}
+
+impl Default for SpeakerConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 50,
+            margin: 6.0,
+            min_hold: 2000,
+            smooth: 300,
+            timeout: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeakerChange<K> {
+    pub ts: i64,
+    pub from: Option<K>,
+    pub to: K,
+}
+
+struct SpeakerState {
+    // SILENCE_LEVEL - level, smoothed
+    loudness: f64,
+    last_ts: i64,
+    ext_ts: Option<i64>,
+}
+
+pub struct ActiveSpeakerDetector<K> {
+    config: SpeakerConfig,
+    states: HashMap<K, SpeakerState>,
+    current: Option<K>,
+    since: i64,
+    events: VecDeque<SpeakerChange<K>>,
+}
+
+impl<K: Copy + Eq + Hash> ActiveSpeakerDetector<K> {
+    pub fn new(config: SpeakerConfig) -> Self {
+        Self {
+            config,
+            states: Default::default(),
+            current: None,
+            since: 0,
+            events: Default::default(),
+        }
+    }
+
+    pub fn current(&self) -> Option<K> {
+        self.current
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        self.states.remove(key);
+    }
+
+    // level of RFC 6464 in -dBov
+    pub fn update_ext_level(&mut self, key: K, ts: i64, level: u8) {
+        self.update(key, ts, level);
+        if let Some(state) = self.states.get_mut(&key) {
+            state.ext_ts = Some(ts);
+        }
+        self.evaluate(ts);
+    }
+
+    // level of decoded samples in -dBov, see pcm_audio_level
+    pub fn update_pcm_level(&mut self, key: K, ts: i64, level: u8) {
+        let ext_ts = self.states.get(&key).and_then(|x| x.ext_ts);
+        if let Some(ext_ts) = ext_ts {
+            if ts - ext_ts <= self.config.timeout {
+                return
+            }
+        }
+        self.update(key, ts, level);
+        self.evaluate(ts);
+    }
+
+    pub fn pull_event(&mut self) -> Option<SpeakerChange<K>> {
+        self.events.pop_front()
+    }
+
+    fn update(&mut self, key: K, ts: i64, level: u8) {
+        let loudness = (SILENCE_LEVEL - level.min(SILENCE_LEVEL)) as f64;
+        let state = self.states.entry(key).or_insert(SpeakerState {
+            loudness,
+            last_ts: ts,
+            ext_ts: None,
+        });
+
+        let elapsed = (ts - state.last_ts).max(0);
+        let alpha = (elapsed as f64 / self.config.smooth.max(1) as f64).min(1.0);
+        state.loudness += alpha * (loudness - state.loudness);
+        state.last_ts = state.last_ts.max(ts);
+    }
+
+    fn loudness(&self, key: &K, ts: i64) -> Option<f64> {
+        self.states.get(key)
+        .filter(|x| ts - x.last_ts <= self.config.timeout)
+        .map(|x| x.loudness)
+    }
+
+    fn evaluate(&mut self, ts: i64) {
+        let min_loudness = (SILENCE_LEVEL - self.config.threshold.min(SILENCE_LEVEL)) as f64;
+
+        let loudest = self.states.keys()
+            .filter_map(|key| self.loudness(key, ts).map(|x| (*key, x)))
+            .filter(|x| x.1 >= min_loudness)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        let (key, loudness) = match loudest {
+            Some(v) => v,
+            None => return,
+        };
+
+        if self.current == Some(key) {
+            return
+        }
+
+        if let Some(current) = &self.current {
+            if ts - self.since < self.config.min_hold {
+                return
+            }
+
+            let current_loudness = self.loudness(current, ts).unwrap_or(0.0);
+            if loudness < current_loudness + self.config.margin {
+                return
+            }
+        }
+
+        self.events.push_back(SpeakerChange {
+            ts,
+            from: self.current,
+            to: key,
+        });
+        self.current = Some(key);
+        self.since = ts;
+    }
+}
+
+
+#[test]
+fn test_pcm_audio_level() {
+    assert_eq!(pcm_audio_level(&[]), SILENCE_LEVEL);
+    assert_eq!(pcm_audio_level(&[0; 160]), SILENCE_LEVEL);
+    assert_eq!(pcm_audio_level(&[i16::MAX, -i16::MAX]), 0);
+
+    // about -20 dBov
+    assert_eq!(pcm_audio_level(&[3277, -3277]), 20);
+}
+
+#[test]
+fn test_active_speaker() {
+    let mut detector = ActiveSpeakerDetector::new(SpeakerConfig::default());
+
+    // 1 talks, 2 is silent
+    for ts in (0..1000).step_by(20) {
+        detector.update_ext_level(1, ts, 30);
+        detector.update_pcm_level(2, ts, 100);
+    }
+    assert_eq!(detector.pull_event(), Some(SpeakerChange { ts: 0, from: None, to: 1 }));
+    assert_eq!(detector.current(), Some(1));
+
+    // 2 is a bit louder but within the margin
+    for ts in (1000..3000).step_by(20) {
+        detector.update_ext_level(1, ts, 30);
+        detector.update_pcm_level(2, ts, 27);
+    }
+    assert_eq!(detector.pull_event(), None);
+
+    // 1 stops, switch after the smoothed level dropped
+    for ts in (3000..4000).step_by(20) {
+        detector.update_ext_level(1, ts, 127);
+        detector.update_pcm_level(2, ts, 27);
+    }
+    let event = detector.pull_event().unwrap();
+    assert_eq!((event.from, event.to), (Some(1), 2));
+    assert!(event.ts >= 3000 && event.ts < 3300);
+
+    // 1 talks again, held by the min hold time
+    for ts in (4000..4000 + 100).step_by(20) {
+        detector.update_ext_level(1, ts, 10);
+        detector.update_pcm_level(2, ts, 27);
+    }
+    assert_eq!(detector.pull_event(), None);
+
+    // pcm level of 1 is ignored while the extension is received
+    detector.update_pcm_level(1, 4100, 127);
+    assert_eq!(detector.current(), Some(2));
+}
@@ -0,0 +1,130 @@
+// reference integrity of a decoded video flow,
+// recorded streams can't ask PLI/FIR, so wait for the next keyframe after loss
+
+// what channel shows from loss until next keyframe
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConcealPolicy {
+    // the last good frame
+    #[default]
+    Freeze,
+
+    // a plain image of the same size
+    Placeholder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConcealAction {
+    // references are intact
+    Decode,
+
+    // the first frame after loss, channel becomes stale
+    Stale,
+
+    // still waiting keyframe
+    Skip,
+
+    // keyframe after loss, channel is fresh again
+    Recover,
+}
+
+impl ConcealAction {
+    pub fn is_decode(&self) -> bool {
+        matches!(self, Self::Decode | Self::Recover)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConcealStats {
+    // times of becoming stale
+    pub events: u64,
+
+    // frames not decoded while stale
+    pub skipped_frames: u64,
+
+    pub recoveries: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct RefIntegrity {
+    stale: bool,
+
+    // packets lost since the last frame
+    lost: bool,
+    stats: ConcealStats,
+}
+
+impl RefIntegrity {
+    // corrupt: data of frame or its references are broken
+    pub fn push(&mut self, keyframe: bool, corrupt: bool) -> ConcealAction {
+        let corrupt = corrupt || (std::mem::take(&mut self.lost) && !keyframe);
+
+        if keyframe && !corrupt {
+            if self.stale {
+                self.stale = false;
+                self.stats.recoveries += 1;
+                return ConcealAction::Recover;
+            }
+            return ConcealAction::Decode;
+        }
+
+        if corrupt && !self.stale {
+            self.stale = true;
+            self.stats.events += 1;
+            self.stats.skipped_frames += 1;
+            return ConcealAction::Stale;
+        }
+
+        if self.stale {
+            self.stats.skipped_frames += 1;
+            ConcealAction::Skip
+        } else {
+            ConcealAction::Decode
+        }
+    }
+
+    // packets lost between frames, e.g. reported by jitter buffer,
+    // the next frame is broken unless it is a keyframe
+    pub fn push_lost(&mut self) {
+        self.lost = true;
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    pub fn stats(&self) -> &ConcealStats {
+        &self.stats
+    }
+}
+
+
+#[test]
+fn test_ref_integrity() {
+    let mut integrity = RefIntegrity::default();
+    assert_eq!(integrity.push(true, false), ConcealAction::Decode);
+    assert_eq!(integrity.push(false, false), ConcealAction::Decode);
+
+    // loss, then frames depending on the broken one until keyframe
+    assert_eq!(integrity.push(false, true), ConcealAction::Stale);
+    assert_eq!(integrity.push(false, false), ConcealAction::Skip);
+    assert_eq!(integrity.push(false, true), ConcealAction::Skip);
+    assert!(integrity.is_stale());
+
+    // a broken keyframe doesn't recover
+    assert_eq!(integrity.push(true, true), ConcealAction::Skip);
+    assert_eq!(integrity.push(true, false), ConcealAction::Recover);
+    assert_eq!(integrity.push(false, false), ConcealAction::Decode);
+    assert!(!integrity.is_stale());
+
+    // lost packets break the next frame but not a keyframe
+    integrity.push_lost();
+    assert_eq!(integrity.push(true, false), ConcealAction::Decode);
+    integrity.push_lost();
+    assert_eq!(integrity.push(false, false), ConcealAction::Stale);
+
+    assert_eq!(integrity.stats(), &ConcealStats {
+        events: 2,
+        skipped_frames: 5,
+        recoveries: 1,
+    });
+}
@@ -13,6 +13,9 @@ use crate::ffeasy::video::{image::FFYuvImage, scaler::FFAutoScaler};
 pub enum VChFlag {
     ShareScreen     = 0b_0000_0001,
     Talker          ,
+
+    // waiting keyframe after loss, the image is frozen or a placeholder
+    Stale           ,
 }
 
 pub type VChFlags = BitFlags<VChFlag>;
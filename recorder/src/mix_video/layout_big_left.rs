@@ -101,18 +101,15 @@ impl LayoutOp for LayoutBigLeft {
     }
 }
 
+// share screen first, then talker
 fn get_big_ch_id(channels: &VChannels) -> Option<VChId> {
-    let mut iter = channels.iter();
-    
-    let mut selected = iter.next().map(|x|*x.0);
-
-    for item in iter {
-        if item.1.flags.contains(VChFlag::ShareScreen) {
-            selected = Some(*item.0);
-            break;
-        }
-    }
-    selected
+    let find = |flag: VChFlag| channels.iter()
+        .find(|x| x.1.flags.contains(flag))
+        .map(|x| *x.0);
+
+    find(VChFlag::ShareScreen)
+    .or_else(|| find(VChFlag::Talker))
+    .or_else(|| channels.keys().next().cloned())
 }
 
 
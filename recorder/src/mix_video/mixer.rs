@@ -2,8 +2,8 @@
 
 
 use ffmpeg_next as ff;
-use crate::ffeasy::video::{image::FFYuvImage, scaler::FFAutoScaler, VideoSize};
-use super::{layout_dynamic::LayoutDynamic, LayoutOp, VChFlags, VChId, VChannel, VChannels};
+use crate::ffeasy::video::{image::FFYuvImage, scaler::FFAutoScaler, VideoSize, YuvColor};
+use super::{layout_dynamic::LayoutDynamic, LayoutOp, VChFlag, VChFlags, VChId, VChannel, VChannels};
 
 
 type Result<T> = std::result::Result<T, ff::Error>;
@@ -59,6 +59,28 @@ impl VideoMixer {
         Ok(())
     }
 
+    pub fn set_ch_flag(&mut self, ch_id: &VChId, flag: VChFlag, enabled: bool) {
+        if let Some(ch) = self.channels.get_mut(ch_id) {
+            ch.flags.set(flag, enabled);
+        }
+    }
+
+    // replaces the image of channel by a plain one of the same size
+    pub fn set_ch_placeholder(&mut self, ch_id: &VChId, color: &YuvColor) {
+        if let Some(ch) = self.channels.get_mut(ch_id) {
+            if let Some(image) = &mut ch.image {
+                image.fill_color(color);
+            }
+        }
+    }
+
+    // only one talker, None clears it
+    pub fn set_talker(&mut self, ch_id: Option<&VChId>) {
+        for (id, ch) in self.channels.iter_mut() {
+            ch.flags.set(VChFlag::Talker, Some(id) == ch_id);
+        }
+    }
+
     pub fn get_output(&mut self) -> Result<&FFYuvImage> {
         self.layout.get_output(&mut self.channels, &mut self.image)?;
         Ok(&self.image)
@@ -8,5 +8,9 @@ pub mod layout_dynamic;
 
 pub mod mixer;
 
+pub mod conceal;
+
+pub mod simulcast;
+
 mod defines;
 pub use defines::*;
@@ -0,0 +1,117 @@
+// chooses one layer of simulcast for decoding,
+// switches only at keyframes of the new layer since layers don't share references
+
+use std::collections::BTreeMap;
+
+// layer not received longer than it is unavailable
+const LAYER_TIMEOUT_MS: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SimulcastPolicy {
+    // the highest received layer, goes down if it stops
+    #[default]
+    Available,
+
+    // the highest ever received layer
+    Highest,
+
+    // the lowest ever received layer
+    Lowest,
+}
+
+#[derive(Debug, Default)]
+pub struct SimulcastSelector {
+    policy: SimulcastPolicy,
+    current: Option<usize>,
+
+    // last received time by rank
+    layers: BTreeMap<usize, i64>,
+    switches: u64,
+}
+
+impl SimulcastSelector {
+    pub fn new(policy: SimulcastPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    // returns true if the frame of layer rank goes to decoder,
+    // the current layer goes on until a keyframe of the target arrives
+    pub fn push(&mut self, rank: usize, keyframe: bool, ts: i64) -> bool {
+        self.layers.insert(rank, ts);
+
+        if self.current == Some(rank) {
+            return true
+        }
+
+        if keyframe && self.target(ts) == Some(rank) {
+            if self.current.is_some() {
+                self.switches += 1;
+            }
+            self.current = Some(rank);
+            return true
+        }
+        false
+    }
+
+    fn target(&self, now: i64) -> Option<usize> {
+        match self.policy {
+            SimulcastPolicy::Available => self.layers.iter()
+                .filter(|(_rank, ts)| now - **ts < LAYER_TIMEOUT_MS)
+                .map(|(rank, _ts)| *rank)
+                .next_back(),
+            SimulcastPolicy::Highest => self.layers.keys().next_back().copied(),
+            SimulcastPolicy::Lowest => self.layers.keys().next().copied(),
+        }
+    }
+
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn switches(&self) -> u64 {
+        self.switches
+    }
+}
+
+
+#[test]
+fn test_simulcast_selector() {
+    let mut selector = SimulcastSelector::new(SimulcastPolicy::Available);
+
+    // waits keyframe of the first layer
+    assert!(!selector.push(0, false, 0));
+    assert!(selector.push(0, true, 10));
+    assert!(!selector.push(1, false, 20));
+    assert!(selector.push(0, false, 30));
+
+    // the higher layer is taken at its keyframe
+    assert!(selector.push(1, true, 40));
+    assert!(!selector.push(0, true, 50));
+    assert_eq!(selector.current(), Some(1));
+
+    // the higher layer stops, goes down at the next keyframe
+    assert!(!selector.push(0, true, 40 + LAYER_TIMEOUT_MS - 1));
+    assert!(!selector.push(0, false, 40 + LAYER_TIMEOUT_MS));
+    assert!(selector.push(0, true, 40 + LAYER_TIMEOUT_MS + 10));
+    assert_eq!(selector.switches(), 2);
+
+    let mut selector = SimulcastSelector::new(SimulcastPolicy::Highest);
+    assert!(selector.push(1, true, 0));
+    assert!(!selector.push(2, false, 10));
+    assert!(selector.push(1, true, 20));
+    assert!(selector.push(2, true, 30));
+
+    // never goes down
+    assert!(!selector.push(1, true, 30 + LAYER_TIMEOUT_MS * 2));
+    assert_eq!(selector.current(), Some(2));
+
+    let mut selector = SimulcastSelector::new(SimulcastPolicy::Lowest);
+    assert!(selector.push(1, true, 0));
+    assert!(!selector.push(0, false, 10));
+    assert!(selector.push(0, true, 20));
+    assert!(!selector.push(1, true, 30));
+    assert_eq!(selector.current(), Some(0));
+}
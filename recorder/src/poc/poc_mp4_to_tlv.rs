@@ -4,12 +4,12 @@ use anyhow::{bail, Context, Result};
 
 use chrono::Local;
 
-use ffmpeg_next::{format::input_with_dictionary, media::Type as FFType};
+use ffmpeg_next::{format::{context::Input, input_with_dictionary}, media::Type as FFType};
 
 
 // use rtp::{codecs::h264::H264Packet, packetizer::Depacketizer};
 
-use crate::{ffeasy::{gen_sdp::gen_av_only_sdp, rtp_mem::{load_rtp_mem_sync, RtpMemCursor, RtpMemData, RtpMemPacket}}, tlv_custom::{ChInfo, TlvCustomFileWriter}};
+use crate::{ffeasy::{gen_sdp::gen_av_only_sdp, rtp_mem::{load_rtp_mem_sync_with, RtpMemCursor, RtpMemData, RtpMemOptions, RtpMemPacket}}, tlv_custom::{ChInfo, TlvCustomFileWriter}};
 
 
 
@@ -25,7 +25,7 @@ fn test_multi_mp4_to_tlv() {
         // "/Users/simon/Downloads/sample-5s.mp4".as_ref(),
     ];
 
-    multi_mp4_to_tlv(inputs.iter(), output_tlv.as_ref(), None ).unwrap();
+    multi_mp4_to_tlv(inputs.iter(), output_tlv.as_ref(), None, &Default::default()).unwrap();
 }
 
 #[test]
@@ -33,17 +33,32 @@ fn test_single_mp4_to_tlv() {
     let input = "/tmp/sample-data/sample.mp4";
     let output_tlv = "/tmp/output.tlv2";
 
-    single_mp4_to_tlv(input.as_ref(), output_tlv.as_ref(), None ).unwrap();
+    single_mp4_to_tlv(input.as_ref(), output_tlv.as_ref(), None, &Default::default()).unwrap();
 
     // let output_mp4 = "/tmp/output.h264";
     // super::poc_tlv_to_h264::tlv_to_h264(output_tlv.as_ref(), output_mp4.as_ref()).unwrap();
 }
 
+#[test]
+fn test_single_mp4_to_tlv_impaired() {
+    use crate::rtp::pack::impair::RtpImpair;
+
+    let input = "/tmp/sample-data/sample.mp4";
+    let output_tlv = "/tmp/output-impaired.tlv2";
+
+    let options = RtpMemOptions {
+        mtu: Some(1000),
+        impair: Some(RtpImpair::new().with_seed(1).with_loss(20).with_reorder(50, 3)),
+    };
+    single_mp4_to_tlv(input.as_ref(), output_tlv.as_ref(), None, &options).unwrap();
+}
+
 
 fn multi_mp4_to_tlv<'a, I, P>( 
     inputs: I, 
     output: &Path, 
     max_frames: Option<u64>,
+    options: &RtpMemOptions,
 ) -> Result<()> 
 where 
     I: Iterator<Item = &'a P>,
@@ -54,7 +69,7 @@ where
 
     let mut start_ch_id = 0;
     for input in inputs { 
-        sources.push(RtpMemSource::open(input.as_ref(), max_frames, start_ch_id, 0)?);
+        sources.push(RtpMemSource::open(input.as_ref(), max_frames, start_ch_id, 0, options)?);
         start_ch_id += 8;
         // sources.push(RtpMemSource::open(input.as_ref(), max_frames, start_ch_id, 100)?);
     }
@@ -124,7 +139,7 @@ struct RtpMemSource {
 }
 
 impl RtpMemSource {
-    pub fn open(input: &Path, max_frames: Option<u64>, start_ch_id: u64, time_offset: i64) -> Result<Self> {
+    pub fn open(input: &Path, max_frames: Option<u64>, start_ch_id: u64, time_offset: i64, options: &RtpMemOptions) -> Result<Self> {
         let ictx = input_with_dictionary(&input, Default::default())
         .with_context(||format!("failed open [{input:?}]"))?;
         println!("opened input {input:?}");
@@ -139,9 +154,9 @@ impl RtpMemSource {
             bail!("Not found video or audio in [{:?}]", input)
         }
 
-        let rtp_mem = load_rtp_mem_sync(input, max_frames.unwrap_or(u64::MAX))?;
+        let rtp_mem = load_rtp_mem_sync_with(input, max_frames.unwrap_or(u64::MAX), options)?;
 
-        let (sdp, _octx) = gen_av_only_sdp(&ictx)?;
+        let sdp = mem_sdp(&ictx, &rtp_mem)?;
 
         Ok(Self {
             que: rtp_mem,
@@ -214,7 +229,7 @@ impl SourcesReader {
     }
 }
 
-fn single_mp4_to_tlv(input: &Path, output: &Path, max_frames: Option<u64>) -> Result<Option<Vec<(Vec<u8>, Vec<Vec<u8>>)>>> {
+fn single_mp4_to_tlv(input: &Path, output: &Path, max_frames: Option<u64>, options: &RtpMemOptions) -> Result<Option<Vec<(Vec<u8>, Vec<Vec<u8>>)>>> {
 
     let ictx = input_with_dictionary(&input, Default::default())
         .with_context(||format!("failed open [{input:?}]"))?;
@@ -230,7 +245,9 @@ fn single_mp4_to_tlv(input: &Path, output: &Path, max_frames: Option<u64>) -> Re
         bail!("Not found video or audio in [{:?}]", input)
     }
 
-    let (sdp, _octx) = gen_av_only_sdp(&ictx)?;
+    let max_frames = max_frames.unwrap_or(u64::MAX);
+    let rtp_mem = load_rtp_mem_sync_with(input, max_frames, options)?;
+    let sdp = mem_sdp(&ictx, &rtp_mem)?;
 
 
     let mut ofile = TlvCustomFileWriter::open(&output)
@@ -249,8 +266,6 @@ fn single_mp4_to_tlv(input: &Path, output: &Path, max_frames: Option<u64>) -> Re
     })?;
     
 
-    let max_frames = max_frames.unwrap_or(u64::MAX);
-    let rtp_mem = load_rtp_mem_sync(input, max_frames)?;
     let spspps = rtp_mem.video().as_ref().map(|track| {
         track.spspps().clone()
     });
@@ -282,3 +297,13 @@ fn single_mp4_to_tlv(input: &Path, output: &Path, max_frames: Option<u64>) -> Re
 
     Ok(spspps)
 }
+
+// sdp of native packed tracks, ffmpeg's one if any track is packed by ffmpeg
+fn mem_sdp(ictx: &Input, rtp_mem: &RtpMemData) -> Result<String> {
+    if !rtp_mem.sdp().is_empty() {
+        return Ok(String::from_utf8(rtp_mem.sdp().to_vec())?)
+    }
+
+    let (sdp, _octx) = gen_av_only_sdp(ictx)?;
+    Ok(sdp)
+}
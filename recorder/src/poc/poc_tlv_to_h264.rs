@@ -1,7 +1,6 @@
 use std::{fs::File, io::Write, path::Path, str::FromStr};
 use anyhow::{anyhow, Context, Result};
 
-use bytes::Bytes;
 
 
 use crate::{rtp::{codec::h264::{RtpDepackerH264, RtpH264Parameters}, depack::RtpCodecDepacker}, tlv2::{TlvFileSyncReader, Type, VecBuf}, tlv_custom::{ChInfo, TlvType, TLV_MAGIC}};
@@ -117,7 +116,7 @@ pub fn tlv_to_h264(input: &Path, output: &Path) -> Result<()> {
                             // let frame = h264_depack.depacketize(&payload)?;
 
                             h264_deapck.push_rtp_slice(&data)?;
-                            let frame = h264_deapck.pull_frame().unwrap().unwrap_or(Bytes::new());
+                            let frame = h264_deapck.pull_frame().unwrap().map(|x|x.data).unwrap_or_default();
 
                             if frame.len() > 0 {
                                 let h = h264_reader::nal::NalHeader::new(frame[4])
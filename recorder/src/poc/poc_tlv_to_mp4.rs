@@ -1,8 +1,8 @@
-use std::{collections::HashMap, path::Path};
-use anyhow::{anyhow, Result};
-use bytes::{BufMut, BytesMut};
+use std::{collections::HashMap, path::{Path, PathBuf}};
+use anyhow::{Context, Result};
+use serde::Serialize;
 use ff::{ChannelLayout, Rescale};
-use crate::{ffeasy::{audio::{audio_packed_i16_format, audio_planar_f32_format, input::{audio_decoder_receive_frame, make_audio_decoder}, swr::SResampler}, encoder::{FFAudioEncoder, FFVideoEncoder}, ffi::{audio_frame_packed_i16_samples, audio_frame_packed_i16_samples_mut}, output::{FFOutput, FFTrack, FFWriter}, video::{image::FFYuvImage, make_video_decoder, video_decoder_receive_frame, FFVideoArgs, VideoSize}}, media::CodecId, mix_audio::mixer::{AChId, PcmMixer, PcmTimedMixer}, mix_video::{mixer::VideoMixer, VChId}, rtp::{  codec::{aac::RtpDepackerAAC, h264::{RtpDepackerH264, RtpH264Parameters}}, depack::RtpCodecDepacker}, sdp::sdp::{SdpCodec, SdpMediaType}, tlv_custom::{parse_tlv_file, ChPacket, ContextMut, Flow, FlowIndex, FlowMut, Handler, StreamIndex, StreamInfo, TrackIndex}};
+use crate::{ffeasy::{audio::{audio_packed_i16_format, audio_planar_f32_format, input::{audio_decoder_receive_frame, make_audio_decoder}, libfdkaac::LibFdkAacDecoder, libopus::LibOpusDecoder, swr::SResampler}, encoder::{FFAudioEncoder, FFVideoEncoder}, ffi::{audio_frame_packed_i16_samples, audio_frame_packed_i16_samples_mut}, output::{FFOutput, FFTrack, FFWriter}, video::{image::FFYuvImage, make_video_decoder, video_decoder_receive_frame, FFVideoArgs, VideoSize, YuvColor}}, media::{find_codec, CodecId}, mix_audio::{gap::{AudioGapFill, AudioPlace, AudioTimeline, GapFiller}, mixer::{AChId, PcmMixer, PcmTimedMixer}, speaker::{ActiveSpeakerDetector, SpeakerConfig}}, mix_video::{conceal::{ConcealAction, ConcealPolicy, RefIntegrity}, mixer::VideoMixer, simulcast::{SimulcastPolicy, SimulcastSelector}, VChFlag, VChId}, rtp::{  codec::{dtmf::{make_dtmf_tone_frames, DtmfEvent}, h264::{H264ParamTracker, RtpH264Parameters}, latm::AudioSpecificConfig, mpa::parse_mpa_header, opus::{opus_packet_samples, OPUS_CLOCK_RATE}, g711::{G711Decoder, G711Law, G711_SAMPLERATE}}, depack::{DepackedFrame, RtpCodecDepacker}, jitter::JitterLost, rtpext::{AudioLevel, VideoOrientation}}, sdp::sdp::{SdpCodec, SdpMediaType, SimulcastRidOrder}, tlv_custom::{parse_tlv_file, ChPacket, ContextMut, Flow, FlowIndex, FlowMut, Handler, StreamIndex, StreamInfo, TrackIndex}};
 use ffmpeg_next as ff;
 
 #[test]
@@ -19,10 +19,33 @@ fn test_tlv_file_to_mp4() {
     let odir = "/tmp";
     let oname_prefix = "ostream_";
     let max_packets: Option<u64> = Some(8000);
-    tlv_file_to_mp4(ipath.as_ref(), odir.as_ref(), oname_prefix.as_ref(), max_packets);
+    let options = TlvToMp4Options {
+        dtmf_beep: true,
+        ..Default::default()
+    };
+    tlv_file_to_mp4(ipath.as_ref(), odir.as_ref(), oname_prefix.as_ref(), max_packets, &options);
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TlvToMp4Options {
+    // render dtmf digits as beeps in the mixed audio
+    pub dtmf_beep: bool,
+
+    // what mixed video channels show from loss until next keyframe
+    pub conceal: ConcealPolicy,
+
+    // which layer of simulcast the mixer decodes
+    pub simulcast: SimulcastPolicy,
+
+    // what fills missing media time of mixed audio channels
+    pub gap_fill: AudioGapFill,
+
+    // how layers of simulcast are ranked when rids have no max dimensions
+    pub rid_order: SimulcastRidOrder,
 }
 
-pub fn tlv_file_to_mp4(ipath: &Path, odir: &Path, oname_prefix: &str, max_packets: Option<u64>)  {
+// layers of simulcast are written as separate tracks, the mixer decodes one of them by policy
+pub fn tlv_file_to_mp4(ipath: &Path, odir: &Path, oname_prefix: &str, max_packets: Option<u64>, options: &TlvToMp4Options)  {
     let file_info = parse_tlv_file(ipath, &mut ()).unwrap();
 
     let mut output_paths = Vec::new();
@@ -80,6 +103,7 @@ pub fn tlv_file_to_mp4(ipath: &Path, odir: &Path, oname_prefix: &str, max_packet
 
     let mut conver = Converter {
         max_packets,
+        rid_order: options.rid_order,
         mixer: Some(Mixer {
             video: Some(MixContextVideo {
                 mixer: VideoMixer::new(VideoSize {
@@ -87,11 +111,18 @@ pub fn tlv_file_to_mp4(ipath: &Path, odir: &Path, oname_prefix: &str, max_packet
                     height: mix_video_args.height as u32,
                 }).unwrap(),
                 tracks: Default::default(),
+                orientations: Default::default(),
                 last_mix_ts: None,
+                conceal: options.conceal,
+                simulcast: options.simulcast,
+                selectors: Default::default(),
                 encoder: video_encoder,
                 o_track: video_track,
             }),
-            audio: Some(MixContextAudio::new(audio_encoder, audio_track)),
+            audio: Some(MixContextAudio::new(audio_encoder, audio_track)
+                .with_dtmf_beep(options.dtmf_beep)
+                .with_gap_fill(options.gap_fill)),
+            speaker: ActiveSpeakerDetector::new(SpeakerConfig::default()),
             first_ts: None,
             writer,
         }),
@@ -102,13 +133,16 @@ pub fn tlv_file_to_mp4(ipath: &Path, odir: &Path, oname_prefix: &str, max_packet
 
     for stream in file_info.streams.iter() {
         
-        let opath = odir.join(format!("{oname_prefix}{}.mp4", stream.index));
+        let opath_base = odir.join(format!("{oname_prefix}{}", stream.index));
+        let opath = segment_path(&opath_base, 0);
         
         let mut output = FFOutput::open(&opath.as_path()).unwrap();
         println!("opened output [{opath:?}]");
         output_paths.push(opath);
 
         let mut depackers: Vec<Option<Box<dyn RtpCodecDepacker>>> = Vec::new();
+        let mut clock_rates = Vec::new();
+        let mut codecs = Vec::new();
         let mut ctracks = Vec::new();
 
         for track in stream.tracks.iter() {
@@ -118,64 +152,43 @@ pub fn tlv_file_to_mp4(ipath: &Path, odir: &Path, oname_prefix: &str, max_packet
             };
 
             for flow in track.flows.iter() {
-                let flow = match flow.codec.codec_id {
-                    CodecId::H264 => {
-                        let fmtp: &str = flow.codec.fmtps
-                        .get(0)
-                        .map(|x|x.as_str()).unwrap_or("");
-
-                        depackers.push(Some( RtpDepackerH264::new(Some(fmtp)).unwrap().into_box() ));
-
-                        let args = parse_h264(&flow.codec).unwrap();
-
-
-                        output.add_h264_track(
-                            args.width, 
-                            args.height, 
-                            &args.extra,
-                        ).unwrap().index();
-                        println!("add h264 track, spspps {}", args.extra.len());
+                let clock_rate = flow.codec.clock_rate;
+                let desc = find_codec(flow.codec.codec_id)
+                .filter(|desc| desc.depacker.is_some() && desc.track.is_some());
+
+                // written as is if both depacker and track
+                let added = desc.map(|desc| -> Result<_> {
+                    let depacker = desc.make_depacker(&flow.codec)?;
+                    desc.add_track(&mut output, &flow.codec)?;
+                    Ok(depacker)
+                });
+
+                let flow = match added {
+                    Some(Ok(depacker)) => {
+                        depackers.push(depacker);
+                        codecs.push(flow.codec.clone());
+                        println!("add {:?} track", flow.codec.codec_id);
 
                         let otrack_index = otrack_count;
                         otrack_count += 1;
-                        
+
                         CFlow {
                             otrack_index: Some(otrack_index),
-                            // otrack: None,
                         }
                     },
-                    CodecId::AAC => {
-                        output.add_aac_track(
-                            flow.codec.clock_rate as i32, 
-                            flow.codec.channels.unwrap_or(1) as i32,
-                        ).unwrap().index();
-                        
-                        let fmtp = flow.codec.fmtps
-                        .get(0)
-                        .map(|x|x.as_str());
-
-                        println!("add aac track");
-                        
-                        depackers.push(Some(RtpDepackerAAC::new (
-                            flow.codec.clock_rate,
-                            flow.codec.channels.map(|x| std::num::NonZeroU16::new(x as u16)).unwrap_or(None),
-                            fmtp,
-                        ).unwrap().into_box() ));
-                        
-                        let otrack_index = otrack_count;
-                        otrack_count += 1;
-
+                    Some(Err(e)) => {
+                        println!("skip {:?} track, {e}", flow.codec.codec_id);
                         CFlow {
-                            otrack_index: Some(otrack_index),
-                            // otrack: None,
+                            otrack_index: None,
                         }
                     },
-                    _ => CFlow {
+                    None => CFlow {
                         otrack_index: None,
                         // otrack: None,
                         // depacker: Some(Box::new(RtpDepackerH264::default())),
                     },
                 };
+                clock_rates.resize(depackers.len(), clock_rate);
 
                 ctrack.flows.push(flow);
             }
@@ -185,13 +198,24 @@ pub fn tlv_file_to_mp4(ipath: &Path, odir: &Path, oname_prefix: &str, max_packet
         
         let stream_index = conver.streams.len();
         let writer = output.begin_write().unwrap();
+        let mut otracks = Vec::new();
         for (index, item) in writer.tracks_iter().enumerate() {
             if let Some(depacker) = depackers[index].take() {
+                // in-band parameters of h264 may change mp4 track
+                let h264 = (codecs[index].codec_id == CodecId::H264).then(|| {
+                    let params = codecs[index].fmtps.first().and_then(|x| RtpH264Parameters::parse_from_str(x).ok());
+                    H264ParamTracker::new(params)
+                });
+
                 let otrack = OTrack {
                     name: format!("stream_{stream_index}_track_{index}_item_{}", item.index),
                     track: item,
                     depacker,
+                    clock_rate: clock_rates[index],
+                    last_rtp: None,
                     wrote_packets: 0,
+                    h264,
+                    orientation: None,
                 };
 
                 println!("add otrack [{}]", otrack.name);
@@ -199,6 +223,7 @@ pub fn tlv_file_to_mp4(ipath: &Path, odir: &Path, oname_prefix: &str, max_packet
                 // let flow = find_cflow_mut(&mut ctracks, index).unwrap();
                 // flow.otrack = Some(otrack);
 
+                otracks.push(conver.otracks.len());
                 conver.otracks.push(otrack);
             }
         }
@@ -207,20 +232,59 @@ pub fn tlv_file_to_mp4(ipath: &Path, odir: &Path, oname_prefix: &str, max_packet
             writer,
             tracks: ctracks,
             first_ts: None,
+            opath_base,
+            segment: 0,
+            codecs,
+            otracks,
         });
     }
 
-    parse_tlv_file(ipath, &mut conver).unwrap();
+    let file_info = parse_tlv_file(ipath, &mut conver).unwrap();
+
+    conver.write_dtmf_metadata();
+
+    for stream in file_info.streams.iter() {
+        for (index, track) in stream.tracks.iter().enumerate() {
+            println!("stream[{}] track[{index}] {:?}", stream.index, track.stats);
+        }
+    }
 
     for (index, path) in output_paths.iter().enumerate() {
         println!("output[{index}]=[{path:?}]");
     }
 
+    if let Some(video) = conver.mixer.as_ref().and_then(|x| x.video.as_ref()) {
+        for (index, track) in video.tracks.iter() {
+            println!("mix video: flow {index:?} conceal {:?}", track.integrity.stats());
+        }
+        for (index, selector) in video.selectors.iter() {
+            println!("mix video: flow {index:?} simulcast layer {:?}, switches {}", selector.current(), selector.switches());
+        }
+    }
+
+    if let Some(audio) = conver.mixer.as_ref().and_then(|x| x.audio.as_ref()) {
+        for (index, track) in audio.tracks.iter() {
+            println!("mix audio: flow {index:?} gaps {:?}", track.timeline.stats());
+        }
+    }
+
     println!("total wrote packets {}", conver.num_packets);
 
     // Ok(())
 }
 
+// the first segment keeps the plain name of stream,
+// segments started by changes of parameter sets are suffixed by their number
+fn segment_path(opath_base: &Path, segment: usize) -> PathBuf {
+    let mut opath = opath_base.as_os_str().to_os_string();
+    if segment == 0 {
+        opath.push(".mp4");
+    } else {
+        opath.push(format!("_{segment}.mp4"));
+    }
+    PathBuf::from(opath)
+}
+
 // fn find_cflow_mut(ctracks: &mut Vec<CTrack>, index: usize) -> Option<&mut CFlow> {
 //     for track in ctracks.iter_mut() {
 //         for flow in track.flows.iter_mut() {
@@ -239,9 +303,45 @@ struct Converter {
     mixer: Option<Mixer>,
     num_packets: u64,
     max_packets: Option<u64>,
+    dtmfs: Vec<DtmfRecord>,
+    rid_order: SimulcastRidOrder,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DtmfRecord {
+    #[serde(skip)]
+    stream: usize,
+
+    digit: char,
+
+    // start time in milliseconds, same clock as tlv packets
+    ts: i64,
+
+    duration: i64,
+
+    // -dBm0
+    volume: u8,
 }
 
 impl Converter {
+    // as json array in "description" of each output
+    fn write_dtmf_metadata(&mut self) {
+        if self.dtmfs.is_empty() {
+            return;
+        }
+
+        for (index, stream) in self.streams.iter_mut().enumerate() {
+            let records: Vec<&DtmfRecord> = self.dtmfs.iter().filter(|x|x.stream == index).collect();
+            if !records.is_empty() {
+                stream.writer.set_metadata("description", &serde_json::to_string(&records).unwrap());
+            }
+        }
+
+        if let Some(mixer) = &mut self.mixer {
+            mixer.writer.set_metadata("description", &serde_json::to_string(&self.dtmfs).unwrap());
+        }
+    }
+
     fn get_flow_mut(&mut self, index: &FlowIndex) -> Option<&mut CFlow> {
         if let Some(stream) = self.streams.get_mut(index.track.stream) {
             if let Some(track) = stream.tracks.get_mut(index.track.track) {
@@ -253,26 +353,121 @@ impl Converter {
         None
     }
 
-    fn handle_flow_rtp(&mut self, flow: &mut FlowMut<FlowExt>, rtp_packet: &ChPacket) {
+    // starts a new output file of stream, tracks of h264 are added by their latest parameters
+    fn start_new_segment(&mut self, stream_index: usize) -> Result<()> {
+        let stream = match self.streams.get_mut(stream_index) {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        stream.segment += 1;
+        let opath = segment_path(&stream.opath_base, stream.segment);
+
+        let mut output = FFOutput::open(&opath.as_path())?;
+        println!("opened output [{opath:?}]");
+
+        for (codec, otrack_index) in stream.codecs.iter().zip(stream.otracks.iter()) {
+            let params = self.otracks[*otrack_index].h264.as_ref().and_then(|x| x.params());
+            match params {
+                Some(params) => {
+                    let (width, height) = params.generic.pixel_dimensions;
+                    output.add_h264_track(width as i32, height as i32, &params.annexb_spspps())?;
+                }
+                None => {
+                    let desc = find_codec(codec.codec_id).with_context(|| format!("no codec {:?}", codec.codec_id))?;
+                    desc.add_track(&mut output, codec)?;
+                }
+            }
+        }
+
+        let mut writer = output.begin_write()?;
+        for (index, otrack_index) in stream.otracks.iter().enumerate() {
+            let otrack = &mut self.otracks[*otrack_index];
+            otrack.track = writer.get_track(index).with_context(|| format!("no track {index}"))?;
+            if let Some(orientation) = otrack.orientation {
+                writer.set_track_display_matrix(&otrack.track, orientation.rotation, orientation.flip)?;
+            }
+        }
+
+        std::mem::replace(&mut stream.writer, writer).write_trailer()?;
+
+        // pts of each segment starts from zero
+        stream.first_ts = None;
+        for otrack_index in stream.otracks.iter() {
+            self.otracks[*otrack_index].last_rtp = None;
+        }
+        Ok(())
+    }
+
+    fn handle_flow_orientation(&mut self, flow: &mut FlowMut<FlowExt>, orientation: VideoOrientation) {
+        println!("video orientation: flow {:?}, {orientation:?}", flow.index());
+
         let ext = flow.ext_mut();
-        if let Some(otrack_index) = ext.otrack_index {
-            // println!("on_flow_rtp: track {index}");
+        ext.orientation = Some(orientation);
 
+        if let Some(otrack_index) = ext.otrack_index {
             let stream_index = flow.index().track.stream;
-
             let r1 = self.otracks.get_mut(otrack_index);
             let r2 = self.streams.get_mut(stream_index);
             if let (Some(otrack), Some(stream)) = (r1, r2) {
-                // println!("on_flow_rtp: otrack.name [{}]", otrack.name);
+                otrack.orientation = Some(orientation);
+                // the last one wins if changed
+                stream.writer.set_track_display_matrix(&otrack.track, orientation.rotation, orientation.flip).unwrap();
+            }
+        }
+
+        if let Some(mixer) = &mut self.mixer {
+            mixer.handle_flow_orientation(flow.flow(), orientation);
+        }
+    }
+
+    fn handle_flow_rtp(&mut self, flow: &mut FlowMut<FlowExt>, rtp_packet: &ChPacket) {
+        // CVO is carried by the last packet of frames
+        if let Some(orientation) = flow.rtp_ext().video_orientation {
+            if flow.ext_mut().orientation != Some(orientation) {
+                self.handle_flow_orientation(flow, orientation);
+            }
+        }
+
+        if let Some(level) = flow.rtp_ext().audio_level {
+            if let Some(mixer) = &mut self.mixer {
+                mixer.handle_flow_audio_level(flow.flow(), level, rtp_packet.ts);
+            }
+        }
+
+        let ext = flow.ext_mut();
+        if let Some(otrack_index) = ext.otrack_index {
+            // println!("on_flow_rtp: track {index}");
 
+            let stream_index = flow.index().track.stream;
+
+            let mut frames = Vec::new();
+            if let Some(otrack) = self.otracks.get_mut(otrack_index) {
                 otrack.depacker.push_rtp_slice(&rtp_packet.data).unwrap();
                 while let Some(frame) = otrack.depacker.pull_frame().unwrap() {
+                    frames.push(frame);
+                }
+            }
+
+            for frame in frames {
+                let new_segment = self.otracks.get_mut(otrack_index)
+                    .map(|x| x.update_parameters(&frame))
+                    .unwrap_or(false);
+                if new_segment {
+                    self.start_new_segment(stream_index).unwrap();
+                }
+
+                let r1 = self.otracks.get_mut(otrack_index);
+                let r2 = self.streams.get_mut(stream_index);
+                if let (Some(otrack), Some(stream)) = (r1, r2) {
+                    // println!("on_flow_rtp: otrack.name [{}]", otrack.name);
+
                     let mut ffpacket = ff::Packet::copy(&frame[..]);
                     
                     // let src_time_base = ff::Rational::new(1, 30);
                     // let pts = otrack.wrote_packets as i64;
                     
-                    let pts = match stream.first_ts {
+                    let elapsed = match stream.first_ts {
                         Some(first) => rtp_packet.ts - first,
                         None => {
                             stream.first_ts = Some(rtp_packet.ts);
@@ -280,9 +475,20 @@ impl Converter {
                         },
                     };
 
-                    let src_time_base = milli_time_base();
+                    // rtp timestamp of frame, the first frame is aligned to its arrival in the stream
+                    let pts = match otrack.last_rtp {
+                        Some((last_ts, last_pts)) => last_pts + frame.rtp_ts.wrapping_sub(last_ts) as i32 as i64,
+                        None => elapsed * otrack.clock_rate as i64 / 1000,
+                    };
+                    otrack.last_rtp = Some((frame.rtp_ts, pts));
 
-                    println!("wrote pakcet, stream {stream_index}, track {otrack_index}, pts {pts}, {}", pretty_hex::simple_hex(&&frame[..4]));
+                    let src_time_base = ff::Rational::new(1, otrack.clock_rate as i32);
+
+                    if frame.keyframe {
+                        ffpacket.set_flags(ff::packet::Flags::KEY);
+                    }
+
+                    println!("wrote pakcet, stream {stream_index}, track {otrack_index}, pts {pts}, {}", pretty_hex::simple_hex(&&frame[..frame.len().min(4)]));
 
                     ffpacket.set_pts(Some(pts));
                     // ffpacket.set_dts(Some(packet.ts));
@@ -291,10 +497,26 @@ impl Converter {
                     otrack.wrote_packets += 1;
 
                     if let Some(mixer) = &mut self.mixer {
-                        mixer.handle_flow_depacked(flow.flow(), &ffpacket, rtp_packet.ts)
+                        set_mix_packet_flags(&mut ffpacket, &frame);
+                        mixer.handle_flow_depacked(flow.flow(), &ffpacket, &frame, rtp_packet.ts)
                     }
                 }
             }
+        } else if let Some(depacker) = &mut ext.mix_depacker {
+            depacker.push_rtp_slice(&rtp_packet.data).unwrap();
+
+            let mut frames = Vec::new();
+            while let Some(frame) = depacker.pull_frame().unwrap() {
+                frames.push(frame);
+            }
+
+            if let Some(mixer) = &mut self.mixer {
+                for frame in frames {
+                    let mut ffpacket = ff::Packet::copy(&frame[..]);
+                    set_mix_packet_flags(&mut ffpacket, &frame);
+                    mixer.handle_flow_depacked(flow.flow(), &ffpacket, &frame, rtp_packet.ts)
+                }
+            }
         }
 
 
@@ -312,11 +534,19 @@ impl Handler for Converter {
         Ok(())
     }
     
-    fn on_add_flow(&mut self, _ctx: ContextMut<'_, Self>, index: FlowIndex, _codec: &SdpCodec) -> Result<Self::Flow> {
+    fn on_add_flow(&mut self, _ctx: ContextMut<'_, Self>, index: FlowIndex, codec: &SdpCodec) -> Result<Self::Flow> {
         let otrack_index = self.get_flow_mut(&index).map(|x|x.otrack_index.clone()).unwrap_or(None);
 
+        // codecs without output track are mixed only
+        let mix_depacker = match (otrack_index, &self.mixer, find_codec(codec.codec_id)) {
+            (None, Some(_mixer), Some(desc)) if desc.track.is_none() => desc.make_depacker(codec)?,
+            _ => None,
+        };
+
         Ok(FlowExt {
             otrack_index,
+            mix_depacker,
+            orientation: None,
         })
     }
     
@@ -337,31 +567,126 @@ impl Handler for Converter {
         Ok(())
     }
 
-    
+    fn on_flow_dtmf_begin(&mut self, _ctx: ContextMut<'_, Self>, flow: &Flow, ts: i64, event: &DtmfEvent) -> Result<()> {
+        if let Some(mixer) = &mut self.mixer {
+            mixer.handle_flow_dtmf_begin(flow, ts, event);
+        }
+        Ok(())
+    }
+
+    fn on_flow_dtmf_end(&mut self, _ctx: ContextMut<'_, Self>, flow: &Flow, start_ts: i64, event: &DtmfEvent) -> Result<()> {
+        let digit = match event.digit() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        println!("dtmf: stream {}, digit {digit}, ts {start_ts}, duration {}", flow.index.track.stream, event.duration_ms());
+
+        self.dtmfs.push(DtmfRecord {
+            stream: flow.index.track.stream,
+            digit,
+            ts: start_ts,
+            duration: event.duration_ms(),
+            volume: event.volume,
+        });
+
+        if let Some(mixer) = &mut self.mixer {
+            mixer.handle_flow_dtmf_end(flow, event);
+        }
+        Ok(())
+    }
+
+    fn on_flow_lost(&mut self, _ctx: ContextMut<'_, Self>, flow: &Flow, lost: &JitterLost) -> Result<()> {
+        println!("lost: flow {:?}, seq {}, count {}", flow.index, lost.seq, lost.count);
+
+        if let Some(mixer) = &mut self.mixer {
+            mixer.handle_flow_lost(flow);
+        }
+        Ok(())
+    }
+
+    fn simulcast_rid_order(&self) -> SimulcastRidOrder {
+        self.rid_order
+    }
 }
 
 fn milli_time_base() -> ff::Rational {
     ff::Rational::new(1, 1000)
 }
 
+// CORRUPT if the frame or its references are broken, loss before a keyframe doesn't break it
+fn set_mix_packet_flags(packet: &mut ff::Packet, frame: &DepackedFrame) {
+    let mut flags = packet.flags();
+    if frame.keyframe {
+        flags |= ff::packet::Flags::KEY;
+    }
+    if frame.corrupt || (frame.loss > 0 && !frame.keyframe) {
+        flags |= ff::packet::Flags::CORRUPT;
+    }
+    packet.set_flags(flags);
+}
+
 struct Mixer {
     video: Option<MixContextVideo>,
     audio: Option<MixContextAudio>,
 
+    // keyed by stream, the talker of video follows it
+    speaker: ActiveSpeakerDetector<usize>,
+
     first_ts: Option<i64>,
     writer: FFWriter,
 }
 
 impl Mixer {
-    pub fn handle_flow_depacked(&mut self, flow: &Flow, packet: &ff::Packet, ts: i64) {
+    pub fn handle_flow_dtmf_begin(&mut self, flow: &Flow, ts: i64, event: &DtmfEvent) {
+        let ts = self.relative_ts(ts);
+        if let Some(audio) = &mut self.audio {
+            audio.handle_flow_dtmf_begin(flow, event, ts);
+        }
+    }
 
-        let ts = match self.first_ts {
-            Some(first) => ts - first,
-            None => {
-                self.first_ts = Some(ts);
-                0
-            },
-        };
+    pub fn handle_flow_dtmf_end(&mut self, flow: &Flow, event: &DtmfEvent) {
+        if let Some(audio) = &mut self.audio {
+            audio.handle_flow_dtmf_end(flow, event);
+        }
+    }
+
+    // the first packet starts the mixed timeline
+    fn relative_ts(&mut self, ts: i64) -> i64 {
+        ts - *self.first_ts.get_or_insert(ts)
+    }
+
+    pub fn handle_flow_orientation(&mut self, flow: &Flow, orientation: VideoOrientation) {
+        if let Some(video) = &mut self.video {
+            video.orientations.insert(flow.source_index(), orientation);
+        }
+    }
+
+    pub fn handle_flow_lost(&mut self, flow: &Flow) {
+        if matches!(flow.codec.media_type, SdpMediaType::Video) {
+            if let Some(video) = &mut self.video {
+                video.handle_flow_lost(flow);
+            }
+        }
+    }
+
+    pub fn handle_flow_audio_level(&mut self, flow: &Flow, level: AudioLevel, ts: i64) {
+        self.speaker.update_ext_level(flow.index.track.stream, ts, level.level);
+        self.handle_speaker_events();
+    }
+
+    fn handle_speaker_events(&mut self) {
+        while let Some(event) = self.speaker.pull_event() {
+            println!("speaker changed: {event:?}");
+            if let Some(video) = &mut self.video {
+                video.set_talker(event.to);
+            }
+        }
+    }
+
+    pub fn handle_flow_depacked(&mut self, flow: &Flow, packet: &ff::Packet, frame: &DepackedFrame, ts: i64) {
+        let arrival_ts = ts;
+        let ts = self.relative_ts(ts);
 
         match flow.codec.media_type {
             SdpMediaType::Video => {
@@ -371,8 +696,12 @@ impl Mixer {
             }
             SdpMediaType::Audio => {
                 if let Some(audio) = &mut self.audio {
-                    audio.handle_flow_depacked(flow, packet, ts);
+                    audio.handle_flow_depacked(flow, packet, frame, ts);
+                    if let Some(level) = audio.flow_level(flow) {
+                        self.speaker.update_pcm_level(flow.index.track.stream, arrival_ts, level);
+                    }
                 }
+                self.handle_speaker_events();
             }
             _ => {}
         }
@@ -391,27 +720,62 @@ impl Mixer {
 struct MixContextVideo {
     mixer: VideoMixer,
     tracks: HashMap<FlowIndex, MixVideoTrack>,
+
+    // latest CVO of flows, decoded frames are rotated before mixing
+    orientations: HashMap<FlowIndex, VideoOrientation>,
     last_mix_ts: Option<i64>,
+
+    // what stale channels show until keyframe
+    conceal: ConcealPolicy,
+
+    // one layer of simulcast is decoded for each source
+    simulcast: SimulcastPolicy,
+    selectors: HashMap<FlowIndex, SimulcastSelector>,
     encoder: FFVideoEncoder,
     o_track: FFTrack,
 }
 
 impl MixContextVideo {
     pub fn handle_flow_depacked(&mut self, flow: &Flow, packet: &ff::Packet, ts: i64) -> bool {
+        if let Some(layer) = &flow.layer {
+            let policy = self.simulcast;
+            let selector = self.selectors.entry(layer.source).or_insert_with(|| SimulcastSelector::new(policy));
+            let last = selector.current();
+            if !selector.push(layer.rank, packet.is_key(), ts) {
+                return false;
+            }
+            if last.is_some_and(|x| x != layer.rank) {
+                println!("mix video: flow {:?} switch simulcast layer {last:?} -> {}, rid {:?}", layer.source, layer.rank, layer.rid);
+            }
+        }
+
         if self.try_handle_video(flow, packet) {
             true
         } else {
-            match flow.codec.codec_id {
-                CodecId::H264 => {
-                    self.add_h264_flow(flow);
-                    self.handle_flow_depacked(flow, packet, ts)
-                },
-                _ => false,
+            if self.add_flow(flow) {
+                self.try_handle_video(flow, packet)
+            } else {
+                false
             }
         }
         // self.try_mix_video(ts);
     }
 
+    // the next frame of flow is concealed unless it is a keyframe
+    pub fn handle_flow_lost(&mut self, flow: &Flow) {
+        // loss of a layer not decoded doesn't matter
+        if let Some(layer) = &flow.layer {
+            let current = self.selectors.get(&layer.source).and_then(|x| x.current());
+            if current != Some(layer.rank) {
+                return;
+            }
+        }
+
+        if let Some(track) = self.tracks.get_mut(&flow.source_index()) {
+            track.integrity.push_lost();
+        }
+    }
+
     pub fn try_mix(&mut self, ts: i64, writer: &mut FFWriter) {
         // 1000/25=40
 
@@ -441,13 +805,65 @@ impl MixContextVideo {
         }
     }
 
+    pub fn set_talker(&mut self, stream: usize) {
+        let id = self.tracks.iter()
+            .find(|x| x.0.track.stream == stream)
+            .map(|x| x.1.id);
+        self.mixer.set_talker(id.as_ref());
+    }
+
     fn try_handle_video(&mut self, flow: &Flow, packet: &ff::Packet) -> bool {
-        if let Some(track) = self.tracks.get_mut(&flow.index) {
+        // layers of simulcast share the channel
+        let index = flow.source_index();
+        if let Some(track) = self.tracks.get_mut(&index) {
+            let action = track.integrity.push(packet.is_key(), packet.is_corrupt());
+            match action {
+                ConcealAction::Stale => {
+                    println!("mix video: flow {:?} stale, conceal {:?}", index, self.conceal);
+                    self.mixer.set_ch_flag(&track.id, VChFlag::Stale, true);
+                    if self.conceal == ConcealPolicy::Placeholder {
+                        self.mixer.set_ch_placeholder(&track.id, &YuvColor::GRAY);
+                    }
+                }
+                ConcealAction::Recover => {
+                    println!("mix video: flow {:?} recovered", index);
+                    self.mixer.set_ch_flag(&track.id, VChFlag::Stale, false);
+                }
+                _ => {}
+            }
+
+            if !action.is_decode() {
+                return true;
+            }
+
+            if let Some(tracker) = &mut track.h264 {
+                match tracker.push_annexb(packet.data().unwrap_or_default()) {
+                    Ok(Some(change)) if change.needs_reinit() => {
+                        // scaler of channel follows the new size by itself
+                        let params = tracker.params().unwrap();
+                        let (width, height) = params.generic.pixel_dimensions;
+                        println!("mix video: flow {:?} reopen decoder, {change:?}, {width}x{height}", index);
+                        match make_video_decoder(ff::codec::Id::H264, width as i32, height as i32, &params.annexb_spspps(), milli_time_base()) {
+                            Ok(decoder) => track.decoder = decoder,
+                            Err(e) => println!("mix video: flow {:?} failed to reopen decoder, {e}", index),
+                        }
+                    }
+                    Err(e) => println!("mix video: flow {:?} bad parameters, {e}", index),
+                    _ => {}
+                }
+            }
+
             // ff::util::frame::Video::new(format, width, height)
             // decoder.time_base();
             track.decoder.send_packet(packet).unwrap();
+            let orientation = self.orientations.get(&index);
             while let Some(frame) = video_decoder_receive_frame(&mut track.decoder).unwrap() {
-                self.mixer.update_ch(&track.id, frame.into()).unwrap();
+                let image: FFYuvImage = frame.into();
+                let image = match orientation {
+                    Some(v) => image.transformed(v.rotation, v.flip),
+                    None => image,
+                };
+                self.mixer.update_ch(&track.id, image).unwrap();
             }
             true
         } else {
@@ -455,20 +871,41 @@ impl MixContextVideo {
         }
     }
 
-    fn add_h264_flow(&mut self, flow: &Flow) {
-        let args = parse_h264(&flow.codec).unwrap();
+    // decoder by the codec registry
+    fn add_flow(&mut self, flow: &Flow) -> bool {
+        let args = match find_codec(flow.codec.codec_id).map(|x| x.decoder_args(&flow.codec)) {
+            Some(Ok(Some(args))) => args,
+            _ => return false,
+        };
+
+        let decoder = match make_video_decoder(
+            args.codec_id, 
+            args.width, 
+            args.height, 
+            &args.extra,
+            milli_time_base(),
+        ) {
+            Ok(v) => v,
+            Err(e) => {
+                println!("mix video: no decoder of {:?}, {e}", args.codec_id);
+                return false
+            },
+        };
+
         let id = self.mixer.add_ch().unwrap();
-        self.tracks.insert(flow.index, MixVideoTrack {
-            decoder: make_video_decoder(
-                ff::codec::Id::H264, 
-                args.width, 
-                args.height, 
-                &args.extra,
-                milli_time_base(),
-            ).unwrap(),
+        let h264 = (flow.codec.codec_id == CodecId::H264).then(|| {
+            let params = flow.codec.fmtps.first().and_then(|x| RtpH264Parameters::parse_from_str(x).ok());
+            H264ParamTracker::new(params)
+        });
+
+        self.tracks.insert(flow.source_index(), MixVideoTrack {
+            decoder,
             id,
+            integrity: Default::default(),
+            h264,
         });
-    }    
+        true
+    }
 }
 
 
@@ -476,6 +913,10 @@ impl MixContextVideo {
 struct MixVideoTrack {
     decoder: ff::codec::decoder::Video,
     id: VChId,
+    integrity: RefIntegrity,
+
+    // decoder is reopened if resolution or profile changes in-band
+    h264: Option<H264ParamTracker>,
 }
 
 struct MixContextAudio {
@@ -488,6 +929,13 @@ struct MixContextAudio {
     mixed_frame: ff::frame::Audio,
     frame_size: usize,
     mixed_resampler: SResampler,
+
+    // render dtmf digits as beeps, one mixer channel per telephone-event flow
+    dtmf_beep: bool,
+    beeps: HashMap<FlowIndex, DtmfBeep>,
+
+    // what fills missing media time of channels
+    gap_fill: AudioGapFill,
 }
 
 impl MixContextAudio {
@@ -524,24 +972,112 @@ impl MixContextAudio {
             encoder,
             o_track,
             mixed_frame,
+            dtmf_beep: false,
+            beeps: Default::default(),
+            gap_fill: Default::default(),
         }
     }
 
-    pub fn handle_flow_depacked(&mut self, flow: &Flow, packet: &ff::Packet, ts: i64) -> bool {
-        if self.try_handle_audio(flow, packet) {
+    pub fn with_dtmf_beep(mut self, dtmf_beep: bool) -> Self {
+        self.dtmf_beep = dtmf_beep;
+        self
+    }
+
+    pub fn with_gap_fill(mut self, gap_fill: AudioGapFill) -> Self {
+        self.gap_fill = gap_fill;
+        self
+    }
+
+    // the tone starts at the rtp timestamp of the event and lasts until its end is known
+    pub fn handle_flow_dtmf_begin(&mut self, flow: &Flow, event: &DtmfEvent, ts: i64) {
+        if !self.dtmf_beep {
+            return;
+        }
+
+        let digit = match event.digit() {
+            Some(v) => v,
+            None => return,
+        };
+
+        let rate = self.mixed_frame.rate();
+        let channels = self.mixed_frame.channels() as u32;
+        let beep = self.beeps.entry(flow.index).or_insert_with(|| DtmfBeep {
+            id: self.mixer.add_ch().unwrap(),
+            timeline: AudioTimeline::new(event.clock_rate, rate, channels),
+            tone: None,
+        });
+
+        let now = self.timed.position(ts);
+        let end = self.mixer.ch_end(&beep.id).unwrap_or(now);
+        let mut rendered = 0;
+        match beep.timeline.push(event.timestamp, now, end) {
+            AudioPlace::Append => {},
+            AudioPlace::Fill(len) => self.mixer.update_ch(&beep.id, &vec![0; len]).unwrap(),
+            // the beginning already mixed is skipped
+            AudioPlace::Trim(len) => rendered = len / channels as usize,
+        }
+
+        beep.tone = Some(BeepTone {
+            digit,
+            volume: event.volume,
+            rendered,
+            total: None,
+        });
+    }
+
+    pub fn handle_flow_dtmf_end(&mut self, flow: &Flow, event: &DtmfEvent) {
+        let rate = self.mixed_frame.rate() as u64;
+        if let Some(tone) = self.beeps.get_mut(&flow.index).and_then(|x| x.tone.as_mut()) {
+            tone.total = Some((event.duration as u64 * rate / event.clock_rate.max(1) as u64) as usize);
+        }
+    }
+
+    // tones are rendered a little ahead of mixing
+    fn render_beeps(&mut self) {
+        let rate = self.mixed_frame.rate() as usize;
+        let channels = self.mixed_frame.channels() as usize;
+        let ahead = self.frame_size * 2;
+        let max = MAX_BEEP_MS * rate / 1000;
+
+        for beep in self.beeps.values_mut() {
+            let tone = match &mut beep.tone {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let end = self.mixer.ch_end(&beep.id).unwrap_or(0);
+            let queued = end.saturating_sub(self.mixer.position()) as usize / channels;
+            let total = tone.total.unwrap_or(max).min(max);
+            let num = ahead.saturating_sub(queued).min(total.saturating_sub(tone.rendered));
+            if num > 0 {
+                let samples = make_dtmf_tone_frames(tone.digit, tone.volume, tone.rendered, num, rate as u32, channels as u32);
+                self.mixer.update_ch(&beep.id, &samples).unwrap();
+                tone.rendered += num;
+            }
+
+            if tone.rendered >= total {
+                beep.tone = None;
+            }
+        }
+    }
+
+    pub fn handle_flow_depacked(&mut self, flow: &Flow, packet: &ff::Packet, frame: &DepackedFrame, ts: i64) -> bool {
+        if self.try_handle_audio(flow, packet, frame, ts) {
             true
+        } else if self.add_flow(flow, packet) {
+            self.handle_flow_depacked(flow, packet, frame, ts)
         } else {
-            match flow.codec.codec_id {
-                CodecId::AAC => {
-                    self.add_aac_flow(flow);
-                    self.handle_flow_depacked(flow, packet, ts)
-                },
-                _ => false,
-            }
+            false
         }
         // self.try_mix_video(ts);
     }
 
+    // -dBov of the last decoded samples
+    pub fn flow_level(&self, flow: &Flow) -> Option<u8> {
+        self.tracks.get(&flow.index)
+        .and_then(|x| self.mixer.ch_level(&x.id))
+    }
+
     fn get_elapsed_since_last_mix(&self, ts: i64) -> i64 {
         match self.last_mix_ts {
             Some(last) => ts - last,
@@ -553,10 +1089,15 @@ impl MixContextAudio {
 
     const MIX_INTERVAL: i64 = 20;
 
+    // channels are mixed behind arrival, so that late samples and fills still land in place
+    const MIX_DELAY: i64 = 100;
+
     pub fn try_mix(&mut self, ts: i64, writer: &mut FFWriter) {
+        let ts = ts - Self::MIX_DELAY;
 
         
         while self.get_elapsed_since_last_mix(ts) >= Self::MIX_INTERVAL {
+            self.render_beeps();
             self.mixed_frame.set_samples(self.frame_size);
             let buf = audio_frame_packed_i16_samples_mut(&mut self.mixed_frame);
             
@@ -603,65 +1144,339 @@ impl MixContextAudio {
         // }
     }
 
-    fn try_handle_audio(&mut self, flow: &Flow, packet: &ff::Packet) -> bool {
+    // decoded samples are placed by rtp timestamp, gaps are filled and passed samples are trimmed
+    fn try_handle_audio(&mut self, flow: &Flow, packet: &ff::Packet, frame: &DepackedFrame, ts: i64) -> bool {
+        let mut rtp_ts = frame.rtp_ts;
         if let Some(track) = self.tracks.get_mut(&flow.index) {
-            // ff::util::frame::Video::new(format, width, height)
-            // decoder.time_base();
-            track.decoder.send_packet(packet).unwrap();
-            while let Some(frame) = audio_decoder_receive_frame(&mut track.decoder).unwrap() {
-                let frame = track.resampler.resample_whole(&frame).unwrap();
-                let samples = audio_frame_packed_i16_samples(&frame);
-                println!(
-                    "audio mixer update: id {:?}, samples {}, rate {}, ch {}, format {:?}",
-                    track.id,
-                    samples.len(),
-                    frame.rate(),
-                    frame.channels(),
-                    frame.format(),
-                );
-
-                self.mixer.update_ch(&track.id, samples).unwrap();
+            let mut pcm = Vec::new();
+            match &mut track.decoder {
+                MixAudioDecoder::FF(decoder) => {
+                    decoder.send_packet(packet).unwrap();
+                    while let Some(frame) = audio_decoder_receive_frame(decoder).unwrap() {
+                        let frame = track.resampler.resample_whole(&frame).unwrap();
+                        pcm.extend_from_slice(audio_frame_packed_i16_samples(&frame));
+                    }
+                },
+                MixAudioDecoder::G711(decoder) => {
+                    let mut decoded = Vec::with_capacity(packet.size());
+                    decoder.decode(packet.data().unwrap_or(&[]), &mut decoded);
+
+                    let mut frame = ff::frame::Audio::new(audio_packed_i16_format(), decoded.len(), ChannelLayout::MONO);
+                    frame.set_rate(G711_SAMPLERATE);
+                    audio_frame_packed_i16_samples_mut(&mut frame).copy_from_slice(&decoded);
+
+                    let frame = track.resampler.resample_whole(&frame).unwrap();
+                    pcm.extend_from_slice(audio_frame_packed_i16_samples(&frame));
+                },
+                MixAudioDecoder::Opus(decoder) => {
+                    let mut decoded = Vec::new();
+                    let data = packet.data().unwrap_or(&[]);
+                    let r = match &frame.fec {
+                        Some(fec) => decoder.decode_fec(fec, opus_packet_samples(data).unwrap_or(0), &mut decoded),
+                        None => decoder.decode(data, &mut decoded),
+                    };
+                    if let Err(e) = r {
+                        println!("mix audio: flow {:?}, {e}", flow.index);
+                    }
+
+                    let channels = decoder.channels();
+                    let mut frame = ff::frame::Audio::new(audio_packed_i16_format(), decoded.len() / channels as usize, ChannelLayout::default(channels as i32));
+                    frame.set_rate(OPUS_CLOCK_RATE);
+                    audio_frame_packed_i16_samples_mut(&mut frame).copy_from_slice(&decoded);
+
+                    let frame = track.resampler.resample_whole(&frame).unwrap();
+                    pcm.extend_from_slice(audio_frame_packed_i16_samples(&frame));
+                },
+                MixAudioDecoder::Aac(decoder, next_rtp) => {
+                    let mut decoded = Vec::new();
+
+                    // access units lost before this one are concealed by libfdk-aac,
+                    // placed from the end of the last one
+                    let frame_rtp = aac_frame_rtp(decoder, flow.codec.clock_rate);
+                    if let Some(next) = next_rtp.filter(|_| frame_rtp > 0) {
+                        let lost = rtp_ts.wrapping_sub(next) as i32 as i64 / frame_rtp as i64;
+                        if lost > 0 && lost <= MAX_AAC_CONCEAL_FRAMES {
+                            for _ in 0..lost {
+                                if let Err(e) = decoder.conceal(&mut decoded) {
+                                    println!("mix audio: flow {:?}, {e}", flow.index);
+                                    break;
+                                }
+                            }
+                            rtp_ts = next;
+                        }
+                    }
+
+                    if let Err(e) = decoder.decode(packet.data().unwrap_or(&[]), &mut decoded) {
+                        println!("mix audio: flow {:?}, {e}", flow.index);
+                    }
+                    let frame_rtp = aac_frame_rtp(decoder, flow.codec.clock_rate);
+                    *next_rtp = (frame_rtp > 0).then(|| frame.rtp_ts.wrapping_add(frame_rtp));
+
+                    let channels = decoder.channels().max(1);
+                    let mut frame = ff::frame::Audio::new(audio_packed_i16_format(), decoded.len() / channels as usize, ChannelLayout::default(channels as i32));
+                    frame.set_rate(decoder.sample_rate());
+                    audio_frame_packed_i16_samples_mut(&mut frame).copy_from_slice(&decoded);
+
+                    let frame = track.resampler.resample_whole(&frame).unwrap();
+                    pcm.extend_from_slice(audio_frame_packed_i16_samples(&frame));
+                },
             }
+
+            // decoder may hold the first packets
+            if pcm.is_empty() {
+                return true
+            }
+
+            let now = self.timed.position(ts);
+            let end = self.mixer.ch_end(&track.id).unwrap_or(now);
+            let place = track.timeline.push(rtp_ts, now, end);
+            let samples = match place {
+                AudioPlace::Append => &pcm[..],
+                AudioPlace::Fill(len) => {
+                    let fill = track.filler.fill(len);
+                    self.mixer.update_ch(&track.id, &fill).unwrap();
+                    &pcm[..]
+                },
+                AudioPlace::Trim(len) => &pcm[len.min(pcm.len())..],
+            };
+            println!("audio mixer update: id {:?}, samples {}, rtp_ts {rtp_ts}, {place:?}", track.id, pcm.len());
+
+            self.mixer.update_ch(&track.id, samples).unwrap();
             true
         } else {
             false
         }
     }
 
-    fn add_aac_flow(&mut self, flow: &Flow) {
-        // let args = parse_h264(&flow.codec).unwrap();
+    // decoder by the codec registry, G.711 is decoded natively
+    fn add_flow(&mut self, flow: &Flow, packet: &ff::Packet) -> bool {
+        let args = match find_codec(flow.codec.codec_id).map(|x| x.decoder_args(&flow.codec)) {
+            Some(Ok(Some(args))) => args,
+            _ => return false,
+        };
+
+        let decoder = match args.codec_id {
+            ff::codec::Id::PCM_MULAW => {
+                self.add_g711_flow(flow, G711Law::ULaw);
+                return true
+            },
+            ff::codec::Id::PCM_ALAW => {
+                self.add_g711_flow(flow, G711Law::ALaw);
+                return true
+            },
+            ff::codec::Id::MP3 => {
+                self.add_mpa_flow(flow, packet);
+                return true
+            },
+            ff::codec::Id::OPUS => return self.add_opus_flow(flow, args.channels as u32),
+            ff::codec::Id::AAC if !args.extra.is_empty() => return self.add_aac_flow(flow, &args.extra),
+            _ => make_audio_decoder(args.codec_id, args.sample_rate, args.channels, milli_time_base()),
+        };
+
+        let decoder = match decoder {
+            Ok(v) => v,
+            Err(e) => {
+                println!("mix audio: no decoder of {:?}, {e}", args.codec_id);
+                return false
+            },
+        };
+
+        let id = self.mixer.add_ch().unwrap();
+
+        let resampler = SResampler::get(
+            decoder.format(),
+            decoder.channel_layout(), 
+            decoder.rate(), 
+            self.mixed_frame.format(),
+            ChannelLayout::default(self.mixed_frame.channels() as i32), 
+            self.mixed_frame.rate() as u32,
+        ).unwrap();
+
+        self.tracks.insert(flow.index, MixAudioTrack {
+            decoder: MixAudioDecoder::FF(decoder),
+            id,
+            resampler,
+            timeline: self.make_timeline(flow),
+            filler: self.make_filler(),
+        });
+        true
+    }
+
+    // the first frame tells the real sample rate and channels
+    fn add_mpa_flow(&mut self, flow: &Flow, packet: &ff::Packet) {
+        let header = parse_mpa_header(packet.data().unwrap_or(&[]));
+        let (samplerate, channels) = match header {
+            Some(header) => (header.sample_rate, header.channels),
+            None => (44100, 2),
+        };
+
         let id = self.mixer.add_ch().unwrap();
         let decoder = make_audio_decoder(
-            ff::codec::Id::AAC, 
-            flow.codec.clock_rate as i32, 
-            flow.codec.channels.unwrap_or(1) as i32, 
+            ff::codec::Id::MP3, 
+            samplerate as i32, 
+            channels as i32, 
             milli_time_base(),
         ).unwrap();
-        
-        // let enc_params = self.encoder.get_parameters();
-        
+
         let resampler = SResampler::get(
             decoder.format(),
             decoder.channel_layout(), 
             decoder.rate(), 
-             self.mixed_frame.format(),
+            self.mixed_frame.format(),
             ChannelLayout::default(self.mixed_frame.channels() as i32), 
             self.mixed_frame.rate() as u32,
         ).unwrap();
 
         self.tracks.insert(flow.index, MixAudioTrack {
-            decoder,
+            decoder: MixAudioDecoder::FF(decoder),
+            id,
+            resampler,
+            timeline: self.make_timeline(flow),
+            filler: self.make_filler(),
+        });
+    }
+
+    // lost and DTX packets are concealed by libopus, from in-band FEC if any
+    fn add_opus_flow(&mut self, flow: &Flow, channels: u32) -> bool {
+        let decoder = match LibOpusDecoder::new(channels) {
+            Ok(v) => v,
+            Err(e) => {
+                println!("mix audio: no opus decoder, {e}");
+                return false
+            },
+        };
+
+        let id = self.mixer.add_ch().unwrap();
+
+        let resampler = SResampler::get(
+            audio_packed_i16_format(),
+            ChannelLayout::default(channels as i32), 
+            OPUS_CLOCK_RATE, 
+            self.mixed_frame.format(),
+            ChannelLayout::default(self.mixed_frame.channels() as i32), 
+            self.mixed_frame.rate() as u32,
+        ).unwrap();
+
+        self.tracks.insert(flow.index, MixAudioTrack {
+            decoder: MixAudioDecoder::Opus(decoder),
             id,
             resampler,
+            timeline: self.make_timeline(flow),
+            filler: self.make_filler(),
         });
-    }    
+        true
+    }
+
+    // lost access units are concealed by libfdk-aac, config is required to open it
+    fn add_aac_flow(&mut self, flow: &Flow, config: &[u8]) -> bool {
+        let decoder = match AudioSpecificConfig::parse_from_bytes(config).and_then(|x| LibFdkAacDecoder::new(&x)) {
+            Ok(v) => v,
+            Err(e) => {
+                println!("mix audio: no aac decoder, {e}");
+                return false
+            },
+        };
+
+        let id = self.mixer.add_ch().unwrap();
+
+        let resampler = SResampler::get(
+            audio_packed_i16_format(),
+            ChannelLayout::default(decoder.channels() as i32), 
+            decoder.sample_rate(), 
+            self.mixed_frame.format(),
+            ChannelLayout::default(self.mixed_frame.channels() as i32), 
+            self.mixed_frame.rate() as u32,
+        ).unwrap();
+
+        self.tracks.insert(flow.index, MixAudioTrack {
+            decoder: MixAudioDecoder::Aac(decoder, None),
+            id,
+            resampler,
+            timeline: self.make_timeline(flow),
+            filler: self.make_filler(),
+        });
+        true
+    }
+
+    fn add_g711_flow(&mut self, flow: &Flow, law: G711Law) {
+        let id = self.mixer.add_ch().unwrap();
+
+        let resampler = SResampler::get(
+            audio_packed_i16_format(),
+            ChannelLayout::MONO, 
+            G711_SAMPLERATE, 
+            self.mixed_frame.format(),
+            ChannelLayout::default(self.mixed_frame.channels() as i32), 
+            self.mixed_frame.rate() as u32,
+        ).unwrap();
+
+        self.tracks.insert(flow.index, MixAudioTrack {
+            decoder: MixAudioDecoder::G711(G711Decoder::new(law)),
+            id,
+            resampler,
+            timeline: self.make_timeline(flow),
+            filler: self.make_filler(),
+        });
+    }
+
+    fn make_timeline(&self, flow: &Flow) -> AudioTimeline {
+        AudioTimeline::new(flow.codec.clock_rate, self.mixed_frame.rate(), self.mixed_frame.channels() as u32)
+    }
+
+    fn make_filler(&self) -> GapFiller {
+        GapFiller::new(self.gap_fill)
+    }
 }
 
 
 struct MixAudioTrack {
-    decoder: ff::codec::decoder::Audio,
+    decoder: MixAudioDecoder,
     resampler: SResampler,
     id: AChId,
+
+    // place of decoded samples in the mixed timeline by rtp timestamp
+    timeline: AudioTimeline,
+    filler: GapFiller,
+}
+
+struct DtmfBeep {
+    id: AChId,
+
+    // place of events in the mixed timeline by rtp timestamp
+    timeline: AudioTimeline,
+    tone: Option<BeepTone>,
+}
+
+// the tone of the current event
+struct BeepTone {
+    digit: char,
+    volume: u8,
+
+    // frames since the event start
+    rendered: usize,
+
+    // frames of the whole event, known at the end
+    total: Option<usize>,
+}
+
+// a tone whose end is lost stops here
+const MAX_BEEP_MS: usize = 5000;
+
+enum MixAudioDecoder {
+    FF(ff::codec::decoder::Audio),
+    G711(G711Decoder),
+    Opus(LibOpusDecoder),
+
+    // rtp timestamp expected for the next access unit
+    Aac(LibFdkAacDecoder, Option<u32>),
+}
+
+// more lost than this is a jump of timestamp, left to the timeline
+const MAX_AAC_CONCEAL_FRAMES: i64 = 50;
+
+// rtp duration of an access unit, 0 until the first one is decoded
+fn aac_frame_rtp(decoder: &LibFdkAacDecoder, clock_rate: u32) -> u32 {
+    (decoder.frame_size() as u64 * clock_rate as u64 / decoder.sample_rate().max(1) as u64) as u32
 }
 
 
@@ -669,7 +1484,36 @@ struct OTrack {
     name: String,
     track: FFTrack,
     depacker: Box<dyn RtpCodecDepacker>,
+    clock_rate: u32,
+
+    // rtp timestamp and pts of the last frame
+    last_rtp: Option<(u32, i64)>,
     wrote_packets: u64,
+
+    h264: Option<H264ParamTracker>,
+    orientation: Option<VideoOrientation>,
+}
+
+impl OTrack {
+    // true if the frame can't be written into the current mp4 track
+    fn update_parameters(&mut self, frame: &DepackedFrame) -> bool {
+        let tracker = match &mut self.h264 {
+            Some(v) => v,
+            None => return false,
+        };
+
+        match tracker.push_annexb(&frame.data) {
+            Ok(Some(change)) => {
+                println!("otrack [{}] parameters changed {change:?}, {:?}", self.name, tracker.params());
+                change.needs_reinit()
+            }
+            Ok(None) => false,
+            Err(e) => {
+                println!("otrack [{}] bad parameters, {e}", self.name);
+                false
+            }
+        }
+    }
 }
 
 
@@ -678,6 +1522,14 @@ struct CStream {
     writer: FFWriter,
     tracks: Vec<CTrack>,
     first_ts: Option<i64>,
+
+    // output path without extension, see segment_path
+    opath_base: PathBuf,
+    segment: usize,
+
+    // codec and otrack index of each output track
+    codecs: Vec<SdpCodec>,
+    otracks: Vec<usize>,
 }
 
 struct CTrack {
@@ -692,33 +1544,9 @@ struct CFlow {
 
 struct FlowExt {
     otrack_index: Option<usize>,
-}
-
-
-
-fn parse_h264(codec: &SdpCodec) -> Result<FFVideoArgs>{
-    let fmtp: &str = codec.fmtps
-        .get(0)
-        .map(|x|x.as_str()).unwrap_or("");
 
-    let param = RtpH264Parameters::parse_from_str(fmtp).map_err(|e|anyhow!("{e}")).unwrap();
+    // for flows which are mixed only, without per stream output track
+    mix_depacker: Option<Box<dyn RtpCodecDepacker>>,
 
-    let mut spspps = BytesMut::new();
-    if param.sps_nal.len() > 0 {
-        spspps.put(&[0,0,0,1][..]);
-        spspps.put(&param.sps_nal[..]);
-    }
-
-    if param.pps_nal.len() > 0 {
-        spspps.put(&[0,0,0,1][..]);
-        spspps.put(&param.pps_nal[..]);
-    }
-
-    Ok(FFVideoArgs {
-        codec_id: ff::codec::Id::H264,
-        width: param.generic.pixel_dimensions.0 as i32,
-        height: param.generic.pixel_dimensions.1 as i32,
-        extra: spspps.freeze(),
-        time_base: ff::Rational::new(1, 90000),
-    })
+    orientation: Option<VideoOrientation>,
 }
@@ -1,6 +1,9 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use bytes::Bytes;
 use std::{marker::PhantomData, num::NonZeroU16};
 
+use crate::rtp::pack::{RtpCodecPacker, RtpPackHeader, RtpPackQueue};
+
 pub struct RtpDepackerAAC(PhantomData<()>);
 impl RtpDepackerAAC {
     pub fn new(
@@ -12,3 +15,111 @@ impl RtpDepackerAAC {
     }
 }
 
+
+
+// RFC 3640 mode AAC-hbr, one access unit per packet,
+// fragmented if larger than mtu
+pub struct RtpPackerAAC {
+    queue: RtpPackQueue,
+    mtu: usize,
+}
+
+// 13 bits size and 3 bits index in au header
+const AAC_HBR_MAX_AU_SIZE: usize = 0x1FFF;
+
+// payload size limit, rtp header not included
+const DEFAULT_MTU: usize = 1200;
+
+impl RtpPackerAAC {
+    pub fn new(header: RtpPackHeader) -> Self {
+        Self {
+            queue: RtpPackQueue::new(header),
+            mtu: DEFAULT_MTU,
+        }
+    }
+
+    pub fn with_mtu(mut self, mtu: usize) -> Self {
+        self.mtu = mtu;
+        self
+    }
+
+    pub fn header(&self) -> &RtpPackHeader {
+        self.queue.header()
+    }
+
+    pub fn into_box(self) -> Box<dyn RtpCodecPacker> {
+        Box::new(self)
+    }
+}
+
+impl RtpCodecPacker for RtpPackerAAC {
+    fn push_frame(&mut self, frame: &[u8], rtp_ts: u32) -> Result<()>  {
+        if frame.is_empty() || frame.len() > AAC_HBR_MAX_AU_SIZE {
+            bail!("invalid aac frame size [{}]", frame.len())
+        }
+
+        if self.mtu <= 4 {
+            bail!("too small mtu [{}]", self.mtu)
+        }
+
+        // AU-headers-length in bits, then the AU-header of index 0,
+        // fragments carry the size of whole access unit
+        let mut payload = Vec::with_capacity(self.mtu);
+        let chunks: Vec<_> = frame.chunks(self.mtu - 4).collect();
+        for (n, chunk) in chunks.iter().enumerate() {
+            payload.clear();
+            payload.extend_from_slice(&16_u16.to_be_bytes());
+            payload.extend_from_slice(&((frame.len() as u16) << 3).to_be_bytes());
+            payload.extend_from_slice(chunk);
+
+            // marker on complete access unit or the last fragment
+            self.queue.push(n + 1 == chunks.len(), rtp_ts, &payload);
+        }
+        Ok(())
+    }
+
+    fn pull_rtp(&mut self) -> Option<Bytes>  {
+        self.queue.pull()
+    }
+}
+
+// fmtp of the packer, config is AudioSpecificConfig
+pub fn aac_hbr_fmtp(config: &[u8]) -> String {
+    let config: String = config.iter().map(|x| format!("{x:02X}")).collect();
+    format!("streamtype=5;profile-level-id=1;mode=AAC-hbr;sizelength=13;indexlength=3;indexdeltalength=3;config={config}")
+}
+
+
+#[test]
+fn test_aac_pack() {
+    use rtp_rs::RtpReader;
+
+    let mut packer = RtpPackerAAC::new(RtpPackHeader::new(97, 0x5678).with_seq(7)).with_mtu(10);
+    packer.push_frame(&[1, 2, 3, 4, 5], 1024).unwrap();
+
+    // 14 bytes in 6 + 6 + 2
+    let frame: Vec<u8> = (10..24).collect();
+    packer.push_frame(&frame, 2048).unwrap();
+
+    let mut packets = Vec::new();
+    while let Some(packet) = packer.pull_rtp() {
+        let rtp = RtpReader::new(&packet).unwrap();
+        assert_eq!((rtp.payload_type(), rtp.ssrc()), (97, 0x5678));
+        packets.push((u16::from(rtp.sequence_number()), rtp.timestamp(), rtp.mark(), rtp.payload().to_vec()));
+    }
+
+    let expect: Vec<(u16, u32, bool, Vec<u8>)> = vec![
+        (7, 1024, true, vec![0x00, 0x10, 0x00, 0x28, 1, 2, 3, 4, 5]),
+        (8, 2048, false, vec![0x00, 0x10, 0x00, 0x70, 10, 11, 12, 13, 14, 15]),
+        (9, 2048, false, vec![0x00, 0x10, 0x00, 0x70, 16, 17, 18, 19, 20, 21]),
+        (10, 2048, true, vec![0x00, 0x10, 0x00, 0x70, 22, 23]),
+    ];
+    assert_eq!(packets, expect);
+
+    assert!(packer.push_frame(&vec![0; 0x2000], 3072).is_err());
+
+    assert_eq!(
+        aac_hbr_fmtp(&[0x11, 0x90]),
+        "streamtype=5;profile-level-id=1;mode=AAC-hbr;sizelength=13;indexlength=3;indexdeltalength=3;config=1190",
+    );
+}
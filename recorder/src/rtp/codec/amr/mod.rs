@@ -0,0 +1,322 @@
+// RFC 4867 RTP Payload Format for AMR and AMR-WB
+
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{BufMut, BytesMut};
+use rtp_rs::RtpReader;
+
+use super::super::depack::{DepackedFrame, RtpCodecDepacker, RtpLossCounter};
+use super::h265::BitReader;
+#[cfg(test)]
+use super::super::rtp::make_rtp_packet;
+
+// speech bits of each frame type, RFC 4867 section 3.6 / 3GPP TS 26.101, 26.201
+const AMR_NB_FRAME_BITS: [usize; 16] = [95, 103, 118, 134, 148, 159, 204, 244, 39, 43, 38, 37, 0, 0, 0, 0];
+const AMR_WB_FRAME_BITS: [usize; 16] = [132, 177, 253, 285, 317, 365, 397, 461, 477, 40, 0, 0, 0, 0, 0, 0];
+
+const AMR_FT_NO_DATA: u8 = 15;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RtpAmrParameters {
+    pub octet_align: bool,
+    pub crc: bool,
+    pub robust_sorting: bool,
+    pub interleaving: Option<u32>,
+    pub mode_set: Vec<u8>,
+    pub mode_change_period: Option<u32>,
+    pub channels: u32,
+}
+
+impl RtpAmrParameters {
+    pub fn parse_from_str(fmtp: &str) -> Result<Self, String> {
+        let mut params = Self {
+            channels: 1,
+            ..Default::default()
+        };
+
+        for p in fmtp.split(';') {
+            let (key, value) = match p.trim().split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None if !p.trim().is_empty() => return Err("key without value".into()),
+                None => continue,
+            };
+
+            match key {
+                "octet-align" => params.octet_align = value == "1",
+                "crc" => params.crc = value == "1",
+                "robust-sorting" => params.robust_sorting = value == "1",
+                "interleaving" => {
+                    params.interleaving = Some(value.parse().map_err(|_e|format!("invalid interleaving [{value}]"))?);
+                },
+                "mode-set" => {
+                    for mode in value.split(',') {
+                        params.mode_set.push(mode.trim().parse().map_err(|_e|format!("invalid mode-set [{value}]"))?);
+                    }
+                },
+                "mode-change-period" => {
+                    params.mode_change_period = Some(value.parse().map_err(|_e|format!("invalid mode-change-period [{value}]"))?);
+                },
+                _ => {},
+            }
+        }
+
+        // crc, robust-sorting and interleaving imply octet-aligned mode
+        if params.crc || params.robust_sorting || params.interleaving.is_some() {
+            params.octet_align = true;
+        }
+
+        Ok(params)
+    }
+}
+
+// output one frame per pull in AMR storage format (RFC 4867 section 5.3),
+// a header byte of frame type and quality followed by octet aligned speech bits,
+// which is what ffmpeg's amrnb/amrwb decoders expect
+pub struct RtpDepackerAMR {
+    wideband: bool,
+    params: RtpAmrParameters,
+    loss: RtpLossCounter,
+    frames: VecDeque<DepackedFrame>,
+}
+
+impl RtpDepackerAMR {
+    pub fn new(wideband: bool, channels: Option<u32>, fmtp: Option<&str>) -> Result<Self> {
+        let mut params = match fmtp {
+            Some(fmtp) => RtpAmrParameters::parse_from_str(fmtp).map_err(|e|anyhow!("{e}"))?,
+            None => RtpAmrParameters { channels: 1, ..Default::default() },
+        };
+
+        if let Some(channels) = channels {
+            params.channels = channels;
+        }
+
+        if params.channels != 1 {
+            bail!("unsupported amr channels [{}]", params.channels)
+        }
+
+        if params.robust_sorting || params.interleaving.is_some() {
+            bail!("unsupported amr robust-sorting/interleaving")
+        }
+
+        Ok(Self {
+            wideband,
+            params,
+            loss: Default::default(),
+            frames: Default::default(),
+        })
+    }
+
+    pub fn into_box(self) -> Box<dyn RtpCodecDepacker> {
+        Box::new(self)
+    }
+
+    pub fn params(&self) -> &RtpAmrParameters {
+        &self.params
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        if self.wideband { 16000 } else { 8000 }
+    }
+
+    fn frame_bits(&self, ft: u8) -> usize {
+        if self.wideband {
+            AMR_WB_FRAME_BITS[ft as usize]
+        } else {
+            AMR_NB_FRAME_BITS[ft as usize]
+        }
+    }
+
+    // SID is excluded since ffmpeg's decoders have no comfort noise generation
+    fn is_speech(&self, ft: u8) -> bool {
+        if self.wideband { ft <= 8 } else { ft <= 7 }
+    }
+
+    // each toc is a 20 ms frame, including NO_DATA
+    fn push_frame(&mut self, frame: BytesMut, ts: u32, index: usize) {
+        let ts = ts.wrapping_add(index as u32 * self.sample_rate() / 50);
+        self.frames.push_back(DepackedFrame::audio(frame.freeze(), ts).with_loss(self.loss.take()));
+    }
+
+    //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // | CMR=15|1| FT=9  |1| FT=9  |0| FT=9  |1|d(0)                   |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    fn push_bandwidth_efficient(&mut self, payload: &[u8], ts: u32) -> Result<()> {
+        let mut r = BitReader::new(payload);
+
+        // CMR
+        r.skip_bits(4).map_err(|e|anyhow!("{e}"))?;
+
+        let mut tocs = Vec::new();
+        loop {
+            let toc = r.read_bits(6).map_err(|e|anyhow!("{e}"))? as u8;
+            tocs.push(toc);
+            if toc & 0x20 == 0 {
+                break;
+            }
+        }
+
+        for (index, toc) in tocs.into_iter().enumerate() {
+            let ft = (toc >> 1) & 0x0F;
+            let q = toc & 0x01;
+            let bits = self.frame_bits(ft);
+
+            let mut frame = BytesMut::with_capacity(1 + bits.div_ceil(8));
+            frame.put_u8((ft << 3) | (q << 2));
+
+            let mut remains = bits;
+            while remains > 0 {
+                let n = remains.min(8);
+                let v = r.read_bits(n).map_err(|e|anyhow!("{e}"))? as u8;
+                frame.put_u8(v << (8 - n));
+                remains -= n;
+            }
+
+            if self.is_speech(ft) {
+                self.push_frame(frame, ts, index);
+            }
+        }
+        Ok(())
+    }
+
+    //  0 1 2 3 4 5 6 7
+    // +-+-+-+-+-+-+-+-+
+    // |  CMR  |R|R|R|R|
+    // +-+-+-+-+-+-+-+-+
+    // |F|  FT   |Q|P|P|  TOC, repeated while F
+    // +-+-+-+-+-+-+-+-+
+    // |     CRC       |  if crc, one for each non-empty frame
+    // +-+-+-+-+-+-+-+-+
+    // |  speech data  |  octet aligned frames
+    fn push_octet_aligned(&mut self, payload: &[u8], ts: u32) -> Result<()> {
+        if payload.is_empty() {
+            bail!("empty amr payload")
+        }
+
+        let mut offset = 1;
+        let mut tocs = Vec::new();
+        loop {
+            let toc = *payload.get(offset).ok_or_else(||anyhow!("amr toc truncated"))?;
+            offset += 1;
+            tocs.push(toc);
+            if toc & 0x80 == 0 {
+                break;
+            }
+        }
+
+        if self.params.crc {
+            offset += tocs.iter()
+            .filter(|toc| self.frame_bits((*toc >> 3) & 0x0F) > 0)
+            .count();
+        }
+
+        for (index, toc) in tocs.into_iter().enumerate() {
+            let ft = (toc >> 3) & 0x0F;
+            let len = self.frame_bits(ft).div_ceil(8);
+            if payload.len() < offset + len {
+                bail!("amr frame size [{len}] exceed remains [{}]", payload.len().saturating_sub(offset))
+            }
+
+            if self.is_speech(ft) {
+                let mut frame = BytesMut::with_capacity(1 + len);
+                frame.put_u8(toc & 0x7C);
+                frame.put(&payload[offset..offset + len]);
+                self.push_frame(frame, ts, index);
+            }
+            offset += len;
+        }
+
+        Ok(())
+    }
+}
+
+impl RtpCodecDepacker for RtpDepackerAMR {
+    fn push_rtp_slice(&mut self, rtp: &[u8]) -> Result<()>  {
+        let rtp = RtpReader::new(rtp)
+        .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+        self.loss.push(rtp.sequence_number().into());
+
+        if self.params.octet_align {
+            self.push_octet_aligned(rtp.payload(), rtp.timestamp())
+        } else {
+            self.push_bandwidth_efficient(rtp.payload(), rtp.timestamp())
+        }
+    }
+
+    fn pull_frame(&mut self) -> Result<Option<DepackedFrame>>  {
+        Ok(self.frames.pop_front())
+    }
+}
+
+pub fn amr_storage_frame_type(frame: &[u8]) -> u8 {
+    frame.first().map(|x|(x >> 3) & 0x0F).unwrap_or(AMR_FT_NO_DATA)
+}
+
+
+#[test]
+fn test_amr_parameters() {
+    let params = RtpAmrParameters::parse_from_str("octet-align=1; mode-set=0,2,5,7; mode-change-period=2").unwrap();
+    assert!(params.octet_align);
+    assert_eq!(params.mode_set, vec![0, 2, 5, 7]);
+    assert_eq!(params.mode_change_period, Some(2));
+
+    let params = RtpAmrParameters::parse_from_str("crc=1").unwrap();
+    assert!(params.octet_align);
+
+    assert!(RtpDepackerAMR::new(false, None, Some("interleaving=4")).is_err());
+}
+
+#[test]
+fn test_amr_depack_octet_aligned() {
+    let mut depack = RtpDepackerAMR::new(false, Some(1), Some("octet-align=1")).unwrap();
+
+    // CMR=15, two frames of mode 7 (12.2 kbps, 31 bytes) followed by NO_DATA
+    let speech1 = [0x11_u8; 31];
+    let speech2 = [0x22_u8; 31];
+    let mut payload = vec![0xF0, 0x80 | (7 << 3) | 0x04, 0x80 | (7 << 3) | 0x04, (15 << 3) | 0x04];
+    payload.extend_from_slice(&speech1);
+    payload.extend_from_slice(&speech2);
+    depack.push_rtp_slice(&make_rtp_packet(97, false, 1, 0, 0x1234, &payload)).unwrap();
+
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!(frame[0], 0x3C);
+    assert_eq!(&frame[1..], &speech1[..]);
+    assert_eq!(amr_storage_frame_type(&frame), 7);
+
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!(&frame[1..], &speech2[..]);
+    assert_eq!(frame.rtp_ts, 160);
+    assert_eq!(depack.pull_frame().unwrap(), None);
+
+    // truncated
+    assert!(depack.push_rtp_slice(&make_rtp_packet(97, false, 2, 320, 0x1234, &payload[..20])).is_err());
+}
+
+#[test]
+fn test_amr_depack_bandwidth_efficient() {
+    let mut depack = RtpDepackerAMR::new(true, None, None).unwrap();
+
+    // AMR-WB mode 0 (132 bits), CMR=15, TOC F=0 FT=0 Q=1,
+    // speech bits are all ones
+    let mut bits = String::from("1111");
+    bits += "000001";
+    bits += &"1".repeat(132);
+    while bits.len() % 8 != 0 {
+        bits += "0";
+    }
+    let payload: Vec<u8> = bits.as_bytes()
+        .chunks(8)
+        .map(|x| u8::from_str_radix(std::str::from_utf8(x).unwrap(), 2).unwrap())
+        .collect();
+
+    depack.push_rtp_slice(&make_rtp_packet(97, false, 1, 0, 0x1234, &payload)).unwrap();
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!(frame.len(), 1 + 17);
+    assert_eq!(frame[0], 0x04);
+    assert!(frame[1..17].iter().all(|x| *x == 0xFF));
+    // 132 = 16 * 8 + 4
+    assert_eq!(frame[17], 0xF0);
+    assert_eq!(depack.pull_frame().unwrap(), None);
+}
@@ -0,0 +1,344 @@
+// RTP Payload Format For AV1 (https://aomediacodec.github.io/av1-rtp-spec/)
+
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use rtp_rs::RtpReader;
+
+use super::super::depack::{DepackedFrame, RtpCodecDepacker, RtpLossCounter};
+#[cfg(test)]
+use super::super::rtp::make_rtp_packet;
+
+
+pub const AV1_OBU_SEQUENCE_HEADER: u8 = 1;
+pub const AV1_OBU_TEMPORAL_DELIMITER: u8 = 2;
+pub const AV1_OBU_FRAME_HEADER: u8 = 3;
+pub const AV1_OBU_TILE_GROUP: u8 = 4;
+pub const AV1_OBU_METADATA: u8 = 5;
+pub const AV1_OBU_FRAME: u8 = 6;
+pub const AV1_OBU_TILE_LIST: u8 = 8;
+pub const AV1_OBU_PADDING: u8 = 15;
+
+const OBU_EXTENSION_BITMASK: u8 = 0x04;
+const OBU_HAS_SIZE_BITMASK: u8 = 0x02;
+
+// temporal delimiter with obu_has_size_field=1 and obu_size=0
+const TEMPORAL_DELIMITER: &[u8] = &[(AV1_OBU_TEMPORAL_DELIMITER << 3) | OBU_HAS_SIZE_BITMASK, 0];
+
+pub fn av1_obu_type(header0: u8) -> u8 {
+    (header0 >> 3) & 0x0F
+}
+
+//  0 1 2 3 4 5 6 7
+// +-+-+-+-+-+-+-+-+
+// |Z|Y| W |N|-|-|-|
+// +-+-+-+-+-+-+-+-+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Av1AggregationHeader {
+    // first OBU element is the continuation of previous packet
+    pub z: bool,
+
+    // last OBU element continues in next packet
+    pub y: bool,
+
+    // number of OBU elements, 0 means every element has a length field
+    pub w: u8,
+
+    // first packet of a coded video sequence
+    pub n: bool,
+}
+
+impl Av1AggregationHeader {
+    pub fn parse(b: u8) -> Self {
+        Self {
+            z: b & 0x80 != 0,
+            y: b & 0x40 != 0,
+            w: (b >> 4) & 0x03,
+            n: b & 0x08 != 0,
+        }
+    }
+}
+
+pub struct Av1Frame {
+    pub data: Bytes,
+    pub timestamp: u32,
+    pub keyframe: bool,
+
+    // packets lost since the previous temporal unit
+    pub loss: u16,
+}
+
+impl From<Av1Frame> for DepackedFrame {
+    fn from(from: Av1Frame) -> Self {
+        DepackedFrame::new(from.data, from.timestamp)
+        .with_keyframe(from.keyframe)
+        .with_loss(from.loss)
+    }
+}
+
+// output one temporal unit in low overhead bitstream format per rtp timestamp,
+// each unit starts with a temporal delimiter and every OBU has obu_size field
+pub struct RtpDepackerAV1 {
+    obu_buf: Option<BytesMut>,
+    obus: Vec<Bytes>,
+    broken: bool,
+    keyframe: bool,
+    last_ts: Option<u32>,
+    last_seq: Option<u16>,
+    loss: RtpLossCounter,
+    frames: VecDeque<Av1Frame>,
+}
+
+impl Default for RtpDepackerAV1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RtpDepackerAV1 {
+    pub fn new() -> Self {
+        Self {
+            obu_buf: None,
+            obus: Default::default(),
+            broken: false,
+            keyframe: false,
+            last_ts: None,
+            last_seq: None,
+            loss: Default::default(),
+            frames: Default::default(),
+        }
+    }
+
+    pub fn into_box(self) -> Box<dyn RtpCodecDepacker> {
+        Box::new(self)
+    }
+
+    pub fn pull_av1_frame(&mut self) -> Option<Av1Frame> {
+        self.frames.pop_front()
+    }
+
+    fn push_payload(&mut self, header: &Av1AggregationHeader, mut payload: &[u8]) -> Result<()> {
+        let mut index = 0;
+        while !payload.is_empty() {
+            let is_last_counted = header.w > 0 && index + 1 == header.w as usize;
+            let len = if is_last_counted {
+                payload.len()
+            } else {
+                let (len, n) = read_leb128(payload)?;
+                payload = &payload[n..];
+                len as usize
+            };
+
+            if len > payload.len() {
+                bail!("OBU element size [{len}] exceed remains [{}]", payload.len())
+            }
+
+            let element = &payload[..len];
+            payload = &payload[len..];
+            let is_last = payload.is_empty();
+
+            if index == 0 && header.z {
+                // lost the start fragment, discard until next start
+                if let Some(buf) = &mut self.obu_buf {
+                    buf.put(element);
+                }
+            } else {
+                if self.obu_buf.is_some() {
+                    // fragment was not finished
+                    self.broken = true;
+                }
+                self.obu_buf = Some(BytesMut::from(element));
+            }
+
+            if !(is_last && header.y) {
+                if let Some(obu) = self.obu_buf.take() {
+                    if !obu.is_empty() {
+                        self.obus.push(obu.freeze());
+                    }
+                }
+            }
+
+            index += 1;
+            if is_last_counted {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish_temporal_unit(&mut self, timestamp: u32) {
+        if self.obu_buf.take().is_some() {
+            // missing the last fragment
+            self.broken = true;
+        }
+
+        let obus = std::mem::take(&mut self.obus);
+        let broken = std::mem::replace(&mut self.broken, false);
+        let keyframe = std::mem::replace(&mut self.keyframe, false);
+        if obus.is_empty() || broken {
+            return;
+        }
+
+        let len = obus.iter().map(|x|x.len() + 8).sum::<usize>() + TEMPORAL_DELIMITER.len();
+        let mut buf = BytesMut::with_capacity(len);
+        buf.put(TEMPORAL_DELIMITER);
+
+        for obu in obus.iter() {
+            let header0 = obu[0];
+            match av1_obu_type(header0) {
+                AV1_OBU_TEMPORAL_DELIMITER | AV1_OBU_TILE_LIST => continue,
+                _ => {},
+            }
+
+            if header0 & OBU_HAS_SIZE_BITMASK != 0 {
+                buf.put(&obu[..]);
+                continue;
+            }
+
+            let header_len = if header0 & OBU_EXTENSION_BITMASK != 0 { 2 } else { 1 };
+            if obu.len() < header_len {
+                // truncated OBU header
+                return;
+            }
+
+            buf.put_u8(header0 | OBU_HAS_SIZE_BITMASK);
+            buf.put(&obu[1..header_len]);
+            write_leb128(&mut buf, (obu.len() - header_len) as u64);
+            buf.put(&obu[header_len..]);
+        }
+
+        self.frames.push_back(Av1Frame {
+            data: buf.freeze(),
+            timestamp,
+            keyframe,
+            loss: self.loss.take(),
+        });
+    }
+}
+
+impl RtpCodecDepacker for RtpDepackerAV1 {
+    fn push_rtp_slice(&mut self, rtp: &[u8]) -> Result<()>  {
+        let rtp = RtpReader::new(rtp)
+        .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+        let ts = rtp.timestamp();
+        if let Some(last) = self.last_ts {
+            if last != ts {
+                // missing marker of previous temporal unit
+                self.finish_temporal_unit(last);
+            }
+        }
+        self.last_ts = Some(ts);
+
+        let payload = rtp.payload();
+        if payload.is_empty() {
+            bail!("empty av1 payload")
+        }
+        let header = Av1AggregationHeader::parse(payload[0]);
+
+        let seq: u16 = rtp.sequence_number().into();
+        self.loss.push(seq);
+        if let Some(last) = self.last_seq {
+            let first_of_unit = !header.z
+                && self.obus.is_empty()
+                && self.obu_buf.is_none();
+
+            if seq != last.wrapping_add(1) && !first_of_unit {
+                // OBUs of the temporal unit depend on each other, discard whole unit
+                self.broken = true;
+                self.obu_buf = None;
+            }
+        }
+        self.last_seq = Some(seq);
+
+        if header.n {
+            self.keyframe = true;
+        }
+
+        let r = self.push_payload(&header, &payload[1..]);
+        if r.is_err() {
+            self.broken = true;
+            self.obu_buf = None;
+        }
+
+        if rtp.mark() {
+            self.finish_temporal_unit(ts);
+        }
+
+        r
+    }
+
+    fn pull_frame(&mut self) -> Result<Option<DepackedFrame>>  {
+        Ok(self.frames.pop_front().map(|x|x.into()))
+    }
+}
+
+// returns (value, bytes consumed)
+pub fn read_leb128(data: &[u8]) -> Result<(u64, usize)> {
+    let mut value = 0_u64;
+    for (i, b) in data.iter().enumerate().take(8) {
+        value |= ((b & 0x7F) as u64) << (i * 7);
+        if b & 0x80 == 0 {
+            return Ok((value, i + 1))
+        }
+    }
+    bail!("invalid leb128")
+}
+
+pub fn write_leb128(buf: &mut BytesMut, mut value: u64) {
+    loop {
+        let b = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.put_u8(b);
+            break;
+        }
+        buf.put_u8(b | 0x80);
+    }
+}
+
+
+#[test]
+fn test_av1_leb128() {
+    let mut buf = BytesMut::new();
+    write_leb128(&mut buf, 300);
+    assert_eq!(&buf[..], &[0xAC, 0x02]);
+    assert_eq!(read_leb128(&buf).unwrap(), (300, 2));
+    assert_eq!(read_leb128(&[0x05, 0xFF]).unwrap(), (5, 1));
+    assert!(read_leb128(&[0x80]).is_err());
+}
+
+#[test]
+fn test_av1_depack() {
+    let mut depack = RtpDepackerAV1::new();
+
+    // W=2 N=1 Y=1, sequence header and the first fragment of frame OBU
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 1, 0, 0x1234, &[0x68, 0x03, 0x08, 0xAA, 0xBB, 0x30, 0x01, 0x02])).unwrap();
+    assert!(depack.pull_av1_frame().is_none());
+
+    // W=1 Z=1, the last fragment
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 2, 0, 0x1234, &[0x90, 0x03, 0x04])).unwrap();
+    let frame = depack.pull_av1_frame().unwrap();
+    assert!(frame.keyframe);
+    assert_eq!(&frame.data[..], &[
+        0x12, 0x00,
+        0x0A, 0x02, 0xAA, 0xBB,
+        0x32, 0x04, 0x01, 0x02, 0x03, 0x04,
+    ]);
+
+    // W=0, temporal delimiter from sender is dropped, OBU with extension
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 3, 3000, 0x1234, &[0x00, 0x01, 0x10, 0x03, 0x34, 0x20, 0x07])).unwrap();
+    let frame = depack.pull_av1_frame().unwrap();
+    assert!(!frame.keyframe);
+    assert_eq!(&frame.data[..], &[0x12, 0x00, 0x36, 0x20, 0x01, 0x07]);
+
+    // lost the middle fragment, the temporal unit is dropped
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 4, 6000, 0x1234, &[0x50, 0x30, 0x01])).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 6, 6000, 0x1234, &[0x90, 0x03])).unwrap();
+    assert_eq!(depack.pull_frame().unwrap(), None);
+
+    // temporal unit after loss
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 8, 9000, 0x1234, &[0x10, 0x30, 0x05])).unwrap();
+    assert_eq!(depack.pull_frame().unwrap().as_deref(), Some(&[0x12, 0x00, 0x32, 0x01, 0x05][..]));
+}
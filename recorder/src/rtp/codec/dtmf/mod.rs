@@ -0,0 +1,254 @@
+// RFC 4733 RTP Payload for DTMF Digits, Telephony Tones, and Telephony Signals
+
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail, Result};
+use rtp_rs::RtpReader;
+#[cfg(test)]
+use super::super::rtp::make_rtp_packet;
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |     event     |E|R| volume    |          duration             |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TelephoneEvent {
+    pub event: u8,
+    pub end: bool,
+
+    // power level in -dBm0
+    pub volume: u8,
+
+    // in rtp timestamp units since the event began
+    pub duration: u16,
+}
+
+impl TelephoneEvent {
+    pub fn parse(payload: &[u8]) -> Result<Self> {
+        if payload.len() < 4 {
+            bail!("telephone-event payload too short [{}]", payload.len())
+        }
+
+        Ok(Self {
+            event: payload[0],
+            end: payload[1] & 0x80 != 0,
+            volume: payload[1] & 0x3F,
+            duration: u16::from_be_bytes([payload[2], payload[3]]),
+        })
+    }
+}
+
+// RFC 4733 section 3.2, events 0-15
+pub fn dtmf_event_digit(event: u8) -> Option<char> {
+    match event {
+        0..=9 => Some((b'0' + event) as char),
+        10 => Some('*'),
+        11 => Some('#'),
+        12..=15 => Some((b'A' + event - 12) as char),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DtmfEvent {
+    pub event: u8,
+
+    // rtp timestamp of the event start
+    pub timestamp: u32,
+
+    // in rtp timestamp units, final one if end
+    pub duration: u32,
+
+    pub volume: u8,
+    pub end: bool,
+    pub clock_rate: u32,
+}
+
+impl DtmfEvent {
+    pub fn digit(&self) -> Option<char> {
+        dtmf_event_digit(self.event)
+    }
+
+    pub fn duration_ms(&self) -> i64 {
+        self.duration as i64 * 1000 / self.clock_rate.max(1) as i64
+    }
+}
+
+// one begin and one end event are reported for each telephone event,
+// retransmitted end packets are merged,
+// the end is reported when next event starts if all end packets lost
+pub struct RtpDtmfParser {
+    clock_rate: u32,
+    current: Option<DtmfEvent>,
+    events: VecDeque<DtmfEvent>,
+}
+
+impl RtpDtmfParser {
+    pub fn new(clock_rate: u32) -> Self {
+        Self {
+            clock_rate,
+            current: None,
+            events: Default::default(),
+        }
+    }
+
+    pub fn push_rtp_slice(&mut self, rtp: &[u8]) -> Result<()> {
+        let rtp = RtpReader::new(rtp)
+        .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+        let tevent = TelephoneEvent::parse(rtp.payload())?;
+        let ts = rtp.timestamp();
+
+        if let Some(current) = &mut self.current {
+            if current.timestamp == ts && current.event == tevent.event {
+                if current.end {
+                    // retransmitted end packet
+                    return Ok(())
+                }
+
+                current.duration = current.duration.max(tevent.duration as u32);
+                current.volume = tevent.volume;
+                if tevent.end {
+                    current.end = true;
+                    self.events.push_back(*current);
+                }
+                return Ok(())
+            }
+
+            if (ts.wrapping_sub(current.timestamp) as i32) < 0 {
+                // late packet of previous event
+                return Ok(())
+            }
+
+            if !current.end {
+                // lost all end packets
+                current.end = true;
+                self.events.push_back(*current);
+            }
+        }
+
+        let current = DtmfEvent {
+            event: tevent.event,
+            timestamp: ts,
+            duration: tevent.duration as u32,
+            volume: tevent.volume,
+            end: false,
+            clock_rate: self.clock_rate,
+        };
+        self.events.push_back(current);
+
+        if tevent.end {
+            // lost the begin packets
+            self.events.push_back(DtmfEvent { end: true, ..current });
+        }
+
+        self.current = Some(DtmfEvent { end: tevent.end, ..current });
+
+        Ok(())
+    }
+
+    pub fn pull_event(&mut self) -> Option<DtmfEvent> {
+        self.events.pop_front()
+    }
+}
+
+// (row, column) frequencies of the keypad, ITU-T Q.23
+pub fn dtmf_tone_freqs(digit: char) -> Option<(u32, u32)> {
+    const ROWS: [u32; 4] = [697, 770, 852, 941];
+    const COLS: [u32; 4] = [1209, 1336, 1477, 1633];
+    const KEYS: [[char; 4]; 4] = [
+        ['1', '2', '3', 'A'],
+        ['4', '5', '6', 'B'],
+        ['7', '8', '9', 'C'],
+        ['*', '0', '#', 'D'],
+    ];
+
+    for (row, keys) in KEYS.iter().enumerate() {
+        if let Some(col) = keys.iter().position(|x| *x == digit) {
+            return Some((ROWS[row], COLS[col]))
+        }
+    }
+    None
+}
+
+// interleaved i16 samples of the dual tone
+pub fn make_dtmf_tone(digit: char, volume: u8, duration_ms: i64, samplerate: u32, channels: u32) -> Vec<i16> {
+    let num = (duration_ms.max(0) as usize) * samplerate as usize / 1000;
+    make_dtmf_tone_frames(digit, volume, 0, num, samplerate, channels)
+}
+
+// num frames of the tone from frame start, so that a long tone can be rendered piece by piece
+pub fn make_dtmf_tone_frames(digit: char, volume: u8, start: usize, num: usize, samplerate: u32, channels: u32) -> Vec<i16> {
+    let (low, high) = match dtmf_tone_freqs(digit) {
+        Some(v) => v,
+        None => return Vec::new(),
+    };
+
+    // each tone takes half of the power level, 0 dBm0 is about 3 dB below full scale
+    let amp = 32767.0 * 0.707 * 0.5 * 10_f64.powf(-(volume as f64) / 20.0);
+
+    let mut samples = Vec::with_capacity(num * channels as usize);
+    for n in start..start + num {
+        let t = n as f64 / samplerate as f64;
+        let v = amp * ((2.0 * std::f64::consts::PI * low as f64 * t).sin() + (2.0 * std::f64::consts::PI * high as f64 * t).sin());
+        for _ in 0..channels {
+            samples.push(v as i16);
+        }
+    }
+    samples
+}
+
+#[test]
+fn test_dtmf_parse() {
+    let event = TelephoneEvent::parse(&[0x0B, 0x8A, 0x03, 0x20]).unwrap();
+    assert_eq!(event, TelephoneEvent { event: 11, end: true, volume: 10, duration: 800 });
+    assert_eq!(dtmf_event_digit(11), Some('#'));
+    assert_eq!(dtmf_event_digit(13), Some('B'));
+    assert_eq!(dtmf_event_digit(16), None);
+    assert_eq!(dtmf_tone_freqs('0'), Some((941, 1336)));
+    assert_eq!(make_dtmf_tone('5', 10, 20, 8000, 2).len(), 320);
+
+    // rendered piece by piece
+    let mut tone = make_dtmf_tone_frames('5', 10, 0, 60, 8000, 2);
+    tone.extend(make_dtmf_tone_frames('5', 10, 60, 100, 8000, 2));
+    assert_eq!(tone, make_dtmf_tone('5', 10, 20, 8000, 2));
+}
+
+#[test]
+fn test_dtmf_events() {
+    let mut parser = RtpDtmfParser::new(8000);
+
+    // digit 1, begin with marker, then update, 3 end packets
+    parser.push_rtp_slice(&make_rtp_packet(101, true, 1, 1000, 0x1234, &[0x01, 0x0A, 0x00, 0xA0])).unwrap();
+    parser.push_rtp_slice(&make_rtp_packet(101, false, 2, 1000, 0x1234, &[0x01, 0x0A, 0x01, 0x40])).unwrap();
+    parser.push_rtp_slice(&make_rtp_packet(101, false, 3, 1000, 0x1234, &[0x01, 0x8A, 0x03, 0x20])).unwrap();
+    parser.push_rtp_slice(&make_rtp_packet(101, false, 4, 1000, 0x1234, &[0x01, 0x8A, 0x03, 0x20])).unwrap();
+    parser.push_rtp_slice(&make_rtp_packet(101, false, 5, 1000, 0x1234, &[0x01, 0x8A, 0x03, 0x20])).unwrap();
+
+    let begin = parser.pull_event().unwrap();
+    assert!(!begin.end);
+    assert_eq!((begin.digit(), begin.timestamp, begin.volume), (Some('1'), 1000, 10));
+
+    let end = parser.pull_event().unwrap();
+    assert!(end.end);
+    assert_eq!((end.digit(), end.duration, end.duration_ms()), (Some('1'), 800, 100));
+    assert_eq!(parser.pull_event(), None);
+
+    // digit #, end packets lost, finished by the next digit
+    parser.push_rtp_slice(&make_rtp_packet(101, true, 6, 5000, 0x1234, &[0x0B, 0x0A, 0x00, 0xA0])).unwrap();
+    parser.push_rtp_slice(&make_rtp_packet(101, false, 7, 5000, 0x1234, &[0x0B, 0x0A, 0x01, 0x40])).unwrap();
+
+    // digit 2, only one end packet received
+    parser.push_rtp_slice(&make_rtp_packet(101, false, 12, 9000, 0x1234, &[0x02, 0x8A, 0x02, 0x80])).unwrap();
+
+    let events: Vec<(Option<char>, bool, u32)> = std::iter::from_fn(|| parser.pull_event())
+        .map(|x| (x.digit(), x.end, x.duration))
+        .collect();
+    assert_eq!(events, vec![
+        (Some('#'), false, 160),
+        (Some('#'), true, 320),
+        (Some('2'), false, 640),
+        (Some('2'), true, 640),
+    ]);
+}
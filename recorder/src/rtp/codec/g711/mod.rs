@@ -0,0 +1,207 @@
+// ITU-T G.711 PCMU (mu-law) / PCMA (A-law), RFC 3551 section 4.5.14
+//   refer from Sun Microsystems g711.c
+
+use bytes::{Bytes, BytesMut, BufMut};
+
+pub const G711_SAMPLERATE: u32 = 8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum G711Law {
+    // PCMU
+    ULaw,
+
+    // PCMA
+    ALaw,
+}
+
+static ULAW_TABLE: [i16; 256] = make_ulaw_table();
+static ALAW_TABLE: [i16; 256] = make_alaw_table();
+
+const fn make_ulaw_table() -> [i16; 256] {
+    let mut table = [0_i16; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[i] = ulaw_to_linear(i as u8);
+        i += 1;
+    }
+    table
+}
+
+const fn make_alaw_table() -> [i16; 256] {
+    let mut table = [0_i16; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[i] = alaw_to_linear(i as u8);
+        i += 1;
+    }
+    table
+}
+
+const fn ulaw_to_linear(u: u8) -> i16 {
+    let u = !u;
+    let t = ((((u & 0x0F) as i32) << 3) + 0x84) << ((u & 0x70) >> 4);
+    if u & 0x80 != 0 {
+        (0x84 - t) as i16
+    } else {
+        (t - 0x84) as i16
+    }
+}
+
+const fn alaw_to_linear(a: u8) -> i16 {
+    let a = a ^ 0x55;
+    let mut t = ((a & 0x0F) as i32) << 4;
+    let seg = (a & 0x70) >> 4;
+    match seg {
+        0 => t += 8,
+        1 => t += 0x108,
+        _ => t = (t + 0x108) << (seg - 1),
+    }
+    if a & 0x80 != 0 {
+        t as i16
+    } else {
+        -t as i16
+    }
+}
+
+const SEG_UEND: [i32; 8] = [0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF, 0x1FFF];
+const SEG_AEND: [i32; 8] = [0x1F, 0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF];
+
+fn search_seg(value: i32, table: &[i32; 8]) -> usize {
+    table.iter()
+    .position(|x| value <= *x)
+    .unwrap_or(table.len())
+}
+
+pub fn linear_to_ulaw(pcm: i16) -> u8 {
+    const BIAS: i32 = 0x84;
+    const CLIP: i32 = 8159;
+
+    // 14 bits
+    let mut value = (pcm as i32) >> 2;
+    let mask = if value < 0 {
+        value = -value;
+        0x7F
+    } else {
+        0xFF
+    };
+
+    value = value.min(CLIP) + (BIAS >> 2);
+
+    let seg = search_seg(value, &SEG_UEND);
+    if seg >= 8 {
+        return 0x7F ^ mask
+    }
+
+    let u = ((seg as i32) << 4) | ((value >> (seg + 1)) & 0x0F);
+    (u as u8) ^ mask
+}
+
+pub fn linear_to_alaw(pcm: i16) -> u8 {
+    // 13 bits
+    let mut value = (pcm as i32) >> 3;
+    let mask = if value >= 0 {
+        0xD5
+    } else {
+        value = -value - 1;
+        0x55
+    };
+
+    let seg = search_seg(value, &SEG_AEND);
+    if seg >= 8 {
+        return 0x7F ^ mask
+    }
+
+    let shift = if seg < 2 { 1 } else { seg };
+    let a = ((seg as i32) << 4) | ((value >> shift) & 0x0F);
+    (a as u8) ^ mask
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct G711Decoder {
+    table: &'static [i16; 256],
+}
+
+impl G711Decoder {
+    pub fn new(law: G711Law) -> Self {
+        let table = match law {
+            G711Law::ULaw => &ULAW_TABLE,
+            G711Law::ALaw => &ALAW_TABLE,
+        };
+        Self { table }
+    }
+
+    pub fn decode_sample(&self, code: u8) -> i16 {
+        self.table[code as usize]
+    }
+
+    pub fn decode(&self, payload: &[u8], output: &mut Vec<i16>) {
+        output.extend(payload.iter().map(|x|self.table[*x as usize]));
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct G711Encoder {
+    law: G711Law,
+}
+
+impl G711Encoder {
+    pub fn new(law: G711Law) -> Self {
+        Self { law }
+    }
+
+    pub fn encode_sample(&self, pcm: i16) -> u8 {
+        match self.law {
+            G711Law::ULaw => linear_to_ulaw(pcm),
+            G711Law::ALaw => linear_to_alaw(pcm),
+        }
+    }
+
+    pub fn encode(&self, pcm: &[i16]) -> Bytes {
+        let mut buf = BytesMut::with_capacity(pcm.len());
+        for sample in pcm.iter() {
+            buf.put_u8(self.encode_sample(*sample));
+        }
+        buf.freeze()
+    }
+}
+
+
+#[test]
+fn test_g711_decode() {
+    let ulaw = G711Decoder::new(G711Law::ULaw);
+    assert_eq!(ulaw.decode_sample(0xFF), 0);
+    assert_eq!(ulaw.decode_sample(0x7F), 0);
+    assert_eq!(ulaw.decode_sample(0x80), 32124);
+    assert_eq!(ulaw.decode_sample(0x00), -32124);
+
+    let alaw = G711Decoder::new(G711Law::ALaw);
+    assert_eq!(alaw.decode_sample(0xD5), 8);
+    assert_eq!(alaw.decode_sample(0x55), -8);
+    assert_eq!(alaw.decode_sample(0xAA), 32256);
+    assert_eq!(alaw.decode_sample(0x2A), -32256);
+
+    let mut pcm = Vec::new();
+    alaw.decode(&[0xD5, 0x55], &mut pcm);
+    assert_eq!(pcm, vec![8, -8]);
+}
+
+#[test]
+fn test_g711_roundtrip() {
+    for law in [G711Law::ULaw, G711Law::ALaw] {
+        let decoder = G711Decoder::new(law);
+        let encoder = G711Encoder::new(law);
+        for code in 0..=255_u8 {
+            if law == G711Law::ULaw && code == 0x7F {
+                // negative zero
+                continue;
+            }
+            assert_eq!(encoder.encode_sample(decoder.decode_sample(code)), code, "{law:?} {code:#x}");
+        }
+
+        // clipped
+        assert_eq!(decoder.decode_sample(encoder.encode_sample(i16::MAX)), decoder.decode_sample(encoder.encode_sample(32767 - 100)));
+    }
+
+    let encoder = G711Encoder::new(G711Law::ULaw);
+    assert_eq!(&encoder.encode(&[0, -32124])[..], &[0xFF, 0x00]);
+}
@@ -0,0 +1,222 @@
+// differential tests of h264 depack backends,
+// runs the same rtp through retina and webrtc-rs and compares annex-b output frame by frame
+
+use std::collections::HashSet;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::rtp::{depack::DepackedFrame, pack::{pack_frames, RtpPackHeader}};
+use super::{h264_fmtp, H264DepackBackend, RtpDepackerH264, RtpPackerH264, FUA_NALU_TYPE, STAPA_NALU_TYPE};
+
+const SAMPLE_INPUT: &str = "/tmp/sample-data/sample.mp4";
+const MTU: usize = 1200;
+
+// parameter sets of a 320x240 baseline stream
+const SYNTHETIC_SPS: &[u8] = &[0x67, 0x42, 0xC0, 0x15, 0xDA, 0x02, 0x00, 0x96, 0xC0, 0x44, 0x00, 0x00, 0x03, 0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xF0, 0x3C, 0x58, 0xBA, 0x80];
+const SYNTHETIC_PPS: &[u8] = &[0x68, 0xCE, 0x32, 0xC8];
+
+// fmtp and rtp packets of video generated by rtp_mem
+fn load_sample() -> (String, Vec<Bytes>) {
+    use crate::ffeasy::{gen_sdp::gen_av_only_sdp_from_file, rtp_mem::load_rtp_mem_sync};
+    use crate::sdp::sdp::{SdpMain, SdpMedia};
+
+    let (sdp, _octx) = gen_av_only_sdp_from_file(SAMPLE_INPUT).unwrap();
+    let sdp = SdpMain::parse_from_str(&sdp).unwrap();
+    let fmtp = sdp.medias.iter()
+        .filter_map(|x| match x {
+            SdpMedia::Video(v) => v.codecs.values().next(),
+            _ => None,
+        })
+        .next()
+        .and_then(|x| x.fmtps.first().cloned())
+        .unwrap();
+
+    let data = load_rtp_mem_sync(SAMPLE_INPUT.as_ref(), 300).unwrap();
+    let mut reader = data.make_reader();
+    let mut packets = Vec::new();
+    while let Some(packet) = reader.read_next() {
+        if packet.ch_id() == 0 {
+            packets.push(packet.data().clone());
+        }
+    }
+    (fmtp, packets)
+}
+
+// fmtp and rtp packets of made up access units packed by RtpPackerH264,
+// slice data never contains a start code, sizes vary so that
+// parameter sets are aggregated and large slices are fragmented
+fn load_synthetic() -> (String, Vec<Bytes>) {
+    let mut frames = Vec::new();
+    for n in 0..60_usize {
+        let keyframe = n % 30 == 0;
+        let mut data = BytesMut::new();
+        if keyframe {
+            for nalu in [SYNTHETIC_SPS, SYNTHETIC_PPS] {
+                data.put(&[0, 0, 0, 1][..]);
+                data.put(nalu);
+            }
+        }
+
+        let sizes = if keyframe {
+            vec![4000]
+        } else if n % 3 == 0 {
+            vec![3000, 300]
+        } else {
+            vec![100 * (n % 3)]
+        };
+        for size in sizes {
+            data.put(&[0, 0, 0, 1][..]);
+            data.put_u8(if keyframe { 0x65 } else { 0x41 });
+            data.extend((0..size).map(|x| ((x + n) % 255 + 1) as u8));
+        }
+        frames.push(DepackedFrame::new(data.freeze(), n as u32 * 3000).with_keyframe(keyframe));
+    }
+
+    let fmtp = h264_fmtp(1, SYNTHETIC_SPS, &[Bytes::from_static(SYNTHETIC_PPS)]);
+    (fmtp, packetize(&frames, false))
+}
+
+// returns frames and number of errors
+fn run_backend<T: AsRef<[u8]>>(backend: H264DepackBackend, fmtp: &str, packets: &[T]) -> (Vec<DepackedFrame>, usize) {
+    let mut depack = RtpDepackerH264::with_backend(backend, Some(fmtp)).unwrap();
+    let mut frames = Vec::new();
+    let mut errors = 0;
+    for packet in packets {
+        if depack.push_rtp_slice(packet.as_ref()).is_err() {
+            errors += 1;
+        }
+        loop {
+            match depack.pull_frame() {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) => break,
+                Err(_) => {
+                    errors += 1;
+                    break;
+                }
+            }
+        }
+    }
+    (frames, errors)
+}
+
+fn assert_same_frames(expect: &[&DepackedFrame], actual: &[&DepackedFrame]) {
+    assert_eq!(expect.len(), actual.len());
+    for (n, (e, a)) in expect.iter().zip(actual.iter()).enumerate() {
+        assert_eq!((e.rtp_ts, e.keyframe), (a.rtp_ts, a.keyframe), "frame {n}");
+        assert!(e.data == a.data, "frame {n} data differs, {} != {}", e.data.len(), a.data.len());
+    }
+}
+
+fn run_both<T: AsRef<[u8]>>(fmtp: &str, packets: &[T]) -> Vec<DepackedFrame> {
+    let (retina, errors) = run_backend(H264DepackBackend::Retina, fmtp, packets);
+    assert_eq!(errors, 0);
+    let (webrtc, errors) = run_backend(H264DepackBackend::WebrtcRs, fmtp, packets);
+    assert_eq!(errors, 0);
+
+    assert!(!retina.is_empty());
+    assert!(webrtc.iter().all(|x| !x.corrupt && x.loss == 0));
+    assert_same_frames(&retina.iter().collect::<Vec<_>>(), &webrtc.iter().collect::<Vec<_>>());
+    retina
+}
+
+fn packetize(frames: &[DepackedFrame], single_nal: bool) -> Vec<Bytes> {
+    let mut packer = RtpPackerH264::new(RtpPackHeader::new(96, 0x1234).with_seq(0xFF00))
+    .with_packetization_mode(if single_nal { 0 } else { 1 })
+    .with_mtu(MTU);
+    pack_frames(&mut packer, frames.iter().map(|x| (&x.data[..], x.rtp_ts))).unwrap()
+}
+
+fn count_nalu_type<T: AsRef<[u8]>>(packets: &[T], nalu_type: u8) -> usize {
+    packets.iter().filter(|x| x.as_ref()[12] & 0x1F == nalu_type).count()
+}
+
+#[test]
+fn test_h264_differential_recorded() {
+    let (fmtp, packets) = load_sample();
+    let frames = run_both(&fmtp, &packets);
+    assert!(frames[0].keyframe);
+}
+
+#[test]
+fn test_h264_differential_stapa_fua() {
+    let (fmtp, packets) = load_synthetic();
+    let (frames, _errors) = run_backend(H264DepackBackend::Retina, &fmtp, &packets);
+
+    let packets = packetize(&frames, false);
+    assert!(count_nalu_type(&packets, STAPA_NALU_TYPE) > 0);
+    assert!(count_nalu_type(&packets, FUA_NALU_TYPE) > 0);
+
+    let output = run_both(&fmtp, &packets);
+    assert_same_frames(&frames.iter().collect::<Vec<_>>(), &output.iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_h264_differential_mode0() {
+    let (fmtp, packets) = load_synthetic();
+    let (frames, _errors) = run_backend(H264DepackBackend::Retina, &fmtp, &packets);
+
+    let packets = packetize(&frames, true);
+    assert_eq!(count_nalu_type(&packets, STAPA_NALU_TYPE) + count_nalu_type(&packets, FUA_NALU_TYPE), 0);
+
+    let fmtp = fmtp.replace("packetization-mode=1", "packetization-mode=0");
+    let output = run_both(&fmtp, &packets);
+    assert_same_frames(&frames.iter().collect::<Vec<_>>(), &output.iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_h264_differential_missing_fragments() {
+    let (fmtp, packets) = load_synthetic();
+    let (frames, _errors) = run_backend(H264DepackBackend::Retina, &fmtp, &packets);
+    let packets = packetize(&frames, false);
+
+    // drop the start fragment or a middle one of some access units,
+    // always followed by fragments of the same access unit
+    let mut damaged = HashSet::new();
+    let mut fua_units = 0;
+    let mut last_ts = None;
+    let mut lossy = Vec::new();
+    for packet in packets.iter() {
+        let ts = u32::from_be_bytes(packet[4..8].try_into().unwrap());
+        let is_fua = packet[12] & 0x1F == FUA_NALU_TYPE;
+        let is_start = is_fua && packet[13] & 0x80 != 0;
+        let is_end = is_fua && packet[13] & 0x40 != 0;
+
+        if is_start && last_ts != Some(ts) {
+            fua_units += 1;
+            last_ts = Some(ts);
+        }
+
+        let drop = !damaged.contains(&ts) && match fua_units % 5 {
+            1 => is_start && !is_end,
+            3 => is_fua && !is_start && !is_end,
+            _ => false,
+        };
+        if drop {
+            damaged.insert(ts);
+        } else {
+            lossy.push(packet);
+        }
+    }
+    assert!(!damaged.is_empty());
+
+    let expect: Vec<_> = frames.iter().filter(|x| !damaged.contains(&x.rtp_ts)).collect();
+
+    // webrtc-rs outputs damaged access units marked as corrupt
+    let (webrtc, errors) = run_backend(H264DepackBackend::WebrtcRs, &fmtp, &lossy);
+    assert_eq!(errors, 0);
+    for frame in webrtc.iter().filter(|x| damaged.contains(&x.rtp_ts)) {
+        assert!(frame.corrupt && frame.loss > 0, "ts {}", frame.rtp_ts);
+    }
+    let actual: Vec<_> = webrtc.iter().filter(|x| !damaged.contains(&x.rtp_ts)).collect();
+    assert!(actual.iter().all(|x| !x.corrupt));
+    assert_same_frames(&expect, &actual);
+
+    // retina may drop or fail on damaged access units, the rest must be the same
+    let (retina, _errors) = run_backend(H264DepackBackend::Retina, &fmtp, &lossy);
+    let actual: Vec<_> = retina.iter().filter(|x| !damaged.contains(&x.rtp_ts)).collect();
+    assert!(!actual.is_empty());
+    for frame in actual {
+        let same = expect.iter().find(|x| x.rtp_ts == frame.rtp_ts).unwrap();
+        assert!(same.data == frame.data, "ts {}", frame.rtp_ts);
+    }
+}
@@ -1,17 +1,48 @@
-
-use anyhow::Result;
+use anyhow::{bail, Result};
 use std::marker::PhantomData;
 
+use crate::rtp::{depack::RtpCodecDepacker, webrtc_rs::depack_webrtc_rs::RtpDepackerWebrtcRsH264};
+
+// both output one access unit of annex-b per frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum H264DepackBackend {
+    #[default]
+    Retina,
+    WebrtcRs,
+}
+
+impl H264DepackBackend {
+    pub fn parse_from_str(s: &str) -> Result<Self> {
+        match s {
+            "retina" => Ok(Self::Retina),
+            "webrtc-rs" | "webrtc_rs" => Ok(Self::WebrtcRs),
+            _ => bail!("unknown h264 depack backend [{s}]"),
+        }
+    }
+}
+
 pub struct RtpDepackerH264(PhantomData<()>);
 impl RtpDepackerH264 {
     pub fn new(fmtp: Option<&str>) -> Result<super::super::retina::depack::RetinaDepackH264> {
         super::super::retina::depack::make_retina_depack_h264(fmtp)
     }
+
+    pub fn with_backend(backend: H264DepackBackend, fmtp: Option<&str>) -> Result<Box<dyn RtpCodecDepacker>> {
+        match backend {
+            H264DepackBackend::Retina => Ok(Self::new(fmtp)?.into_box()),
+            H264DepackBackend::WebrtcRs => Ok(RtpDepackerWebrtcRsH264::new(fmtp)?.into_box()),
+        }
+    }
 }
 
 mod parameters;
 pub use parameters::*;
 
+mod tracker;
+pub use tracker::*;
 
+mod pack;
+pub use pack::*;
 
-
+#[cfg(test)]
+mod differential;
@@ -0,0 +1,304 @@
+// RFC 6184 section 5.6 - 5.8, packs annex-b access units
+
+use anyhow::{anyhow, bail, Result};
+use base64::Engine;
+use bytes::Bytes;
+
+use crate::rtp::pack::{RtpCodecPacker, RtpPackHeader, RtpPackQueue};
+use super::split_annexb;
+
+pub const STAPA_NALU_TYPE: u8 = 24;
+pub const FUA_NALU_TYPE: u8 = 28;
+
+// payload size limit, rtp header not included
+const DEFAULT_MTU: usize = 1200;
+
+pub struct RtpPackerH264 {
+    queue: RtpPackQueue,
+    packetization_mode: u8,
+    mtu: usize,
+}
+
+impl RtpPackerH264 {
+    pub fn new(header: RtpPackHeader) -> Self {
+        Self {
+            queue: RtpPackQueue::new(header),
+            packetization_mode: 1,
+            mtu: DEFAULT_MTU,
+        }
+    }
+
+    // 0: single nal unit per packet regardless of mtu,
+    // 1: small nal units aggregated by STAP-A and large ones fragmented by FU-A
+    pub fn with_packetization_mode(mut self, mode: u8) -> Self {
+        self.packetization_mode = mode;
+        self
+    }
+
+    pub fn with_mtu(mut self, mtu: usize) -> Self {
+        self.mtu = mtu;
+        self
+    }
+
+    pub fn header(&self) -> &RtpPackHeader {
+        self.queue.header()
+    }
+
+    pub fn into_box(self) -> Box<dyn RtpCodecPacker> {
+        Box::new(self)
+    }
+
+    fn pack_nonint(&self, nalus: &[&[u8]]) -> Vec<Vec<u8>> {
+        let mut payloads = Vec::new();
+        let mut stap: Vec<&[u8]> = Vec::new();
+        for nalu in nalus {
+            if nalu.len() > self.mtu {
+                flush_stapa(&mut stap, &mut payloads);
+
+                let chunks: Vec<_> = nalu[1..].chunks(self.mtu - 2).collect();
+                for (n, chunk) in chunks.iter().enumerate() {
+                    let mut header = nalu[0] & 0x1F;
+                    if n == 0 {
+                        header |= 0x80;
+                    }
+                    if n + 1 == chunks.len() {
+                        header |= 0x40;
+                    }
+                    let mut payload = vec![(nalu[0] & 0xE0) | FUA_NALU_TYPE, header];
+                    payload.extend_from_slice(chunk);
+                    payloads.push(payload);
+                }
+                continue;
+            }
+
+            let stap_len = 1 + stap.iter().map(|x| 2 + x.len()).sum::<usize>() + 2 + nalu.len();
+            if stap_len > self.mtu {
+                flush_stapa(&mut stap, &mut payloads);
+            }
+            stap.push(nalu);
+        }
+        flush_stapa(&mut stap, &mut payloads);
+        payloads
+    }
+}
+
+impl RtpCodecPacker for RtpPackerH264 {
+    fn push_frame(&mut self, frame: &[u8], rtp_ts: u32) -> Result<()>  {
+        let nalus = split_annexb(frame);
+
+        let payloads = match self.packetization_mode {
+            0 => nalus.iter().map(|x| x.to_vec()).collect(),
+            1 => {
+                if self.mtu < 3 {
+                    bail!("too small mtu [{}]", self.mtu)
+                }
+                self.pack_nonint(&nalus)
+            }
+            mode => bail!("unsupported packetization-mode [{mode}]"),
+        };
+
+        // marker on the last packet of the access unit
+        for (n, payload) in payloads.iter().enumerate() {
+            self.queue.push(n + 1 == payloads.len(), rtp_ts, payload);
+        }
+        Ok(())
+    }
+
+    fn pull_rtp(&mut self) -> Option<Bytes>  {
+        self.queue.pull()
+    }
+}
+
+// a single nal unit is sent as is
+fn flush_stapa(stap: &mut Vec<&[u8]>, payloads: &mut Vec<Vec<u8>>) {
+    match stap.len() {
+        0 => {}
+        1 => payloads.push(stap[0].to_vec()),
+        _ => {
+            let nri = stap.iter().map(|x| x[0] & 0x60).max().unwrap_or(0);
+            let mut payload = vec![nri | STAPA_NALU_TYPE];
+            for nalu in stap.iter() {
+                payload.extend_from_slice(&(nalu.len() as u16).to_be_bytes());
+                payload.extend_from_slice(nalu);
+            }
+            payloads.push(payload);
+        }
+    }
+    stap.clear();
+}
+
+
+// avcC of mp4 (ISO/IEC 14496-15 section 5.2.4.1),
+// samples are length prefixed and the parameter sets are out of band
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvcConfig {
+    pub nalu_length_size: usize,
+    pub sps: Vec<Bytes>,
+    pub pps: Vec<Bytes>,
+}
+
+impl AvcConfig {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 6 || data[0] != 1 {
+            bail!("invalid avcC, len [{}]", data.len())
+        }
+
+        let nalu_length_size = (data[4] & 0x03) as usize + 1;
+        let mut pos = 5;
+        let num_sps = (data[5] & 0x1F) as usize;
+        pos += 1;
+        let sps = read_param_sets(data, &mut pos, num_sps)?;
+
+        let num_pps = *data.get(pos).ok_or_else(||anyhow!("invalid avcC, no pps"))? as usize;
+        pos += 1;
+        let pps = read_param_sets(data, &mut pos, num_pps)?;
+
+        Ok(Self {
+            nalu_length_size,
+            sps,
+            pps,
+        })
+    }
+
+    // sps and pps in annex-b
+    pub fn annexb_spspps(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for nalu in self.sps.iter().chain(self.pps.iter()) {
+            data.extend_from_slice(&[0, 0, 0, 1]);
+            data.extend_from_slice(nalu);
+        }
+        data
+    }
+
+    // a sample of mp4 to annex-b access unit,
+    // the parameter sets go in front of idr slices unless the sample has them already
+    pub fn sample_to_annexb(&self, sample: &[u8]) -> Result<Vec<u8>> {
+        let mut nalus = Vec::new();
+        let mut pos = 0;
+        while pos < sample.len() {
+            if pos + self.nalu_length_size > sample.len() {
+                bail!("truncated nal length at [{pos}]")
+            }
+            let len = sample[pos..pos + self.nalu_length_size]
+                .iter()
+                .fold(0_usize, |len, x| (len << 8) | *x as usize);
+            pos += self.nalu_length_size;
+            if len == 0 || pos + len > sample.len() {
+                bail!("invalid nal length [{len}] at [{pos}]")
+            }
+            nalus.push(&sample[pos..pos + len]);
+            pos += len;
+        }
+
+        let has_sps = nalus.iter().any(|x| x[0] & 0x1F == 7);
+        let has_idr = nalus.iter().any(|x| x[0] & 0x1F == 5);
+
+        let mut data = Vec::with_capacity(sample.len() + 64);
+        if has_idr && !has_sps {
+            data.extend_from_slice(&self.annexb_spspps());
+        }
+        for nalu in nalus {
+            data.extend_from_slice(&[0, 0, 0, 1]);
+            data.extend_from_slice(nalu);
+        }
+        Ok(data)
+    }
+}
+
+fn read_param_sets(data: &[u8], pos: &mut usize, num: usize) -> Result<Vec<Bytes>> {
+    let mut nalus = Vec::with_capacity(num);
+    for _ in 0..num {
+        if *pos + 2 > data.len() {
+            bail!("invalid avcC, truncated at [{}]", *pos)
+        }
+        let len = u16::from_be_bytes([data[*pos], data[*pos + 1]]) as usize;
+        *pos += 2;
+        if len == 0 || *pos + len > data.len() {
+            bail!("invalid avcC, parameter set len [{len}]")
+        }
+        nalus.push(Bytes::copy_from_slice(&data[*pos..*pos + len]));
+        *pos += len;
+    }
+    Ok(nalus)
+}
+
+// fmtp of the packer, RFC 6184 section 8.1
+pub fn h264_fmtp(packetization_mode: u8, sps: &[u8], pps: &[Bytes]) -> String {
+    let engine = base64::engine::general_purpose::STANDARD;
+    let mut sprop = engine.encode(sps);
+    for nalu in pps {
+        sprop.push(',');
+        sprop.push_str(&engine.encode(nalu));
+    }
+
+    // profile_idc, constraint flags and level_idc follow the nal header
+    let profile_level_id: String = sps.iter().skip(1).take(3).map(|x| format!("{x:02X}")).collect();
+    format!("packetization-mode={packetization_mode};profile-level-id={profile_level_id};sprop-parameter-sets={sprop}")
+}
+
+
+#[test]
+fn test_h264_pack() {
+    use rtp_rs::RtpReader;
+
+    // sps, pps and an idr slice of 25 bytes
+    let mut frame = vec![0, 0, 0, 1, 0x67, 0x42, 0x00, 0x1F, 0, 0, 0, 1, 0x68, 0xCE, 0x38, 0x80, 0, 0, 1, 0x65];
+    frame.extend(1..=24_u8);
+
+    let mut packer = RtpPackerH264::new(RtpPackHeader::new(96, 0x1234).with_seq(100)).with_mtu(13);
+    packer.push_frame(&frame, 3000).unwrap();
+    let mut packets = Vec::new();
+    while let Some(packet) = packer.pull_rtp() {
+        packets.push(packet);
+    }
+
+    let payloads: Vec<_> = packets.iter().map(|x| RtpReader::new(x).unwrap()).collect();
+    let seqs: Vec<u16> = payloads.iter().map(|x| x.sequence_number().into()).collect();
+    assert_eq!(seqs, [100, 101, 102, 103]);
+    let marks: Vec<_> = payloads.iter().map(|x| x.mark()).collect();
+    assert_eq!(marks, [false, false, false, true]);
+    assert!(payloads.iter().all(|x| x.timestamp() == 3000 && x.ssrc() == 0x1234 && x.payload_type() == 96));
+
+    // sps and pps in one STAP-A, the slice in three FU-A
+    assert_eq!(payloads[0].payload(), &[0x78, 0, 4, 0x67, 0x42, 0x00, 0x1F, 0, 4, 0x68, 0xCE, 0x38, 0x80]);
+    assert_eq!(payloads[1].payload(), &[0x7C, 0x85, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+    assert_eq!(payloads[2].payload(), &[0x7C, 0x05, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22]);
+    assert_eq!(payloads[3].payload(), &[0x7C, 0x45, 23, 24]);
+
+    // single nal unit mode
+    let mut packer = RtpPackerH264::new(RtpPackHeader::new(96, 0x1234)).with_packetization_mode(0).with_mtu(12);
+    packer.push_frame(&frame, 6000).unwrap();
+    let mut nalus = Vec::new();
+    while let Some(packet) = packer.pull_rtp() {
+        let rtp = RtpReader::new(&packet).unwrap();
+        nalus.push((rtp.payload()[0], rtp.payload().len(), rtp.mark()));
+    }
+    assert_eq!(nalus, [(0x67, 4, false), (0x68, 4, false), (0x65, 25, true)]);
+    assert_eq!(packer.header().seq, 3);
+}
+
+
+#[test]
+fn test_avc_config() {
+    let avcc = [
+        1, 0x42, 0x00, 0x1F, 0xFF, 0xE1, 0, 4, 0x67, 0x42, 0x00, 0x1F,
+        1, 0, 4, 0x68, 0xCE, 0x38, 0x80,
+    ];
+    let config = AvcConfig::parse(&avcc).unwrap();
+    assert_eq!(config.nalu_length_size, 4);
+    assert_eq!(config.sps, [Bytes::from_static(&[0x67, 0x42, 0x00, 0x1F])]);
+    assert_eq!(config.pps, [Bytes::from_static(&[0x68, 0xCE, 0x38, 0x80])]);
+    assert!(AvcConfig::parse(&avcc[..10]).is_err());
+
+    // parameter sets in front of the idr slice only
+    let idr = config.sample_to_annexb(&[0, 0, 0, 2, 0x65, 0xAA]).unwrap();
+    assert_eq!(split_annexb(&idr), [&[0x67, 0x42, 0x00, 0x1F][..], &[0x68, 0xCE, 0x38, 0x80], &[0x65, 0xAA]]);
+    let slice = config.sample_to_annexb(&[0, 0, 0, 1, 0x41, 0, 0, 0, 2, 0x06, 0x05]).unwrap();
+    assert_eq!(slice, [0, 0, 0, 1, 0x41, 0, 0, 0, 1, 0x06, 0x05]);
+    assert!(config.sample_to_annexb(&[0, 0, 0, 3, 0x41]).is_err());
+
+    assert_eq!(
+        h264_fmtp(1, &config.sps[0], &config.pps),
+        "packetization-mode=1;profile-level-id=42001F;sprop-parameter-sets=Z0IAHw==,aM44gA==",
+    );
+}
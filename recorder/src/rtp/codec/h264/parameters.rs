@@ -88,7 +88,22 @@ impl RtpH264Parameters {
         Self::parse_sps_and_pps(&sps_nal, &pps_nal, pack_mode)
     }
     
-    fn parse_sps_and_pps(sps_nal: &[u8], pps_nal: &[u8], packetization_mode: u8) -> Result<Self, String> {
+    // sps and pps in annexb, as extradata of ffmpeg decoder and track
+    pub fn annexb_spspps(&self) -> Bytes {
+        let mut spspps = BytesMut::new();
+        if !self.sps_nal.is_empty() {
+            spspps.put(&[0,0,0,1][..]);
+            spspps.put(&self.sps_nal[..]);
+        }
+
+        if !self.pps_nal.is_empty() {
+            spspps.put(&[0,0,0,1][..]);
+            spspps.put(&self.pps_nal[..]);
+        }
+        spspps.freeze()
+    }
+
+    pub fn parse_sps_and_pps(sps_nal: &[u8], pps_nal: &[u8], packetization_mode: u8) -> Result<Self, String> {
         let sps_rbsp = h264_reader::rbsp::decode_nal(sps_nal).map_err(|_| "bad sps")?;
         if sps_rbsp.len() < 5 {
             return Err("bad sps".into());
@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Result};
+
+use super::RtpH264Parameters;
+
+const SPS_NALU_TYPE: u8 = 7;
+const PPS_NALU_TYPE: u8 = 8;
+
+// nalus of annex-b, start codes of 3 or 4 bytes
+pub fn split_annexb(data: &[u8]) -> Vec<&[u8]> {
+    let mut nalus = Vec::new();
+    let mut start = None;
+    let mut pos = 0;
+    while pos + 3 <= data.len() {
+        if data[pos] == 0 && data[pos + 1] == 0 && data[pos + 2] == 1 {
+            if let Some(start) = start {
+                nalus.push(trim_trailing_zeros(&data[start..pos]));
+            }
+            pos += 3;
+            start = Some(pos);
+        } else {
+            pos += 1;
+        }
+    }
+
+    if let Some(start) = start {
+        nalus.push(trim_trailing_zeros(&data[start..]));
+    }
+    nalus.retain(|x| !x.is_empty());
+    nalus
+}
+
+// zero byte of the next 4 bytes start code, a nalu never ends with zero
+fn trim_trailing_zeros(nalu: &[u8]) -> &[u8] {
+    let end = nalu.iter().rposition(|x| *x != 0).map(|x| x + 1).unwrap_or(0);
+    &nalu[..end]
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct H264ParamChange {
+    // pixel dimensions, cropping included
+    pub resolution: bool,
+
+    // profile, constraints and level
+    pub profile: bool,
+}
+
+impl H264ParamChange {
+    // decoder and mp4 track made by old parameters can't go on
+    pub fn needs_reinit(&self) -> bool {
+        self.resolution || self.profile
+    }
+}
+
+// tracks in-band SPS/PPS of annex-b access units
+#[derive(Default)]
+pub struct H264ParamTracker {
+    params: Option<RtpH264Parameters>,
+    packetization_mode: u8,
+}
+
+impl H264ParamTracker {
+    pub fn new(params: Option<RtpH264Parameters>) -> Self {
+        Self {
+            packetization_mode: params.as_ref().map(|x| x.packetization_mode).unwrap_or(1),
+            params,
+        }
+    }
+
+    pub fn params(&self) -> Option<&RtpH264Parameters> {
+        self.params.as_ref()
+    }
+
+    // returns the change if SPS/PPS in the access unit differ from current ones,
+    // everything is changed for the first parameters
+    pub fn push_annexb(&mut self, data: &[u8]) -> Result<Option<H264ParamChange>> {
+        let mut sps = None;
+        let mut pps = None;
+        for nalu in split_annexb(data) {
+            match nalu[0] & 0x1F {
+                SPS_NALU_TYPE => sps = Some(nalu),
+                PPS_NALU_TYPE => pps = Some(nalu),
+                _ => {}
+            }
+        }
+
+        if sps.is_none() && pps.is_none() {
+            return Ok(None)
+        }
+
+        // the other one may be sent in previous access units
+        let sps = match (sps, &self.params) {
+            (Some(v), _) => v,
+            (None, Some(params)) => &params.sps_nal[..],
+            (None, None) => return Ok(None),
+        };
+        let pps = match (pps, &self.params) {
+            (Some(v), _) => v,
+            (None, Some(params)) => &params.pps_nal[..],
+            (None, None) => return Ok(None),
+        };
+
+        if let Some(params) = &self.params {
+            if params.sps_nal == sps && params.pps_nal == pps {
+                return Ok(None)
+            }
+        }
+
+        let params = RtpH264Parameters::parse_sps_and_pps(sps, pps, self.packetization_mode)
+        .map_err(|e| anyhow!("{e}"))?;
+
+        let change = match &self.params {
+            Some(last) => H264ParamChange {
+                resolution: last.generic.pixel_dimensions != params.generic.pixel_dimensions,
+                profile: last.generic.rfc6381_codec != params.generic.rfc6381_codec,
+            },
+            None => H264ParamChange {
+                resolution: true,
+                profile: true,
+            },
+        };
+        self.params = Some(params);
+        Ok(Some(change))
+    }
+}
+
+
+#[test]
+fn test_split_annexb() {
+    let data = [0, 0, 0, 1, 0x67, 1, 0, 0, 1, 0x68, 2, 0, 0, 0, 0, 1, 0x65, 3, 0, 0, 1];
+    let nalus = split_annexb(&data);
+    assert_eq!(nalus, vec![&[0x67, 1][..], &[0x68, 2][..], &[0x65, 3][..]]);
+    assert!(split_annexb(&[0x65, 1]).is_empty());
+}
+
+#[test]
+fn test_h264_param_tracker() {
+    // 512x288 baseline
+    let params = RtpH264Parameters::parse_from_str("packetization-mode=1; sprop-parameter-sets=Z0LAFdoCAJbARAAAAwAEAAADAPA8WLqA,aM4yyA==").unwrap();
+    let mut tracker = H264ParamTracker::new(Some(params.clone()));
+
+    let mut data = vec![0, 0, 0, 1];
+    data.extend_from_slice(&params.sps_nal);
+    data.extend_from_slice(&[0, 0, 0, 1]);
+    data.extend_from_slice(&params.pps_nal);
+    data.extend_from_slice(&[0, 0, 0, 1, 0x65, 0x88]);
+    assert_eq!(tracker.push_annexb(&data).unwrap(), None);
+    assert_eq!(tracker.push_annexb(&[0, 0, 0, 1, 0x41, 0x9A]).unwrap(), None);
+
+    // 640x368 cropped to 640x360, pps of last one
+    let sps = [0x67, 0x42, 0xC0, 0x1E, 0xDA, 0x02, 0x80, 0xBF, 0xE5, 0x40];
+    let mut data = vec![0, 0, 0, 1];
+    data.extend_from_slice(&sps);
+    data.extend_from_slice(&[0, 0, 0, 1, 0x65, 0x88]);
+    let change = tracker.push_annexb(&data).unwrap().unwrap();
+    assert_eq!(change, H264ParamChange { resolution: true, profile: false });
+    assert!(change.needs_reinit());
+    assert_eq!(tracker.params().unwrap().generic.pixel_dimensions, (640, 360));
+    assert_eq!(&tracker.params().unwrap().pps_nal[..], &params.pps_nal[..]);
+
+    // main profile
+    let sps = [0x67, 0x4D, 0xC0, 0x1E, 0xDA, 0x02, 0x80, 0xBF, 0xE5, 0x40];
+    let mut data = vec![0, 0, 0, 1];
+    data.extend_from_slice(&sps);
+    let change = tracker.push_annexb(&data).unwrap().unwrap();
+    assert_eq!(change, H264ParamChange { resolution: false, profile: true });
+    assert_eq!(tracker.params().unwrap().generic.rfc6381_codec, "avc1.4DC01E");
+
+    // the first parameters
+    let mut tracker = H264ParamTracker::new(None);
+    assert_eq!(tracker.push_annexb(&data).unwrap(), None);
+    data.extend_from_slice(&[0, 0, 0, 1]);
+    data.extend_from_slice(&params.pps_nal);
+    assert!(tracker.push_annexb(&data).unwrap().unwrap().needs_reinit());
+}
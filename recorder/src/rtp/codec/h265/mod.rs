@@ -0,0 +1,458 @@
+// RFC 7798 RTP Payload Format for High Efficiency Video Coding (HEVC)
+//   refer from rtp_h265/librtp/payload/rtp-h265-unpack.c
+
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use rtp_rs::RtpReader;
+
+use super::super::depack::{DepackedFrame, RtpCodecDepacker, RtpLossCounter};
+#[cfg(test)]
+use super::super::rtp::make_rtp_packet;
+
+mod parameters;
+pub use parameters::*;
+
+const AP_NALU_TYPE: u8 = 48;
+const FU_NALU_TYPE: u8 = 49;
+const PACI_NALU_TYPE: u8 = 50;
+
+const FU_START_BITMASK: u8 = 0x80;
+const FU_END_BITMASK: u8 = 0x40;
+const FU_TYPE_BITMASK: u8 = 0x3F;
+
+const ANNEXB_NALUSTART_CODE: &[u8] = &[0x00, 0x00, 0x00, 0x01];
+
+// output one Annex-B access unit per frame,
+// VPS/SPS/PPS are injected before IRAP frames if not present in-band
+pub struct RtpDepackerH265 {
+    has_donl: bool,
+    vps: Option<Bytes>,
+    sps: Option<Bytes>,
+    pps: Option<Bytes>,
+    nalus: Vec<Bytes>,
+    fu_buf: Option<BytesMut>,
+
+    // packets of current frame lost
+    broken: bool,
+    last_ts: Option<u32>,
+    last_seq: Option<u16>,
+    loss: RtpLossCounter,
+    frames: VecDeque<DepackedFrame>,
+}
+
+impl RtpDepackerH265 {
+    // parameter sets are expected in-band unless all of sprop-vps/sps/pps are given
+    pub fn new(fmtp: Option<&str>) -> Result<Self> {
+        let fmtp = fmtp.unwrap_or("");
+        let params = if ["sprop-vps", "sprop-sps", "sprop-pps"].iter().all(|x| fmtp.contains(x)) {
+            Some(RtpH265Parameters::parse_from_str(fmtp).map_err(|e|anyhow!("{e}"))?)
+        } else {
+            None
+        };
+
+        let mut me = Self::with_params(params.as_ref());
+        me.has_donl = parse_h265_max_don_diff(fmtp).map_err(|e|anyhow!("{e}"))? > 0;
+        Ok(me)
+    }
+
+    pub fn with_params(params: Option<&RtpH265Parameters>) -> Self {
+        Self {
+            has_donl: params.map(|x|x.max_don_diff > 0).unwrap_or(false),
+            vps: params.map(|x|x.vps_nal.clone()),
+            sps: params.map(|x|x.sps_nal.clone()),
+            pps: params.map(|x|x.pps_nal.clone()),
+            nalus: Default::default(),
+            fu_buf: None,
+            broken: false,
+            last_ts: None,
+            last_seq: None,
+            loss: Default::default(),
+            frames: Default::default(),
+        }
+    }
+
+    pub fn into_box(self) -> Box<dyn RtpCodecDepacker> {
+        Box::new(self)
+    }
+
+    fn push_payload(&mut self, payload: &[u8]) -> Result<()> {
+        if payload.len() < 3 {
+            bail!("short packet")
+        }
+
+        let nalu_type = h265_nal_type(payload[0]);
+
+        match nalu_type {
+            AP_NALU_TYPE => {
+                let mut offset = 2;
+                let mut don_len = if self.has_donl { 2 } else { 0 };
+                while offset < payload.len() {
+                    offset += don_len;
+                    if payload.len() < offset + 2 {
+                        bail!("AP truncated")
+                    }
+                    let nalu_size = ((payload[offset] as usize) << 8) | payload[offset + 1] as usize;
+                    offset += 2;
+
+                    if nalu_size < 2 || payload.len() < offset + nalu_size {
+                        bail!("AP nalu size [{nalu_size}] exceed remains [{}]", payload.len() - offset.min(payload.len()))
+                    }
+
+                    self.nalus.push(Bytes::copy_from_slice(&payload[offset..offset + nalu_size]));
+                    offset += nalu_size;
+
+                    // DOND of following units is 1 byte
+                    don_len = if self.has_donl { 1 } else { 0 };
+                }
+            },
+            FU_NALU_TYPE => {
+                let fu_header = payload[2];
+
+                // DONL is only present in the start fragment
+                let is_start = fu_header & FU_START_BITMASK != 0;
+                let data_offset = if self.has_donl && is_start { 5 } else { 3 };
+                if payload.len() < data_offset {
+                    bail!("FU truncated")
+                }
+
+                if is_start {
+                    let mut buf = BytesMut::with_capacity(2 + payload.len());
+                    buf.put_u8((payload[0] & 0x81) | ((fu_header & FU_TYPE_BITMASK) << 1));
+                    buf.put_u8(payload[1]);
+                    self.fu_buf = Some(buf);
+                }
+
+                match &mut self.fu_buf {
+                    Some(buf) => buf.put(&payload[data_offset..]),
+                    None => {
+                        // lost the start fragment, discard until next start
+                        return Ok(())
+                    },
+                }
+
+                if fu_header & FU_END_BITMASK != 0 {
+                    if let Some(buf) = self.fu_buf.take() {
+                        self.nalus.push(buf.freeze());
+                    }
+                }
+            },
+            PACI_NALU_TYPE => {
+                bail!("unsupported PACI packet")
+            },
+            51..=63 => {
+                bail!("unknown nalu type [{nalu_type}]")
+            },
+            _ => {
+                if self.has_donl {
+                    // strip DONL between payload header and nalu data
+                    if payload.len() < 5 {
+                        bail!("single nalu truncated")
+                    }
+                    let mut buf = BytesMut::with_capacity(payload.len() - 2);
+                    buf.put(&payload[0..2]);
+                    buf.put(&payload[4..]);
+                    self.nalus.push(buf.freeze());
+                } else {
+                    self.nalus.push(Bytes::copy_from_slice(payload));
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn finish_frame(&mut self, timestamp: u32) {
+        self.fu_buf = None;
+        let corrupt = std::mem::replace(&mut self.broken, false);
+
+        if self.nalus.is_empty() {
+            return;
+        }
+
+        let mut has_vps = false;
+        let mut has_sps = false;
+        let mut has_pps = false;
+        let mut is_irap = false;
+        let mut new_parameters = false;
+
+        for nalu in self.nalus.iter() {
+            match h265_nal_type(nalu[0]) {
+                H265_NAL_VPS => {
+                    has_vps = true;
+                    new_parameters |= self.vps.as_ref() != Some(nalu);
+                    self.vps = Some(nalu.clone());
+                },
+                H265_NAL_SPS => {
+                    has_sps = true;
+                    new_parameters |= self.sps.as_ref() != Some(nalu);
+                    self.sps = Some(nalu.clone());
+                },
+                H265_NAL_PPS => {
+                    has_pps = true;
+                    new_parameters |= self.pps.as_ref() != Some(nalu);
+                    self.pps = Some(nalu.clone());
+                },
+                t if h265_is_irap(t) => is_irap = true,
+                _ => {},
+            }
+        }
+
+        let mut inject = Vec::new();
+        if is_irap {
+            if !has_vps {
+                inject.extend(self.vps.clone());
+            }
+            if !has_sps {
+                inject.extend(self.sps.clone());
+            }
+            if !has_pps {
+                inject.extend(self.pps.clone());
+            }
+        }
+
+        // AUD must be the first unit of access unit
+        let insert_at = match self.nalus.first() {
+            Some(first) if h265_nal_type(first[0]) == H265_NAL_AUD => 1,
+            _ => 0,
+        };
+
+        let nalus = std::mem::take(&mut self.nalus);
+        let len = nalus.iter().chain(inject.iter()).map(|x|x.len() + 4).sum();
+        let mut frame = BytesMut::with_capacity(len);
+
+        for (index, nalu) in nalus.iter().enumerate() {
+            if index == insert_at {
+                for ps in inject.iter() {
+                    frame.put(ANNEXB_NALUSTART_CODE);
+                    frame.put(&ps[..]);
+                }
+            }
+            frame.put(ANNEXB_NALUSTART_CODE);
+            frame.put(&nalu[..]);
+        }
+
+        self.frames.push_back(DepackedFrame {
+            data: frame.freeze(),
+            rtp_ts: timestamp,
+            keyframe: is_irap,
+            loss: self.loss.take(),
+            corrupt,
+            new_parameters,
+            fec: None,
+        });
+    }
+}
+
+impl RtpCodecDepacker for RtpDepackerH265 {
+    fn push_rtp_slice(&mut self, rtp: &[u8]) -> Result<()>  {
+        let rtp = RtpReader::new(rtp)
+        .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+        let seq: u16 = rtp.sequence_number().into();
+        let lost = self.loss.push(seq);
+        if let Some(last) = self.last_seq {
+            if seq != last.wrapping_add(1) {
+                // discard incomplete fragments
+                self.fu_buf = None;
+            }
+        }
+        self.last_seq = Some(seq);
+
+        let ts = rtp.timestamp();
+        if let Some(last_ts) = self.last_ts {
+            if last_ts != ts {
+                // missing marker of previous frame, the lost may be its tail
+                self.broken |= lost > 0;
+                self.finish_frame(last_ts);
+            }
+        }
+        self.last_ts = Some(ts);
+
+        // the lost may be the head of this frame
+        self.broken |= lost > 0;
+
+        let r = self.push_payload(rtp.payload());
+
+        if rtp.mark() {
+            self.finish_frame(ts);
+        }
+
+        r
+    }
+
+    fn pull_frame(&mut self) -> Result<Option<DepackedFrame>>  {
+        Ok(self.frames.pop_front())
+    }
+}
+
+
+#[test]
+fn test_h265_depack_single_and_ap() {
+    let mut depack = RtpDepackerH265::new(None).unwrap();
+
+    // AP with VPS, SPS, PPS
+    let mut ap = vec![AP_NALU_TYPE << 1, 1];
+    for nalu in [&[0x40, 0x01, 0xAA][..], &[0x42, 0x01, 0xBB], &[0x44, 0x01, 0xCC]] {
+        ap.extend_from_slice(&(nalu.len() as u16).to_be_bytes());
+        ap.extend_from_slice(nalu);
+    }
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 1, 0, 0x1234, &ap)).unwrap();
+    assert_eq!(depack.pull_frame().unwrap(), None);
+
+    // IDR_W_RADL
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 2, 0, 0x1234, &[19 << 1, 1, 0xDD])).unwrap();
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!(&frame[..], &[
+        0, 0, 0, 1, 0x40, 0x01, 0xAA,
+        0, 0, 0, 1, 0x42, 0x01, 0xBB,
+        0, 0, 0, 1, 0x44, 0x01, 0xCC,
+        0, 0, 0, 1, 19 << 1, 1, 0xDD,
+    ]);
+    assert!(frame.keyframe && frame.new_parameters);
+
+    // next IDR without parameter sets, injected from previous in-band
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 3, 3000, 0x1234, &[19 << 1, 1, 0xEE])).unwrap();
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!(frame.len(), 28);
+    assert_eq!(&frame[25..], &[19 << 1, 1, 0xEE]);
+    assert_eq!((frame.rtp_ts, frame.keyframe, frame.new_parameters), (3000, true, false));
+
+    // TRAIL_R, no injection
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 4, 6000, 0x1234, &[1 << 1, 1, 0xFF])).unwrap();
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!(&frame[..], &[0, 0, 0, 1, 1 << 1, 1, 0xFF]);
+    assert!(!frame.keyframe);
+
+    // lost the first packet of the frame
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 6, 9000, 0x1234, &[1 << 1, 1, 0x11])).unwrap();
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!((frame.loss, frame.corrupt), (1, true));
+}
+
+#[test]
+fn test_h265_depack_fu() {
+    let mut depack = RtpDepackerH265::new(None).unwrap();
+
+    let header = [FU_NALU_TYPE << 1, 1];
+    let nal_type = 1_u8;
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 10, 0, 0x1234, &[header[0], header[1], FU_START_BITMASK | nal_type, 1, 2])).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 11, 0, 0x1234, &[header[0], header[1], nal_type, 3, 4])).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 12, 0, 0x1234, &[header[0], header[1], FU_END_BITMASK | nal_type, 5])).unwrap();
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!(&frame[..], &[0, 0, 0, 1, nal_type << 1, 1, 1, 2, 3, 4, 5]);
+
+    // lost middle fragment, the nalu is dropped
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 13, 3000, 0x1234, &[header[0], header[1], FU_START_BITMASK | nal_type, 1, 2])).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 15, 3000, 0x1234, &[header[0], header[1], FU_END_BITMASK | nal_type, 5])).unwrap();
+    assert_eq!(depack.pull_frame().unwrap(), None);
+}
+
+#[test]
+fn test_h265_depack_donl() {
+    let fmtp = "sprop-max-don-diff=2; sprop-vps=QAEMAf//AWAAAAMAkAAAAwAAAwB4lZgJ; sprop-sps=QgEBAWAAAAMAkAAAAwAAAwB4oAPAgBDlllZqvK4BAAADAAEAAAMAGQg=; sprop-pps=RAHBcrRiQA==";
+    let mut depack = RtpDepackerH265::new(Some(fmtp)).unwrap();
+
+    // FU with DONL, CRA
+    let nal_type = 21_u8;
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 1, 0, 0x1234, &[FU_NALU_TYPE << 1, 1, FU_START_BITMASK | nal_type, 0, 7, 1, 2])).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 2, 0, 0x1234, &[FU_NALU_TYPE << 1, 1, FU_END_BITMASK | nal_type, 3])).unwrap();
+    let frame = depack.pull_frame().unwrap().unwrap();
+
+    // parameter sets from fmtp injected before CRA
+    let params = RtpH265Parameters::parse_from_str(fmtp).unwrap();
+    let ps_len = params.generic.extra_data.len();
+    assert_eq!(&frame[..ps_len], &params.generic.extra_data[..]);
+    assert_eq!(&frame[ps_len..], &[0, 0, 0, 1, nal_type << 1, 1, 1, 2, 3]);
+
+    // single nalu with DONL, TRAIL_R
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 3, 3000, 0x1234, &[1 << 1, 1, 0, 9, 0xAB, 0xCD])).unwrap();
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!(&frame[..], &[0, 0, 0, 1, 1 << 1, 1, 0xAB, 0xCD]);
+
+    // parameter sets in-band, AP with DONL and DOND
+    let mut depack = RtpDepackerH265::new(Some("sprop-max-don-diff=2")).unwrap();
+    let mut ap = vec![AP_NALU_TYPE << 1, 1, 0, 1];
+    for (n, nalu) in [&[0x40, 0x01, 0xAA][..], &[0x42, 0x01, 0xBB], &[0x44, 0x01, 0xCC]].into_iter().enumerate() {
+        if n > 0 {
+            ap.push(0);
+        }
+        ap.extend_from_slice(&(nalu.len() as u16).to_be_bytes());
+        ap.extend_from_slice(nalu);
+    }
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 1, 0, 0x1234, &ap)).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 2, 0, 0x1234, &[19 << 1, 1, 0, 2, 0xDD])).unwrap();
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!(&frame[..], &[
+        0, 0, 0, 1, 0x40, 0x01, 0xAA,
+        0, 0, 0, 1, 0x42, 0x01, 0xBB,
+        0, 0, 0, 1, 0x44, 0x01, 0xCC,
+        0, 0, 0, 1, 19 << 1, 1, 0xDD,
+    ]);
+    assert!(frame.keyframe && frame.new_parameters);
+}
+
+// conformance with ffmpeg's rtp packetizer, see rtp_h265/README.md
+#[test]
+fn test_h265_depack_sample() {
+    use crate::ffeasy::{gen_sdp::gen_av_only_sdp_from_file, rtp_mem::load_rtp_mem_sync, video::{make_video_decoder, video_decoder_receive_frame}};
+    use crate::sdp::sdp::{SdpMain, SdpMedia};
+    use ffmpeg_next as ff;
+
+    let input = "/tmp/sample-data/sampleh265.mp4";
+    let max_frames = 300;
+
+    let (sdp, _octx) = gen_av_only_sdp_from_file(input).unwrap();
+    let sdp = SdpMain::parse_from_str(&sdp).unwrap();
+    let codec = sdp.medias.iter()
+        .filter_map(|x| match x {
+            SdpMedia::Video(v) => v.codecs.values().next(),
+            _ => None,
+        })
+        .next()
+        .unwrap();
+    let fmtp = codec.fmtps.get(0).map(|x|x.as_str());
+    let params = RtpH265Parameters::parse_from_str(fmtp.unwrap()).unwrap();
+    println!("{params:?}");
+
+    let mut depack = RtpDepackerH265::new(fmtp).unwrap();
+    let mut decoder = make_video_decoder(
+        ff::codec::Id::HEVC,
+        params.generic.pixel_dimensions.0 as i32,
+        params.generic.pixel_dimensions.1 as i32,
+        &params.generic.extra_data,
+        ff::Rational::new(1, 90000),
+    ).unwrap();
+
+    let data = load_rtp_mem_sync(input.as_ref(), max_frames).unwrap();
+    let mut reader = data.make_reader();
+
+    let mut timestamps = std::collections::HashSet::new();
+    let mut num_frames = 0;
+    let mut num_decoded = 0;
+    while let Some(packet) = reader.read_next() {
+        if packet.ch_id() != 0 {
+            continue;
+        }
+        let rtp = RtpReader::new(packet.data()).unwrap();
+        timestamps.insert(rtp.timestamp());
+
+        depack.push_rtp_slice(packet.data()).unwrap();
+        while let Some(frame) = depack.pull_frame().unwrap() {
+            if num_frames == 0 {
+                assert_eq!(h265_nal_type(frame[4]), H265_NAL_VPS);
+            }
+            num_frames += 1;
+
+            decoder.send_packet(&ff::Packet::copy(&frame[..])).unwrap();
+            while let Some(decoded) = video_decoder_receive_frame(&mut decoder).unwrap() {
+                assert_eq!((decoded.width(), decoded.height()), params.generic.pixel_dimensions);
+                num_decoded += 1;
+            }
+        }
+    }
+
+    println!("rtp timestamps {}, depacked frames {num_frames}, decoded {num_decoded}", timestamps.len());
+    assert_eq!(num_frames, timestamps.len());
+    assert!(num_decoded > 0);
+}
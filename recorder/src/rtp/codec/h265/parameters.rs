@@ -0,0 +1,372 @@
+
+
+use base64::Engine;
+use bytes::{BufMut, Bytes, BytesMut};
+
+use super::super::h264::{LimitedHex, VideoParameters};
+
+pub const H265_NAL_VPS: u8 = 32;
+pub const H265_NAL_SPS: u8 = 33;
+pub const H265_NAL_PPS: u8 = 34;
+pub const H265_NAL_AUD: u8 = 35;
+
+pub fn h265_nal_type(header0: u8) -> u8 {
+    (header0 >> 1) & 0x3F
+}
+
+// BLA/IDR/CRA and reserved IRAP types, H.265 Table 7-1
+pub fn h265_is_irap(nal_type: u8) -> bool {
+    (16..=23).contains(&nal_type)
+}
+
+#[derive(Clone)]
+pub struct RtpH265Parameters {
+    pub generic: VideoParameters,
+
+    pub vps_nal: Bytes,
+
+    pub sps_nal: Bytes,
+
+    pub pps_nal: Bytes,
+
+    // DONL/DOND fields present when sprop-max-don-diff > 0
+    pub max_don_diff: u32,
+}
+
+impl std::fmt::Debug for RtpH265Parameters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("H265Parameters")
+            .field("generic", &self.generic)
+            .field(
+                "vps",
+                &LimitedHex::new(&self.vps_nal, 16),
+            )
+            .field(
+                "sps",
+                &LimitedHex::new(&self.sps_nal, 16),
+            )
+            .field(
+                "pps",
+                &LimitedHex::new(&self.pps_nal, 16),
+            )
+            .field("max_don_diff", &self.max_don_diff)
+            .finish()
+    }
+}
+
+impl RtpH265Parameters {
+
+    pub fn parse_from_str(fmtp: &str) -> Result<Self, String> {
+        let mut vps = None;
+        let mut sps = None;
+        let mut pps = None;
+        let max_don_diff = parse_h265_max_don_diff(fmtp)?;
+
+        for p in fmtp.split(';') {
+            match p.trim().split_once('=') {
+                Some(("sprop-vps", value)) => vps = Some(decode_sprop(value)?),
+                Some(("sprop-sps", value)) => sps = Some(decode_sprop(value)?),
+                Some(("sprop-pps", value)) => pps = Some(decode_sprop(value)?),
+                None if !p.trim().is_empty() => return Err("key without value".into()),
+                _ => (),
+            }
+        }
+
+        let vps_nal = vps.ok_or_else(|| "no sprop-vps".to_string())?;
+        let sps_nal = sps.ok_or_else(|| "no sprop-sps".to_string())?;
+        let pps_nal = pps.ok_or_else(|| "no sprop-pps".to_string())?;
+        Self::parse_parameter_sets(&vps_nal, &sps_nal, &pps_nal, max_don_diff)
+    }
+
+    pub fn parse_parameter_sets(vps_nal: &[u8], sps_nal: &[u8], pps_nal: &[u8], max_don_diff: u32) -> Result<Self, String> {
+        check_nal_type(vps_nal, H265_NAL_VPS)?;
+        check_nal_type(sps_nal, H265_NAL_SPS)?;
+        check_nal_type(pps_nal, H265_NAL_PPS)?;
+
+        let sps = H265Sps::parse(sps_nal)?;
+
+        // Annex-B VPS/SPS/PPS, ffmpeg's hevc decoder and mov muxer both accept it as extradata
+        let mut extra_data = BytesMut::with_capacity(12 + vps_nal.len() + sps_nal.len() + pps_nal.len());
+        for nal in [vps_nal, sps_nal, pps_nal] {
+            extra_data.put(&[0, 0, 0, 1][..]);
+            extra_data.put(nal);
+        }
+        let extra_data = extra_data.freeze();
+        let vps_start = 4;
+        let sps_start = vps_start + vps_nal.len() + 4;
+        let pps_start = sps_start + sps_nal.len() + 4;
+
+        Ok(Self {
+            generic: VideoParameters {
+                rfc6381_codec: sps.rfc6381_codec(),
+                pixel_dimensions: sps.pixel_dimensions(),
+                pixel_aspect_ratio: None,
+                frame_rate: None,
+                extra_data: extra_data.clone(),
+            },
+            vps_nal: extra_data.slice(vps_start..vps_start + vps_nal.len()),
+            sps_nal: extra_data.slice(sps_start..sps_start + sps_nal.len()),
+            pps_nal: extra_data.slice(pps_start..pps_start + pps_nal.len()),
+            max_don_diff,
+        })
+    }
+}
+
+// DONL/DOND fields are present if greater than 0,
+// signaled whether or not parameter sets are given out of band
+pub fn parse_h265_max_don_diff(fmtp: &str) -> Result<u32, String> {
+    let value = fmtp.split(';').find_map(|p| match p.trim().split_once('=') {
+        Some(("sprop-max-don-diff", value)) => Some(value),
+        _ => None,
+    });
+    match value {
+        Some(value) => value.trim().parse().map_err(|_e|"invalid sprop-max-don-diff".to_string()),
+        None => Ok(0),
+    }
+}
+
+fn decode_sprop(value: &str) -> Result<Vec<u8>, String> {
+    // only the first one is used if there are multiple
+    let value = value.split(',').next().unwrap_or("").trim();
+    let nal = base64::engine::general_purpose::STANDARD
+        .decode(value)
+        .map_err(|_| "bad sprop: NAL has invalid base64 encoding".to_string())?;
+    if nal.len() < 2 {
+        return Err("bad sprop: empty NAL".into());
+    }
+    Ok(nal)
+}
+
+fn check_nal_type(nal: &[u8], expect: u8) -> Result<(), String> {
+    match nal.first() {
+        Some(b0) if h265_nal_type(*b0) == expect => Ok(()),
+        Some(b0) => Err(format!("expect nal type {expect} but {}", h265_nal_type(*b0))),
+        None => Err("empty nal".into()),
+    }
+}
+
+
+// fields of H.265 SPS (7.3.2.2) needed by recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct H265Sps {
+    pub profile_space: u8,
+    pub tier_flag: u8,
+    pub profile_idc: u8,
+    pub profile_compatibility_flags: u32,
+    pub constraint_flags: [u8; 6],
+    pub level_idc: u8,
+    pub chroma_format_idc: u32,
+    pub width: u32,
+    pub height: u32,
+    pub conf_win: Option<(u32, u32, u32, u32)>,
+}
+
+impl H265Sps {
+    pub fn parse(nal: &[u8]) -> Result<Self, String> {
+        if nal.len() < 3 {
+            return Err("bad sps".into());
+        }
+
+        // skip 2 bytes nal header
+        let rbsp = decode_rbsp(&nal[2..]);
+        let mut r = BitReader::new(&rbsp);
+
+        let _vps_id = r.read_bits(4)?;
+        let max_sub_layers_minus1 = r.read_bits(3)? as usize;
+        let _temporal_id_nesting = r.read_bits(1)?;
+
+        // profile_tier_level(1, max_sub_layers_minus1)
+        let profile_space = r.read_bits(2)? as u8;
+        let tier_flag = r.read_bits(1)? as u8;
+        let profile_idc = r.read_bits(5)? as u8;
+        let profile_compatibility_flags = r.read_bits(32)?;
+        let mut constraint_flags = [0_u8; 6];
+        for v in constraint_flags.iter_mut() {
+            *v = r.read_bits(8)? as u8;
+        }
+        let level_idc = r.read_bits(8)? as u8;
+
+        let mut sub_layer_flags = [(false, false); 8];
+        for flags in sub_layer_flags.iter_mut().take(max_sub_layers_minus1) {
+            flags.0 = r.read_bits(1)? != 0;
+            flags.1 = r.read_bits(1)? != 0;
+        }
+        if max_sub_layers_minus1 > 0 {
+            for _ in max_sub_layers_minus1..8 {
+                r.skip_bits(2)?;
+            }
+        }
+        for (profile_present, level_present) in sub_layer_flags.iter().take(max_sub_layers_minus1) {
+            if *profile_present {
+                r.skip_bits(88)?;
+            }
+            if *level_present {
+                r.skip_bits(8)?;
+            }
+        }
+
+        let _sps_id = r.read_ue()?;
+        let chroma_format_idc = r.read_ue()?;
+        if chroma_format_idc == 3 {
+            let _separate_colour_plane = r.read_bits(1)?;
+        }
+        let width = r.read_ue()?;
+        let height = r.read_ue()?;
+        let conf_win = if r.read_bits(1)? != 0 {
+            Some((r.read_ue()?, r.read_ue()?, r.read_ue()?, r.read_ue()?))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            profile_space,
+            tier_flag,
+            profile_idc,
+            profile_compatibility_flags,
+            constraint_flags,
+            level_idc,
+            chroma_format_idc,
+            width,
+            height,
+            conf_win,
+        })
+    }
+
+    // cropped size
+    pub fn pixel_dimensions(&self) -> (u32, u32) {
+        let (sub_width, sub_height) = match self.chroma_format_idc {
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+
+        match self.conf_win {
+            Some((left, right, top, bottom)) => (
+                self.width.saturating_sub(sub_width * (left + right)),
+                self.height.saturating_sub(sub_height * (top + bottom)),
+            ),
+            None => (self.width, self.height),
+        }
+    }
+
+    // ISO/IEC 14496-15 E.3
+    pub fn rfc6381_codec(&self) -> String {
+        let space = match self.profile_space {
+            1 => "A",
+            2 => "B",
+            3 => "C",
+            _ => "",
+        };
+        let tier = if self.tier_flag != 0 { 'H' } else { 'L' };
+
+        let mut codec = format!(
+            "hvc1.{space}{}.{:X}.{tier}{}",
+            self.profile_idc,
+            self.profile_compatibility_flags.reverse_bits(),
+            self.level_idc,
+        );
+
+        let num = self.constraint_flags.iter().rposition(|x| *x != 0).map(|x| x + 1).unwrap_or(0);
+        for v in &self.constraint_flags[..num] {
+            codec.push_str(&format!(".{v:X}"));
+        }
+        codec
+    }
+}
+
+// remove emulation prevention bytes
+pub fn decode_rbsp(data: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &b in data {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+
+        if b == 0 {
+            zeros += 1;
+        } else {
+            zeros = 0;
+        }
+        rbsp.push(b);
+    }
+    rbsp
+}
+
+#[derive(Clone)]
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn remaining_bits(&self) -> usize {
+        self.data.len() * 8 - self.pos
+    }
+
+    pub fn read_bits(&mut self, num: usize) -> Result<u32, String> {
+        if num > 32 || num > self.remaining_bits() {
+            return Err(format!("read {num} bits but remains {}", self.remaining_bits()));
+        }
+
+        let mut value = 0_u64;
+        for _ in 0..num {
+            let byte = self.data[self.pos / 8];
+            let bit = (byte >> (7 - (self.pos % 8))) & 1;
+            value = (value << 1) | bit as u64;
+            self.pos += 1;
+        }
+        Ok(value as u32)
+    }
+
+    pub fn skip_bits(&mut self, num: usize) -> Result<(), String> {
+        if num > self.remaining_bits() {
+            return Err(format!("skip {num} bits but remains {}", self.remaining_bits()));
+        }
+        self.pos += num;
+        Ok(())
+    }
+
+    pub fn read_ue(&mut self) -> Result<u32, String> {
+        let mut zeros = 0;
+        while self.read_bits(1)? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                return Err("invalid exp-golomb".into());
+            }
+        }
+        let value = self.read_bits(zeros)?;
+        Ok(((1_u64 << zeros) - 1 + value as u64) as u32)
+    }
+}
+
+
+#[test]
+fn test_h265_parameters() {
+    // from ffmpeg -f rtp output of a 1920x1080 main profile sample
+    let fmtp = "sprop-vps=QAEMAf//AWAAAAMAkAAAAwAAAwB4lZgJ; sprop-sps=QgEBAWAAAAMAkAAAAwAAAwB4oAPAgBDlllZqvK4BAAADAAEAAAMAGQg=; sprop-pps=RAHBcrRiQA==";
+    let params = RtpH265Parameters::parse_from_str(fmtp).unwrap();
+    assert_eq!(params.generic.pixel_dimensions, (1920, 1080));
+    assert_eq!(params.generic.rfc6381_codec, "hvc1.1.6.L120.90");
+    assert_eq!(params.max_don_diff, 0);
+    assert_eq!(h265_nal_type(params.vps_nal[0]), H265_NAL_VPS);
+    assert_eq!(h265_nal_type(params.sps_nal[0]), H265_NAL_SPS);
+    assert_eq!(h265_nal_type(params.pps_nal[0]), H265_NAL_PPS);
+    assert_eq!(&params.generic.extra_data[..4], &[0, 0, 0, 1]);
+}
+
+#[test]
+fn test_h265_bit_reader() {
+    let data = [0b1010_0110, 0b0100_0000];
+    let mut r = BitReader::new(&data);
+    assert_eq!(r.read_ue(), Ok(0));
+    assert_eq!(r.read_ue(), Ok(1));
+    assert_eq!(r.read_ue(), Ok(2));
+    assert_eq!(r.read_ue(), Ok(3));
+    assert_eq!(decode_rbsp(&[0, 0, 3, 1, 0, 0, 3]), vec![0, 0, 1, 0, 0]);
+}
@@ -0,0 +1,241 @@
+// RFC 6416 RTP Payload Format for MPEG-4 Audio/Visual Streams, MP4A-LATM
+
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{BytesMut, BufMut};
+use rtp_rs::RtpReader;
+
+use super::super::depack::{DepackedFrame, RtpCodecDepacker, RtpLossCounter};
+use super::h265::BitReader;
+#[cfg(test)]
+use super::super::rtp::make_rtp_packet;
+
+mod parameters;
+pub use parameters::*;
+
+// an AudioMuxElement may be fragmented into several packets with the same timestamp,
+// the marker bit is set on the last one.
+// output raw AAC access units, same as mpeg4-generic depacker
+const AAC_FRAME_SAMPLES: u32 = 1024;
+
+pub struct RtpDepackerLATM {
+    params: RtpLatmParameters,
+    config: Option<StreamMuxConfig>,
+    buf: BytesMut,
+    broken: bool,
+    last_ts: Option<u32>,
+    last_seq: Option<u16>,
+    loss: RtpLossCounter,
+    frames: VecDeque<DepackedFrame>,
+}
+
+impl RtpDepackerLATM {
+    pub fn new(fmtp: Option<&str>) -> Result<Self> {
+        let params = match fmtp {
+            Some(fmtp) => RtpLatmParameters::parse_from_str(fmtp).map_err(|e|anyhow!("{e}"))?,
+            None => Default::default(),
+        };
+
+        let config = params.stream_mux_config().transpose()?;
+        if !params.cpresent && config.is_none() {
+            bail!("latm config is required when cpresent=0")
+        }
+
+        Ok(Self {
+            params,
+            config,
+            buf: Default::default(),
+            broken: false,
+            last_ts: None,
+            last_seq: None,
+            loss: Default::default(),
+            frames: Default::default(),
+        })
+    }
+
+    pub fn into_box(self) -> Box<dyn RtpCodecDepacker> {
+        Box::new(self)
+    }
+
+    pub fn params(&self) -> &RtpLatmParameters {
+        &self.params
+    }
+
+    // None until the in band StreamMuxConfig arrived if cpresent=1
+    pub fn config(&self) -> Option<&StreamMuxConfig> {
+        self.config.as_ref()
+    }
+
+    fn finish_mux_element(&mut self, ts: u32) -> Result<()> {
+        let data = std::mem::take(&mut self.buf);
+        if std::mem::replace(&mut self.broken, false) || data.is_empty() {
+            return Ok(())
+        }
+
+        // RFC 6416 section 6.1, several byte aligned AudioMuxElements may be in one packet
+        let mut r = BitReader::new(&data);
+        let mut ts = ts;
+        while r.remaining_bits() >= 8 {
+            match self.read_mux_element(&mut r, ts)? {
+                Some(frames) => ts = ts.wrapping_add(frames * AAC_FRAME_SAMPLES),
+                None => break,
+            }
+            let padding = r.remaining_bits() % 8;
+            skip(&mut r, padding)?;
+        }
+
+        Ok(())
+    }
+
+    // number of access units, None if waiting for StreamMuxConfig
+    fn read_mux_element(&mut self, r: &mut BitReader, ts: u32) -> Result<Option<u32>> {
+        if self.params.cpresent {
+            let use_same_stream_mux = read(r, 1)?;
+            if use_same_stream_mux == 0 {
+                self.config = Some(StreamMuxConfig::parse(r)?);
+            }
+        }
+
+        let (num_sub_frames, other_data_len_bits) = match &self.config {
+            Some(config) => (config.num_sub_frames as u32, config.other_data_len_bits),
+            None => return Ok(None),
+        };
+
+        for n in 0..=num_sub_frames {
+            // PayloadLengthInfo
+            let mut len = 0;
+            loop {
+                let tmp = read(r, 8)?;
+                len += tmp as usize;
+                if tmp != 255 {
+                    break;
+                }
+            }
+
+            // PayloadMux
+            if r.remaining_bits() < len * 8 {
+                bail!("latm payload size [{len}] exceed remains [{}]", r.remaining_bits() / 8)
+            }
+
+            let mut frame = BytesMut::with_capacity(len);
+            for _ in 0..len {
+                frame.put_u8(read(r, 8)? as u8);
+            }
+            // sub frames follow at the rtp clock rate, which is the sample rate
+            let ts = ts.wrapping_add(n * AAC_FRAME_SAMPLES);
+            self.frames.push_back(DepackedFrame::audio(frame.freeze(), ts).with_loss(self.loss.take()));
+        }
+
+        if let Some(bits) = other_data_len_bits {
+            skip(r, bits as usize)?;
+        }
+
+        Ok(Some(num_sub_frames + 1))
+    }
+}
+
+impl RtpCodecDepacker for RtpDepackerLATM {
+    fn push_rtp_slice(&mut self, rtp: &[u8]) -> Result<()>  {
+        let rtp = RtpReader::new(rtp)
+        .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+        let ts = rtp.timestamp();
+        if self.last_ts != Some(ts) && !self.buf.is_empty() {
+            // missing the last fragment
+            self.buf.clear();
+            self.broken = false;
+        }
+
+        let seq: u16 = rtp.sequence_number().into();
+        self.loss.push(seq);
+        if let Some(last) = self.last_seq {
+            // unless a new element starts, the lost one may be a fragment of it
+            let first_of_element = self.buf.is_empty() && self.last_ts != Some(ts);
+            if seq != last.wrapping_add(1) && !first_of_element {
+                self.broken = true;
+            }
+        }
+        self.last_seq = Some(seq);
+        self.last_ts = Some(ts);
+
+        self.buf.put(rtp.payload());
+
+        if rtp.mark() {
+            self.finish_mux_element(ts)?;
+        }
+
+        Ok(())
+    }
+
+    fn pull_frame(&mut self) -> Result<Option<DepackedFrame>>  {
+        Ok(self.frames.pop_front())
+    }
+}
+
+
+#[test]
+fn test_latm_depack() {
+    let mut depack = RtpDepackerLATM::new(Some("profile-level-id=15;object=2;cpresent=0;config=400024203fc0")).unwrap();
+    assert_eq!(depack.config().unwrap().asc.sample_rate, 44100);
+
+    // one access unit
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 1, 0, 0x1234, &[0x03, 0x21, 0x22, 0x23])).unwrap();
+    assert_eq!(depack.pull_frame().unwrap().as_deref(), Some(&[0x21, 0x22, 0x23][..]));
+
+    // 300 bytes access unit fragmented, length is 255 + 45
+    let au: Vec<u8> = (0..300).map(|x| x as u8).collect();
+    let mut payload = vec![0xFF, 45];
+    payload.extend_from_slice(&au);
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 2, 1024, 0x1234, &payload[..100])).unwrap();
+    assert_eq!(depack.pull_frame().unwrap(), None);
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 3, 1024, 0x1234, &payload[100..])).unwrap();
+    assert_eq!(depack.pull_frame().unwrap().as_deref(), Some(&au[..]));
+
+    // lost a fragment
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 4, 2048, 0x1234, &payload[..100])).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 6, 2048, 0x1234, &payload[200..])).unwrap();
+    assert_eq!(depack.pull_frame().unwrap(), None);
+
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 7, 3072, 0x1234, &[0x01, 0x31])).unwrap();
+    assert_eq!(depack.pull_frame().unwrap().as_deref(), Some(&[0x31][..]));
+
+    // two AudioMuxElements in one packet
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 8, 4096, 0x1234, &[0x02, 0x41, 0x42, 0x01, 0x43])).unwrap();
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!((&frame[..], frame.rtp_ts), (&[0x41, 0x42][..], 4096));
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!((&frame[..], frame.rtp_ts), (&[0x43][..], 5120));
+    assert_eq!(depack.pull_frame().unwrap(), None);
+}
+
+#[test]
+fn test_latm_depack_inband_config() {
+    let mut depack = RtpDepackerLATM::new(None).unwrap();
+    assert!(depack.config().is_none());
+
+    // useSameStreamMux=0 followed by StreamMuxConfig, the access unit is not byte aligned
+    let mut bits = String::from("0");
+    for b in [0x40_u8, 0x00, 0x24, 0x20, 0x3F, 0xC0] {
+        bits += &format!("{b:08b}");
+    }
+    // StreamMuxConfig is 44 bits
+    bits.truncate(45);
+    bits += "00000010";
+    bits += "1010101001010101";
+    while bits.len() % 8 != 0 {
+        bits += "0";
+    }
+    let payload: Vec<u8> = bits.as_bytes()
+        .chunks(8)
+        .map(|x| u8::from_str_radix(std::str::from_utf8(x).unwrap(), 2).unwrap())
+        .collect();
+
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 1, 0, 0x1234, &payload)).unwrap();
+    assert_eq!(depack.config().unwrap().asc.channels, 2);
+    assert_eq!(depack.pull_frame().unwrap().as_deref(), Some(&[0xAA, 0x55][..]));
+
+    // useSameStreamMux=1
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 2, 1024, 0x1234, &[0x80, 0x80, 0x80])).unwrap();
+    assert_eq!(depack.pull_frame().unwrap().as_deref(), Some(&[0x01][..]));
+}
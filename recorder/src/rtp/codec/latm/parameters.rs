@@ -0,0 +1,332 @@
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+
+use super::super::h265::BitReader;
+
+const AAC_SAMPLE_RATES: [u32; 13] = [96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350];
+
+// RFC 6416 section 7.1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtpLatmParameters {
+    pub profile_level_id: Option<u32>,
+    pub object: Option<u32>,
+    pub bitrate: Option<u32>,
+
+    // StreamMuxConfig is carried in band if true
+    pub cpresent: bool,
+
+    // StreamMuxConfig in hex
+    pub config: Option<Vec<u8>>,
+
+    pub sbr_enabled: Option<bool>,
+}
+
+impl Default for RtpLatmParameters {
+    fn default() -> Self {
+        Self {
+            profile_level_id: None,
+            object: None,
+            bitrate: None,
+            cpresent: true,
+            config: None,
+            sbr_enabled: None,
+        }
+    }
+}
+
+impl RtpLatmParameters {
+    pub fn parse_from_str(fmtp: &str) -> Result<Self, String> {
+        let mut params = Self::default();
+
+        for p in fmtp.split(';') {
+            let (key, value) = match p.trim().split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None if !p.trim().is_empty() => return Err("key without value".into()),
+                None => continue,
+            };
+
+            match key {
+                "profile-level-id" => params.profile_level_id = Some(parse_u32(key, value)?),
+                "object" => params.object = Some(parse_u32(key, value)?),
+                "bitrate" => params.bitrate = Some(parse_u32(key, value)?),
+                "cpresent" => params.cpresent = value != "0",
+                "config" => params.config = Some(decode_hex(value).ok_or_else(||format!("invalid config [{value}]"))?),
+                "SBR-enabled" => params.sbr_enabled = Some(value == "1"),
+                _ => {},
+            }
+        }
+
+        Ok(params)
+    }
+
+    pub fn stream_mux_config(&self) -> Option<Result<StreamMuxConfig>> {
+        self.config.as_ref().map(|x|StreamMuxConfig::parse(&mut BitReader::new(x)))
+    }
+}
+
+// ISO/IEC 14496-3 section 1.6.2.1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioSpecificConfig {
+    pub object_type: u8,
+    pub sample_rate: u32,
+    pub channels: u32,
+
+    // explicit SBR/PS signaling, the output sample rate
+    pub ext_sample_rate: Option<u32>,
+
+    // byte aligned copy, used as decoder/muxer extradata
+    pub raw: Bytes,
+}
+
+impl AudioSpecificConfig {
+    pub fn parse_from_bytes(data: &[u8]) -> Result<Self> {
+        Self::parse(&mut BitReader::new(data))
+    }
+
+    pub fn parse(r: &mut BitReader) -> Result<Self> {
+        let origin = r.clone();
+        let start = r.remaining_bits();
+
+        let mut object_type = read_object_type(r)?;
+        let sample_rate = read_sample_rate(r)?;
+        let channel_config = read(r, 4)?;
+
+        let mut ext_sample_rate = None;
+        if object_type == 5 || object_type == 29 {
+            ext_sample_rate = Some(read_sample_rate(r)?);
+            object_type = read_object_type(r)?;
+        }
+
+        match object_type {
+            1 | 2 | 3 | 4 | 6 | 7 | 17 | 19 | 20 | 21 | 22 | 23 => {
+                // GASpecificConfig
+                let _frame_length_flag = read(r, 1)?;
+                if read(r, 1)? == 1 {
+                    // core coder delay
+                    read(r, 14)?;
+                }
+                let extension_flag = read(r, 1)?;
+
+                if channel_config == 0 {
+                    bail!("unsupported program_config_element")
+                }
+
+                if object_type == 6 || object_type == 20 {
+                    // layer number
+                    read(r, 3)?;
+                }
+
+                if extension_flag == 1 {
+                    match object_type {
+                        22 => { read(r, 16)?; },
+                        17 | 19 | 20 | 23 => { read(r, 3)?; },
+                        _ => {},
+                    }
+                    // extension flag 3
+                    read(r, 1)?;
+                }
+            }
+            _ => bail!("unsupported audio object type [{object_type}]"),
+        }
+
+        let channels = match channel_config {
+            7 => 8,
+            n => n,
+        };
+
+        let bits = start - r.remaining_bits();
+        let raw = copy_bits(origin, bits)?;
+
+        Ok(Self {
+            object_type,
+            sample_rate,
+            channels,
+            ext_sample_rate,
+            raw,
+        })
+    }
+}
+
+// ISO/IEC 14496-3 section 1.7.3.1, only one program with one layer is supported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamMuxConfig {
+    pub audio_mux_version: u8,
+    pub num_sub_frames: u8,
+    pub asc: AudioSpecificConfig,
+    pub frame_length_type: u8,
+    pub other_data_len_bits: Option<u32>,
+}
+
+impl StreamMuxConfig {
+    pub fn parse_from_bytes(data: &[u8]) -> Result<Self> {
+        Self::parse(&mut BitReader::new(data))
+    }
+
+    pub fn parse(r: &mut BitReader) -> Result<Self> {
+        let audio_mux_version = read(r, 1)? as u8;
+        let audio_mux_version_a = if audio_mux_version == 1 { read(r, 1)? } else { 0 };
+        if audio_mux_version_a != 0 {
+            bail!("unsupported audioMuxVersionA")
+        }
+
+        if audio_mux_version == 1 {
+            // taraBufferFullness
+            latm_get_value(r)?;
+        }
+
+        let all_streams_same_time_framing = read(r, 1)?;
+        let num_sub_frames = read(r, 6)? as u8;
+        let num_program = read(r, 4)?;
+        let num_layer = read(r, 3)?;
+        if all_streams_same_time_framing == 0 || num_program != 0 || num_layer != 0 {
+            bail!("unsupported multiple latm streams")
+        }
+
+        let asc = if audio_mux_version == 0 {
+            AudioSpecificConfig::parse(r)?
+        } else {
+            let len = latm_get_value(r)? as usize;
+            let start = r.remaining_bits();
+            let asc = AudioSpecificConfig::parse(r)?;
+            let used = start - r.remaining_bits();
+            if used > len {
+                bail!("AudioSpecificConfig exceed length [{len}]")
+            }
+            skip(r, len - used)?;
+            asc
+        };
+
+        let frame_length_type = read(r, 3)? as u8;
+        match frame_length_type {
+            0 => {
+                // latmBufferFullness
+                read(r, 8)?;
+            }
+            _ => bail!("unsupported frameLengthType [{frame_length_type}]"),
+        }
+
+        let mut other_data_len_bits = None;
+        if read(r, 1)? == 1 {
+            let len = if audio_mux_version == 1 {
+                latm_get_value(r)?
+            } else {
+                let mut len = 0;
+                loop {
+                    let esc = read(r, 1)?;
+                    len = (len << 8) + read(r, 8)?;
+                    if esc == 0 {
+                        break;
+                    }
+                }
+                len
+            };
+            other_data_len_bits = Some(len);
+        }
+
+        if read(r, 1)? == 1 {
+            // crcCheckSum
+            read(r, 8)?;
+        }
+
+        Ok(Self {
+            audio_mux_version,
+            num_sub_frames,
+            asc,
+            frame_length_type,
+            other_data_len_bits,
+        })
+    }
+}
+
+fn latm_get_value(r: &mut BitReader) -> Result<u32> {
+    let bytes = read(r, 2)? + 1;
+    let mut value = 0;
+    for _ in 0..bytes {
+        value = (value << 8) | read(r, 8)?;
+    }
+    Ok(value)
+}
+
+fn read_object_type(r: &mut BitReader) -> Result<u8> {
+    let object_type = read(r, 5)?;
+    if object_type == 31 {
+        Ok((32 + read(r, 6)?) as u8)
+    } else {
+        Ok(object_type as u8)
+    }
+}
+
+fn read_sample_rate(r: &mut BitReader) -> Result<u32> {
+    let index = read(r, 4)?;
+    if index == 0x0F {
+        return read(r, 24)
+    }
+    AAC_SAMPLE_RATES.get(index as usize)
+    .copied()
+    .ok_or_else(||anyhow!("invalid sampling frequency index [{index}]"))
+}
+
+fn copy_bits(mut src: BitReader, bits: usize) -> Result<Bytes> {
+    let mut buf = Vec::with_capacity(bits.div_ceil(8));
+    let mut remains = bits;
+    while remains > 0 {
+        let n = remains.min(8);
+        buf.push((read(&mut src, n)? << (8 - n)) as u8);
+        remains -= n;
+    }
+    Ok(buf.into())
+}
+
+pub(super) fn read(r: &mut BitReader, num: usize) -> Result<u32> {
+    r.read_bits(num).map_err(|e|anyhow!("{e}"))
+}
+
+pub(super) fn skip(r: &mut BitReader, num: usize) -> Result<()> {
+    r.skip_bits(num).map_err(|e|anyhow!("{e}"))
+}
+
+fn parse_u32(key: &str, value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_e|format!("invalid {key} [{value}]"))
+}
+
+pub fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes()
+    .chunks(2)
+    .map(|x| match x {
+        [_, _] => u8::from_str_radix(std::str::from_utf8(x).ok()?, 16).ok(),
+        _ => None,
+    })
+    .collect()
+}
+
+
+#[test]
+fn test_latm_parameters() {
+    let params = RtpLatmParameters::parse_from_str("profile-level-id=15;object=2;cpresent=0;config=400024203fc0").unwrap();
+    assert_eq!(params.profile_level_id, Some(15));
+    assert!(!params.cpresent);
+
+    let config = params.stream_mux_config().unwrap().unwrap();
+    assert_eq!(config.audio_mux_version, 0);
+    assert_eq!(config.num_sub_frames, 0);
+    assert_eq!(config.frame_length_type, 0);
+    assert_eq!(config.other_data_len_bits, None);
+    assert_eq!(config.asc.object_type, 2);
+    assert_eq!(config.asc.sample_rate, 44100);
+    assert_eq!(config.asc.channels, 2);
+    assert_eq!(&config.asc.raw[..], &[0x12, 0x10]);
+
+    assert!(RtpLatmParameters::parse_from_str("config=4000242").is_err());
+    assert!(RtpLatmParameters::parse_from_str("").unwrap().cpresent);
+}
+
+#[test]
+fn test_audio_specific_config() {
+    // AAC LC 44100 stereo
+    let asc = AudioSpecificConfig::parse_from_bytes(&[0x12, 0x10]).unwrap();
+    assert_eq!((asc.object_type, asc.sample_rate, asc.channels, asc.ext_sample_rate), (2, 44100, 2, None));
+
+    // HE-AAC, 22050 core with SBR to 44100
+    let asc = AudioSpecificConfig::parse_from_bytes(&[0x2B, 0x92, 0x08, 0x00]).unwrap();
+    assert_eq!((asc.object_type, asc.sample_rate, asc.channels, asc.ext_sample_rate), (2, 22050, 2, Some(44100)));
+}
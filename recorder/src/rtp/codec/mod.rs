@@ -1,3 +1,23 @@
 pub mod h264;
 
+pub mod h265;
+
+pub mod vp8;
+
+pub mod vp9;
+
+pub mod av1;
+
 pub mod aac;
+
+pub mod latm;
+
+pub mod mpa;
+
+pub mod opus;
+
+pub mod g711;
+
+pub mod amr;
+
+pub mod dtmf;
@@ -0,0 +1,309 @@
+// RFC 2250 section 3.5 MPEG Audio elementary streams (MPA),
+//   rtp clock rate is always 90000
+
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{Buf, BytesMut, BufMut};
+use rtp_rs::RtpReader;
+
+use super::super::depack::{DepackedFrame, RtpCodecDepacker, RtpLossCounter};
+#[cfg(test)]
+use super::super::rtp::make_rtp_packet;
+
+pub const MPA_CLOCK_RATE: u32 = 90000;
+
+// RFC 3555 section 4.1.17, all parameters are optional
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RtpMpaParameters {
+    pub layer: Option<u8>,
+    pub samplerate: Option<u32>,
+
+    // stereo, joint_stereo, single_channel, dual_channel
+    pub mode: Option<String>,
+
+    pub bitrate: Option<u32>,
+}
+
+impl RtpMpaParameters {
+    pub fn parse_from_str(fmtp: &str) -> Result<Self, String> {
+        let mut params = Self::default();
+
+        for p in fmtp.split(';') {
+            let (key, value) = match p.trim().split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None if !p.trim().is_empty() => return Err("key without value".into()),
+                None => continue,
+            };
+
+            match key {
+                "layer" => params.layer = Some(value.parse().map_err(|_e|format!("invalid layer [{value}]"))?),
+                "samplerate" => params.samplerate = Some(value.parse().map_err(|_e|format!("invalid samplerate [{value}]"))?),
+                "mode" => params.mode = Some(value.to_string()),
+                "bitrate" => params.bitrate = Some(value.parse().map_err(|_e|format!("invalid bitrate [{value}]"))?),
+                _ => {},
+            }
+        }
+
+        Ok(params)
+    }
+
+    pub fn channels(&self) -> u32 {
+        match self.mode.as_deref() {
+            Some("single_channel") => 1,
+            _ => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MpaHeader {
+    // 1, 2 or 25 for MPEG-2.5
+    pub version: u8,
+    pub layer: u8,
+    pub bitrate: u32,
+    pub sample_rate: u32,
+    pub channels: u32,
+    pub frame_len: usize,
+    pub samples: u32,
+}
+
+const MPA_BITRATES_V1: [[u32; 15]; 3] = [
+    [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+    [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+];
+
+const MPA_BITRATES_V2: [[u32; 15]; 2] = [
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+const MPA_SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+// free format is not supported
+pub fn parse_mpa_header(data: &[u8]) -> Option<MpaHeader> {
+    if data.len() < 4 {
+        return None
+    }
+    let h = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+    if h >> 21 != 0x7FF {
+        return None
+    }
+
+    let version = match (h >> 19) & 0x03 {
+        0 => 25,
+        2 => 2,
+        3 => 1,
+        _ => return None,
+    };
+
+    let layer = match (h >> 17) & 0x03 {
+        1 => 3,
+        2 => 2,
+        3 => 1,
+        _ => return None,
+    };
+
+    let bitrate_index = ((h >> 12) & 0x0F) as usize;
+    let sample_rate_index = ((h >> 10) & 0x03) as usize;
+    if bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+        return None
+    }
+
+    let padding = (h >> 9) & 0x01;
+    let channels = if (h >> 6) & 0x03 == 3 { 1 } else { 2 };
+
+    let bitrate = if version == 1 {
+        MPA_BITRATES_V1[layer as usize - 1][bitrate_index]
+    } else {
+        MPA_BITRATES_V2[if layer == 1 { 0 } else { 1 }][bitrate_index]
+    } * 1000;
+
+    let sample_rate = match version {
+        1 => MPA_SAMPLE_RATES[sample_rate_index],
+        2 => MPA_SAMPLE_RATES[sample_rate_index] / 2,
+        _ => MPA_SAMPLE_RATES[sample_rate_index] / 4,
+    };
+
+    let (frame_len, samples) = match (layer, version) {
+        (1, _) => ((12 * bitrate / sample_rate + padding) * 4, 384),
+        (2, _) | (3, 1) => (144 * bitrate / sample_rate + padding, 1152),
+        _ => (72 * bitrate / sample_rate + padding, 576),
+    };
+
+    Some(MpaHeader {
+        version,
+        layer,
+        bitrate,
+        sample_rate,
+        channels,
+        frame_len: frame_len as usize,
+        samples,
+    })
+}
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |             MBZ               |          Frag_offset          |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// a packet carries either whole frames or a fragment of one frame,
+// output one mpeg audio frame (with its header) per pull
+pub struct RtpDepackerMPA {
+    buf: BytesMut,
+
+    // rtp timestamp of the frame at the head of buf
+    buf_ts: u32,
+    last_header: Option<MpaHeader>,
+    loss: RtpLossCounter,
+    frames: VecDeque<DepackedFrame>,
+}
+
+impl Default for RtpDepackerMPA {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RtpDepackerMPA {
+    pub fn new() -> Self {
+        Self {
+            buf: Default::default(),
+            buf_ts: 0,
+            last_header: None,
+            loss: Default::default(),
+            frames: Default::default(),
+        }
+    }
+
+    pub fn into_box(self) -> Box<dyn RtpCodecDepacker> {
+        Box::new(self)
+    }
+
+    pub fn last_header(&self) -> Option<&MpaHeader> {
+        self.last_header.as_ref()
+    }
+
+    fn split_frames(&mut self) -> Result<()> {
+        while !self.buf.is_empty() {
+            let header = match parse_mpa_header(&self.buf) {
+                Some(v) => v,
+                None => {
+                    self.buf.clear();
+                    bail!("invalid mpeg audio header")
+                },
+            };
+
+            if self.buf.len() < header.frame_len {
+                // wait for next fragment
+                break;
+            }
+
+            let data = self.buf.split_to(header.frame_len).freeze();
+            self.frames.push_back(DepackedFrame::audio(data, self.buf_ts).with_loss(self.loss.take()));
+            self.last_header = Some(header);
+
+            // following frames in the same packet
+            let duration = header.samples as u64 * MPA_CLOCK_RATE as u64 / header.sample_rate as u64;
+            self.buf_ts = self.buf_ts.wrapping_add(duration as u32);
+        }
+        Ok(())
+    }
+}
+
+impl RtpCodecDepacker for RtpDepackerMPA {
+    fn push_rtp_slice(&mut self, rtp: &[u8]) -> Result<()>  {
+        let rtp = RtpReader::new(rtp)
+        .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+        let mut payload = rtp.payload();
+        if payload.len() < 4 {
+            bail!("mpa payload too short [{}]", payload.len())
+        }
+
+        self.loss.push(rtp.sequence_number().into());
+
+        let _mbz = payload.get_u16();
+        let frag_offset = payload.get_u16() as usize;
+
+        if frag_offset == 0 {
+            // the previous frame is incomplete if remains
+            self.buf.clear();
+            self.buf_ts = rtp.timestamp();
+        } else if frag_offset != self.buf.len() {
+            // lost fragment
+            self.buf.clear();
+            return Ok(())
+        }
+
+        self.buf.put(payload);
+        self.split_frames()
+    }
+
+    fn pull_frame(&mut self) -> Result<Option<DepackedFrame>>  {
+        Ok(self.frames.pop_front())
+    }
+}
+
+
+#[cfg(test)]
+fn make_mp3_frame(fill: u8) -> Vec<u8> {
+    // MPEG-1 layer 3, 128 kbps, 44100, stereo, 417 bytes
+    let mut frame = vec![fill; 417];
+    frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+    frame
+}
+
+#[test]
+fn test_mpa_header() {
+    let header = parse_mpa_header(&[0xFF, 0xFB, 0x90, 0x00]).unwrap();
+    assert_eq!((header.version, header.layer, header.bitrate, header.sample_rate), (1, 3, 128000, 44100));
+    assert_eq!((header.channels, header.frame_len, header.samples), (2, 417, 1152));
+
+    // MPEG-2 layer 3, 64 kbps, 22050, mono, padding
+    let header = parse_mpa_header(&[0xFF, 0xF3, 0x82, 0xC0]).unwrap();
+    assert_eq!((header.version, header.sample_rate, header.channels), (2, 22050, 1));
+    assert_eq!((header.frame_len, header.samples), (209, 576));
+
+    assert!(parse_mpa_header(&[0xFF, 0xFB, 0x00, 0x00]).is_none());
+
+    let params = RtpMpaParameters::parse_from_str("layer=3;samplerate=22050;mode=single_channel").unwrap();
+    assert_eq!(params.layer, Some(3));
+    assert_eq!(params.channels(), 1);
+}
+
+#[test]
+fn test_mpa_depack() {
+    let mut depack = RtpDepackerMPA::new();
+
+    // two frames in one packet
+    let frame1 = make_mp3_frame(1);
+    let frame2 = make_mp3_frame(2);
+    let mut payload = vec![0, 0, 0, 0];
+    payload.extend_from_slice(&frame1);
+    payload.extend_from_slice(&frame2);
+    depack.push_rtp_slice(&make_rtp_packet(14, false, 1, 0, 0x1234, &payload)).unwrap();
+    assert_eq!(depack.pull_frame().unwrap().as_deref(), Some(&frame1[..]));
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!((&frame[..], frame.rtp_ts), (&frame2[..], 2351));
+    assert_eq!(depack.last_header().unwrap().sample_rate, 44100);
+
+    // fragmented frame
+    let frame3 = make_mp3_frame(3);
+    let mut payload = vec![0, 0, 0, 0];
+    payload.extend_from_slice(&frame3[..200]);
+    depack.push_rtp_slice(&make_rtp_packet(14, false, 2, 4702, 0x1234, &payload)).unwrap();
+    assert_eq!(depack.pull_frame().unwrap(), None);
+
+    let mut payload = vec![0, 0, 0, 200];
+    payload.extend_from_slice(&frame3[200..]);
+    depack.push_rtp_slice(&make_rtp_packet(14, false, 3, 4702, 0x1234, &payload)).unwrap();
+    assert_eq!(depack.pull_frame().unwrap().as_deref(), Some(&frame3[..]));
+
+    // lost the first fragment
+    let mut payload = vec![0, 0, 0, 200];
+    payload.extend_from_slice(&frame3[200..]);
+    depack.push_rtp_slice(&make_rtp_packet(14, false, 5, 9404, 0x1234, &payload)).unwrap();
+    assert_eq!(depack.pull_frame().unwrap(), None);
+}
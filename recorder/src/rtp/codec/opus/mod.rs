@@ -0,0 +1,376 @@
+// RFC 7587 RTP Payload Format for the Opus Speech and Audio Codec
+
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use rtp_rs::RtpReader;
+
+use super::super::depack::{DepackedFrame, RtpCodecDepacker, RtpLossCounter};
+use super::super::pack::{RtpCodecPacker, RtpPackHeader, RtpPackQueue};
+#[cfg(test)]
+use super::super::rtp::make_rtp_packet;
+
+mod parameters;
+pub use parameters::*;
+
+// rtp clock rate of opus is always 48000
+pub const OPUS_CLOCK_RATE: u32 = 48000;
+
+// do not fill gaps longer than this, e.g. sender paused or timestamp jumped
+const MAX_GAP_SAMPLES: u32 = OPUS_CLOCK_RATE * 10;
+
+pub struct OpusFrame {
+    pub data: Bytes,
+    pub timestamp: u32,
+
+    // duration in 48 kHz samples
+    pub samples: u32,
+
+    // generated for lost packet or DTX,
+    // a TOC only packet which makes the decoder run its concealment (RFC 6716 section 4.4)
+    pub concealed: bool,
+
+    // the next packet for the last lost frame of a gap if useinbandfec,
+    // decoded with decode_fec of libopus, which falls back to concealment if it has no LBRR
+    pub fec: Option<Bytes>,
+
+    // packets lost since the previous frame
+    pub loss: u16,
+}
+
+impl From<OpusFrame> for DepackedFrame {
+    fn from(frame: OpusFrame) -> Self {
+        DepackedFrame::audio(frame.data, frame.timestamp)
+        .with_loss(frame.loss)
+        .with_fec(frame.fec)
+    }
+}
+
+// one opus packet per rtp packet,
+// gaps in rtp timestamp (packet loss or DTX) are filled with concealment packets
+// so that the output keeps a continuous timeline
+pub struct RtpDepackerOpus {
+    params: RtpOpusParameters,
+    conceal: bool,
+    next_ts: Option<u32>,
+    last_toc: Option<u8>,
+    loss: RtpLossCounter,
+    frames: VecDeque<OpusFrame>,
+}
+
+impl RtpDepackerOpus {
+    pub fn new(fmtp: Option<&str>) -> Result<Self> {
+        let params = match fmtp {
+            Some(fmtp) => RtpOpusParameters::parse_from_str(fmtp).map_err(|e|anyhow!("{e}"))?,
+            None => Default::default(),
+        };
+        Ok(Self::with_params(params))
+    }
+
+    pub fn with_params(params: RtpOpusParameters) -> Self {
+        Self {
+            params,
+            conceal: true,
+            next_ts: None,
+            last_toc: None,
+            loss: Default::default(),
+            frames: Default::default(),
+        }
+    }
+
+    pub fn with_conceal(mut self, conceal: bool) -> Self {
+        self.conceal = conceal;
+        self
+    }
+
+    pub fn params(&self) -> &RtpOpusParameters {
+        &self.params
+    }
+
+    pub fn into_box(self) -> Box<dyn RtpCodecDepacker> {
+        Box::new(self)
+    }
+
+    pub fn pull_opus_frame(&mut self) -> Option<OpusFrame> {
+        self.frames.pop_front()
+    }
+
+    fn fill_gap(&mut self, ts: u32, next: &[u8], lost: bool) {
+        let (next_ts, toc) = match (self.next_ts, self.last_toc) {
+            (Some(next_ts), Some(toc)) => (next_ts, toc),
+            _ => return,
+        };
+
+        let gap = ts.wrapping_sub(next_ts);
+        if gap == 0 || gap > MAX_GAP_SAMPLES {
+            return;
+        }
+
+        // code 0 packet with an empty frame
+        let toc = toc & 0xFC;
+        let frame_samples = opus_frame_samples(toc);
+        let data = Bytes::copy_from_slice(&[toc]);
+
+        // no FEC for the gap of DTX
+        let num = gap / frame_samples;
+        for n in 0..num {
+            let fec = (lost && self.params.useinbandfec && n + 1 == num)
+                .then(|| Bytes::copy_from_slice(next));
+            self.frames.push_back(OpusFrame {
+                data: data.clone(),
+                timestamp: next_ts.wrapping_add(n * frame_samples),
+                samples: frame_samples,
+                concealed: true,
+                fec,
+                loss: 0,
+            });
+        }
+    }
+}
+
+impl RtpCodecDepacker for RtpDepackerOpus {
+    fn push_rtp_slice(&mut self, rtp: &[u8]) -> Result<()>  {
+        let rtp = RtpReader::new(rtp)
+        .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+        let payload = rtp.payload();
+        if payload.is_empty() {
+            // some senders use empty payload for DTX, the gap is filled by next packet
+            return Ok(())
+        }
+
+        self.loss.push(rtp.sequence_number().into());
+
+        let ts = rtp.timestamp();
+        if let Some(next_ts) = self.next_ts {
+            if (ts.wrapping_sub(next_ts) as i32) < 0 {
+                // reordered or duplicated, the gap has been filled already
+                return Ok(())
+            }
+        }
+
+        let samples = opus_packet_samples(payload)
+        .ok_or_else(||anyhow!("invalid opus packet"))?;
+
+        // the loss goes to the first frame of the gap
+        let first = self.frames.len();
+        let loss = self.loss.take();
+        if self.conceal {
+            self.fill_gap(ts, payload, loss > 0);
+        }
+
+        self.frames.push_back(OpusFrame {
+            data: Bytes::copy_from_slice(payload),
+            timestamp: ts,
+            samples,
+            concealed: false,
+            fec: None,
+            loss: 0,
+        });
+        if let Some(frame) = self.frames.get_mut(first) {
+            frame.loss = loss;
+        }
+
+        self.next_ts = Some(ts.wrapping_add(samples));
+        self.last_toc = Some(payload[0]);
+
+        Ok(())
+    }
+
+    fn pull_frame(&mut self) -> Result<Option<DepackedFrame>>  {
+        Ok(self.frames.pop_front().map(|x|x.into()))
+    }
+}
+
+// one opus packet per rtp packet,
+// marker on the first packet of a talkspurt, i.e. the first one or after DTX (RFC 7587 section 4.2)
+pub struct RtpPackerOpus {
+    queue: RtpPackQueue,
+    next_ts: Option<u32>,
+}
+
+impl RtpPackerOpus {
+    pub fn new(header: RtpPackHeader) -> Self {
+        Self {
+            queue: RtpPackQueue::new(header),
+            next_ts: None,
+        }
+    }
+
+    pub fn header(&self) -> &RtpPackHeader {
+        self.queue.header()
+    }
+
+    pub fn into_box(self) -> Box<dyn RtpCodecPacker> {
+        Box::new(self)
+    }
+}
+
+impl RtpCodecPacker for RtpPackerOpus {
+    fn push_frame(&mut self, frame: &[u8], rtp_ts: u32) -> Result<()>  {
+        let samples = opus_packet_samples(frame)
+        .ok_or_else(||anyhow!("invalid opus packet"))?;
+
+        let mark = self.next_ts != Some(rtp_ts);
+        self.queue.push(mark, rtp_ts, frame);
+        self.next_ts = Some(rtp_ts.wrapping_add(samples));
+        Ok(())
+    }
+
+    fn pull_rtp(&mut self) -> Option<Bytes>  {
+        self.queue.pull()
+    }
+}
+
+// samples at 48 kHz of each frame, RFC 6716 section 3.1
+pub fn opus_frame_samples(toc: u8) -> u32 {
+    let config = toc >> 3;
+    match config {
+        // SILK, 10/20/40/60 ms
+        0..=11 => [480, 960, 1920, 2880][(config % 4) as usize],
+
+        // Hybrid, 10/20 ms
+        12..=15 => [480, 960][(config % 2) as usize],
+
+        // CELT, 2.5/5/10/20 ms
+        _ => [120, 240, 480, 960][(config % 4) as usize],
+    }
+}
+
+pub fn opus_packet_samples(packet: &[u8]) -> Option<u32> {
+    let toc = *packet.first()?;
+    let num_frames = match toc & 0x03 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => (*packet.get(1)? & 0x3F) as u32,
+    };
+
+    let samples = num_frames * opus_frame_samples(toc);
+
+    // at most 120 ms per packet
+    if num_frames == 0 || samples > 5760 {
+        return None
+    }
+    Some(samples)
+}
+
+
+#[test]
+fn test_opus_packet_samples() {
+    // CELT FB 20ms
+    assert_eq!(opus_packet_samples(&[0xFC, 0x00]), Some(960));
+    // SILK WB 20ms, two frames
+    assert_eq!(opus_packet_samples(&[0x49, 0x00]), Some(1920));
+    // CELT 2.5ms, code 3 with 4 frames
+    assert_eq!(opus_packet_samples(&[0x83, 0x04]), Some(480));
+    // SILK 60ms, code 3 with 3 frames exceeds 120ms
+    assert_eq!(opus_packet_samples(&[0x1B, 0x03]), None);
+    assert_eq!(opus_packet_samples(&[0x03]), None);
+}
+
+#[test]
+fn test_opus_depack() {
+    let mut depack = RtpDepackerOpus::new(Some("minptime=10;useinbandfec=1")).unwrap();
+    assert!(depack.params().useinbandfec);
+
+    depack.push_rtp_slice(&make_rtp_packet(111, false, 1, 0, 0x1234, &[0xFC, 0x01])).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(111, false, 2, 960, 0x1234, &[0xFC, 0x02])).unwrap();
+
+    // lost seq 3, filled with one concealment packet
+    depack.push_rtp_slice(&make_rtp_packet(111, false, 4, 2880, 0x1234, &[0xFC, 0x04])).unwrap();
+
+    // DTX, 3 packets not sent
+    depack.push_rtp_slice(&make_rtp_packet(111, false, 5, 6720, 0x1234, &[0xFD, 0x05, 0x05])).unwrap();
+
+    // reordered late packet is dropped
+    depack.push_rtp_slice(&make_rtp_packet(111, false, 3, 1920, 0x1234, &[0xFC, 0x03])).unwrap();
+
+    let mut frames = Vec::new();
+    let mut losses = Vec::new();
+    let mut fecs = Vec::new();
+    while let Some(frame) = depack.pull_opus_frame() {
+        losses.push(frame.loss);
+        fecs.push(frame.fec.clone());
+        frames.push((frame.timestamp, frame.samples, frame.concealed, frame.data));
+    }
+    // DTX is not a loss
+    assert_eq!(losses, [0, 0, 1, 0, 0, 0, 0, 0]);
+
+    // the lost frame is recovered from the next packet, the gap of DTX is not
+    assert_eq!(fecs[2].as_deref(), Some(&[0xFC, 0x04][..]));
+    assert!(fecs.iter().enumerate().all(|(n, x)| n == 2 || x.is_none()));
+
+    let expect: Vec<(u32, u32, bool, &[u8])> = vec![
+        (0, 960, false, &[0xFC, 0x01]),
+        (960, 960, false, &[0xFC, 0x02]),
+        (1920, 960, true, &[0xFC]),
+        (2880, 960, false, &[0xFC, 0x04]),
+        (3840, 960, true, &[0xFC]),
+        (4800, 960, true, &[0xFC]),
+        (5760, 960, true, &[0xFC]),
+        (6720, 1920, false, &[0xFD, 0x05, 0x05]),
+    ];
+    assert_eq!(frames.len(), expect.len());
+    for (frame, expect) in frames.iter().zip(expect.iter()) {
+        assert_eq!((frame.0, frame.1, frame.2, &frame.3[..]), *expect);
+    }
+}
+
+#[test]
+fn test_opus_pack() {
+    let mut packer = RtpPackerOpus::new(RtpPackHeader::new(111, 0x1234).with_seq(1));
+    packer.push_frame(&[0xFC, 0x01], 0).unwrap();
+    packer.push_frame(&[0xFC, 0x02], 960).unwrap();
+
+    // DTX, starts a talkspurt
+    packer.push_frame(&[0xFD, 0x03, 0x03], 4800).unwrap();
+    packer.push_frame(&[0xFC, 0x04], 6720).unwrap();
+    assert!(packer.push_frame(&[0x03], 7680).is_err());
+
+    let mut marks = Vec::new();
+    let mut depack = RtpDepackerOpus::new(None).unwrap();
+    while let Some(packet) = packer.pull_rtp() {
+        marks.push(RtpReader::new(&packet).unwrap().mark());
+        depack.push_rtp_slice(&packet).unwrap();
+    }
+    assert_eq!(marks, [true, false, true, false]);
+
+    // the gap of DTX is concealed by depacker
+    let mut frames = Vec::new();
+    while let Some(frame) = depack.pull_opus_frame() {
+        frames.push((frame.timestamp, frame.concealed, frame.loss));
+    }
+    assert_eq!(frames, [
+        (0, false, 0),
+        (960, false, 0),
+        (1920, true, 0),
+        (2880, true, 0),
+        (3840, true, 0),
+        (4800, false, 0),
+        (6720, false, 0),
+    ]);
+}
+
+#[test]
+fn test_opus_depack_fec() {
+    // two packets lost, only the last one is recovered from in-band FEC
+    let mut depack = RtpDepackerOpus::new(Some("useinbandfec=1")).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(111, false, 1, 0, 0x1234, &[0x4C, 0x01])).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(111, false, 4, 2880, 0x1234, &[0x4C, 0x04])).unwrap();
+
+    let mut frames = Vec::new();
+    while let Some(frame) = depack.pull_opus_frame() {
+        frames.push((frame.timestamp, frame.concealed, frame.fec.is_some(), frame.loss));
+    }
+    assert_eq!(frames, [(0, false, false, 0), (960, true, false, 2), (1920, true, true, 0), (2880, false, false, 0)]);
+
+    // plain concealment without useinbandfec
+    let mut depack = RtpDepackerOpus::new(None).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(111, false, 1, 0, 0x1234, &[0x4C, 0x01])).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(111, false, 3, 1920, 0x1234, &[0x4C, 0x03])).unwrap();
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert!(frame.fec.is_none());
+    let frame = depack.pull_frame().unwrap().unwrap();
+    assert_eq!((&frame.data[..], frame.fec, frame.loss), (&[0x4C][..], None, 1));
+}
@@ -0,0 +1,87 @@
+// RFC 7587 section 6.1, all parameters are optional
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RtpOpusParameters {
+    // receiver prefers stereo
+    pub stereo: bool,
+
+    // sender is likely to send stereo
+    pub sprop_stereo: bool,
+
+    // packets carry LBRR of the previous frame, lost frames are recovered from the next packet
+    pub useinbandfec: bool,
+
+    pub usedtx: bool,
+    pub cbr: bool,
+    pub maxplaybackrate: Option<u32>,
+    pub sprop_maxcapturerate: Option<u32>,
+    pub maxaveragebitrate: Option<u32>,
+    pub minptime: Option<u32>,
+}
+
+impl RtpOpusParameters {
+    pub fn parse_from_str(fmtp: &str) -> Result<Self, String> {
+        let mut params = Self::default();
+
+        for p in fmtp.split(';') {
+            let (key, value) = match p.trim().split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None if !p.trim().is_empty() => return Err("key without value".into()),
+                None => continue,
+            };
+
+            match key {
+                "stereo" => params.stereo = parse_bool(key, value)?,
+                "sprop-stereo" => params.sprop_stereo = parse_bool(key, value)?,
+                "useinbandfec" => params.useinbandfec = parse_bool(key, value)?,
+                "usedtx" => params.usedtx = parse_bool(key, value)?,
+                "cbr" => params.cbr = parse_bool(key, value)?,
+                "maxplaybackrate" => params.maxplaybackrate = Some(parse_u32(key, value)?),
+                "sprop-maxcapturerate" => params.sprop_maxcapturerate = Some(parse_u32(key, value)?),
+                "maxaveragebitrate" => params.maxaveragebitrate = Some(parse_u32(key, value)?),
+                "minptime" => params.minptime = Some(parse_u32(key, value)?),
+                _ => {},
+            }
+        }
+
+        Ok(params)
+    }
+
+    // rtpmap is always opus/48000/2, the actual channels depend on stereo parameters
+    pub fn channels(&self) -> u32 {
+        if self.stereo || self.sprop_stereo {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(format!("invalid {key} [{value}]")),
+    }
+}
+
+fn parse_u32(key: &str, value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_e|format!("invalid {key} [{value}]"))
+}
+
+
+#[test]
+fn test_opus_parameters() {
+    let params = RtpOpusParameters::parse_from_str("minptime=10;useinbandfec=1; stereo=1; maxplaybackrate=16000").unwrap();
+    assert!(params.useinbandfec);
+    assert!(params.stereo);
+    assert!(!params.usedtx);
+    assert_eq!(params.minptime, Some(10));
+    assert_eq!(params.channels(), 2);
+    assert_eq!(params.maxplaybackrate, Some(16000));
+
+    let params = RtpOpusParameters::parse_from_str("").unwrap();
+    assert_eq!(params.channels(), 1);
+    assert_eq!(params.maxplaybackrate, None);
+
+    assert!(RtpOpusParameters::parse_from_str("stereo=yes").is_err());
+}
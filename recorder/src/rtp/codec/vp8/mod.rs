@@ -0,0 +1,306 @@
+// RFC 7741 RTP Payload Format for VP8 Video
+
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use rtp_rs::RtpReader;
+
+use super::super::depack::{DepackedFrame, RtpCodecDepacker, RtpLossCounter};
+#[cfg(test)]
+use super::super::rtp::make_rtp_packet;
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Vp8Descriptor {
+    pub non_reference: bool,
+    pub start: bool,
+    pub partition_id: u8,
+    pub picture_id: Option<u16>,
+    pub tl0_pic_idx: Option<u8>,
+    pub tid: Option<u8>,
+    pub layer_sync: bool,
+    pub key_idx: Option<u8>,
+
+    // length of payload descriptor, vp8 payload follows
+    pub header_len: usize,
+}
+
+impl Vp8Descriptor {
+    //  0 1 2 3 4 5 6 7
+    // +-+-+-+-+-+-+-+-+
+    // |X|R|N|S|R| PID | (REQUIRED)
+    // +-+-+-+-+-+-+-+-+
+    // |I|L|T|K| RSV   | (OPTIONAL)
+    // +-+-+-+-+-+-+-+-+
+    // |M| PictureID   | (OPTIONAL)
+    // +-+-+-+-+-+-+-+-+
+    // |   PictureID   | (OPTIONAL, M=1)
+    // +-+-+-+-+-+-+-+-+
+    // |   TL0PICIDX   | (OPTIONAL)
+    // +-+-+-+-+-+-+-+-+
+    // |TID|Y| KEYIDX  | (OPTIONAL)
+    // +-+-+-+-+-+-+-+-+
+    pub fn parse(payload: &[u8]) -> Result<Self> {
+        let mut r = ByteReader(payload);
+
+        let b0 = r.read()?;
+        let mut desc = Self {
+            non_reference: b0 & 0x20 != 0,
+            start: b0 & 0x10 != 0,
+            partition_id: b0 & 0x07,
+            ..Default::default()
+        };
+
+        if b0 & 0x80 != 0 {
+            let ext = r.read()?;
+
+            if ext & 0x80 != 0 {
+                let b = r.read()?;
+                if b & 0x80 != 0 {
+                    let b2 = r.read()?;
+                    desc.picture_id = Some((((b & 0x7F) as u16) << 8) | b2 as u16);
+                } else {
+                    desc.picture_id = Some(b as u16);
+                }
+            }
+
+            if ext & 0x40 != 0 {
+                desc.tl0_pic_idx = Some(r.read()?);
+            }
+
+            if ext & 0x30 != 0 {
+                let b = r.read()?;
+                if ext & 0x20 != 0 {
+                    desc.tid = Some(b >> 6);
+                    desc.layer_sync = b & 0x20 != 0;
+                }
+                if ext & 0x10 != 0 {
+                    desc.key_idx = Some(b & 0x1F);
+                }
+            }
+        }
+
+        desc.header_len = payload.len() - r.0.len();
+        Ok(desc)
+    }
+
+    pub fn is_frame_start(&self) -> bool {
+        self.start && self.partition_id == 0
+    }
+}
+
+pub struct Vp8Frame {
+    pub data: Bytes,
+    pub timestamp: u32,
+    pub keyframe: bool,
+    pub picture_id: Option<u16>,
+    pub tid: Option<u8>,
+
+    // packets lost since the previous frame
+    pub loss: u16,
+}
+
+impl From<Vp8Frame> for DepackedFrame {
+    fn from(from: Vp8Frame) -> Self {
+        DepackedFrame::new(from.data, from.timestamp)
+        .with_keyframe(from.keyframe)
+        .with_loss(from.loss)
+    }
+}
+
+// output one raw vp8 frame (as in ivf/webm) per rtp timestamp
+pub struct RtpDepackerVP8 {
+    buf: Option<BytesMut>,
+    desc: Vp8Descriptor,
+    last_ts: Option<u32>,
+    last_seq: Option<u16>,
+    loss: RtpLossCounter,
+    frames: VecDeque<Vp8Frame>,
+}
+
+impl Default for RtpDepackerVP8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RtpDepackerVP8 {
+    pub fn new() -> Self {
+        Self {
+            buf: None,
+            desc: Default::default(),
+            last_ts: None,
+            last_seq: None,
+            loss: Default::default(),
+            frames: Default::default(),
+        }
+    }
+
+    pub fn into_box(self) -> Box<dyn RtpCodecDepacker> {
+        Box::new(self)
+    }
+
+    pub fn pull_vp8_frame(&mut self) -> Option<Vp8Frame> {
+        self.frames.pop_front()
+    }
+
+    fn finish_frame(&mut self, timestamp: u32) {
+        if let Some(buf) = self.buf.take() {
+            if buf.is_empty() {
+                return;
+            }
+
+            let data = buf.freeze();
+            self.frames.push_back(Vp8Frame {
+                keyframe: vp8_is_keyframe(&data),
+                timestamp,
+                picture_id: self.desc.picture_id,
+                tid: self.desc.tid,
+                loss: self.loss.take(),
+                data,
+            });
+        }
+    }
+}
+
+impl RtpCodecDepacker for RtpDepackerVP8 {
+    fn push_rtp_slice(&mut self, rtp: &[u8]) -> Result<()>  {
+        let rtp = RtpReader::new(rtp)
+        .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+        let seq: u16 = rtp.sequence_number().into();
+        self.loss.push(seq);
+        if let Some(last) = self.last_seq {
+            if seq != last.wrapping_add(1) {
+                // discard incomplete frame
+                self.buf = None;
+            }
+        }
+        self.last_seq = Some(seq);
+
+        let ts = rtp.timestamp();
+        if let Some(last) = self.last_ts {
+            if last != ts {
+                // missing marker of previous frame
+                self.finish_frame(last);
+            }
+        }
+        self.last_ts = Some(ts);
+
+        let payload = rtp.payload();
+        let desc = Vp8Descriptor::parse(payload)?;
+        let data = &payload[desc.header_len..];
+
+        if desc.is_frame_start() {
+            if data.is_empty() {
+                bail!("empty vp8 payload at frame start")
+            }
+            self.buf = Some(BytesMut::with_capacity(data.len() * 4));
+            self.desc = desc;
+        }
+
+        // lost the start of frame, discard until next start
+        if let Some(buf) = &mut self.buf {
+            buf.put(data);
+        }
+
+        if rtp.mark() {
+            self.finish_frame(ts);
+        }
+
+        Ok(())
+    }
+
+    fn pull_frame(&mut self) -> Result<Option<DepackedFrame>>  {
+        Ok(self.frames.pop_front().map(|x|x.into()))
+    }
+}
+
+// frame tag P bit, RFC 6386 section 9.1
+pub fn vp8_is_keyframe(frame: &[u8]) -> bool {
+    !frame.is_empty() && frame[0] & 0x01 == 0
+}
+
+pub fn vp8_keyframe_size(frame: &[u8]) -> Option<(u16, u16)> {
+    if frame.len() < 10 || !vp8_is_keyframe(frame) {
+        return None
+    }
+
+    if frame[3..6] != [0x9d, 0x01, 0x2a] {
+        return None
+    }
+
+    let width = u16::from_le_bytes([frame[6], frame[7]]) & 0x3FFF;
+    let height = u16::from_le_bytes([frame[8], frame[9]]) & 0x3FFF;
+    Some((width, height))
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn read(&mut self) -> Result<u8> {
+        match self.0.split_first() {
+            Some((b, remains)) => {
+                self.0 = remains;
+                Ok(*b)
+            },
+            None => bail!("vp8 payload descriptor truncated"),
+        }
+    }
+}
+
+
+#[test]
+fn test_vp8_descriptor() {
+    // X=1 S=1, I=1 L=1 T=1 K=1, 15 bits picture id
+    let desc = Vp8Descriptor::parse(&[0x90, 0xF0, 0x81, 0x23, 0x05, 0x6A, 0xAA]).unwrap();
+    assert!(desc.is_frame_start());
+    assert_eq!(desc.picture_id, Some(0x0123));
+    assert_eq!(desc.tl0_pic_idx, Some(5));
+    assert_eq!(desc.tid, Some(1));
+    assert!(desc.layer_sync);
+    assert_eq!(desc.key_idx, Some(0x0A));
+    assert_eq!(desc.header_len, 6);
+
+    // no extension
+    let desc = Vp8Descriptor::parse(&[0x01, 0xAA]).unwrap();
+    assert!(!desc.is_frame_start());
+    assert_eq!(desc.partition_id, 1);
+    assert_eq!(desc.header_len, 1);
+
+    assert!(Vp8Descriptor::parse(&[0x80, 0x80]).is_err());
+}
+
+#[test]
+fn test_vp8_depack() {
+    let mut depack = RtpDepackerVP8::new();
+
+    // keyframe 640x480 in two packets, 7 bits picture id
+    let key = [0x10, 0x02, 0x00, 0x9d, 0x01, 0x2a, 0x80, 0x02, 0xe0, 0x01];
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 1, 0, 0x1234, &[[0x90, 0x80, 0x11].as_slice(), &key[..6]].concat())).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 2, 0, 0x1234, &[[0x80, 0x80, 0x11].as_slice(), &key[6..]].concat())).unwrap();
+
+    let frame = depack.pull_vp8_frame().unwrap();
+    assert_eq!(&frame.data[..], &key[..]);
+    assert!(frame.keyframe);
+    assert_eq!(frame.picture_id, Some(0x11));
+    assert_eq!(vp8_keyframe_size(&frame.data), Some((640, 480)));
+
+    // delta frame
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 3, 3000, 0x1234, &[0x10, 0x01, 0x02])).unwrap();
+    let frame = depack.pull_vp8_frame().unwrap();
+    assert!(!frame.keyframe);
+    assert_eq!(&frame.data[..], &[0x01, 0x02]);
+
+    // lost middle packet, the frame is dropped
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 4, 6000, 0x1234, &[0x10, 0x01])).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 6, 6000, 0x1234, &[0x00, 0x03])).unwrap();
+    assert_eq!(depack.pull_frame().unwrap(), None);
+
+    // frame without marker is finished by next timestamp
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 7, 9000, 0x1234, &[0x10, 0x05])).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 8, 12000, 0x1234, &[0x10, 0x07])).unwrap();
+    assert_eq!(depack.pull_frame().unwrap().as_deref(), Some(&[0x05][..]));
+    assert_eq!(depack.pull_frame().unwrap().as_deref(), Some(&[0x07][..]));
+}
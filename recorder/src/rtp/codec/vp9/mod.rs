@@ -0,0 +1,569 @@
+// RFC 9628 RTP Payload Format for VP9 Video
+
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use rtp_rs::RtpReader;
+
+use super::super::depack::{DepackedFrame, RtpCodecDepacker, RtpLossCounter};
+use super::h265::BitReader;
+#[cfg(test)]
+use super::super::rtp::make_rtp_packet;
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Vp9Descriptor {
+    // inter-picture predicted
+    pub inter_predicted: bool,
+    pub flexible: bool,
+    pub begin: bool,
+    pub end: bool,
+    pub not_reference: bool,
+    pub picture_id: Option<u16>,
+    pub tid: Option<u8>,
+    pub switching_up: bool,
+    pub sid: Option<u8>,
+    pub inter_layer_dependency: bool,
+    pub tl0_pic_idx: Option<u8>,
+    pub p_diffs: Vec<u8>,
+    pub ss: Option<Vp9ScalabilityStructure>,
+
+    // length of payload descriptor, vp9 payload follows
+    pub header_len: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Vp9ScalabilityStructure {
+    pub num_spatial_layers: u8,
+
+    // (width, height) of each spatial layer
+    pub resolutions: Vec<(u16, u16)>,
+}
+
+impl Vp9Descriptor {
+    //  0 1 2 3 4 5 6 7
+    // +-+-+-+-+-+-+-+-+
+    // |I|P|L|F|B|E|V|Z| (REQUIRED)
+    // +-+-+-+-+-+-+-+-+
+    // |M| PICTURE ID  | (REQUIRED if I)
+    // +-+-+-+-+-+-+-+-+
+    // |   PICTURE ID  | (REQUIRED if M)
+    // +-+-+-+-+-+-+-+-+
+    // | TID |U| SID |D| (REQUIRED if L)
+    // +-+-+-+-+-+-+-+-+
+    // |   TL0PICIDX   | (REQUIRED if L and not F)
+    // +-+-+-+-+-+-+-+-+
+    // |   P_DIFF    |N| (REQUIRED if F and P, up to 3 times)
+    // +-+-+-+-+-+-+-+-+
+    // |      SS       | (REQUIRED if V)
+    // +-+-+-+-+-+-+-+-+
+    pub fn parse(payload: &[u8]) -> Result<Self> {
+        let mut r = ByteReader(payload);
+
+        let b0 = r.read()?;
+        let mut desc = Self {
+            inter_predicted: b0 & 0x40 != 0,
+            flexible: b0 & 0x10 != 0,
+            begin: b0 & 0x08 != 0,
+            end: b0 & 0x04 != 0,
+            not_reference: b0 & 0x01 != 0,
+            ..Default::default()
+        };
+
+        if b0 & 0x80 != 0 {
+            let b = r.read()?;
+            if b & 0x80 != 0 {
+                let b2 = r.read()?;
+                desc.picture_id = Some((((b & 0x7F) as u16) << 8) | b2 as u16);
+            } else {
+                desc.picture_id = Some(b as u16);
+            }
+        }
+
+        if b0 & 0x20 != 0 {
+            let b = r.read()?;
+            desc.tid = Some(b >> 5);
+            desc.switching_up = b & 0x10 != 0;
+            desc.sid = Some((b >> 1) & 0x07);
+            desc.inter_layer_dependency = b & 0x01 != 0;
+
+            if !desc.flexible {
+                desc.tl0_pic_idx = Some(r.read()?);
+            }
+        }
+
+        if desc.flexible && desc.inter_predicted {
+            loop {
+                let b = r.read()?;
+                desc.p_diffs.push(b >> 1);
+                if b & 0x01 == 0 {
+                    break;
+                }
+                if desc.p_diffs.len() >= 3 {
+                    bail!("too many vp9 P_DIFF")
+                }
+            }
+        }
+
+        if b0 & 0x02 != 0 {
+            desc.ss = Some(parse_ss(&mut r)?);
+        }
+
+        desc.header_len = payload.len() - r.0.len();
+        Ok(desc)
+    }
+}
+
+//  +-+-+-+-+-+-+-+-+
+//  | N_S |Y|G|-|-|-|
+//  +-+-+-+-+-+-+-+-+
+//  |     WIDTH     | (OPTIONAL) -\
+//  |               |              N_S + 1 times
+//  |     HEIGHT    | (OPTIONAL)   |
+//  |               |            -/
+//  +-+-+-+-+-+-+-+-+
+//  |      N_G      | (OPTIONAL)
+//  +-+-+-+-+-+-+-+-+
+//  | TID |U| R |-|-| (OPTIONAL) -\
+//  |    P_DIFF     | (OPTIONAL)   N_G times, R times P_DIFF
+//  +-+-+-+-+-+-+-+-+            -/
+fn parse_ss(r: &mut ByteReader) -> Result<Vp9ScalabilityStructure> {
+    let b = r.read()?;
+    let mut ss = Vp9ScalabilityStructure {
+        num_spatial_layers: (b >> 5) + 1,
+        resolutions: Vec::new(),
+    };
+
+    if b & 0x10 != 0 {
+        for _ in 0..ss.num_spatial_layers {
+            let width = u16::from_be_bytes([r.read()?, r.read()?]);
+            let height = u16::from_be_bytes([r.read()?, r.read()?]);
+            ss.resolutions.push((width, height));
+        }
+    }
+
+    if b & 0x08 != 0 {
+        let num_pics = r.read()?;
+        for _ in 0..num_pics {
+            let g = r.read()?;
+            let num_refs = (g >> 2) & 0x03;
+            for _ in 0..num_refs {
+                r.read()?;
+            }
+        }
+    }
+
+    Ok(ss)
+}
+
+pub struct Vp9Frame {
+    pub data: Bytes,
+    pub timestamp: u32,
+    pub keyframe: bool,
+    pub picture_id: Option<u16>,
+    pub tid: Option<u8>,
+    pub num_layers: usize,
+
+    // packets lost since the previous picture
+    pub loss: u16,
+}
+
+impl From<Vp9Frame> for DepackedFrame {
+    fn from(from: Vp9Frame) -> Self {
+        DepackedFrame::new(from.data, from.timestamp)
+        .with_keyframe(from.keyframe)
+        .with_loss(from.loss)
+    }
+}
+
+// output one vp9 picture per rtp timestamp,
+// multiple spatial layer frames are packed as superframe (VP9 bitstream spec Annex B)
+pub struct RtpDepackerVP9 {
+    layer_buf: Option<BytesMut>,
+    layers: Vec<Bytes>,
+    broken: bool,
+    desc: Vp9Descriptor,
+    ss: Option<Vp9ScalabilityStructure>,
+    last_ts: Option<u32>,
+    last_seq: Option<u16>,
+    loss: RtpLossCounter,
+    frames: VecDeque<Vp9Frame>,
+}
+
+impl Default for RtpDepackerVP9 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RtpDepackerVP9 {
+    pub fn new() -> Self {
+        Self {
+            layer_buf: None,
+            layers: Default::default(),
+            broken: false,
+            desc: Default::default(),
+            ss: None,
+            last_ts: None,
+            last_seq: None,
+            loss: Default::default(),
+            frames: Default::default(),
+        }
+    }
+
+    pub fn into_box(self) -> Box<dyn RtpCodecDepacker> {
+        Box::new(self)
+    }
+
+    pub fn pull_vp9_frame(&mut self) -> Option<Vp9Frame> {
+        self.frames.pop_front()
+    }
+
+    // the latest scalability structure received
+    pub fn scalability(&self) -> Option<&Vp9ScalabilityStructure> {
+        self.ss.as_ref()
+    }
+
+    fn finish_layer(&mut self) {
+        if let Some(buf) = self.layer_buf.take() {
+            if !buf.is_empty() {
+                self.layers.push(buf.freeze());
+            }
+        }
+    }
+
+    fn finish_picture(&mut self, timestamp: u32) {
+        if self.layer_buf.is_some() {
+            // missing end of layer frame
+            self.broken = true;
+            self.layer_buf = None;
+        }
+
+        let layers = std::mem::take(&mut self.layers);
+        let broken = std::mem::replace(&mut self.broken, false);
+        if layers.is_empty() || broken {
+            return;
+        }
+
+        let data = if layers.len() == 1 {
+            layers[0].clone()
+        } else {
+            make_superframe(&layers)
+        };
+
+        self.frames.push_back(Vp9Frame {
+            keyframe: vp9_is_keyframe(&data),
+            timestamp,
+            picture_id: self.desc.picture_id,
+            tid: self.desc.tid,
+            num_layers: layers.len(),
+            loss: self.loss.take(),
+            data,
+        });
+    }
+}
+
+impl RtpCodecDepacker for RtpDepackerVP9 {
+    fn push_rtp_slice(&mut self, rtp: &[u8]) -> Result<()>  {
+        let rtp = RtpReader::new(rtp)
+        .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+        let ts = rtp.timestamp();
+        if let Some(last) = self.last_ts {
+            if last != ts {
+                // missing marker of previous picture
+                self.finish_picture(last);
+            }
+        }
+        self.last_ts = Some(ts);
+
+        let payload = rtp.payload();
+        let desc = Vp9Descriptor::parse(payload)?;
+        let data = &payload[desc.header_len..];
+
+        let seq: u16 = rtp.sequence_number().into();
+        self.loss.push(seq);
+        if let Some(last) = self.last_seq {
+            let first_of_picture = desc.begin
+                && desc.sid.unwrap_or(0) == 0
+                && self.layers.is_empty()
+                && self.layer_buf.is_none();
+
+            if seq != last.wrapping_add(1) && !first_of_picture {
+                // upper layers depend on the lost one, discard whole picture
+                self.broken = true;
+                self.layer_buf = None;
+            }
+        }
+        self.last_seq = Some(seq);
+
+        if let Some(ss) = &desc.ss {
+            self.ss = Some(ss.clone());
+        }
+
+        if desc.begin {
+            if self.layer_buf.is_some() {
+                self.broken = true;
+            }
+            self.layer_buf = Some(BytesMut::with_capacity(data.len() * 4));
+        }
+
+        let end = desc.end;
+        if self.layer_buf.is_some() {
+            self.desc = desc;
+        }
+
+        // lost the begin of layer frame, discard until next begin
+        if let Some(buf) = &mut self.layer_buf {
+            buf.put(data);
+        }
+
+        if end {
+            self.finish_layer();
+        }
+
+        if rtp.mark() {
+            self.finish_picture(ts);
+        }
+
+        Ok(())
+    }
+
+    fn pull_frame(&mut self) -> Result<Option<DepackedFrame>>  {
+        Ok(self.frames.pop_front().map(|x|x.into()))
+    }
+}
+
+// frame sizes are written in 4 bytes
+fn make_superframe(layers: &[Bytes]) -> Bytes {
+    let marker = 0xC0 | (3 << 3) | (layers.len() as u8 - 1);
+    let index_len = 2 + 4 * layers.len();
+    let len = layers.iter().map(|x|x.len()).sum::<usize>() + index_len;
+
+    let mut buf = BytesMut::with_capacity(len);
+    for layer in layers.iter() {
+        buf.put(&layer[..]);
+    }
+
+    buf.put_u8(marker);
+    for layer in layers.iter() {
+        buf.put_u32_le(layer.len() as u32);
+    }
+    buf.put_u8(marker);
+
+    buf.freeze()
+}
+
+// uncompressed header, VP9 bitstream spec section 6.2
+pub fn vp9_is_keyframe(frame: &[u8]) -> bool {
+    matches!(parse_frame_type(&mut BitReader::new(frame)), Ok((_profile, true)))
+}
+
+pub fn vp9_keyframe_size(frame: &[u8]) -> Option<(u16, u16)> {
+    parse_keyframe_size(frame).ok().flatten()
+}
+
+fn parse_keyframe_size(frame: &[u8]) -> Result<Option<(u16, u16)>, String> {
+    let mut r = BitReader::new(frame);
+    let (profile, keyframe) = parse_frame_type(&mut r)?;
+    if !keyframe {
+        return Ok(None)
+    }
+
+    // show_frame, error_resilient_mode
+    r.skip_bits(2)?;
+
+    if r.read_bits(24)? != 0x498342 {
+        return Err("invalid vp9 sync code".into());
+    }
+
+    // color_config
+    if profile >= 2 {
+        // ten_or_twelve_bit
+        r.skip_bits(1)?;
+    }
+
+    let color_space = r.read_bits(3)?;
+    if color_space != 7 {
+        // color_range
+        r.skip_bits(1)?;
+        if profile == 1 || profile == 3 {
+            // subsampling_x, subsampling_y, reserved_zero
+            r.skip_bits(3)?;
+        }
+    } else if profile == 1 || profile == 3 {
+        // reserved_zero
+        r.skip_bits(1)?;
+    }
+
+    let width = r.read_bits(16)? + 1;
+    let height = r.read_bits(16)? + 1;
+    Ok(Some((width as u16, height as u16)))
+}
+
+// returns (profile, is_keyframe)
+fn parse_frame_type(r: &mut BitReader) -> Result<(u32, bool), String> {
+    if r.read_bits(2)? != 2 {
+        return Err("invalid vp9 frame marker".into());
+    }
+
+    let profile_low = r.read_bits(1)?;
+    let profile_high = r.read_bits(1)?;
+    let profile = (profile_high << 1) | profile_low;
+    if profile == 3 {
+        r.skip_bits(1)?;
+    }
+
+    let show_existing_frame = r.read_bits(1)?;
+    if show_existing_frame != 0 {
+        return Ok((profile, false))
+    }
+
+    let frame_type = r.read_bits(1)?;
+    Ok((profile, frame_type == 0))
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn read(&mut self) -> Result<u8> {
+        match self.0.split_first() {
+            Some((b, remains)) => {
+                self.0 = remains;
+                Ok(*b)
+            },
+            None => bail!("vp9 payload descriptor truncated"),
+        }
+    }
+}
+
+
+// profile 0 keyframe 640x480
+#[cfg(test)]
+const VP9_KEY_HEADER: [u8; 9] = [0x82, 0x49, 0x83, 0x42, 0x20, 0x27, 0xF0, 0x1D, 0xF0];
+
+#[test]
+fn test_vp9_descriptor() {
+    // I=1 L=1 B=1 E=1 V=1, 15 bits picture id, non-flexible,
+    // SS with 2 spatial layers and resolutions, 1 picture group with 1 reference
+    let payload = [
+        0xAE, 0x80, 0x02, 0x22, 0x07,
+        0x38, 0x01, 0x40, 0x00, 0xB4, 0x02, 0x80, 0x01, 0x68,
+        0x01, 0x24, 0x01,
+        0xAA,
+    ];
+    let desc = Vp9Descriptor::parse(&payload).unwrap();
+    assert!(desc.begin && desc.end);
+    assert!(!desc.flexible);
+    assert_eq!(desc.picture_id, Some(2));
+    assert_eq!(desc.tid, Some(1));
+    assert_eq!(desc.sid, Some(1));
+    assert_eq!(desc.tl0_pic_idx, Some(7));
+    assert_eq!(desc.ss, Some(Vp9ScalabilityStructure {
+        num_spatial_layers: 2,
+        resolutions: vec![(320, 180), (640, 360)],
+    }));
+    assert_eq!(desc.header_len, payload.len() - 1);
+
+    // flexible mode with 2 P_DIFF
+    let desc = Vp9Descriptor::parse(&[0x5C, 0x03, 0x04, 0xAA]).unwrap();
+    assert_eq!(desc.p_diffs, vec![1, 2]);
+    assert_eq!(desc.header_len, 3);
+
+    assert!(Vp9Descriptor::parse(&[0x80]).is_err());
+}
+
+#[test]
+fn test_vp9_keyframe() {
+    assert!(vp9_is_keyframe(&VP9_KEY_HEADER));
+    assert_eq!(vp9_keyframe_size(&VP9_KEY_HEADER), Some((640, 480)));
+
+    // inter frame
+    assert!(!vp9_is_keyframe(&[0x86, 0x00]));
+    assert_eq!(vp9_keyframe_size(&[0x86, 0x00]), None);
+}
+
+#[test]
+fn test_vp9_depack() {
+    let mut depack = RtpDepackerVP9::new();
+
+    // keyframe in two packets
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 1, 0, 0x1234, &[[0x08].as_slice(), &VP9_KEY_HEADER[..4]].concat())).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 2, 0, 0x1234, &[[0x04].as_slice(), &VP9_KEY_HEADER[4..]].concat())).unwrap();
+    let frame = depack.pull_vp9_frame().unwrap();
+    assert!(frame.keyframe);
+    assert_eq!(frame.num_layers, 1);
+    assert_eq!(&frame.data[..], &VP9_KEY_HEADER[..]);
+
+    // two spatial layers in one picture, packed as superframe
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 3, 3000, 0x1234, &[0x4C, 0x86, 0x01])).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 4, 3000, 0x1234, &[0x4C, 0x86, 0x02, 0x03])).unwrap();
+    let frame = depack.pull_vp9_frame().unwrap();
+    assert!(!frame.keyframe);
+    assert_eq!(frame.num_layers, 2);
+    assert_eq!(&frame.data[..], &[
+        0x86, 0x01,
+        0x86, 0x02, 0x03,
+        0xD9, 2, 0, 0, 0, 3, 0, 0, 0, 0xD9,
+    ]);
+
+    // lost packet, the picture is dropped
+    depack.push_rtp_slice(&make_rtp_packet(96, false, 5, 6000, 0x1234, &[0x48, 0x86])).unwrap();
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 7, 6000, 0x1234, &[0x44, 0x01])).unwrap();
+    assert_eq!(depack.pull_frame().unwrap(), None);
+
+    depack.push_rtp_slice(&make_rtp_packet(96, true, 8, 9000, 0x1234, &[0x4C, 0x86, 0x05])).unwrap();
+    assert_eq!(depack.pull_frame().unwrap().as_deref(), Some(&[0x86, 0x05][..]));
+}
+
+// conformance with ffmpeg's rtp packetizer
+#[test]
+fn test_vp9_depack_sample() {
+    use crate::ffeasy::{rtp_mem::load_rtp_mem_sync, video::{make_video_decoder, video_decoder_receive_frame}};
+    use ffmpeg_next as ff;
+
+    let input = "/tmp/sample-data/samplevp9.webm";
+    let max_frames = 300;
+
+    let data = load_rtp_mem_sync(input.as_ref(), max_frames).unwrap();
+    let mut reader = data.make_reader();
+
+    let mut depack = RtpDepackerVP9::new();
+    let mut decoder = None;
+    let mut num_frames = 0;
+    let mut num_decoded = 0;
+    while let Some(packet) = reader.read_next() {
+        if packet.ch_id() != 0 {
+            continue;
+        }
+
+        depack.push_rtp_slice(packet.data()).unwrap();
+        while let Some(frame) = depack.pull_vp9_frame() {
+            if num_frames == 0 {
+                assert!(frame.keyframe);
+            }
+            num_frames += 1;
+
+            if decoder.is_none() {
+                let (width, height) = vp9_keyframe_size(&frame.data).unwrap();
+                decoder = Some(make_video_decoder(
+                    ff::codec::Id::VP9,
+                    width as i32,
+                    height as i32,
+                    &[],
+                    ff::Rational::new(1, 90000),
+                ).unwrap());
+            }
+
+            let decoder = decoder.as_mut().unwrap();
+            decoder.send_packet(&ff::Packet::copy(&frame.data[..])).unwrap();
+            while video_decoder_receive_frame(decoder).unwrap().is_some() {
+                num_decoded += 1;
+            }
+        }
+    }
+
+    println!("depacked frames {num_frames}, decoded {num_decoded}");
+    assert!(num_decoded > 0);
+}
@@ -1,12 +1,13 @@
 
 use anyhow::{Result, anyhow};
 use bytes::Bytes;
-use super::RtpCodecDepacker;
+use super::{DepackedFrame, RtpCodecDepacker, RtpLossCounter};
 
 
 #[derive(Default)]
 pub struct RtpDepackerSimpleAudio {
-    frame: Option<Bytes>,
+    frame: Option<DepackedFrame>,
+    loss: RtpLossCounter,
 }
 
 impl RtpCodecDepacker for RtpDepackerSimpleAudio {
@@ -14,11 +15,14 @@ impl RtpCodecDepacker for RtpDepackerSimpleAudio {
     fn push_rtp_slice(&mut self, rtp: &[u8]) -> Result<()>  {
         let rtp = rtp_rs::RtpReader::new(rtp)
         .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
-        self.frame = Some(Bytes::copy_from_slice(rtp.payload()));
+        self.loss.push(rtp.sequence_number().into());
+        let frame = DepackedFrame::audio(Bytes::copy_from_slice(rtp.payload()), rtp.timestamp())
+            .with_loss(self.loss.take());
+        self.frame = Some(frame);
         Ok(())
     }
 
-    fn pull_frame(&mut self) -> Result<Option<Bytes>>  {
+    fn pull_frame(&mut self) -> Result<Option<DepackedFrame>>  {
         Ok(self.frame.take())
     }
 }
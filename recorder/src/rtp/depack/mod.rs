@@ -3,25 +3,120 @@
 use anyhow::Result;
 use bytes::Bytes;
 
-use crate::sdp::sdp::{SdpCodec, SdpCodecId};
-
-use super::codec::h264::RtpDepackerH264;
+use crate::{media::find_codec, sdp::sdp::SdpCodec};
 
 pub mod depack_simple;
 
 pub trait RtpCodecDepacker {
     fn push_rtp_slice(&mut self, rtp: &[u8]) -> Result<()> ;
-    fn pull_frame(&mut self) -> Result<Option<Bytes>> ;
+    fn pull_frame(&mut self) -> Result<Option<DepackedFrame>> ;
 }
 
+// one frame (access unit) of codec, derefs to its data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepackedFrame {
+    pub data: Bytes,
 
-pub fn make_rtp_depacker(codec: &SdpCodec) -> Result<Option<Box<dyn RtpCodecDepacker>>>  {
-    match codec.codec_id {
-        SdpCodecId::H264 => {
-            let fmtp = codec.fmtps.get(0).map(|x|x.as_str());
-            Ok(Some(RtpDepackerH264::new(fmtp)?.into_box()))
+    // rtp timestamp of the frame
+    pub rtp_ts: u32,
+
+    // decodable without previous frames, always true for audio
+    pub keyframe: bool,
+
+    // packets lost since the previous frame
+    pub loss: u16,
+
+    // some data of the frame is missing
+    pub corrupt: bool,
+
+    // parameter sets (VPS/SPS/PPS) changed in-band by this frame
+    pub new_parameters: bool,
+
+    // in-band FEC which recovers this concealed frame, see OpusFrame
+    pub fec: Option<Bytes>,
+}
+
+impl DepackedFrame {
+    pub fn new(data: Bytes, rtp_ts: u32) -> Self {
+        Self {
+            data,
+            rtp_ts,
+            keyframe: false,
+            loss: 0,
+            corrupt: false,
+            new_parameters: false,
+            fec: None,
         }
-        _ => Ok(None),
+    }
+
+    pub fn audio(data: Bytes, rtp_ts: u32) -> Self {
+        Self {
+            keyframe: true,
+            ..Self::new(data, rtp_ts)
+        }
+    }
+
+    pub fn with_keyframe(mut self, keyframe: bool) -> Self {
+        self.keyframe = keyframe;
+        self
+    }
+
+    pub fn with_loss(mut self, loss: u16) -> Self {
+        self.loss = loss;
+        self
+    }
+
+    pub fn with_fec(mut self, fec: Option<Bytes>) -> Self {
+        self.fec = fec;
+        self
+    }
+}
+
+impl std::ops::Deref for DepackedFrame {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+// counts lost packets by gaps of sequence number,
+// reordered or duplicated packets are not counted
+#[derive(Debug, Default)]
+pub struct RtpLossCounter {
+    last_seq: Option<u16>,
+    lost: u16,
+}
+
+impl RtpLossCounter {
+    // returns the number of packets lost right before seq
+    pub fn push(&mut self, seq: u16) -> u16 {
+        let lost = match self.last_seq {
+            Some(last) => seq.wrapping_sub(last).wrapping_sub(1),
+            None => 0,
+        };
+
+        if lost >= 0x8000 {
+            return 0
+        }
+
+        self.last_seq = Some(seq);
+        self.lost = self.lost.saturating_add(lost);
+        lost
+    }
+
+    // lost packets since the last take
+    pub fn take(&mut self) -> u16 {
+        std::mem::take(&mut self.lost)
+    }
+}
+
+
+// by the codec registry, None if no depacker for the codec
+pub fn make_rtp_depacker(codec: &SdpCodec) -> Result<Option<Box<dyn RtpCodecDepacker>>>  {
+    match find_codec(codec.codec_id) {
+        Some(desc) => desc.make_depacker(codec),
+        None => Ok(None),
     }
 }
 
@@ -0,0 +1,390 @@
+// RFC 5109 ULPFEC and draft-ietf-payload-flexible-fec-scheme-03 FlexFEC (same as libwebrtc)
+//   - only XOR recovery of ULPFEC level 0 and FlexFEC flexible mask are supported,
+//   - a missing packet is recovered when all other packets protected by a FEC packet are received
+
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use rtp_rs::RtpReader;
+#[cfg(test)]
+use super::rtp::make_rtp_packet;
+
+// recent media packets kept for recovery
+const MAX_MEDIA_PACKETS: usize = 512;
+
+// FEC packets waiting for more media packets
+const MAX_FEC_PACKETS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FecPacket {
+    // ssrc of the protected media
+    pub ssrc: u32,
+
+    // protected sequence numbers
+    pub seqs: Vec<u16>,
+
+    // XOR of the first 2 bytes of the protected headers, P X CC M PT
+    pub header_recovery: [u8; 2],
+
+    pub ts_recovery: u32,
+
+    // XOR of the lengths after the fixed 12 bytes header
+    pub length_recovery: u16,
+
+    // XOR of the bytes after the fixed 12 bytes header
+    pub payload: Bytes,
+}
+
+fn mask_seqs(sn_base: u16, mask: &[u8], bits: usize, seqs: &mut Vec<u16>) {
+    for index in 0..bits {
+        if mask[index / 8] & (0x80 >> (index % 8)) != 0 {
+            seqs.push(sn_base.wrapping_add(index as u16));
+        }
+    }
+}
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |E|L|P|X|  CC   |M| PT recovery |            SN base            |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                          TS recovery                          |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |        length recovery        |       Protection Length       |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |             mask              |  mask cont. (present if L=1)  |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+pub fn parse_ulpfec(data: &[u8]) -> Result<FecPacket> {
+    let rtp = RtpReader::new(data)
+    .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+    let payload = rtp.payload();
+    if payload.len() < 14 {
+        bail!("ulpfec header truncated")
+    }
+
+    if payload[0] & 0x80 != 0 {
+        bail!("ulpfec extension flag is set")
+    }
+
+    let mask_len = if payload[0] & 0x40 != 0 { 6 } else { 2 };
+    let header_len = 12 + mask_len;
+    if payload.len() < header_len {
+        bail!("ulpfec mask truncated")
+    }
+
+    let sn_base = u16::from_be_bytes([payload[2], payload[3]]);
+    let protection_len = u16::from_be_bytes([payload[10], payload[11]]) as usize;
+    if payload.len() < header_len + protection_len {
+        bail!("ulpfec protection length [{protection_len}] exceed remains [{}]", payload.len() - header_len)
+    }
+
+    let mut seqs = Vec::new();
+    mask_seqs(sn_base, &payload[12..header_len], mask_len * 8, &mut seqs);
+
+    Ok(FecPacket {
+        ssrc: rtp.ssrc(),
+        seqs,
+        header_recovery: [payload[0], payload[1]],
+        ts_recovery: u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]),
+        length_recovery: u16::from_be_bytes([payload[8], payload[9]]),
+        payload: Bytes::copy_from_slice(&payload[header_len..header_len + protection_len]),
+    })
+}
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |R|F|P|X|  CC   |M| PT recovery |         length recovery       |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                          TS recovery                          |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |   SSRCCount   |                    reserved                   |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                             SSRC_i                            |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |           SN base_i           |k|          Mask [0-14]        |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |k|                   Mask [15-45] (optional)                   |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |k|                                                             |
+// +-+                   Mask [46-108] (optional)                  |
+// |                                                               |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// only one protected ssrc, the mask parts are ended by k bit
+pub fn parse_flexfec(data: &[u8]) -> Result<FecPacket> {
+    let rtp = RtpReader::new(data)
+    .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+    let payload = rtp.payload();
+    if payload.len() < 20 {
+        bail!("flexfec header truncated")
+    }
+
+    if payload[0] & 0xC0 != 0 {
+        bail!("flexfec retransmission or fixed mask is not supported")
+    }
+
+    if payload[8] != 1 {
+        bail!("flexfec ssrc count [{}] is not supported", payload[8])
+    }
+
+    let ssrc = u32::from_be_bytes([payload[12], payload[13], payload[14], payload[15]]);
+    let sn_base = u16::from_be_bytes([payload[16], payload[17]]);
+
+    // (bytes, bits) of each mask part
+    const MASK_PARTS: [(usize, usize); 3] = [(2, 15), (4, 31), (8, 63)];
+
+    let mut seqs = Vec::new();
+    let mut offset = 18;
+    let mut base = sn_base;
+    for (index, (bytes, bits)) in MASK_PARTS.iter().enumerate() {
+        if payload.len() < offset + bytes {
+            bail!("flexfec mask truncated")
+        }
+
+        let part = &payload[offset..offset + bytes];
+        let k = part[0] & 0x80 != 0;
+
+        // shift out the k bit
+        let mut mask = vec![0_u8; *bytes];
+        for (i, b) in mask.iter_mut().enumerate() {
+            *b = (part[i] << 1) | part.get(i + 1).map(|x| x >> 7).unwrap_or(0);
+        }
+        mask_seqs(base, &mask, *bits, &mut seqs);
+
+        offset += bytes;
+        base = base.wrapping_add(*bits as u16);
+
+        if k {
+            break;
+        }
+
+        if index == MASK_PARTS.len() - 1 {
+            bail!("flexfec mask without k bit")
+        }
+    }
+
+    Ok(FecPacket {
+        ssrc,
+        seqs,
+        header_recovery: [payload[0], payload[1]],
+        ts_recovery: u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]),
+        length_recovery: u16::from_be_bytes([payload[2], payload[3]]),
+        payload: Bytes::copy_from_slice(&payload[offset..]),
+    })
+}
+
+// recovers media packets of one ssrc
+#[derive(Default)]
+pub struct RtpFecReceiver {
+    media: HashMap<u16, Bytes>,
+    media_order: VecDeque<u16>,
+    fecs: VecDeque<FecPacket>,
+    packets: VecDeque<Bytes>,
+    recovered: u64,
+}
+
+impl RtpFecReceiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // number of packets rebuilt from FEC
+    pub fn recovered(&self) -> u64 {
+        self.recovered
+    }
+
+    // returns false if the packet was received or recovered already
+    pub fn push_media(&mut self, rtp: Bytes) -> bool {
+        if rtp.len() < 12 {
+            return false
+        }
+
+        let seq = u16::from_be_bytes([rtp[2], rtp[3]]);
+        if !self.insert_media(seq, rtp) {
+            return false
+        }
+
+        self.recover();
+        true
+    }
+
+    pub fn push_fec(&mut self, fec: FecPacket) {
+        if self.fecs.len() >= MAX_FEC_PACKETS {
+            self.fecs.pop_front();
+        }
+        self.fecs.push_back(fec);
+        self.recover();
+    }
+
+    pub fn pull_rtp(&mut self) -> Option<Bytes> {
+        self.packets.pop_front()
+    }
+
+    fn insert_media(&mut self, seq: u16, rtp: Bytes) -> bool {
+        if self.media.contains_key(&seq) {
+            return false
+        }
+
+        if self.media_order.len() >= MAX_MEDIA_PACKETS {
+            if let Some(old) = self.media_order.pop_front() {
+                self.media.remove(&old);
+            }
+        }
+        self.media_order.push_back(seq);
+        self.media.insert(seq, rtp);
+        true
+    }
+
+    fn recover(&mut self) {
+        // a recovered packet may complete other FEC packets
+        loop {
+            let mut recovered = None;
+            let mut index = 0;
+            while index < self.fecs.len() {
+                let fec = &self.fecs[index];
+                let mut missing = fec.seqs.iter().filter(|x| !self.media.contains_key(x));
+                match (missing.next(), missing.next()) {
+                    (None, _) => {
+                        self.fecs.remove(index);
+                    },
+                    (Some(seq), None) => {
+                        let seq = *seq;
+                        let fec = self.fecs.remove(index);
+                        recovered = fec.and_then(|fec| self.recover_packet(&fec, seq).map(|x| (seq, x)));
+                        if recovered.is_some() {
+                            break;
+                        }
+                    },
+                    _ => index += 1,
+                }
+            }
+
+            match recovered {
+                Some((seq, packet)) => {
+                    self.insert_media(seq, packet.clone());
+                    self.packets.push_back(packet);
+                    self.recovered += 1;
+                },
+                None => break,
+            }
+        }
+    }
+
+    fn recover_packet(&self, fec: &FecPacket, seq: u16) -> Option<Bytes> {
+        let mut header = fec.header_recovery;
+        let mut ts = fec.ts_recovery;
+        let mut length = fec.length_recovery;
+        let mut payload = fec.payload.to_vec();
+
+        let mut ssrc = fec.ssrc;
+        for media_seq in fec.seqs.iter().filter(|x| **x != seq) {
+            let media = self.media.get(media_seq)?;
+            header[0] ^= media[0];
+            header[1] ^= media[1];
+            ts ^= u32::from_be_bytes([media[4], media[5], media[6], media[7]]);
+            length ^= (media.len() - 12) as u16;
+            ssrc = u32::from_be_bytes([media[8], media[9], media[10], media[11]]);
+            for (x, y) in payload.iter_mut().zip(media[12..].iter()) {
+                *x ^= y;
+            }
+        }
+
+        let length = length as usize;
+        if length > payload.len() {
+            // not fully protected
+            return None
+        }
+
+        let mut buf = BytesMut::with_capacity(12 + length);
+        buf.put_u8(0x80 | (header[0] & 0x3F));
+        buf.put_u8(header[1]);
+        buf.put_u16(seq);
+        buf.put_u32(ts);
+        buf.put_u32(ssrc);
+        buf.put(&payload[..length]);
+        Some(buf.freeze())
+    }
+}
+
+
+// XOR of the media packets as the sender does
+#[cfg(test)]
+fn make_fec_fields(medias: &[&[u8]]) -> ([u8; 2], u32, u16, Vec<u8>) {
+    let mut header = [0_u8; 2];
+    let mut ts = 0;
+    let mut length = 0;
+    let mut payload = vec![0_u8; medias.iter().map(|x| x.len() - 12).max().unwrap()];
+    for media in medias {
+        header[0] ^= media[0];
+        header[1] ^= media[1];
+        ts ^= u32::from_be_bytes([media[4], media[5], media[6], media[7]]);
+        length ^= (media.len() - 12) as u16;
+        for (x, y) in payload.iter_mut().zip(media[12..].iter()) {
+            *x ^= y;
+        }
+    }
+    (header, ts, length, payload)
+}
+
+#[test]
+fn test_ulpfec_recover() {
+    let m1 = make_rtp_packet(96, false, 100, 3000, 0x1234, &[0x11, 0x12, 0x13]);
+    let m2 = make_rtp_packet(96, true, 101, 3000, 0x1234, &[0x21]);
+    let m3 = make_rtp_packet(96, true, 102, 6000, 0x1234, &[0x31, 0x32]);
+
+    let (header, ts, length, payload) = make_fec_fields(&[&m1, &m2, &m3]);
+    let mut fec_payload = vec![header[0] & 0x3F, header[1], 0, 100];
+    fec_payload.extend_from_slice(&ts.to_be_bytes());
+    fec_payload.extend_from_slice(&length.to_be_bytes());
+    fec_payload.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    fec_payload.extend_from_slice(&[0xE0, 0x00]);
+    fec_payload.extend_from_slice(&payload);
+
+    let rtp = make_rtp_packet(97, false, 103, 6000, 0x1234, &fec_payload);
+    let fec = parse_ulpfec(&rtp).unwrap();
+    assert_eq!(fec.seqs, vec![100, 101, 102]);
+
+    let mut receiver = RtpFecReceiver::new();
+    assert!(receiver.push_media(m1.into()));
+    assert!(receiver.push_media(m3.clone().into()));
+    assert!(!receiver.push_media(m3.into()));
+    receiver.push_fec(fec);
+
+    assert_eq!(receiver.pull_rtp().as_deref(), Some(&m2[..]));
+    assert_eq!(receiver.pull_rtp(), None);
+    assert_eq!(receiver.recovered(), 1);
+
+    // late arrival of the recovered
+    assert!(!receiver.push_media(m2.into()));
+}
+
+#[test]
+fn test_flexfec_recover() {
+    let m1 = make_rtp_packet(96, false, 65535, 3000, 0x1234, &[0x11, 0x12]);
+    let m2 = make_rtp_packet(96, true, 0, 3000, 0x1234, &[0x21, 0x22, 0x23, 0x24]);
+
+    let (header, ts, length, payload) = make_fec_fields(&[&m1, &m2]);
+    let mut fec_payload = vec![header[0] & 0x3F, header[1]];
+    fec_payload.extend_from_slice(&length.to_be_bytes());
+    fec_payload.extend_from_slice(&ts.to_be_bytes());
+    fec_payload.extend_from_slice(&[1, 0, 0, 0]);
+    fec_payload.extend_from_slice(&0x1234_u32.to_be_bytes());
+    fec_payload.extend_from_slice(&65520_u16.to_be_bytes());
+    // k=0 with empty mask, then k=1 with bits 15 and 16
+    fec_payload.extend_from_slice(&[0x00, 0x00, 0x80 | 0x40 | 0x20, 0x00, 0x00, 0x00]);
+    fec_payload.extend_from_slice(&payload);
+
+    // FlexFEC has its own ssrc
+    let rtp = make_rtp_packet(96, false, 7, 6000, 0x5678, &fec_payload);
+    let fec = parse_flexfec(&rtp).unwrap();
+    assert_eq!((fec.ssrc, &fec.seqs[..]), (0x1234, &[65535, 0][..]));
+
+    let mut receiver = RtpFecReceiver::new();
+    receiver.push_fec(fec);
+    assert_eq!(receiver.pull_rtp(), None);
+    assert!(receiver.push_media(m1.into()));
+    assert_eq!(receiver.pull_rtp().as_deref(), Some(&m2[..]));
+}
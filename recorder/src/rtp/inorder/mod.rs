@@ -47,6 +47,46 @@ impl<T>  RtpInorderBuf<T> {
         }
     }
 
+    /// insert without releasing anything, returns false if duplicate or older than released
+    pub fn push(&mut self, seq: u16, data: T) -> bool {
+        let seq64 = self.seq_ext.convert(seq);
+        if let Some(seq_next) = self.seq_next {
+            if seq64 < seq_next {
+                return false
+            }
+        }
+
+        if self.buf.contains_key(&seq64) {
+            return false
+        }
+
+        self.buf.insert(seq64, data);
+        true
+    }
+
+    pub fn peek(&self) -> Option<(RtpOrder, &T)> {
+        self.buf.first_key_value().map(|(seq, data)| {
+            let order = self.check_order(*seq).unwrap_or(RtpOrder::Normal(*seq));
+            (order, data)
+        })
+    }
+
+    pub fn seq_next(&self) -> Option<i64> {
+        self.seq_next
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
     pub fn inorder_pop(&mut self) -> Option<(i64, T)> {
         if let Some(first) = self.buf.first_key_value() {
             let seq = *first.0;
@@ -133,6 +173,21 @@ fn test_rtp_order_buf() {
     assert_eq!(buf.pop(), None );
 }
 
+#[test]
+fn test_rtp_order_push_peek() {
+    let mut buf = RtpInorderBuf::new(8);
+    assert!(buf.push(10, 'a'));
+    assert!(buf.push(12, 'c'));
+    assert!(!buf.push(12, 'c'));
+    assert_eq!(buf.peek(), Some((RtpOrder::Normal(10), &'a')));
+    assert_eq!(buf.pop(), Some((RtpOrder::Normal(10), 'a')) );
+    assert_eq!(buf.peek(), Some((RtpOrder::Lost(1, 12), &'c')));
+    assert_eq!(buf.pop(), Some((RtpOrder::Lost(1, 12), 'c')) );
+    assert!(!buf.push(11, 'b'));
+    assert_eq!(buf.seq_next(), Some(13));
+    assert!(buf.is_empty());
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct U16Extender {
     last_input: Option<u16>,
@@ -185,3 +240,49 @@ fn test_extend() {
     assert_eq!(u16_extend(65535, 0, 10), 10_i64 + 1 );
 }
 
+
+#[derive(Debug, Clone, Copy)]
+pub struct U32Extender {
+    last_input: Option<u32>,
+    ext: i64,
+}
+
+impl U32Extender {
+    pub fn new() -> Self {
+        Self {
+            last_input: None,
+            ext:0,
+        }
+    }
+
+    pub fn convert(&mut self, val: u32) -> i64 {
+        match self.last_input {
+            Some(last_input) => {
+                self.ext = u32_extend(last_input, val, self.ext);
+                self.last_input = Some(val);
+            },
+            None => {
+                self.last_input = Some(val);
+                self.ext = val as i64;
+            },
+        }
+        self.ext
+    }
+}
+
+fn u32_extend(last: u32, curr: u32, ext: i64) -> i64 {
+    let d1 = curr.wrapping_sub(last);
+    let d2 = last.wrapping_sub(curr);
+    if d1 <= d2 { 
+        ext + (d1 as i64)
+    } else {
+        ext - (d2 as i64)
+    }
+}
+
+#[test]
+fn test_extend_u32() {
+    assert_eq!(u32_extend(0, 3000, 10), 3010_i64);
+    assert_eq!(u32_extend(u32::MAX - 100, 200, 10), 10_i64 + 301 );
+    assert_eq!(u32_extend(200, u32::MAX - 100, 10), 10_i64 - 301 );
+}
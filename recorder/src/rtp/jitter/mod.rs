@@ -0,0 +1,360 @@
+// Time based jitter buffer on top of RtpInorderBuf
+//   - packets are held until  arrival_base + media_time + target_delay,
+//     where arrival_base is the min transit (arrival - media time) of recent packets
+//   - target_delay adapts from interarrival jitter (RFC 3550 section 6.4.1)
+//   - a missing seq is declared lost when the packet after the gap is due,
+//     an explicit JitterOutput::Lost is emitted before that packet
+
+use std::collections::VecDeque;
+
+use super::inorder::{RtpInorderBuf, RtpOrder, U32Extender};
+
+
+#[derive(Debug, Clone)]
+pub struct JitterConfig {
+    pub min_delay_ms: i64,
+    pub max_delay_ms: i64,
+    pub init_delay_ms: i64,
+
+    // target_delay = jitter * jitter_factor
+    pub jitter_factor: i64,
+
+    // number of recent packets used to find min transit
+    pub transit_window: usize,
+
+    // hard limit of buffered packets, released regardless of time when reached
+    pub max_packets: usize,
+}
+
+impl Default for JitterConfig {
+    fn default() -> Self {
+        Self {
+            min_delay_ms: 20,
+            max_delay_ms: 1000,
+            init_delay_ms: 100,
+            jitter_factor: 4,
+            transit_window: 256,
+            max_packets: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JitterOutput<T> {
+    Packet(JitterPacket<T>),
+    Lost(JitterLost),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JitterPacket<T> {
+    pub order: RtpOrder,
+
+    // extended rtp timestamp
+    pub ts: i64,
+
+    pub data: T,
+}
+
+impl<T> JitterPacket<T> {
+    pub fn seq(&self) -> i64 {
+        match self.order {
+            RtpOrder::Normal(seq) => seq,
+            RtpOrder::Lost(_, seq) => seq,
+            RtpOrder::Old => 0,
+        }
+    }
+}
+
+// a gap of [seq, seq+count) in sequence number,
+// the lost media lies between prev_ts (last released) and next_ts (next packet)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JitterLost {
+    pub seq: i64,
+    pub count: u16,
+    pub prev_ts: Option<i64>,
+    pub next_ts: i64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JitterStats {
+    pub received: u64,
+    pub released: u64,
+    pub lost: u64,
+    pub late: u64,
+    pub overflow: u64,
+}
+
+pub struct RtpJitterBuf<T> {
+    inner: RtpInorderBuf<JitterSlot<T>>,
+    config: JitterConfig,
+    clock_rate: u32,
+    ts_ext: U32Extender,
+    transits: VecDeque<i64>,
+    base_transit: Option<i64>,
+    last_transit: Option<i64>,
+    jitter_q4: i64,
+    target_delay: i64,
+    last_released_ts: Option<i64>,
+    pending: Option<JitterPacket<T>>,
+    stats: JitterStats,
+}
+
+struct JitterSlot<T> {
+    ts: i64,
+    data: T,
+}
+
+impl<T> RtpJitterBuf<T> {
+    pub fn new(clock_rate: u32, config: JitterConfig) -> Self {
+        let target_delay = config.init_delay_ms.clamp(config.min_delay_ms, config.max_delay_ms);
+        Self {
+            inner: RtpInorderBuf::new(config.max_packets),
+            clock_rate,
+            ts_ext: U32Extender::new(),
+            transits: VecDeque::with_capacity(config.transit_window),
+            base_transit: None,
+            last_transit: None,
+            jitter_q4: 0,
+            target_delay,
+            last_released_ts: None,
+            pending: None,
+            stats: Default::default(),
+            config,
+        }
+    }
+
+    pub fn target_delay_ms(&self) -> i64 {
+        self.target_delay
+    }
+
+    pub fn jitter_ms(&self) -> i64 {
+        self.jitter_q4 >> 4
+    }
+
+    pub fn stats(&self) -> &JitterStats {
+        &self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len() + self.pending.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// now is the arrival time in milliseconds,
+    /// returns false if the packet is dropped as duplicate or arrived after declared lost
+    pub fn push(&mut self, now: i64, seq: u16, ts: u32, data: T) -> bool {
+        self.stats.received += 1;
+
+        let ts = self.ts_ext.convert(ts);
+
+        if !self.inner.push(seq, JitterSlot { ts, data }) {
+            self.stats.late += 1;
+            return false
+        }
+
+        self.update_transit(now - self.ts_to_millis(ts));
+        true
+    }
+
+    /// release next packet or gap which is due at now
+    pub fn pop(&mut self, now: i64) -> Option<JitterOutput<T>> {
+        if let Some(packet) = self.pending.take() {
+            return Some(self.release(packet))
+        }
+
+        let due = match self.inner.peek() {
+            Some((_order, slot)) => {
+                let overflow = self.inner.len() >= self.inner.capacity();
+                if overflow {
+                    self.stats.overflow += 1;
+                }
+                overflow || now >= self.playout_time(slot.ts)
+            },
+            None => false,
+        };
+
+        if due {
+            self.pop_head()
+        } else {
+            None
+        }
+    }
+
+    /// release next packet or gap regardless of time
+    pub fn flush(&mut self) -> Option<JitterOutput<T>> {
+        if let Some(packet) = self.pending.take() {
+            return Some(self.release(packet))
+        }
+        self.pop_head()
+    }
+
+    /// the time at which the head packet will be due, useful for scheduling next pop
+    pub fn next_due(&self) -> Option<i64> {
+        if let Some(packet) = &self.pending {
+            return Some(self.playout_time(packet.ts))
+        }
+
+        self.inner.peek().map(|(_order, slot)| self.playout_time(slot.ts))
+    }
+
+    fn pop_head(&mut self) -> Option<JitterOutput<T>> {
+        let (order, slot) = self.inner.pop()?;
+        let packet = JitterPacket {
+            order,
+            ts: slot.ts,
+            data: slot.data,
+        };
+
+        match order {
+            RtpOrder::Lost(count, seq) => {
+                self.stats.lost += count as u64;
+                let lost = JitterLost {
+                    seq: seq - count as i64,
+                    count,
+                    prev_ts: self.last_released_ts,
+                    next_ts: packet.ts,
+                };
+                self.pending = Some(packet);
+                Some(JitterOutput::Lost(lost))
+            },
+            _ => Some(self.release(packet)),
+        }
+    }
+
+    fn release(&mut self, packet: JitterPacket<T>) -> JitterOutput<T> {
+        self.stats.released += 1;
+        self.last_released_ts = Some(packet.ts);
+        JitterOutput::Packet(packet)
+    }
+
+    fn playout_time(&self, ts: i64) -> i64 {
+        self.ts_to_millis(ts) + self.base_transit.unwrap_or(0) + self.target_delay
+    }
+
+    fn ts_to_millis(&self, ts: i64) -> i64 {
+        ts * 1000 / self.clock_rate as i64
+    }
+
+    fn update_transit(&mut self, transit: i64) {
+        // RFC 3550 A.8
+        if let Some(last) = self.last_transit {
+            let d = (transit - last).abs();
+            self.jitter_q4 += d - ((self.jitter_q4 + 8) >> 4);
+        }
+        self.last_transit = Some(transit);
+
+        if self.transits.len() >= self.config.transit_window {
+            self.transits.pop_front();
+        }
+        self.transits.push_back(transit);
+        self.base_transit = self.transits.iter().min().cloned();
+
+        let target = (self.jitter_ms() * self.config.jitter_factor)
+            .clamp(self.config.min_delay_ms, self.config.max_delay_ms);
+
+        if target > self.target_delay {
+            self.target_delay = target;
+        } else if target < self.target_delay {
+            // shrink slowly to avoid oscillation
+            self.target_delay -= 1;
+        }
+    }
+}
+
+#[test]
+fn test_jitter_inorder() {
+    let config = JitterConfig {
+        min_delay_ms: 40,
+        init_delay_ms: 40,
+        ..Default::default()
+    };
+    let mut buf = RtpJitterBuf::new(8000, config);
+
+    // 20ms per packet, 160 samples
+    assert!(buf.push(0, 1, 0, 'a'));
+    assert!(buf.push(20, 2, 160, 'b'));
+    assert_eq!(buf.pop(20), None);
+    assert_eq!(buf.next_due(), Some(40));
+
+    match buf.pop(40) {
+        Some(JitterOutput::Packet(p)) => {
+            assert_eq!(p.order, RtpOrder::Normal(1));
+            assert_eq!(p.data, 'a');
+        },
+        _ => panic!("expect packet"),
+    }
+    assert_eq!(buf.pop(40), None);
+    match buf.pop(60) {
+        Some(JitterOutput::Packet(p)) => {
+            assert_eq!(p.order, RtpOrder::Normal(2));
+            assert_eq!(p.ts, 160);
+        },
+        _ => panic!("expect packet"),
+    }
+    assert_eq!(buf.pop(1000), None);
+}
+
+#[test]
+fn test_jitter_reorder_and_lost() {
+    let config = JitterConfig {
+        min_delay_ms: 40,
+        init_delay_ms: 40,
+        ..Default::default()
+    };
+    let mut buf = RtpJitterBuf::new(8000, config);
+
+    assert!(buf.push(0, 1, 0, 1));
+    assert!(buf.push(25, 3, 320, 3));
+    assert!(buf.push(30, 2, 160, 2));
+    assert!(buf.push(60, 5, 640, 5));
+
+    let mut seqs = Vec::new();
+    let mut lost = Vec::new();
+    let mut now = 0;
+    while now < 200 {
+        while let Some(item) = buf.pop(now) {
+            match item {
+                JitterOutput::Packet(p) => seqs.push(p.seq()),
+                JitterOutput::Lost(l) => lost.push(l),
+            }
+        }
+        now += 10;
+    }
+
+    assert_eq!(seqs, vec![1, 2, 3, 5]);
+    assert_eq!(lost.len(), 1);
+    assert_eq!(lost[0].seq, 4);
+    assert_eq!(lost[0].count, 1);
+    assert_eq!(lost[0].prev_ts, Some(320));
+    assert_eq!(lost[0].next_ts, 640);
+
+    // arrived after declared lost
+    assert!(!buf.push(210, 4, 480, 4));
+    assert_eq!(buf.stats().lost, 1);
+    assert_eq!(buf.stats().late, 1);
+}
+
+#[test]
+fn test_jitter_adapt() {
+    let config = JitterConfig {
+        min_delay_ms: 20,
+        init_delay_ms: 20,
+        ..Default::default()
+    };
+    let mut buf = RtpJitterBuf::new(8000, config);
+
+    let mut seq = 0_u16;
+    let mut ts = 0_u32;
+    for n in 0..100_i64 {
+        let now = n * 20 + if n % 2 == 0 { 0 } else { 30 };
+        buf.push(now, seq, ts, ());
+        seq = seq.wrapping_add(1);
+        ts = ts.wrapping_add(160);
+        while buf.pop(now).is_some() {}
+    }
+    assert!(buf.jitter_ms() > 10);
+    assert!(buf.target_delay_ms() > 20);
+}
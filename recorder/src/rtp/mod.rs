@@ -11,4 +11,18 @@ pub mod fmtp;
 
 pub mod depack;
 
+pub mod pack;
+
 pub mod inorder;
+
+pub mod jitter;
+
+pub mod red;
+
+pub mod rtx;
+
+pub mod fec;
+
+pub mod rtpext;
+
+pub mod srtp;
@@ -0,0 +1,170 @@
+// drops and reorders packets like a lossy network,
+// deterministic by the seed so that a failing test can be replayed
+
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RtpImpairStats {
+    pub input: u64,
+    pub dropped: u64,
+    pub reordered: u64,
+}
+
+#[derive(Clone)]
+pub struct RtpImpair {
+    // per mille
+    loss: u32,
+    reorder: u32,
+
+    // delayed packets go behind at most this many packets
+    reorder_depth: u32,
+
+    // packets delayed with the number of packets to wait
+    delayed: Vec<(u32, Bytes)>,
+    output: VecDeque<Bytes>,
+    rng: u32,
+    stats: RtpImpairStats,
+}
+
+impl Default for RtpImpair {
+    fn default() -> Self {
+        Self {
+            loss: 0,
+            reorder: 0,
+            reorder_depth: 3,
+            delayed: Vec::new(),
+            output: Default::default(),
+            rng: 0x2545_F491,
+            stats: Default::default(),
+        }
+    }
+}
+
+impl RtpImpair {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        // xorshift never leaves zero
+        self.rng = if seed == 0 { 0x2545_F491 } else { seed };
+        self
+    }
+
+    pub fn with_loss(mut self, per_mille: u32) -> Self {
+        self.loss = per_mille;
+        self
+    }
+
+    pub fn with_reorder(mut self, per_mille: u32, depth: u32) -> Self {
+        self.reorder = per_mille;
+        self.reorder_depth = depth.max(1);
+        self
+    }
+
+    pub fn push(&mut self, packet: Bytes) {
+        self.stats.input += 1;
+
+        if self.next_per_mille() < self.loss {
+            self.stats.dropped += 1;
+            return;
+        }
+
+        if self.next_per_mille() < self.reorder {
+            let wait = 1 + self.next_rand() % self.reorder_depth;
+            self.delayed.push((wait, packet));
+            self.stats.reordered += 1;
+            return;
+        }
+
+        self.output.push_back(packet);
+
+        // release delayed packets whose wait is over, in the order they were delayed
+        for item in self.delayed.iter_mut() {
+            item.0 -= 1;
+        }
+        while let Some(n) = self.delayed.iter().position(|x| x.0 == 0) {
+            let (_wait, packet) = self.delayed.remove(n);
+            self.output.push_back(packet);
+        }
+    }
+
+    pub fn pull(&mut self) -> Option<Bytes> {
+        self.output.pop_front()
+    }
+
+    // releases all delayed packets, e.g. at the end of stream
+    pub fn flush(&mut self) {
+        for (_wait, packet) in self.delayed.drain(..) {
+            self.output.push_back(packet);
+        }
+    }
+
+    pub fn stats(&self) -> &RtpImpairStats {
+        &self.stats
+    }
+
+    fn next_per_mille(&mut self) -> u32 {
+        self.next_rand() % 1000
+    }
+
+    // xorshift32
+    fn next_rand(&mut self) -> u32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng
+    }
+}
+
+// impairs all packets at once
+pub fn impair_packets(impair: &mut RtpImpair, packets: Vec<Bytes>) -> Vec<Bytes> {
+    let mut output = Vec::with_capacity(packets.len());
+    for packet in packets {
+        impair.push(packet);
+        while let Some(packet) = impair.pull() {
+            output.push(packet);
+        }
+    }
+    impair.flush();
+    while let Some(packet) = impair.pull() {
+        output.push(packet);
+    }
+    output
+}
+
+
+#[test]
+fn test_rtp_impair() {
+    let packets: Vec<Bytes> = (0..1000_u16).map(|x| Bytes::copy_from_slice(&x.to_be_bytes())).collect();
+    let seq = |x: &Bytes| u16::from_be_bytes([x[0], x[1]]);
+
+    // passes through by default
+    let output = impair_packets(&mut RtpImpair::new(), packets.clone());
+    assert_eq!(output, packets);
+
+    let mut impair = RtpImpair::new().with_seed(7).with_loss(50).with_reorder(100, 3);
+    let output = impair_packets(&mut impair, packets.clone());
+    let stats = impair.stats().clone();
+    assert_eq!(stats.input, 1000);
+    assert_eq!(output.len() as u64, stats.input - stats.dropped);
+    assert!((20..80).contains(&stats.dropped), "{stats:?}");
+    assert!((50..150).contains(&stats.reordered), "{stats:?}");
+
+    // no duplicates and none goes far from its place
+    let mut sorted: Vec<_> = output.iter().map(seq).collect();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(sorted.len(), output.len());
+    let late = output.windows(2).filter(|x| seq(&x[0]) > seq(&x[1])).count();
+    assert!(late > 0);
+    for (n, packet) in output.iter().enumerate() {
+        assert!((seq(packet) as i64 - n as i64).abs() < 100);
+    }
+
+    // replayed by the same seed
+    let mut impair = RtpImpair::new().with_seed(7).with_loss(50).with_reorder(100, 3);
+    assert_eq!(impair_packets(&mut impair, packets), output);
+}
@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use bytes::Bytes;
+
+use super::rtp::make_rtp_packet;
+
+pub mod impair;
+
+// the reverse of RtpCodecDepacker, one frame (access unit) in and its rtp packets out
+pub trait RtpCodecPacker {
+    fn push_frame(&mut self, frame: &[u8], rtp_ts: u32) -> Result<()> ;
+    fn pull_rtp(&mut self) -> Option<Bytes> ;
+}
+
+// header of the outgoing stream,
+// sequence number goes up by one for each packet made
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtpPackHeader {
+    pub payload_type: u8,
+    pub ssrc: u32,
+    pub seq: u16,
+}
+
+impl RtpPackHeader {
+    pub fn new(payload_type: u8, ssrc: u32) -> Self {
+        Self {
+            payload_type,
+            ssrc,
+            seq: 0,
+        }
+    }
+
+    pub fn with_seq(mut self, seq: u16) -> Self {
+        self.seq = seq;
+        self
+    }
+
+    pub fn make_packet(&mut self, mark: bool, ts: u32, payload: &[u8]) -> Bytes {
+        let packet = make_rtp_packet(self.payload_type, mark, self.seq, ts, self.ssrc, payload);
+        self.seq = self.seq.wrapping_add(1);
+        packet
+    }
+}
+
+// packets made but not pulled yet, shared by the packers
+#[derive(Debug)]
+pub struct RtpPackQueue {
+    header: RtpPackHeader,
+    packets: VecDeque<Bytes>,
+}
+
+impl RtpPackQueue {
+    pub fn new(header: RtpPackHeader) -> Self {
+        Self {
+            header,
+            packets: Default::default(),
+        }
+    }
+
+    pub fn header(&self) -> &RtpPackHeader {
+        &self.header
+    }
+
+    pub fn push(&mut self, mark: bool, ts: u32, payload: &[u8]) {
+        let packet = self.header.make_packet(mark, ts, payload);
+        self.packets.push_back(packet);
+    }
+
+    pub fn pull(&mut self) -> Option<Bytes> {
+        self.packets.pop_front()
+    }
+}
+
+// all packets of frames by rtp timestamp
+pub fn pack_frames<'a, I>(packer: &mut dyn RtpCodecPacker, frames: I) -> Result<Vec<Bytes>>
+where
+    I: IntoIterator<Item = (&'a [u8], u32)>,
+{
+    let mut packets = Vec::new();
+    for (frame, rtp_ts) in frames {
+        packer.push_frame(frame, rtp_ts)?;
+        while let Some(packet) = packer.pull_rtp() {
+            packets.push(packet);
+        }
+    }
+    Ok(packets)
+}
+
+
+#[test]
+fn test_rtp_pack_header() {
+    let mut queue = RtpPackQueue::new(RtpPackHeader::new(96, 0x1234).with_seq(u16::MAX));
+    queue.push(false, 3000, &[0xA1]);
+    queue.push(true, 3000, &[0xA2, 0xA3]);
+    assert_eq!(queue.header().seq, 1);
+
+    let packet = queue.pull().unwrap();
+    assert_eq!(&packet[..], &[0x80, 96, 0xFF, 0xFF, 0, 0, 0x0B, 0xB8, 0, 0, 0x12, 0x34, 0xA1]);
+
+    let packet = queue.pull().unwrap();
+    let rtp = rtp_rs::RtpReader::new(&packet).unwrap();
+    assert_eq!((rtp.payload_type(), rtp.mark()), (96, true));
+    assert_eq!((u16::from(rtp.sequence_number()), rtp.timestamp(), rtp.ssrc()), (0, 3000, 0x1234));
+    assert_eq!(rtp.payload(), &[0xA2, 0xA3]);
+
+    assert!(queue.pull().is_none());
+}
@@ -0,0 +1,227 @@
+// RFC 2198 RTP Payload for Redundant Audio Data
+//   - blocks are unwrapped into standalone rtp packets with the block payload type,
+//     so that the codec depackers work as usual, the primary keeps the original header
+//   - redundant blocks are emitted only for packets which were not delivered,
+//     the seq of a redundant block is derived from its distance to the primary (same as libwebrtc)
+
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use rtp_rs::RtpReader;
+
+use super::rtp::{make_rtp_packet, make_rtp_packet_with_header};
+
+// number of recent delivered timestamps for de-duplicate
+const MAX_RECENT: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedBlock<'a> {
+    pub payload_type: u8,
+
+    // 0 for the primary block
+    pub ts_offset: u32,
+
+    pub data: &'a [u8],
+}
+
+//  0                   1                    2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |F|   block PT  |  timestamp offset         |   block length    |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// the last header is one byte with F=0 and block PT of the primary,
+// blocks are in the same order as headers, the primary is the last
+pub fn parse_red_payload(payload: &[u8]) -> Result<Vec<RedBlock<'_>>> {
+    let mut headers = Vec::new();
+    let mut offset = 0;
+    loop {
+        let b0 = *payload.get(offset).ok_or_else(||anyhow!("red header truncated"))?;
+        if b0 & 0x80 == 0 {
+            headers.push((b0 & 0x7F, 0, None));
+            offset += 1;
+            break;
+        }
+
+        if payload.len() < offset + 4 {
+            bail!("red header truncated")
+        }
+        let v = u32::from_be_bytes([payload[offset], payload[offset+1], payload[offset+2], payload[offset+3]]);
+        headers.push((b0 & 0x7F, (v >> 10) & 0x3FFF, Some((v & 0x3FF) as usize)));
+        offset += 4;
+    }
+
+    let mut blocks = Vec::with_capacity(headers.len());
+    for (payload_type, ts_offset, len) in headers {
+        let len = len.unwrap_or(payload.len() - offset.min(payload.len()));
+        if payload.len() < offset + len {
+            bail!("red block length [{len}] exceed remains [{}]", payload.len().saturating_sub(offset))
+        }
+        blocks.push(RedBlock {
+            payload_type,
+            ts_offset,
+            data: &payload[offset..offset + len],
+        });
+        offset += len;
+    }
+    Ok(blocks)
+}
+
+pub struct RtpRedUnwrapper {
+    recent: VecDeque<u32>,
+    last_ts: Option<u32>,
+    packets: VecDeque<Bytes>,
+    recovered: u64,
+}
+
+impl Default for RtpRedUnwrapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RtpRedUnwrapper {
+    pub fn new() -> Self {
+        Self {
+            recent: Default::default(),
+            last_ts: None,
+            packets: Default::default(),
+            recovered: 0,
+        }
+    }
+
+    // number of packets rebuilt from redundant blocks
+    pub fn recovered(&self) -> u64 {
+        self.recovered
+    }
+
+    pub fn push_rtp_slice(&mut self, data: &[u8]) -> Result<()> {
+        let rtp = RtpReader::new(data)
+        .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+        let seq: u16 = rtp.sequence_number().into();
+        let ts = rtp.timestamp();
+        let ssrc = rtp.ssrc();
+
+        let blocks = parse_red_payload(rtp.payload())?;
+        let num = blocks.len();
+
+        for (index, block) in blocks.iter().enumerate() {
+            let distance = (num - 1 - index) as u16;
+            let block_ts = ts.wrapping_sub(block.ts_offset);
+
+            if self.recent.contains(&block_ts) {
+                // already delivered
+                continue;
+            }
+
+            if distance > 0 {
+                match self.last_ts {
+                    // older than delivered, too late to be useful
+                    Some(last) if (block_ts.wrapping_sub(last) as i32) > 0 => {},
+                    _ => continue,
+                }
+            }
+
+            if block.data.is_empty() {
+                continue;
+            }
+
+            let packet = if distance == 0 {
+                // the original header is required by ulpfec recovery
+                make_rtp_packet_with_header(&data[..rtp.payload_offset()], block.payload_type, block.data)
+            } else {
+                make_rtp_packet(
+                    block.payload_type,
+                    false,
+                    seq.wrapping_sub(distance),
+                    block_ts,
+                    ssrc,
+                    block.data,
+                )
+            };
+            self.packets.push_back(packet);
+
+            if distance > 0 {
+                self.recovered += 1;
+            }
+
+            if self.recent.len() >= MAX_RECENT {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(block_ts);
+
+            match self.last_ts {
+                Some(last) if (block_ts.wrapping_sub(last) as i32) <= 0 => {},
+                _ => self.last_ts = Some(block_ts),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn pull_rtp(&mut self) -> Option<Bytes> {
+        self.packets.pop_front()
+    }
+}
+
+
+#[cfg(test)]
+fn make_red(seq: u16, ts: u32, blocks: &[(u32, &[u8])], primary: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0x80, 63];
+    buf.extend_from_slice(&seq.to_be_bytes());
+    buf.extend_from_slice(&ts.to_be_bytes());
+    buf.extend_from_slice(&0x1234_u32.to_be_bytes());
+    for (offset, data) in blocks {
+        let v = 0x8000_0000 | (111 << 24) | (offset << 10) | data.len() as u32;
+        buf.extend_from_slice(&v.to_be_bytes());
+    }
+    buf.push(111);
+    for (_offset, data) in blocks {
+        buf.extend_from_slice(data);
+    }
+    buf.extend_from_slice(primary);
+    buf
+}
+
+#[test]
+fn test_red_parse() {
+    let rtp = make_red(1, 1920, &[(960, &[0x01, 0x02])], &[0x03]);
+    let blocks = parse_red_payload(&rtp[12..]).unwrap();
+    assert_eq!(blocks, vec![
+        RedBlock { payload_type: 111, ts_offset: 960, data: &[0x01, 0x02] },
+        RedBlock { payload_type: 111, ts_offset: 0, data: &[0x03] },
+    ]);
+
+    assert!(parse_red_payload(&rtp[12..15]).is_err());
+}
+
+#[test]
+fn test_red_unwrap() {
+    let mut red = RtpRedUnwrapper::new();
+
+    red.push_rtp_slice(&make_red(10, 960, &[(960, &[0xA0])], &[0xA1])).unwrap();
+    red.push_rtp_slice(&make_red(11, 1920, &[(960, &[0xA1])], &[0xA2])).unwrap();
+
+    // seq 12 lost, recovered from 13
+    red.push_rtp_slice(&make_red(13, 3840, &[(1920, &[0xA2]), (960, &[0xA3])], &[0xA4])).unwrap();
+
+    // late seq 12 is dropped
+    red.push_rtp_slice(&make_red(12, 2880, &[(960, &[0xA2])], &[0xA3])).unwrap();
+
+    let mut packets = Vec::new();
+    while let Some(packet) = red.pull_rtp() {
+        let rtp = RtpReader::new(&packet).unwrap();
+        assert_eq!(rtp.payload_type(), 111);
+        packets.push((u16::from(rtp.sequence_number()), rtp.timestamp(), rtp.payload()[0]));
+    }
+
+    // the first redundant block is before the first packet
+    assert_eq!(packets, vec![
+        (10, 960, 0xA1),
+        (11, 1920, 0xA2),
+        (12, 2880, 0xA3),
+        (13, 3840, 0xA4),
+    ]);
+    assert_eq!(red.recovered(), 1);
+}
@@ -4,7 +4,7 @@ use std::num::{NonZeroU16, NonZeroU32};
 use anyhow::{Result, anyhow};
 use bytes::{Buf, BufMut, Bytes};
 
-use super::super::depack::RtpCodecDepacker;
+use super::super::depack::{DepackedFrame, RtpCodecDepacker, RtpLossCounter};
 
 pub type RetinaDepackH264 = RetinaDepack<PostPullAnnexb>;
 
@@ -22,6 +22,7 @@ pub fn make_retina_depack_h264(fmtp: Option<&str>) -> Result<RetinaDepackH264> {
         depack,
         ctx: retina::PacketContext::dummy(),
         clock_rate,
+        loss: Default::default(),
         post: PostPullAnnexb(),
     } )
 }
@@ -45,20 +46,26 @@ pub fn make_retina_depack_aac(
         depack,
         ctx: retina::PacketContext::dummy(),
         clock_rate,
+        loss: Default::default(),
         post: PostPullAudio(),
     } )
 }
 
 
 pub trait PostPullOp {
-    fn post_pull(&mut self, item: retina::codec::CodecItem) -> Result<Option<Bytes>>;
+    fn post_pull(&mut self, item: retina::codec::CodecItem) -> Result<Option<DepackedFrame>>;
 }
 
 pub struct PostPullAnnexb();
 impl PostPullOp for PostPullAnnexb {
-    fn post_pull(&mut self, item: retina::codec::CodecItem) -> Result<Option<Bytes>> {
+    fn post_pull(&mut self, item: retina::codec::CodecItem) -> Result<Option<DepackedFrame>> {
         match item {
             retina::codec::CodecItem::VideoFrame(frame) => {
+                let rtp_ts = frame.timestamp().timestamp() as u32;
+                let keyframe = frame.is_random_access_point();
+                let loss = frame.loss();
+                let new_parameters = frame.has_new_parameters();
+
                 let mut frame = frame.into_data();
                 let mut data = &mut frame[..];
                 while data.len() >= 4 {
@@ -67,7 +74,12 @@ impl PostPullOp for PostPullAnnexb {
                     data = &mut data[unit_len..];
                 }
 
-                Ok(Some(frame.into()))
+                Ok(Some(DepackedFrame {
+                    new_parameters,
+                    ..DepackedFrame::new(frame.into(), rtp_ts)
+                    .with_keyframe(keyframe)
+                    .with_loss(loss)
+                }))
             }
             _ => Ok(None)
         }
@@ -76,11 +88,12 @@ impl PostPullOp for PostPullAnnexb {
 
 pub struct PostPullAudio();
 impl PostPullOp for PostPullAudio {
-    fn post_pull(&mut self, item: retina::codec::CodecItem) -> Result<Option<Bytes>> {
+    fn post_pull(&mut self, item: retina::codec::CodecItem) -> Result<Option<DepackedFrame>> {
         match item {
             retina::codec::CodecItem::AudioFrame(frame) => {
-                let frame = Bytes::copy_from_slice(frame.data()) ;
-                Ok(Some(frame.into()))
+                let data = Bytes::copy_from_slice(frame.data()) ;
+                let rtp_ts = frame.timestamp().timestamp() as u32;
+                Ok(Some(DepackedFrame::audio(data, rtp_ts).with_loss(frame.loss())))
             }
             _ => Ok(None)
         }
@@ -92,6 +105,7 @@ pub struct RetinaDepack<P> {
     ctx: retina::PacketContext,
     depack: retina::codec::Depacketizer,
     clock_rate: u32,
+    loss: RtpLossCounter,
     post: P,
 }
 
@@ -106,15 +120,21 @@ impl<P: PostPullOp + 'static> RetinaDepack<P> {
         let rtp = rtp_rs::RtpReader::new(data)
         .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
 
+        let timestamp = retina::Timestamp::new(
+            rtp.timestamp() as i64,
+            NonZeroU32::new(self.clock_rate).unwrap(),
+            0,
+        ).unwrap();
+
         let packet = retina::rtp::ReceivedPacketBuilder {
             ctx: self.ctx.clone(),
             stream_id: 0,
             sequence_number: rtp.sequence_number().into(),
-            timestamp: retina::Timestamp::new(0, NonZeroU32::new(self.clock_rate).unwrap(), 0).unwrap(),
+            timestamp,
             payload_type: rtp.payload_type(),
             ssrc: rtp.ssrc(),
             mark: rtp.mark(),
-            loss: 0,
+            loss: self.loss.push(rtp.sequence_number().into()),
         }
         .build(Bytes::copy_from_slice(rtp.payload()))
         .map_err(|e|anyhow!("{e}"))?;
@@ -132,7 +152,7 @@ impl<P: PostPullOp + 'static> RtpCodecDepacker for RetinaDepack<P> {
         Ok(())
     }
 
-    fn pull_frame(&mut self) -> Result<Option<Bytes>>  {
+    fn pull_frame(&mut self) -> Result<Option<DepackedFrame>>  {
         
         let r = self.depack.pull(
             &retina::ConnectionContext::dummy(),
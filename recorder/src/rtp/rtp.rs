@@ -1,4 +1,6 @@
 
+use bytes::{BufMut, Bytes, BytesMut};
+
 // use anyhow::Result;
 // use bytes::Bytes;
 
@@ -20,3 +22,25 @@ pub fn check_is_rtcp(data: &[u8]) -> bool {
     let pt = data[1] & 0x7F;
     return (63 < pt) && (pt < 96);
 }
+
+// standalone rtp packet without csrc and extension
+pub fn make_rtp_packet(payload_type: u8, mark: bool, seq: u16, ts: u32, ssrc: u32, payload: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(12 + payload.len());
+    buf.put_u8(0x80);
+    buf.put_u8(if mark { 0x80 | payload_type } else { payload_type });
+    buf.put_u16(seq);
+    buf.put_u32(ts);
+    buf.put_u32(ssrc);
+    buf.put(payload);
+    buf.freeze()
+}
+
+// keep the header (csrc and extension) of the original packet, padding is removed
+pub fn make_rtp_packet_with_header(header: &[u8], payload_type: u8, payload: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(header.len() + payload.len());
+    buf.put(header);
+    buf[0] &= !0x20;
+    buf[1] = (buf[1] & 0x80) | payload_type;
+    buf.put(payload);
+    buf.freeze()
+}
@@ -0,0 +1,319 @@
+// RFC 8285 A General Mechanism for RTP Header Extensions,
+//   the local identifiers are mapped to known extensions by a=extmap of sdp
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use rtp_rs::RtpReader;
+
+pub const URI_AUDIO_LEVEL: &str = "urn:ietf:params:rtp-hdrext:ssrc-audio-level";
+pub const URI_ABS_SEND_TIME: &str = "http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time";
+pub const URI_TRANSPORT_CC: &str = "http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01";
+pub const URI_VIDEO_ORIENTATION: &str = "urn:3gpp:video-orientation";
+pub const URI_MID: &str = "urn:ietf:params:rtp-hdrext:sdes:mid";
+pub const URI_RID: &str = "urn:ietf:params:rtp-hdrext:sdes:rtp-stream-id";
+pub const URI_REPAIRED_RID: &str = "urn:ietf:params:rtp-hdrext:sdes:repaired-rtp-stream-id";
+pub const URI_PLAYOUT_DELAY: &str = "http://www.webrtc.org/experiments/rtp-hdrext/playout-delay";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RtpExtKind {
+    AudioLevel,
+    AbsSendTime,
+    TransportCC,
+    VideoOrientation,
+    Mid,
+    Rid,
+    RepairedRid,
+    PlayoutDelay,
+}
+
+impl RtpExtKind {
+    pub fn from_uri(uri: &str) -> Option<Self> {
+        match uri {
+            URI_AUDIO_LEVEL => Some(Self::AudioLevel),
+            URI_ABS_SEND_TIME => Some(Self::AbsSendTime),
+            URI_TRANSPORT_CC => Some(Self::TransportCC),
+            URI_VIDEO_ORIENTATION => Some(Self::VideoOrientation),
+            URI_MID => Some(Self::Mid),
+            URI_RID => Some(Self::Rid),
+            URI_REPAIRED_RID => Some(Self::RepairedRid),
+            URI_PLAYOUT_DELAY => Some(Self::PlayoutDelay),
+            _ => None,
+        }
+    }
+}
+
+// RFC 6464
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioLevel {
+    pub voice: bool,
+
+    // in -dBov, 127 is silence
+    pub level: u8,
+}
+
+// 3GPP TS 26.114 section 7.4.5, coordination of video orientation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoOrientation {
+    // back-facing camera
+    pub camera: bool,
+
+    // horizontal flip
+    pub flip: bool,
+
+    // 0, 90, 180 or 270
+    pub rotation: u16,
+}
+
+// in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayoutDelay {
+    pub min: u32,
+    pub max: u32,
+}
+
+// typed values of the known extensions in one packet
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RtpExtensions {
+    pub audio_level: Option<AudioLevel>,
+
+    // 6.18 fixed point seconds, 24 bits
+    pub abs_send_time: Option<u32>,
+
+    pub transport_seq: Option<u16>,
+    pub video_orientation: Option<VideoOrientation>,
+    pub mid: Option<String>,
+    pub rid: Option<String>,
+    pub repaired_rid: Option<String>,
+    pub playout_delay: Option<PlayoutDelay>,
+}
+
+impl RtpExtensions {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn set(&mut self, kind: RtpExtKind, data: &[u8]) -> Result<()> {
+        match kind {
+            RtpExtKind::AudioLevel => {
+                let b = *data.first().ok_or_else(||anyhow!("empty audio level"))?;
+                self.audio_level = Some(AudioLevel {
+                    voice: b & 0x80 != 0,
+                    level: b & 0x7F,
+                });
+            },
+            RtpExtKind::AbsSendTime => {
+                if data.len() < 3 {
+                    bail!("abs-send-time too short [{}]", data.len())
+                }
+                self.abs_send_time = Some(u32::from_be_bytes([0, data[0], data[1], data[2]]));
+            },
+            RtpExtKind::TransportCC => {
+                if data.len() < 2 {
+                    bail!("transport-cc too short [{}]", data.len())
+                }
+                self.transport_seq = Some(u16::from_be_bytes([data[0], data[1]]));
+            },
+            RtpExtKind::VideoOrientation => {
+                let b = *data.first().ok_or_else(||anyhow!("empty video orientation"))?;
+                self.video_orientation = Some(VideoOrientation {
+                    camera: b & 0x08 != 0,
+                    flip: b & 0x04 != 0,
+                    rotation: (b & 0x03) as u16 * 90,
+                });
+            },
+            RtpExtKind::Mid => self.mid = Some(parse_sdes(data)?),
+            RtpExtKind::Rid => self.rid = Some(parse_sdes(data)?),
+            RtpExtKind::RepairedRid => self.repaired_rid = Some(parse_sdes(data)?),
+            RtpExtKind::PlayoutDelay => {
+                if data.len() < 3 {
+                    bail!("playout-delay too short [{}]", data.len())
+                }
+                // 12 bits each in 10 ms
+                let v = u32::from_be_bytes([0, data[0], data[1], data[2]]);
+                self.playout_delay = Some(PlayoutDelay {
+                    min: (v >> 12) * 10,
+                    max: (v & 0xFFF) * 10,
+                });
+            },
+        }
+        Ok(())
+    }
+}
+
+fn parse_sdes(data: &[u8]) -> Result<String> {
+    std::str::from_utf8(data)
+    .map(|x| x.to_string())
+    .map_err(|_e|anyhow!("invalid sdes item"))
+}
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |       0xBE    |    0xDE       |           length              |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |  ID   |  L    |     data      |  ID   |  L    |     data...
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// one-byte header with profile 0xBEDE, length is L + 1,
+// two-byte header with profile 0x100X, 8 bits ID and 8 bits length,
+// returns (id, data) of each element
+pub fn parse_rtp_extension_elements(profile: u16, data: &[u8]) -> Result<Vec<(u8, &[u8])>> {
+    let two_byte = if profile == 0xBEDE {
+        false
+    } else if profile & 0xFFF0 == 0x1000 {
+        true
+    } else {
+        bail!("unknown extension profile [{profile:#06x}]")
+    };
+
+    let mut elements = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let (id, len, header_len) = if two_byte {
+            let id = data[offset];
+            if id == 0 {
+                // padding
+                offset += 1;
+                continue;
+            }
+            let len = *data.get(offset + 1).ok_or_else(||anyhow!("extension element truncated"))? as usize;
+            (id, len, 2)
+        } else {
+            let id = data[offset] >> 4;
+            if id == 0 {
+                // padding
+                offset += 1;
+                continue;
+            }
+            if id == 15 {
+                // reserved, stop parsing
+                break;
+            }
+            (id, (data[offset] & 0x0F) as usize + 1, 1)
+        };
+
+        let start = offset + header_len;
+        if data.len() < start + len {
+            bail!("extension element [{id}] length [{len}] exceed remains [{}]", data.len().saturating_sub(start))
+        }
+        elements.push((id, &data[start..start + len]));
+        offset = start + len;
+    }
+    Ok(elements)
+}
+
+// local identifier to extension kind
+#[derive(Debug, Clone, Default)]
+pub struct RtpExtMap {
+    kinds: HashMap<u8, RtpExtKind>,
+}
+
+impl RtpExtMap {
+    pub fn new<'a, I: IntoIterator<Item = (u8, &'a str)>>(extmaps: I) -> Self {
+        Self {
+            kinds: extmaps.into_iter()
+                .filter_map(|(id, uri)| RtpExtKind::from_uri(uri).map(|x| (id, x)))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    pub fn get(&self, id: u8) -> Option<RtpExtKind> {
+        self.kinds.get(&id).cloned()
+    }
+
+    // unknown or malformed elements are ignored, the rest are kept
+    pub fn parse(&self, rtp: &[u8]) -> Result<RtpExtensions> {
+        let rtp = RtpReader::new(rtp)
+        .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+        let mut exts = RtpExtensions::default();
+        if let Some((profile, data)) = rtp.extension() {
+            for (id, data) in parse_rtp_extension_elements(profile, data)? {
+                if let Some(kind) = self.get(id) {
+                    if let Err(e) = exts.set(kind, data) {
+                        tracing::debug!("skip rtp extension {kind:?}, {e:?}");
+                    }
+                }
+            }
+        }
+        Ok(exts)
+    }
+}
+
+
+#[cfg(test)]
+fn make_rtp(profile: u16, ext: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0x90, 96];
+    buf.extend_from_slice(&1_u16.to_be_bytes());
+    buf.extend_from_slice(&3000_u32.to_be_bytes());
+    buf.extend_from_slice(&0x1234_u32.to_be_bytes());
+    buf.extend_from_slice(&profile.to_be_bytes());
+    buf.extend_from_slice(&((ext.len() / 4) as u16).to_be_bytes());
+    buf.extend_from_slice(ext);
+    buf.extend_from_slice(&[0xA0, 0xA1]);
+    buf
+}
+
+#[test]
+fn test_rtpext_one_byte() {
+    let extmap = RtpExtMap::new([
+        (1, URI_AUDIO_LEVEL),
+        (2, URI_ABS_SEND_TIME),
+        (3, URI_TRANSPORT_CC),
+        (4, URI_MID),
+        (5, "urn:unknown"),
+    ]);
+
+    let ext = [
+        0x10, 0x8A, // audio level, voice, 10
+        0x22, 0x12, 0x34, 0x56, // abs-send-time
+        0x00, // padding
+        0x31, 0x01, 0x02, // transport seq
+        0x40, b'0', // mid
+        0x50, 0xFF, // unknown
+        0x00, 0x00,
+    ];
+    let exts = extmap.parse(&make_rtp(0xBEDE, &ext)).unwrap();
+    assert_eq!(exts.audio_level, Some(AudioLevel { voice: true, level: 10 }));
+    assert_eq!(exts.abs_send_time, Some(0x123456));
+    assert_eq!(exts.transport_seq, Some(0x0102));
+    assert_eq!(exts.mid.as_deref(), Some("0"));
+    assert_eq!(exts.rid, None);
+
+    // truncated element
+    assert!(extmap.parse(&make_rtp(0xBEDE, &[0x13, 0x01, 0x02, 0x00])).is_err());
+}
+
+#[test]
+fn test_rtpext_two_byte() {
+    let extmap = RtpExtMap::new([
+        (10, URI_RID),
+        (11, URI_VIDEO_ORIENTATION),
+        (12, URI_PLAYOUT_DELAY),
+    ]);
+
+    let ext = [
+        10, 1, b'h',
+        11, 1, 0x0B, // back camera, 270
+        12, 3, 0x00, 0x20, 0x0A, // min 20 ms, max 100 ms
+        0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    let exts = extmap.parse(&make_rtp(0x1000, &ext)).unwrap();
+    assert_eq!(exts.rid.as_deref(), Some("h"));
+    assert_eq!(exts.video_orientation, Some(VideoOrientation { camera: true, flip: false, rotation: 270 }));
+    assert_eq!(exts.playout_delay, Some(PlayoutDelay { min: 20, max: 100 }));
+
+    // malformed playout delay
+    let ext = [
+        12, 1, 0x00,
+        10, 1, b'q',
+        0x00, 0x00,
+    ];
+    let exts = extmap.parse(&make_rtp(0x1000, &ext)).unwrap();
+    assert_eq!(exts.playout_delay, None);
+    assert_eq!(exts.rid.as_deref(), Some("q"));
+}
@@ -0,0 +1,57 @@
+// RFC 4588 RTP Retransmission Payload Format
+//   - the rtx stream has its own payload type and ssrc (session multiplexing),
+//   - the original packet is restored with the original sequence number (OSN)
+//     from the first 2 bytes of the payload
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use rtp_rs::RtpReader;
+
+use super::rtp::make_rtp_packet;
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                         RTP Header                            |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |            OSN                |                               |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+                               |
+// |                  Original RTP Packet Payload                  |
+// returns None for the padding-only packets used for bandwidth probing
+pub fn unwrap_rtx_packet(rtx: &[u8], payload_type: u8, ssrc: u32) -> Result<Option<Bytes>> {
+    let rtp = RtpReader::new(rtx)
+    .map_err(|e|anyhow!("invalid rtp [{e:?}]"))?;
+
+    let payload = rtp.payload();
+    if payload.len() <= 2 {
+        return Ok(None)
+    }
+
+    let osn = u16::from_be_bytes([payload[0], payload[1]]);
+
+    Ok(Some(make_rtp_packet(
+        payload_type,
+        rtp.mark(),
+        osn,
+        rtp.timestamp(),
+        ssrc,
+        &payload[2..],
+    )))
+}
+
+
+#[test]
+fn test_rtx_unwrap() {
+    let mut payload = 1234_u16.to_be_bytes().to_vec();
+    payload.extend_from_slice(&[0xA1, 0xA2]);
+    let rtx = make_rtp_packet(97, true, 500, 3000, 0x2222, &payload);
+
+    let packet = unwrap_rtx_packet(&rtx, 96, 0x1111).unwrap().unwrap();
+    let rtp = RtpReader::new(&packet).unwrap();
+    assert_eq!((rtp.payload_type(), rtp.mark()), (96, true));
+    assert_eq!((u16::from(rtp.sequence_number()), rtp.timestamp(), rtp.ssrc()), (1234, 3000, 0x1111));
+    assert_eq!(rtp.payload(), &[0xA1, 0xA2]);
+
+    // padding only
+    assert_eq!(unwrap_rtx_packet(&rtx[..14], 96, 0x1111).unwrap(), None);
+}
@@ -0,0 +1,757 @@
+// RFC 3711 SRTP and RFC 7714 AES-GCM for SRTP,
+//   decrypts and authenticates SRTP/SRTCP before depacking,
+//   keys are given by SDES a=crypto (RFC 4568) or by a key map of ssrc,
+//   SrtpSession works on raw packets, at ingest before writing tlv (then a=crypto
+//   should be removed from the written sdp) or by parse_tlv_file_with_keys
+
+use std::collections::HashMap;
+
+use aes::{cipher::{consts::U16, BlockEncrypt, KeyInit}, Aes128, Aes256, Block};
+use aes_gcm::{aead::AeadInPlace, Aes128Gcm, Aes256Gcm, Nonce, Tag};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use bytes::{BufMut, Bytes, BytesMut};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+const REPLAY_WINDOW_SIZE: u64 = 64;
+
+const LABEL_RTP_ENCRYPTION: u8 = 0;
+const LABEL_RTCP_ENCRYPTION: u8 = 3;
+
+const GCM_TAG_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SrtpProfile {
+    AesCm128HmacSha1_80,
+    AesCm128HmacSha1_32,
+    AeadAes128Gcm,
+    AeadAes256Gcm,
+}
+
+impl SrtpProfile {
+    // crypto-suite of SDES
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "AES_CM_128_HMAC_SHA1_80" => Some(Self::AesCm128HmacSha1_80),
+            "AES_CM_128_HMAC_SHA1_32" => Some(Self::AesCm128HmacSha1_32),
+            "AEAD_AES_128_GCM" => Some(Self::AeadAes128Gcm),
+            "AEAD_AES_256_GCM" => Some(Self::AeadAes256Gcm),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::AesCm128HmacSha1_80 => "AES_CM_128_HMAC_SHA1_80",
+            Self::AesCm128HmacSha1_32 => "AES_CM_128_HMAC_SHA1_32",
+            Self::AeadAes128Gcm => "AEAD_AES_128_GCM",
+            Self::AeadAes256Gcm => "AEAD_AES_256_GCM",
+        }
+    }
+
+    pub fn master_key_len(&self) -> usize {
+        match self {
+            Self::AeadAes256Gcm => 32,
+            _ => 16,
+        }
+    }
+
+    pub fn master_salt_len(&self) -> usize {
+        if self.is_aead() { 12 } else { 14 }
+    }
+
+    pub fn is_aead(&self) -> bool {
+        matches!(self, Self::AeadAes128Gcm | Self::AeadAes256Gcm)
+    }
+
+    fn rtp_tag_len(&self) -> usize {
+        match self {
+            Self::AesCm128HmacSha1_80 => 10,
+            Self::AesCm128HmacSha1_32 => 4,
+            _ => GCM_TAG_LEN,
+        }
+    }
+
+    // RFC 4568 section 6.2, SRTCP of _32 still uses 80 bits tag
+    fn rtcp_tag_len(&self) -> usize {
+        match self {
+            Self::AesCm128HmacSha1_80 | Self::AesCm128HmacSha1_32 => 10,
+            _ => GCM_TAG_LEN,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct SrtpMasterKey {
+    pub profile: SrtpProfile,
+    pub key: Vec<u8>,
+    pub salt: Vec<u8>,
+
+    // length of MKI field in packets, the value is not checked
+    pub mki_len: usize,
+
+    // RFC 4568 section 6.3.2, packets are authenticated only
+    pub unencrypted_srtp: bool,
+    pub unencrypted_srtcp: bool,
+}
+
+// keys are not printed
+impl std::fmt::Debug for SrtpMasterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SrtpMasterKey")
+        .field("profile", &self.profile)
+        .field("mki_len", &self.mki_len)
+        .field("unencrypted_srtp", &self.unencrypted_srtp)
+        .field("unencrypted_srtcp", &self.unencrypted_srtcp)
+        .finish()
+    }
+}
+
+impl SrtpMasterKey {
+    pub fn new(profile: SrtpProfile, key: &[u8], salt: &[u8]) -> Result<Self> {
+        if key.len() != profile.master_key_len() || salt.len() != profile.master_salt_len() {
+            bail!("invalid master key/salt length [{}/{}] for [{}]", key.len(), salt.len(), profile.name())
+        }
+        Ok(Self {
+            profile,
+            key: key.to_vec(),
+            salt: salt.to_vec(),
+            mki_len: 0,
+            unencrypted_srtp: false,
+            unencrypted_srtcp: false,
+        })
+    }
+
+    // session params of a=crypto, unknown ones are ignored
+    pub fn with_session_params<S: AsRef<str>>(mut self, params: &[S]) -> Self {
+        for param in params {
+            match param.as_ref() {
+                "UNENCRYPTED_SRTP" => self.unencrypted_srtp = true,
+                "UNENCRYPTED_SRTCP" => self.unencrypted_srtcp = true,
+                _ => {},
+            }
+        }
+        self
+    }
+
+    // RFC 4568 section 6.1, "inline:" <key||salt> ["|" lifetime] ["|" MKI ":" length],
+    // only the first of multiple key params is used
+    pub fn from_sdes(suite: &str, key_params: &str) -> Result<Self> {
+        let profile = SrtpProfile::from_name(suite)
+            .with_context(||format!("unsupported crypto suite [{suite}]"))?;
+
+        let key_param = key_params.split(';').next().unwrap_or_default();
+        let key_info = key_param.strip_prefix("inline:")
+            .with_context(||"expect inline key method")?;
+
+        let mut iter = key_info.split('|');
+        let key_salt = iter.next().unwrap_or_default();
+        let key_salt = base64::engine::general_purpose::STANDARD.decode(key_salt)
+            .with_context(||"invalid base64 of key")?;
+
+        let key_len = profile.master_key_len();
+        if key_salt.len() != key_len + profile.master_salt_len() {
+            bail!("invalid key length [{}] for [{suite}]", key_salt.len())
+        }
+
+        let mut master = Self::new(profile, &key_salt[..key_len], &key_salt[key_len..])?;
+
+        for field in iter {
+            // lifetime has no ':'
+            if let Some((_mki, len)) = field.split_once(':') {
+                master.mki_len = len.parse().with_context(||"invalid mki length")?;
+            }
+        }
+
+        Ok(master)
+    }
+}
+
+// keys by ssrc supplied out of sdp
+pub type SrtpKeyMap = HashMap<u32, SrtpMasterKey>;
+
+// SRTP streams of one or more ssrc, keys are tried in order for unknown ssrc
+#[derive(Default)]
+pub struct SrtpSession {
+    contexts: Vec<SrtpContext>,
+    ssrcs: HashMap<u32, usize>,
+    rocs: HashMap<u32, u32>,
+}
+
+impl SrtpSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contexts.is_empty()
+    }
+
+    pub fn add_key(&mut self, key: &SrtpMasterKey) -> Result<()> {
+        self.contexts.push(SrtpContext::new(key)?);
+        Ok(())
+    }
+
+    pub fn add_ssrc_key(&mut self, ssrc: u32, key: &SrtpMasterKey) -> Result<()> {
+        self.add_key(key)?;
+        self.ssrcs.insert(ssrc, self.contexts.len() - 1);
+        Ok(())
+    }
+
+    pub fn add_key_map(&mut self, keys: &SrtpKeyMap) -> Result<()> {
+        for (ssrc, key) in keys.iter() {
+            self.add_ssrc_key(*ssrc, key)?;
+        }
+        Ok(())
+    }
+
+    // rollover counter of the first packet, for recording started in the middle
+    pub fn set_roc(&mut self, ssrc: u32, roc: u32) {
+        self.rocs.insert(ssrc, roc);
+        for ctx in self.contexts.iter_mut() {
+            ctx.set_roc(ssrc, roc);
+        }
+    }
+
+    pub fn decrypt_rtp(&mut self, packet: &[u8]) -> Result<Bytes> {
+        let ssrc = read_u32(packet, 8)?;
+        self.decrypt_with(ssrc, |ctx| ctx.decrypt_rtp(packet))
+    }
+
+    pub fn decrypt_rtcp(&mut self, packet: &[u8]) -> Result<Bytes> {
+        let ssrc = read_u32(packet, 4)?;
+        self.decrypt_with(ssrc, |ctx| ctx.decrypt_rtcp(packet))
+    }
+
+    fn decrypt_with<F>(&mut self, ssrc: u32, mut func: F) -> Result<Bytes>
+    where
+        F: FnMut(&mut SrtpContext) -> Result<Bytes>,
+    {
+        if let Some(index) = self.ssrcs.get(&ssrc) {
+            return func(&mut self.contexts[*index])
+        }
+
+        let mut last_err = None;
+        for (index, ctx) in self.contexts.iter_mut().enumerate() {
+            if let Some(roc) = self.rocs.get(&ssrc) {
+                ctx.set_roc(ssrc, *roc);
+            }
+
+            match func(ctx) {
+                Ok(v) => {
+                    self.ssrcs.insert(ssrc, index);
+                    return Ok(v)
+                },
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(||anyhow!("no srtp key for ssrc [{ssrc}]")))
+    }
+}
+
+// one master key
+pub struct SrtpContext {
+    profile: SrtpProfile,
+    mki_len: usize,
+    unencrypted_srtp: bool,
+    unencrypted_srtcp: bool,
+    rtp: SessionCipher,
+    rtcp: SessionCipher,
+    rtp_streams: HashMap<u32, RtpStreamState>,
+    rtcp_streams: HashMap<u32, ReplayWindow>,
+}
+
+impl SrtpContext {
+    pub fn new(master: &SrtpMasterKey) -> Result<Self> {
+        Ok(Self {
+            profile: master.profile,
+            mki_len: master.mki_len,
+            unencrypted_srtp: master.unencrypted_srtp,
+            unencrypted_srtcp: master.unencrypted_srtcp,
+            rtp: SessionCipher::derive(master, LABEL_RTP_ENCRYPTION)?,
+            rtcp: SessionCipher::derive(master, LABEL_RTCP_ENCRYPTION)?,
+            rtp_streams: Default::default(),
+            rtcp_streams: Default::default(),
+        })
+    }
+
+    // takes effect before the first packet of ssrc
+    pub fn set_roc(&mut self, ssrc: u32, roc: u32) {
+        let stream = self.rtp_streams.entry(ssrc).or_default();
+        if stream.window.highest.is_none() {
+            stream.roc = roc;
+        }
+    }
+
+    pub fn decrypt_rtp(&mut self, packet: &[u8]) -> Result<Bytes> {
+        let header_len = rtp_header_len(packet)?;
+        let trailer_len = self.profile.rtp_tag_len() + self.mki_len;
+        if packet.len() < header_len + trailer_len {
+            bail!("srtp too short [{}]", packet.len())
+        }
+
+        let seq = u16::from_be_bytes([packet[2], packet[3]]);
+        let ssrc = read_u32(packet, 8)?;
+
+        let stream = self.rtp_streams.entry(ssrc).or_default();
+        let index = stream.estimate_index(seq);
+        stream.window.check(index)?;
+        let roc = (index >> 16) as u32;
+
+        let mut output = BytesMut::with_capacity(packet.len());
+        output.put_slice(&packet[..header_len]);
+
+        match &self.rtp.kind {
+            CipherKind::AesCm { aes, mac } => {
+                // RFC 3711 section 3.1, MKI and tag are at the end
+                let auth_len = packet.len() - trailer_len;
+                let tag = &packet[packet.len() - self.profile.rtp_tag_len()..];
+
+                let mut mac = mac.as_ref().clone();
+                mac.update(&packet[..auth_len]);
+                mac.update(&roc.to_be_bytes());
+                mac.verify_truncated_left(tag).map_err(|_e|anyhow!("srtp auth failed"))?;
+
+                output.put_slice(&packet[header_len..auth_len]);
+                if !self.unencrypted_srtp {
+                    let iv = aes_cm_iv(&self.rtp.salt, ssrc, index);
+                    aes_cm_apply(aes.as_ref(), iv, &mut output[header_len..]);
+                }
+            },
+            _ => {
+                // RFC 7714 section 8.1, tag is a part of ciphertext and MKI follows it
+                let cipher_end = packet.len() - self.mki_len;
+                let tag_at = cipher_end - GCM_TAG_LEN;
+
+                let mut iv = [0_u8; 12];
+                iv[2..6].copy_from_slice(&ssrc.to_be_bytes());
+                iv[6..10].copy_from_slice(&roc.to_be_bytes());
+                iv[10..12].copy_from_slice(&seq.to_be_bytes());
+
+                if self.unencrypted_srtp {
+                    // the whole packet is authenticated as aad
+                    self.rtp.aead_decrypt(iv, &packet[..tag_at], &mut [], &packet[tag_at..cipher_end])
+                    .with_context(||"srtp auth failed")?;
+                    output.put_slice(&packet[header_len..tag_at]);
+                } else {
+                    output.put_slice(&packet[header_len..tag_at]);
+                    self.rtp.aead_decrypt(iv, &packet[..header_len], &mut output[header_len..], &packet[tag_at..cipher_end])
+                    .with_context(||"srtp auth failed")?;
+                }
+            }
+        }
+
+        stream.window.update(index);
+
+        Ok(output.freeze())
+    }
+
+    pub fn decrypt_rtcp(&mut self, packet: &[u8]) -> Result<Bytes> {
+        const HEADER_LEN: usize = 8;
+
+        let tag_len = self.profile.rtcp_tag_len();
+        if packet.len() < HEADER_LEN + 4 + tag_len + self.mki_len {
+            bail!("srtcp too short [{}]", packet.len())
+        }
+        let ssrc = read_u32(packet, 4)?;
+
+        // E flag and 31 bits index before MKI and tag for AES-CM,
+        // after tag and before MKI for AES-GCM
+        let index_at = if self.profile.is_aead() {
+            packet.len() - self.mki_len - 4
+        } else {
+            packet.len() - self.mki_len - tag_len - 4
+        };
+        let e_index = read_u32(packet, index_at)?;
+        let encrypted = e_index & 0x8000_0000 != 0;
+        let index = (e_index & 0x7FFF_FFFF) as u64;
+        if encrypted && self.unencrypted_srtcp {
+            bail!("srtcp encrypted against UNENCRYPTED_SRTCP")
+        }
+
+        let window = self.rtcp_streams.entry(ssrc).or_default();
+        window.check(index)?;
+
+        let mut output = BytesMut::with_capacity(packet.len());
+        output.put_slice(&packet[..HEADER_LEN]);
+
+        match &self.rtcp.kind {
+            CipherKind::AesCm { aes, mac } => {
+                let auth_len = index_at + 4;
+                let tag = &packet[packet.len() - tag_len..];
+
+                let mut mac = mac.as_ref().clone();
+                mac.update(&packet[..auth_len]);
+                mac.verify_truncated_left(tag).map_err(|_e|anyhow!("srtcp auth failed"))?;
+
+                output.put_slice(&packet[HEADER_LEN..index_at]);
+                if encrypted {
+                    let iv = aes_cm_iv(&self.rtcp.salt, ssrc, index);
+                    aes_cm_apply(aes.as_ref(), iv, &mut output[HEADER_LEN..]);
+                }
+            },
+            _ => {
+                // RFC 7714 section 9
+                let tag_at = index_at - GCM_TAG_LEN;
+                if tag_at < HEADER_LEN {
+                    bail!("srtcp too short [{}]", packet.len())
+                }
+
+                let mut iv = [0_u8; 12];
+                iv[2..6].copy_from_slice(&ssrc.to_be_bytes());
+                iv[8..12].copy_from_slice(&(index as u32).to_be_bytes());
+
+                let e_index = &packet[index_at..index_at + 4];
+                let tag = &packet[tag_at..index_at];
+                if encrypted {
+                    let aad = [&packet[..HEADER_LEN], e_index].concat();
+                    output.put_slice(&packet[HEADER_LEN..tag_at]);
+                    self.rtcp.aead_decrypt(iv, &aad, &mut output[HEADER_LEN..], tag)
+                    .with_context(||"srtcp auth failed")?;
+                } else {
+                    // authenticated only
+                    let aad = [&packet[..tag_at], e_index].concat();
+                    self.rtcp.aead_decrypt(iv, &aad, &mut [], tag)
+                    .with_context(||"srtcp auth failed")?;
+                    output.put_slice(&packet[HEADER_LEN..tag_at]);
+                }
+            }
+        }
+
+        window.update(index);
+
+        Ok(output.freeze())
+    }
+}
+
+enum CipherKind {
+    AesCm {
+        aes: Box<Aes128>,
+        mac: Box<Hmac<Sha1>>,
+    },
+    Aes128Gcm(Box<Aes128Gcm>),
+    Aes256Gcm(Box<Aes256Gcm>),
+}
+
+struct SessionCipher {
+    kind: CipherKind,
+
+    // 14 bytes for AES-CM, 12 bytes for AES-GCM
+    salt: Vec<u8>,
+}
+
+impl SessionCipher {
+    // RFC 3711 section 4.3, labels are encryption, auth, salt in order
+    fn derive(master: &SrtpMasterKey, label: u8) -> Result<Self> {
+        let profile = master.profile;
+        let key = derive_session_key(master, label, profile.master_key_len())?;
+        let salt = derive_session_key(master, label + 2, profile.master_salt_len())?;
+
+        let kind = match profile {
+            SrtpProfile::AesCm128HmacSha1_80 | SrtpProfile::AesCm128HmacSha1_32 => {
+                let auth = derive_session_key(master, label + 1, 20)?;
+                CipherKind::AesCm {
+                    aes: Box::new(Aes128::new_from_slice(&key).map_err(|_e|anyhow!("invalid aes key"))?),
+                    mac: Box::new(<Hmac<Sha1> as Mac>::new_from_slice(&auth).map_err(|_e|anyhow!("invalid hmac key"))?),
+                }
+            },
+            SrtpProfile::AeadAes128Gcm => CipherKind::Aes128Gcm(Box::new(
+                Aes128Gcm::new_from_slice(&key).map_err(|_e|anyhow!("invalid aes key"))?
+            )),
+            SrtpProfile::AeadAes256Gcm => CipherKind::Aes256Gcm(Box::new(
+                Aes256Gcm::new_from_slice(&key).map_err(|_e|anyhow!("invalid aes key"))?
+            )),
+        };
+
+        Ok(Self { kind, salt })
+    }
+
+    fn aead_decrypt(&self, iv: [u8; 12], aad: &[u8], buf: &mut [u8], tag: &[u8]) -> Result<()> {
+        let mut nonce = iv;
+        for (x, s) in nonce.iter_mut().zip(self.salt.iter()) {
+            *x ^= s;
+        }
+        let nonce = Nonce::from_slice(&nonce);
+        let tag = Tag::from_slice(tag);
+
+        let r = match &self.kind {
+            CipherKind::Aes128Gcm(cipher) => cipher.decrypt_in_place_detached(nonce, aad, buf, tag),
+            CipherKind::Aes256Gcm(cipher) => cipher.decrypt_in_place_detached(nonce, aad, buf, tag),
+            CipherKind::AesCm { .. } => bail!("not aead cipher"),
+        };
+        r.map_err(|_e|anyhow!("aead decrypt failed"))
+    }
+}
+
+// AES-CM PRF with key derivation rate 0, the master salt of AES-GCM
+// is padded with zeros to 112 bits as libsrtp does
+fn derive_session_key(master: &SrtpMasterKey, label: u8, len: usize) -> Result<Vec<u8>> {
+    let mut iv = [0_u8; 16];
+    iv[..master.salt.len()].copy_from_slice(&master.salt);
+    iv[7] ^= label;
+
+    let mut output = vec![0_u8; len];
+    match master.key.len() {
+        16 => aes_cm_apply(&Aes128::new_from_slice(&master.key).map_err(|_e|anyhow!("invalid aes key"))?, iv, &mut output),
+        32 => aes_cm_apply(&Aes256::new_from_slice(&master.key).map_err(|_e|anyhow!("invalid aes key"))?, iv, &mut output),
+        n => bail!("invalid master key length [{n}]"),
+    }
+    Ok(output)
+}
+
+// RFC 3711 section 4.1.1, IV = (salt * 2^16) XOR (SSRC * 2^64) XOR (index * 2^16)
+fn aes_cm_iv(salt: &[u8], ssrc: u32, index: u64) -> [u8; 16] {
+    let mut iv = [0_u8; 16];
+    iv[..14].copy_from_slice(&salt[..14]);
+    for (x, v) in iv[4..8].iter_mut().zip(ssrc.to_be_bytes()) {
+        *x ^= v;
+    }
+    for (x, v) in iv[8..14].iter_mut().zip(&index.to_be_bytes()[2..]) {
+        *x ^= v;
+    }
+    iv
+}
+
+// xor data with keystream of 128 bits counter
+fn aes_cm_apply<C: BlockEncrypt<BlockSize = U16>>(cipher: &C, iv: [u8; 16], data: &mut [u8]) {
+    let mut counter = u128::from_be_bytes(iv);
+    for chunk in data.chunks_mut(16) {
+        let mut block = Block::from(counter.to_be_bytes());
+        cipher.encrypt_block(&mut block);
+        for (x, k) in chunk.iter_mut().zip(block.iter()) {
+            *x ^= k;
+        }
+        counter = counter.wrapping_add(1);
+    }
+}
+
+#[derive(Default)]
+struct RtpStreamState {
+    // rollover counter before the first packet
+    roc: u32,
+    window: ReplayWindow,
+}
+
+impl RtpStreamState {
+    // RFC 3711 appendix A, guess ROC from the highest received index
+    fn estimate_index(&self, seq: u16) -> u64 {
+        let highest = match self.window.highest {
+            Some(v) => v,
+            None => return ((self.roc as u64) << 16) | seq as u64,
+        };
+
+        let roc = highest >> 16;
+        let s_l = highest as u16;
+        let v = if s_l < 0x8000 {
+            if seq > s_l && seq - s_l > 0x8000 {
+                roc.saturating_sub(1)
+            } else {
+                roc
+            }
+        } else if s_l - 0x8000 > seq {
+            roc + 1
+        } else {
+            roc
+        };
+        (v << 16) | seq as u64
+    }
+}
+
+#[derive(Default)]
+struct ReplayWindow {
+    highest: Option<u64>,
+
+    // bit n is highest - n
+    bitmap: u64,
+}
+
+impl ReplayWindow {
+    fn check(&self, index: u64) -> Result<()> {
+        if let Some(highest) = self.highest {
+            if index <= highest {
+                let delta = highest - index;
+                if delta >= REPLAY_WINDOW_SIZE {
+                    bail!("srtp index [{index}] too old, highest [{highest}]")
+                }
+                if self.bitmap & (1 << delta) != 0 {
+                    bail!("srtp index [{index}] replayed")
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, index: u64) {
+        match self.highest {
+            Some(highest) if index <= highest => {
+                self.bitmap |= 1 << (highest - index);
+            },
+            Some(highest) => {
+                let shift = index - highest;
+                self.bitmap = if shift >= REPLAY_WINDOW_SIZE { 0 } else { self.bitmap << shift };
+                self.bitmap |= 1;
+                self.highest = Some(index);
+            },
+            None => {
+                self.bitmap = 1;
+                self.highest = Some(index);
+            },
+        }
+    }
+}
+
+// without padding check, the padding count is encrypted
+fn rtp_header_len(packet: &[u8]) -> Result<usize> {
+    if packet.len() < 12 {
+        bail!("rtp too short [{}]", packet.len())
+    }
+
+    let mut len = 12 + (packet[0] & 0x0F) as usize * 4;
+    if packet[0] & 0x10 != 0 {
+        if packet.len() < len + 4 {
+            bail!("rtp extension truncated")
+        }
+        len += 4 + u16::from_be_bytes([packet[len + 2], packet[len + 3]]) as usize * 4;
+    }
+
+    if packet.len() < len {
+        bail!("rtp header truncated")
+    }
+    Ok(len)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let b = data.get(offset..offset + 4).with_context(||"packet too short")?;
+    Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+
+#[cfg(test)]
+fn make_plain_rtp() -> Vec<u8> {
+    let mut buf = vec![0x80, 0x0f, 0x12, 0x34, 0xde, 0xca, 0xfb, 0xad, 0xca, 0xfe, 0xba, 0xbe];
+    buf.extend_from_slice(&[0xab; 16]);
+    buf
+}
+
+#[test]
+fn test_srtp_aes_cm() {
+    // RFC 3711 appendix B.3 master key, packet of libsrtp test
+    let master = SrtpMasterKey::from_sdes(
+        "AES_CM_128_HMAC_SHA1_80",
+        "inline:4fl6DT4Bi+DWT6MsBt5BOQ7Gda1Jiv7rtpYLOqvm|2^20|1:4",
+    ).unwrap();
+    assert_eq!(master.mki_len, 4);
+    let master = SrtpMasterKey { mki_len: 0, ..master };
+
+    let srtp = [
+        0x80, 0x0f, 0x12, 0x34, 0xde, 0xca, 0xfb, 0xad, 0xca, 0xfe, 0xba, 0xbe,
+        0x4e, 0x55, 0xdc, 0x4c, 0xe7, 0x99, 0x78, 0xd8, 0x8c, 0xa4, 0xd2, 0x15,
+        0x94, 0x9d, 0x24, 0x02, 0xb7, 0x8d, 0x6a, 0xcc, 0x99, 0xea, 0x17, 0x9b,
+        0x8d, 0xbb,
+    ];
+
+    let mut session = SrtpSession::new();
+    session.add_key(&master).unwrap();
+    assert_eq!(&session.decrypt_rtp(&srtp).unwrap()[..], &make_plain_rtp()[..]);
+    assert!(session.decrypt_rtp(&srtp).is_err(), "replayed");
+
+    let mut tampered = srtp;
+    tampered[20] ^= 1;
+    let mut session = SrtpSession::new();
+    session.add_key(&master).unwrap();
+    assert!(session.decrypt_rtp(&tampered).is_err());
+
+    // supplied rollover counter
+    let srtp_roc1 = [
+        0x80, 0x0f, 0x12, 0x34, 0xde, 0xca, 0xfb, 0xad, 0xca, 0xfe, 0xba, 0xbe,
+        0x17, 0x92, 0x65, 0xc8, 0xbf, 0x30, 0x3e, 0x4c, 0x24, 0xe7, 0x27, 0x9f,
+        0x73, 0x9b, 0xb5, 0xa6, 0xf8, 0xb3, 0x91, 0x6f, 0xd6, 0x70, 0x05, 0x7b,
+        0xf1, 0xdc,
+    ];
+    let mut session = SrtpSession::new();
+    session.add_ssrc_key(0xcafebabe, &master).unwrap();
+    session.set_roc(0xcafebabe, 1);
+    assert_eq!(&session.decrypt_rtp(&srtp_roc1).unwrap()[..], &make_plain_rtp()[..]);
+
+    // 32 bits tag, sequence number wraps to the next roc
+    let master = SrtpMasterKey { profile: SrtpProfile::AesCm128HmacSha1_32, ..master };
+
+    // authenticated only
+    let unencrypted = master.clone().with_session_params(&["UNENCRYPTED_SRTP", "UNENCRYPTED_SRTCP"]);
+    let auth = derive_session_key(&unencrypted, LABEL_RTP_ENCRYPTION + 1, 20).unwrap();
+    let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(&auth).unwrap();
+    let mut packet = make_plain_rtp();
+    mac.update(&packet);
+    mac.update(&0_u32.to_be_bytes());
+    packet.extend_from_slice(&mac.finalize().into_bytes()[..4]);
+    let mut session = SrtpSession::new();
+    session.add_key(&unencrypted).unwrap();
+    assert_eq!(&session.decrypt_rtp(&packet).unwrap()[..], &make_plain_rtp()[..]);
+
+    let mut session = SrtpSession::new();
+    session.add_key(&master).unwrap();
+    let seq_ffff = [
+        0x80, 0x0f, 0xff, 0xff, 0xde, 0xca, 0xfb, 0xad, 0xca, 0xfe, 0xba, 0xbe,
+        0xf3, 0x6e, 0x96, 0xfc, 0xdb, 0x92, 0x72, 0x1a,
+    ];
+    let seq_0001 = [
+        0x80, 0x0f, 0x00, 0x01, 0xde, 0xca, 0xfb, 0xad, 0xca, 0xfe, 0xba, 0xbe,
+        0xb6, 0xf1, 0xf0, 0xa4, 0xab, 0xa8, 0x87, 0xb4,
+    ];
+    assert_eq!(&session.decrypt_rtp(&seq_ffff).unwrap()[12..], &[0xab; 4]);
+    assert_eq!(&session.decrypt_rtp(&seq_0001).unwrap()[12..], &[0xab; 4]);
+    assert!(session.decrypt_rtp(&seq_ffff).is_err(), "replayed");
+
+    // SRTCP, E flag and index 1
+    let srtcp = [
+        0x81, 0xc8, 0x00, 0x0c, 0xde, 0xca, 0xfb, 0xad, 0x32, 0x1c, 0x39, 0xa8,
+        0x09, 0xbf, 0x86, 0x64, 0x4e, 0xe8, 0x28, 0x4b, 0x4a, 0x34, 0xc9, 0x93,
+        0xc0, 0x51, 0x41, 0x39, 0x80, 0x00, 0x00, 0x01, 0xd7, 0x2c, 0xf8, 0xcd,
+        0xca, 0xb0, 0xce, 0xa7, 0x52, 0x8a,
+    ];
+    let rtcp = session.decrypt_rtcp(&srtcp).unwrap();
+    assert_eq!(&rtcp[..8], &srtcp[..8]);
+    assert_eq!(&rtcp[8..], &(0..20).collect::<Vec<u8>>()[..]);
+    assert!(session.decrypt_rtcp(&srtcp).is_err(), "replayed");
+}
+
+#[test]
+fn test_srtp_aes_gcm() {
+    let salt = [0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xab];
+
+    let key128: Vec<u8> = (0..16).collect();
+    let master = SrtpMasterKey::new(SrtpProfile::AeadAes128Gcm, &key128, &salt).unwrap();
+    let mut session = SrtpSession::new();
+    session.add_key(&master).unwrap();
+
+    let srtp = [
+        0x80, 0x0f, 0x12, 0x34, 0xde, 0xca, 0xfb, 0xad, 0xca, 0xfe, 0xba, 0xbe,
+        0xc5, 0x00, 0x2e, 0xde, 0x04, 0xcf, 0xdd, 0x2e, 0xb9, 0x11, 0x59, 0xe0,
+        0x88, 0x0a, 0xa0, 0x6e, 0xd2, 0x97, 0x68, 0x26, 0xf7, 0x96, 0xb2, 0x01,
+        0xdf, 0x31, 0x31, 0xa1, 0x27, 0xe8, 0xa3, 0x92,
+    ];
+    assert_eq!(&session.decrypt_rtp(&srtp).unwrap()[..], &make_plain_rtp()[..]);
+
+    let srtcp = [
+        0x81, 0xc8, 0x00, 0x0c, 0xde, 0xca, 0xfb, 0xad, 0x31, 0xbf, 0xb8, 0x82,
+        0xa2, 0x1f, 0xf1, 0x19, 0xcf, 0x9b, 0x90, 0xdf, 0xe1, 0x1e, 0x29, 0x6d,
+        0x83, 0x7d, 0xb1, 0xcb, 0xb3, 0x7b, 0xf4, 0x58, 0x31, 0xef, 0xbf, 0xc8,
+        0x9f, 0xbf, 0xc1, 0xab, 0x60, 0x50, 0xb0, 0xb3, 0x80, 0x00, 0x00, 0x01,
+    ];
+    let rtcp = session.decrypt_rtcp(&srtcp).unwrap();
+    assert_eq!(&rtcp[8..], &(0..20).collect::<Vec<u8>>()[..]);
+
+    let key256: Vec<u8> = (0..32).collect();
+    let master = SrtpMasterKey::new(SrtpProfile::AeadAes256Gcm, &key256, &salt).unwrap();
+    let mut session = SrtpSession::new();
+    session.add_key(&master).unwrap();
+
+    let srtp = [
+        0x80, 0x0f, 0x12, 0x34, 0xde, 0xca, 0xfb, 0xad, 0xca, 0xfe, 0xba, 0xbe,
+        0x0a, 0xf7, 0xf2, 0x1e, 0x8a, 0x90, 0xbd, 0xad, 0x7a, 0x42, 0x5c, 0x9c,
+        0x31, 0xed, 0x4b, 0xb1, 0xd9, 0x02, 0x38, 0x91, 0x7e, 0x73, 0x90, 0xa2,
+        0x79, 0x35, 0x00, 0xe1, 0x68, 0x1a, 0xca, 0xea,
+    ];
+    assert_eq!(&session.decrypt_rtp(&srtp).unwrap()[..], &make_plain_rtp()[..]);
+}
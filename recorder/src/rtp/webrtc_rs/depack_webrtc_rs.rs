@@ -1,36 +1,109 @@
 // from webrtc_rs
 
 
+use std::collections::VecDeque;
+
 use anyhow::{anyhow, bail, Result};
 use bytes::{BufMut, Bytes, BytesMut};
 use rtp_rs::RtpReader;
-use super::super::depack::RtpCodecDepacker;
+use super::super::codec::h264::RtpH264Parameters;
+use super::super::depack::{DepackedFrame, RtpCodecDepacker, RtpLossCounter};
 
 
+// output one access unit per pull, which ends by marker bit or timestamp change,
+// same as retina's
 #[derive(Default)]
 pub struct RtpDepackerWebrtcRsH264 {
     is_avc: bool,
     fua_buffer: Option<BytesMut>,
-    frame: Option<Bytes>,
+
+    // nalus of current access unit
+    au: BytesMut,
+    au_types: Vec<u8>,
+    au_new_parameters: bool,
+
+    // packets of current access unit lost
+    broken: bool,
+
+    sps: Option<Bytes>,
+    pps: Option<Bytes>,
+    last_ts: Option<u32>,
+    last_seq: Option<u16>,
+    loss: RtpLossCounter,
+    frames: VecDeque<DepackedFrame>,
     // depack: H264Packet,
 }
 
-impl RtpCodecDepacker for RtpDepackerWebrtcRsH264 {
-    fn push_rtp_slice(&mut self, payload: &[u8]) -> Result<()>  {
-        // self.depack.depacketize(b);
+impl RtpDepackerWebrtcRsH264 {
+    // parameter sets in fmtp, if any, are the initial ones
+    pub fn new(fmtp: Option<&str>) -> Result<Self> {
+        let mut depack = Self::default();
+        if let Some(params) = fmtp.and_then(|x| RtpH264Parameters::parse_from_str(x).ok()) {
+            depack.sps = Some(params.sps_nal.clone());
+            depack.pps = Some(params.pps_nal.clone());
+        }
+        Ok(depack)
+    }
 
-        let rtp = RtpReader::new(payload)
-        .map_err(|e|anyhow!("invalid rtp {e:?}"))?;
+    pub fn into_box(self) -> Box<dyn RtpCodecDepacker> {
+        Box::new(self)
+    }
 
-        let payload = rtp.payload();
+    fn put_nalu(&mut self, nalu: &[u8]) {
+        if nalu.is_empty() {
+            return;
+        }
 
-        if payload.len() <= 2 {
+        let nalu_type = nalu[0] & NALU_TYPE_BITMASK;
+        let last = match nalu_type {
+            SPS_NALU_TYPE => Some(&mut self.sps),
+            PPS_NALU_TYPE => Some(&mut self.pps),
+            _ => None,
+        };
+        if let Some(last) = last {
+            if last.as_deref() != Some(nalu) {
+                self.au_new_parameters = true;
+                *last = Some(Bytes::copy_from_slice(nalu));
+            }
+        }
+
+        if self.is_avc {
+            self.au.put_u32(nalu.len() as u32);
+        } else {
+            self.au.put(&*ANNEXB_NALUSTART_CODE);
+        }
+        self.au.put(nalu);
+        self.au_types.push(nalu_type);
+    }
+
+    fn finish_au(&mut self, timestamp: u32) {
+        self.fua_buffer = None;
+        let corrupt = std::mem::replace(&mut self.broken, false);
+        let new_parameters = std::mem::replace(&mut self.au_new_parameters, false);
+
+        if self.au.is_empty() {
+            return;
+        }
+
+        let frame = DepackedFrame {
+            data: self.au.split().freeze(),
+            rtp_ts: timestamp,
+            keyframe: self.au_types.contains(&IDR_NALU_TYPE),
+            loss: self.loss.take(),
+            corrupt,
+            new_parameters,
+            fec: None,
+        };
+        self.au_types.clear();
+        self.frames.push_back(frame);
+    }
+
+    fn push_payload(&mut self, payload: &[u8]) -> Result<()> {
+        if payload.is_empty() {
             // return Err(Error::ErrShortPacket);
             bail!("short packet")
         }
 
-        let mut frame_buf = BytesMut::new();
-
         // NALU Types
         // https://tools.ietf.org/html/rfc6184#section-5.4
         let b0 = payload[0];
@@ -38,20 +111,16 @@ impl RtpCodecDepacker for RtpDepackerWebrtcRsH264 {
 
         match nalu_type {
             1..=23 => {
-                if self.is_avc {
-                    frame_buf.put_u32(payload.len() as u32);
-                } else {
-                    frame_buf.put(&*ANNEXB_NALUSTART_CODE);
-                }
                 // frame_buf.put(&*packet.clone());
-                frame_buf.put(payload);
-
-                self.frame = Some(frame_buf.freeze());
+                self.put_nalu(payload);
                 Ok(())
             }
             STAPA_NALU_TYPE => {
                 let mut curr_offset = STAPA_HEADER_SIZE;
                 while curr_offset < payload.len() {
+                    if payload.len() < curr_offset + STAPA_NALU_LENGTH_SIZE {
+                        bail!("StapA size truncated")
+                    }
                     let nalu_size =
                         ((payload[curr_offset] as usize) << 8) | payload[curr_offset + 1] as usize;
                     curr_offset += STAPA_NALU_LENGTH_SIZE;
@@ -64,17 +133,10 @@ impl RtpCodecDepacker for RtpDepackerWebrtcRsH264 {
                         // ));
                     }
 
-                    if self.is_avc {
-                        frame_buf.put_u32(nalu_size as u32);
-                    } else {
-                        frame_buf.put(&*ANNEXB_NALUSTART_CODE);
-                    }
                     // frame_buf.put(&*packet.slice(curr_offset..curr_offset + nalu_size));
-                    frame_buf.put(&payload[curr_offset..curr_offset + nalu_size]);
+                    self.put_nalu(&payload[curr_offset..curr_offset + nalu_size]);
                     curr_offset += nalu_size;
                 }
-
-                self.frame = Some(frame_buf.freeze());
                 Ok(())
             }
             FUA_NALU_TYPE => {
@@ -83,35 +145,33 @@ impl RtpCodecDepacker for RtpDepackerWebrtcRsH264 {
                     bail!("short packet")
                 }
 
-                if self.fua_buffer.is_none() {
+                let b1 = payload[1];
+                if b1 & FU_START_BITMASK != 0 {
                     self.fua_buffer = Some(BytesMut::new());
                 }
 
-                if let Some(fua_buffer) = &mut self.fua_buffer {
+                match &mut self.fua_buffer {
                     // fua_buffer.put(&*packet.slice(FUA_HEADER_SIZE..));
-                    fua_buffer.put(&payload[FUA_HEADER_SIZE..]);
+                    Some(fua_buffer) => fua_buffer.put(&payload[FUA_HEADER_SIZE..]),
+                    None => {
+                        // missing the start fragment
+                        self.broken = true;
+                        return Ok(())
+                    },
                 }
 
-                let b1 = payload[1];
                 if b1 & FU_END_BITMASK != 0 {
                     let nalu_ref_idc = b0 & NALU_REF_IDC_BITMASK;
                     let fragmented_nalu_type = b1 & NALU_TYPE_BITMASK;
 
                     if let Some(fua_buffer) = self.fua_buffer.take() {
-                        if self.is_avc {
-                            frame_buf.put_u32((fua_buffer.len() + 1) as u32);
-                        } else {
-                            frame_buf.put(&*ANNEXB_NALUSTART_CODE);
-                        }
-                        frame_buf.put_u8(nalu_ref_idc | fragmented_nalu_type);
-                        frame_buf.put(fua_buffer);
+                        let mut nalu = BytesMut::with_capacity(fua_buffer.len() + 1);
+                        nalu.put_u8(nalu_ref_idc | fragmented_nalu_type);
+                        nalu.put(fua_buffer);
+                        self.put_nalu(&nalu);
                     }
-
-                    self.frame = Some(frame_buf.freeze());
-                    Ok(())
-                } else {
-                    Ok(())
                 }
+                Ok(())
             }
             _ => {
                 bail!("unknown nalu type [{nalu_type}]")
@@ -119,17 +179,58 @@ impl RtpCodecDepacker for RtpDepackerWebrtcRsH264 {
             },
         }
     }
+}
+
+impl RtpCodecDepacker for RtpDepackerWebrtcRsH264 {
+    fn push_rtp_slice(&mut self, payload: &[u8]) -> Result<()>  {
+        // self.depack.depacketize(b);
+
+        let rtp = RtpReader::new(payload)
+        .map_err(|e|anyhow!("invalid rtp {e:?}"))?;
+
+        let seq: u16 = rtp.sequence_number().into();
+        let lost = self.loss.push(seq);
+        if let Some(last) = self.last_seq {
+            if seq != last.wrapping_add(1) {
+                // discard incomplete fragments
+                self.fua_buffer = None;
+            }
+        }
+        self.last_seq = Some(seq);
+
+        let ts = rtp.timestamp();
+        if let Some(last_ts) = self.last_ts {
+            if last_ts != ts {
+                // missing marker of previous access unit, the lost may be its tail
+                self.broken |= lost > 0;
+                self.finish_au(last_ts);
+            }
+        }
+        self.last_ts = Some(ts);
+
+        // the lost may be the head of this access unit
+        self.broken |= lost > 0;
+
+        let r = self.push_payload(rtp.payload());
+
+        if rtp.mark() {
+            self.finish_au(ts);
+        }
+
+        r
+    }
 
-    fn pull_frame(&mut self) -> Result<Option<Bytes>>  {
-        Ok(self.frame.take())
+    fn pull_frame(&mut self) -> Result<Option<DepackedFrame>>  {
+        Ok(self.frames.pop_front())
     }
 }
 
 pub const STAPA_NALU_TYPE: u8 = 24;
 pub const FUA_NALU_TYPE: u8 = 28;
 // pub const FUB_NALU_TYPE: u8 = 29;
-// pub const SPS_NALU_TYPE: u8 = 7;
-// pub const PPS_NALU_TYPE: u8 = 8;
+pub const IDR_NALU_TYPE: u8 = 5;
+pub const SPS_NALU_TYPE: u8 = 7;
+pub const PPS_NALU_TYPE: u8 = 8;
 // pub const AUD_NALU_TYPE: u8 = 9;
 // pub const FILLER_NALU_TYPE: u8 = 12;
 
@@ -139,7 +240,7 @@ pub const STAPA_NALU_LENGTH_SIZE: usize = 2;
 
 pub const NALU_TYPE_BITMASK: u8 = 0x1F;
 pub const NALU_REF_IDC_BITMASK: u8 = 0x60;
-// pub const FU_START_BITMASK: u8 = 0x80;
+pub const FU_START_BITMASK: u8 = 0x80;
 pub const FU_END_BITMASK: u8 = 0x40;
 
 // pub const OUTPUT_STAP_AHEADER: u8 = 0x78;
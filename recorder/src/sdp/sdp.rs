@@ -1,6 +1,6 @@
 use std::{collections::HashMap, str::FromStr};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use enumflags2::{bitflags, BitFlags};
 
 use crate::media::CodecId;
@@ -24,7 +24,7 @@ impl SdpMain {
                     medias.push(SdpMedia::Audio(parse_audio(mdesc, index)?));
                 },
                 sdp_rs::lines::media::MediaType::Video => {
-                    medias.push(SdpMedia::Audio(parse_video(mdesc, index)?));
+                    medias.push(SdpMedia::Video(parse_video(mdesc, index)?));
                 },
                 _ => {
                     medias.push(SdpMedia::Unknown);
@@ -80,6 +80,30 @@ pub struct SdpCodec {
 }
 
 
+impl SdpCodec {
+    // the real audio sample rate, which differs from rtp clock rate for G.722
+    pub fn sample_rate(&self) -> u32 {
+        match self.codec_id {
+            // RFC 3551 section 4.5.2, rtp clock rate is 8000 for historical reason
+            CodecId::G722 => 16000,
+            _ => self.clock_rate,
+        }
+    }
+
+    // RFC 4588 section 8.1, payload type of the primary for rtx
+    pub fn rtx_apt(&self) -> Option<u8> {
+        if self.codec_id != CodecId::RtpRTX {
+            return None
+        }
+
+        self.fmtps.iter()
+        .flat_map(|x| x.split(';'))
+        .filter_map(|x| x.trim().split_once('='))
+        .find(|(key, _value)| key.trim() == "apt")
+        .and_then(|(_key, value)| value.trim().parse().ok())
+    }
+}
+
 #[bitflags]
 #[repr(u32)]
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -102,6 +126,210 @@ pub struct SdpAV {
     pub proto: SdpProtoType,
     pub payload_types: Vec<u8>,
     pub codecs: HashMap<u8, SdpCodec>,
+    pub ssrc_groups: Vec<SdpSsrcGroup>,
+    pub extmaps: Vec<SdpExtmap>,
+    pub cryptos: Vec<SdpCrypto>,
+    pub rids: Vec<SdpRid>,
+    pub simulcast: Option<SdpSimulcast>,
+}
+
+impl SdpAV {
+    // rtx payload type paired with the primary payload type
+    pub fn rtx_payload_type(&self, primary: u8) -> Option<u8> {
+        self.codecs.values()
+        .find(|x| x.rtx_apt() == Some(primary))
+        .map(|x| x.payload_type)
+    }
+
+    // RFC 5576 section 4.2, the first ssrc of FID group is the primary
+    pub fn rtx_primary_ssrc(&self, rtx_ssrc: u32) -> Option<u32> {
+        self.ssrc_groups.iter()
+        .filter(|x| x.semantics == "FID" && x.ssrcs.len() >= 2)
+        .find(|x| x.ssrcs[1..].contains(&rtx_ssrc))
+        .map(|x| x.ssrcs[0])
+    }
+
+    // layers of simulcast from the lowest to the highest, empty if not simulcast.
+    // rids are ordered by max-width/max-height if all of them have,
+    // otherwise a=simulcast is taken as listed in rid_order, and legacy SIM group is low to high
+    pub fn simulcast_layers(&self, rid_order: SimulcastRidOrder) -> Vec<SdpSimulcastLayer> {
+        if let Some(simulcast) = &self.simulcast {
+            let ids = if !simulcast.send.is_empty() { &simulcast.send } else { &simulcast.recv };
+            let mut rids: Vec<_> = ids.iter()
+                .map(|id| (id, self.rids.iter().find(|x| x.id == *id).and_then(|x| x.max_pixels())))
+                .collect();
+            if rids.iter().all(|x| x.1.is_some()) {
+                rids.sort_by_key(|x| x.1);
+            } else if rid_order == SimulcastRidOrder::HighToLow {
+                rids.reverse();
+            }
+            return rids.into_iter().map(|x| SdpSimulcastLayer::Rid(x.0.clone())).collect()
+        }
+
+        self.ssrc_groups.iter()
+        .find(|x| x.semantics == "SIM")
+        .map(|x| x.ssrcs.iter().map(|x| SdpSimulcastLayer::Ssrc(*x)).collect())
+        .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdpSimulcastLayer {
+    // identified by rtp stream id extension
+    Rid(String),
+
+    // identified by ssrc of a=ssrc-group:SIM
+    Ssrc(u32),
+}
+
+// order of rids in a=simulcast if any of them has no max-width/max-height
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SimulcastRidOrder {
+    // RFC 8853 section 5.1 lists in decreasing preference, usually the highest first, e.g. "f;h;q"
+    #[default]
+    HighToLow,
+
+    LowToHigh,
+}
+
+// a=ssrc-group:<semantics> <ssrc-id> ...
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdpSsrcGroup {
+    pub semantics: String,
+    pub ssrcs: Vec<u32>,
+}
+
+impl SdpSsrcGroup {
+    pub fn parse_from_str(value: &str) -> Result<Self> {
+        let mut iter = value.split_ascii_whitespace();
+        let semantics = iter.next().with_context(||"empty ssrc-group")?.to_string();
+
+        let mut ssrcs = Vec::new();
+        for s in iter {
+            ssrcs.push(s.parse::<u32>().with_context(||"invalid ssrc-group")?);
+        }
+
+        Ok(Self { semantics, ssrcs })
+    }
+}
+
+// RFC 8285 section 8, a=extmap:<value>["/"<direction>] <URI> <extensionattributes>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdpExtmap {
+    pub id: u8,
+    pub direction: Option<String>,
+    pub uri: String,
+}
+
+impl SdpExtmap {
+    pub fn parse_from_str(value: &str) -> Result<Self> {
+        let mut iter = value.split_ascii_whitespace();
+        let first = iter.next().with_context(||"empty extmap")?;
+        let uri = iter.next().with_context(||"extmap without uri")?.to_string();
+
+        let (id, direction) = match first.split_once('/') {
+            Some((id, direction)) => (id, Some(direction.to_string())),
+            None => (first, None),
+        };
+        let id = id.parse::<u8>().with_context(||"invalid extmap id")?;
+
+        Ok(Self { id, direction, uri })
+    }
+}
+
+// RFC 4568 section 9.1, a=crypto:<tag> <crypto-suite> <key-params> [<session-params>]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdpCrypto {
+    pub tag: u32,
+    pub suite: String,
+    pub key_params: String,
+    pub session_params: Vec<String>,
+}
+
+impl SdpCrypto {
+    pub fn parse_from_str(value: &str) -> Result<Self> {
+        let mut iter = value.split_ascii_whitespace();
+        let tag = iter.next().with_context(||"empty crypto")?
+            .parse::<u32>().with_context(||"invalid crypto tag")?;
+        let suite = iter.next().with_context(||"crypto without suite")?.to_string();
+        let key_params = iter.next().with_context(||"crypto without key params")?.to_string();
+        let session_params = iter.map(|x| x.to_string()).collect();
+
+        Ok(Self { tag, suite, key_params, session_params })
+    }
+}
+
+// RFC 8851 section 10, a=rid:<rid-id> <direction> [pt=<fmt-list>;<restriction>=<value>...]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdpRid {
+    pub id: String,
+    pub direction: String,
+    pub payload_types: Vec<u8>,
+    pub restrictions: Vec<(String, String)>,
+}
+
+impl SdpRid {
+    pub fn parse_from_str(value: &str) -> Result<Self> {
+        let mut iter = value.split_ascii_whitespace();
+        let id = iter.next().with_context(||"empty rid")?.to_string();
+        let direction = iter.next().with_context(||"rid without direction")?.to_string();
+
+        let mut payload_types = Vec::new();
+        let mut restrictions = Vec::new();
+        for param in iter.next().unwrap_or_default().split(';').filter(|x| !x.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            if key == "pt" {
+                for s in value.split(',') {
+                    payload_types.push(s.parse::<u8>().with_context(||"invalid rid pt")?);
+                }
+            } else {
+                restrictions.push((key.to_string(), value.to_string()));
+            }
+        }
+
+        Ok(Self { id, direction, payload_types, restrictions })
+    }
+
+    pub fn restriction(&self, key: &str) -> Option<&str> {
+        self.restrictions.iter()
+        .find(|x| x.0 == key)
+        .map(|x| x.1.as_str())
+    }
+
+    fn max_pixels(&self) -> Option<u64> {
+        let width: u64 = self.restriction("max-width")?.parse().ok()?;
+        let height: u64 = self.restriction("max-height")?.parse().ok()?;
+        Some(width * height)
+    }
+}
+
+// RFC 8853 section 5.1, a=simulcast:<direction> <rid-list> [<direction> <rid-list>],
+// the first one of alternatives is taken and paused ones are kept
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SdpSimulcast {
+    pub send: Vec<String>,
+    pub recv: Vec<String>,
+}
+
+impl SdpSimulcast {
+    pub fn parse_from_str(value: &str) -> Result<Self> {
+        let mut simulcast = Self::default();
+        let mut iter = value.split_ascii_whitespace();
+        while let Some(direction) = iter.next() {
+            let list = iter.next().with_context(||"simulcast without rid list")?;
+            let rids = list.split(';')
+                .filter_map(|x| x.split(',').next())
+                .map(|x| x.trim_start_matches('~').to_string())
+                .filter(|x| !x.is_empty())
+                .collect();
+            match direction {
+                "send" => simulcast.send = rids,
+                "recv" => simulcast.recv = rids,
+                _ => bail!("invalid simulcast direction [{direction}]"),
+            }
+        }
+        Ok(simulcast)
+    }
 }
 
 pub type SdpVideo = SdpAV;
@@ -135,8 +363,28 @@ fn parse_av(mdesc:&sdp_rs::MediaDescription, index: usize) -> Result<SdpAV> {
         proto: mdesc.media.proto.clone(),
         payload_types,
         codecs: Default::default(),
+        ssrc_groups: Default::default(),
+        extmaps: Default::default(),
+        cryptos: Default::default(),
+        rids: Default::default(),
+        simulcast: Default::default(),
     };
 
+    // static payload types may have no rtpmap
+    for payload_type in media.payload_types.iter() {
+        if let Some((codec_id, clock_rate, channels)) = CodecId::from_static_payload_type(*payload_type) {
+            media.codecs.insert(*payload_type, SdpCodec {
+                payload_type: *payload_type,
+                media_type: mdesc.media.media.clone(),
+                codec_id,
+                clock_rate,
+                channels: Some(channels),
+                rtcpfb: Default::default(),
+                fmtps: Default::default(),
+            });
+        }
+    }
+
     for attr in mdesc.attributes.iter() {
         match attr {
             sdp_rs::lines::Attribute::Rtpmap(rtpmap)  => {
@@ -175,6 +423,41 @@ fn parse_av(mdesc:&sdp_rs::MediaDescription, index: usize) -> Result<SdpAV> {
                             }
                         }
                     }
+                } else if name == "ssrc-group" {
+                    if let Some(value) = value {
+                        match SdpSsrcGroup::parse_from_str(value) {
+                            Ok(v) => media.ssrc_groups.push(v),
+                            Err(e) => tracing::warn!("skip invalid ssrc-group [{value}], {e:?}"),
+                        }
+                    }
+                } else if name == "extmap" {
+                    if let Some(value) = value {
+                        match SdpExtmap::parse_from_str(value) {
+                            Ok(v) => media.extmaps.push(v),
+                            Err(e) => tracing::warn!("skip invalid extmap [{value}], {e:?}"),
+                        }
+                    }
+                } else if name == "crypto" {
+                    if let Some(value) = value {
+                        match SdpCrypto::parse_from_str(value) {
+                            Ok(v) => media.cryptos.push(v),
+                            Err(e) => tracing::warn!("skip invalid crypto [{value}], {e:?}"),
+                        }
+                    }
+                } else if name == "rid" {
+                    if let Some(value) = value {
+                        match SdpRid::parse_from_str(value) {
+                            Ok(v) => media.rids.push(v),
+                            Err(e) => tracing::warn!("skip invalid rid [{value}], {e:?}"),
+                        }
+                    }
+                } else if name == "simulcast" {
+                    if let Some(value) = value {
+                        match SdpSimulcast::parse_from_str(value) {
+                            Ok(v) => media.simulcast = Some(v),
+                            Err(e) => tracing::warn!("skip invalid simulcast [{value}], {e:?}"),
+                        }
+                    }
                 }
             }
             _ => {}
@@ -205,5 +488,182 @@ fn test_sdp() {
         a=control:streamid=1
         "
     };
-    SdpMain::parse_from_str(sdp).unwrap();
+    let sdp = SdpMain::parse_from_str(sdp).unwrap();
+    assert!(matches!(sdp.medias[0], SdpMedia::Video(_)));
+    assert!(matches!(sdp.medias[1], SdpMedia::Audio(_)));
+}
+
+#[test]
+fn test_sdp_vpx() {
+    let sdp = indoc::indoc!{
+        "v=0
+        o=- 0 0 IN IP4 127.0.0.1
+        s=No Name
+        t=0 0
+        m=video 9 UDP/TLS/RTP/SAVPF 96 98 97
+        a=rtpmap:96 VP8/90000
+        a=rtpmap:98 VP9/90000
+        a=fmtp:98 profile-id=0
+        a=rtpmap:97 rtx/90000
+        a=fmtp:97 apt=96
+        "
+    };
+    let sdp = SdpMain::parse_from_str(sdp).unwrap();
+    let media = match &sdp.medias[0] {
+        SdpMedia::Video(media) => media,
+        _ => panic!("expect video media"),
+    };
+    assert_eq!(media.codecs[&96].codec_id, CodecId::VP8);
+    assert_eq!(media.codecs[&98].codec_id, CodecId::VP9);
+    assert_eq!(media.codecs[&98].fmtps, vec!["profile-id=0".to_string()]);
+    assert_eq!(media.codecs[&97].codec_id, CodecId::RtpRTX);
+    assert_eq!(media.codecs[&97].rtx_apt(), Some(96));
+    assert_eq!(media.rtx_payload_type(96), Some(97));
+    assert_eq!(media.rtx_payload_type(98), None);
+}
+
+#[test]
+fn test_sdp_ssrc_group_extmap() {
+    let sdp = indoc::indoc!{
+        "v=0
+        o=- 0 0 IN IP4 127.0.0.1
+        s=-
+        t=0 0
+        m=video 9 UDP/TLS/RTP/SAVPF 96 97
+        a=rtpmap:96 VP8/90000
+        a=rtpmap:97 rtx/90000
+        a=fmtp:97 apt=96
+        a=extmap:4 urn:ietf:params:rtp-hdrext:sdes:mid
+        a=extmap:x urn:ietf:params:rtp-hdrext:toffset
+        a=extmap:13/recvonly urn:3gpp:video-orientation
+        a=ssrc-group:FID 1111 2222
+        a=ssrc-group:FID 1111 x
+        a=ssrc:1111 cname:abc
+        a=ssrc:2222 cname:abc
+        "
+    };
+    let sdp = SdpMain::parse_from_str(sdp).unwrap();
+    let media = match &sdp.medias[0] {
+        SdpMedia::Video(media) => media,
+        _ => panic!("expect video media"),
+    };
+    assert_eq!(media.ssrc_groups, vec![SdpSsrcGroup { semantics: "FID".into(), ssrcs: vec![1111, 2222] }]);
+    assert_eq!(media.rtx_primary_ssrc(2222), Some(1111));
+    assert_eq!(media.rtx_primary_ssrc(1111), None);
+    assert_eq!(media.extmaps.len(), 2);
+    assert_eq!(media.extmaps[1], SdpExtmap { id: 13, direction: Some("recvonly".into()), uri: "urn:3gpp:video-orientation".into() });
+}
+
+#[test]
+fn test_sdp_static_payload_type() {
+    let sdp = indoc::indoc!{
+        "v=0
+        o=- 0 0 IN IP4 127.0.0.1
+        s=-
+        t=0 0
+        m=audio 5004 RTP/AVP 0 8 9 101
+        a=rtpmap:101 telephone-event/8000
+        "
+    };
+    let sdp = SdpMain::parse_from_str(sdp).unwrap();
+    let media = match &sdp.medias[0] {
+        SdpMedia::Audio(media) => media,
+        _ => panic!("expect audio media"),
+    };
+    assert_eq!(media.codecs[&0].codec_id, CodecId::PCMU);
+    assert_eq!(media.codecs[&0].clock_rate, 8000);
+    assert_eq!(media.codecs[&8].codec_id, CodecId::PCMA);
+    assert_eq!(media.codecs[&8].channels, Some(1));
+    assert_eq!(media.codecs[&9].codec_id, CodecId::G722);
+    assert_eq!(media.codecs[&9].clock_rate, 8000);
+    assert_eq!(media.codecs[&9].sample_rate(), 16000);
+    assert_eq!(media.codecs[&101].codec_id, CodecId::TelephoneEvent);
+}
+
+#[test]
+fn test_sdp_crypto() {
+    let sdp = indoc::indoc!{
+        "v=0
+        o=- 0 0 IN IP4 127.0.0.1
+        s=-
+        t=0 0
+        m=audio 5004 RTP/SAVP 0
+        a=crypto:1 AES_CM_128_HMAC_SHA1_80 inline:4fl6DT4Bi+DWT6MsBt5BOQ7Gda1Jiv7rtpYLOqvm|2^20|1:4 UNENCRYPTED_SRTCP
+        a=crypto:2 AEAD_AES_128_GCM inline:AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGw==
+        a=crypto:x AES_CM_128_HMAC_SHA1_32
+        "
+    };
+    let sdp = SdpMain::parse_from_str(sdp).unwrap();
+    let media = match &sdp.medias[0] {
+        SdpMedia::Audio(media) => media,
+        _ => panic!("expect audio media"),
+    };
+    assert_eq!(media.cryptos.len(), 2);
+    assert_eq!(media.cryptos[0], SdpCrypto {
+        tag: 1,
+        suite: "AES_CM_128_HMAC_SHA1_80".into(),
+        key_params: "inline:4fl6DT4Bi+DWT6MsBt5BOQ7Gda1Jiv7rtpYLOqvm|2^20|1:4".into(),
+        session_params: vec!["UNENCRYPTED_SRTCP".into()],
+    });
+    assert_eq!(media.cryptos[1].suite, "AEAD_AES_128_GCM");
+}
+
+#[test]
+fn test_sdp_simulcast() {
+    let sdp = indoc::indoc!{
+        "v=0
+        o=- 0 0 IN IP4 127.0.0.1
+        s=-
+        t=0 0
+        m=video 9 UDP/TLS/RTP/SAVPF 96 97
+        a=rtpmap:96 VP8/90000
+        a=rtpmap:97 rtx/90000
+        a=fmtp:97 apt=96
+        a=rid:f send pt=96;max-width=1280;max-height=720
+        a=rid:q send max-width=320;max-height=180
+        a=rid:h send max-width=640;max-height=360
+        a=simulcast:send f;q,x;~h recv r
+        m=video 9 UDP/TLS/RTP/SAVPF 96
+        a=rtpmap:96 VP8/90000
+        a=rid:f send
+        a=rid:h send max-width=640
+        a=rid:q send
+        a=rid:x
+        a=simulcast:recv f;h;q
+        m=video 9 UDP/TLS/RTP/SAVPF 96
+        a=rtpmap:96 VP8/90000
+        a=ssrc-group:SIM 11 22 33
+        a=simulcast:both a
+        "
+    };
+    let sdp = SdpMain::parse_from_str(sdp).unwrap();
+    let medias: Vec<_> = sdp.medias.iter().map(|x| match x {
+        SdpMedia::Video(media) => media,
+        _ => panic!("expect video media"),
+    }).collect();
+
+    assert_eq!(medias[0].rids[0], SdpRid {
+        id: "f".into(),
+        direction: "send".into(),
+        payload_types: vec![96],
+        restrictions: vec![("max-width".into(), "1280".into()), ("max-height".into(), "720".into())],
+    });
+    assert_eq!(medias[0].rids[1].restriction("max-width"), Some("320"));
+    assert_eq!(medias[0].simulcast, Some(SdpSimulcast {
+        send: vec!["f".into(), "q".into(), "h".into()],
+        recv: vec!["r".into()],
+    }));
+
+    let rid = |x: &str| SdpSimulcastLayer::Rid(x.into());
+    assert_eq!(medias[0].simulcast_layers(SimulcastRidOrder::LowToHigh), vec![rid("q"), rid("h"), rid("f")]);
+
+    // without max dimensions, by the order of a=simulcast
+    assert_eq!(medias[1].rids.len(), 3);
+    assert_eq!(medias[1].simulcast_layers(SimulcastRidOrder::HighToLow), vec![rid("q"), rid("h"), rid("f")]);
+    assert_eq!(medias[1].simulcast_layers(SimulcastRidOrder::LowToHigh), vec![rid("f"), rid("h"), rid("q")]);
+    assert_eq!(medias[2].simulcast, None);
+    assert_eq!(medias[2].simulcast_layers(Default::default()), vec![
+        SdpSimulcastLayer::Ssrc(11), SdpSimulcastLayer::Ssrc(22), SdpSimulcastLayer::Ssrc(33),
+    ]);
+    assert!(SdpSimulcast::parse_from_str("both a").is_err());
 }
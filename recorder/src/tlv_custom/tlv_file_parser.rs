@@ -3,7 +3,7 @@ use anyhow::{anyhow, bail, Context, Result};
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use rtp_rs::RtpReader;
-use crate::{rtp::rtp::check_is_rtcp, sdp::sdp::{SdpCodec, SdpMain, SdpMedia}, tlv2::{TlvFileSyncReader, Type, VecBuf}, tlv_custom::{TlvType, TLV_MAGIC}};
+use crate::{media::CodecId, rtp::{codec::dtmf::{DtmfEvent, RtpDtmfParser}, fec::{parse_flexfec, parse_ulpfec, RtpFecReceiver}, jitter::{JitterConfig, JitterLost, JitterOutput, RtpJitterBuf}, red::RtpRedUnwrapper, rtp::check_is_rtcp, rtpext::{RtpExtMap, RtpExtensions}, rtx::unwrap_rtx_packet, srtp::{SrtpKeyMap, SrtpMasterKey, SrtpSession}}, sdp::sdp::{SdpAV, SdpCodec, SdpMain, SdpMedia, SdpSimulcastLayer, SimulcastRidOrder}, tlv2::{TlvFileSyncReader, Type, VecBuf}, tlv_custom::{TlvType, TLV_MAGIC}};
 
 macro_rules! dbgd {
     ($($arg:tt)* ) => (
@@ -40,7 +40,7 @@ fn test_parse() {
         }
     
         fn on_flow_rtp(&mut self, mut ctx: ContextMut<'_, Self>, flow: &mut FlowMut<Self::Flow>, packet: &ChPacket) -> Result<()> {
-            println!("on_flow_rtp: {:?}, codec {:?}, {packet}", flow.index(),  flow.codec().codec_id);
+            println!("on_flow_rtp: {:?}, codec {:?}, {packet}, {:?}", flow.index(),  flow.codec().codec_id, flow.rtp_ext());
             self.num_ch_packets += 1;
             if self.num_ch_packets >= self.max_ch_packets {
                 ctx.set_finished();
@@ -115,6 +115,8 @@ impl<'a, H: Handler> ContextMut<'a, H> {
 
 struct ParserContext<H: Handler> {
     finished: bool,
+    // ts of the latest record of all tracks, jitter buffers of all flows are driven by it
+    now: i64,
     _none: PhantomData<H>,
     // ext: H::Context,
 }
@@ -161,6 +163,20 @@ impl<'a, T> FlowMut<'a, T> {
     pub fn ext_mut(&mut self) -> &mut T {
         &mut self.0.ext
     }
+
+    // RFC 8285 header extensions of the current packet in on_flow_rtp
+    pub fn rtp_ext(&self) -> &RtpExtensions {
+        &self.0.rtp_ext
+    }
+
+    // interarrival jitter and the delay of jitter buffer in milliseconds
+    pub fn jitter_ms(&self) -> i64 {
+        self.0.jitter.buf.jitter_ms()
+    }
+
+    pub fn jitter_delay_ms(&self) -> i64 {
+        self.0.jitter.buf.target_delay_ms()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -213,6 +229,27 @@ pub trait Handler: Sized {
     fn on_flow_rtp(&mut self, ctx: ContextMut<'_, Self>, flow: &mut FlowMut<Self::Flow>, packet: &ChPacket) -> Result<()>;
 
     fn on_track_rtcp(&mut self, ctx: ContextMut<'_, Self>, index: TrackIndex, packet: &ChPacket) -> Result<()>;
+
+    // telephone-event flow, ts is the time of the first received packet of the event
+    fn on_flow_dtmf_begin(&mut self, _ctx: ContextMut<'_, Self>, _flow: &Flow, _ts: i64, _event: &DtmfEvent) -> Result<()> {
+        Ok(())
+    }
+
+    // called once for each event, start_ts is the same as begin
+    fn on_flow_dtmf_end(&mut self, _ctx: ContextMut<'_, Self>, _flow: &Flow, _start_ts: i64, _event: &DtmfEvent) -> Result<()> {
+        Ok(())
+    }
+
+    // packets not arrived before the jitter buffer released the next one,
+    // called right before on_flow_rtp of the next packet
+    fn on_flow_lost(&mut self, _ctx: ContextMut<'_, Self>, _flow: &Flow, _lost: &JitterLost) -> Result<()> {
+        Ok(())
+    }
+
+    // how layers of simulcast are ranked when rids have no max dimensions
+    fn simulcast_rid_order(&self) -> SimulcastRidOrder {
+        Default::default()
+    }
 }
 
 impl Handler for () {
@@ -252,12 +289,43 @@ pub struct Stream {
 #[derive(Debug, Clone)]
 pub struct Track {
     pub flows: Vec<Flow>,
+    pub stats: TrackStats,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TrackStats {
+    // packets rebuilt from redundant blocks of RED
+    pub red_recovered: u64,
+
+    // packets rebuilt from ULPFEC or FlexFEC
+    pub fec_recovered: u64,
+
+    // SRTP/SRTCP packets dropped by authentication or replay check
+    pub srtp_failed: u64,
 }
 
 #[derive(Debug, Clone)]
 pub struct Flow {
     pub index: FlowIndex,
     pub codec: SdpCodec,
+
+    // Some if the flow is one layer of simulcast
+    pub layer: Option<SimulcastLayer>,
+}
+
+impl Flow {
+    // layers of simulcast share the source of the first layer flow
+    pub fn source_index(&self) -> FlowIndex {
+        self.layer.as_ref().map(|x| x.source).unwrap_or(self.index)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulcastLayer {
+    // 0 is the lowest
+    pub rank: usize,
+    pub rid: Option<String>,
+    pub source: FlowIndex,
 }
 
 // pub struct Parser<H: Hanlder> {
@@ -274,6 +342,12 @@ pub struct Flow {
 // }
 
 pub fn parse_tlv_file<H: Handler>(ipath: &Path, handler: &mut H) -> Result<FileInfo> 
+{
+    parse_tlv_file_with_keys(ipath, handler, &SrtpKeyMap::default())
+}
+
+// SRTP keys of a=crypto in sdp are always used, keys are also looked up in srtp_keys by ssrc
+pub fn parse_tlv_file_with_keys<H: Handler>(ipath: &Path, handler: &mut H, srtp_keys: &SrtpKeyMap) -> Result<FileInfo> 
 {
     let mut reader = TlvFileSyncReader::open_with_magic(&ipath, Some(TLV_MAGIC))
     .with_context(||format!("failed open [{ipath:?}]"))?;
@@ -285,6 +359,7 @@ pub fn parse_tlv_file<H: Handler>(ipath: &Path, handler: &mut H) -> Result<FileI
 
     let ctx = ParserContext {
         finished: false,
+        now: 0,
         _none: Default::default(),
         // ext: ctx,
     };
@@ -343,7 +418,7 @@ pub fn parse_tlv_file<H: Handler>(ipath: &Path, handler: &mut H) -> Result<FileI
                         )?;
 
                         {
-                            let stream = ParserStream::<H>::new(stream_index.index, info, &mut handler)?;
+                            let stream = ParserStream::<H>::new(stream_index.index, info, srtp_keys, &mut handler)?;
                     
                             for track_index in 0..stream.num_tracks() {
                                 let ch_id = stream.start_ch_id() + (track_index << 1) as u64;
@@ -370,6 +445,8 @@ pub fn parse_tlv_file<H: Handler>(ipath: &Path, handler: &mut H) -> Result<FileI
 
                         dbgd!("read: ch packet, {packet}");
 
+                        handler.ctx.now = handler.ctx.now.max(ts);
+
                         if let Some(stream_index) = parser.stream_indexes.get_mut(&packet.ch_id) {
                             if let Some(stream) = parser.streams.get_mut(stream_index.index) {
                                 stream.handle_ch_data(packet, &mut handler)?;
@@ -380,6 +457,10 @@ pub fn parse_tlv_file<H: Handler>(ipath: &Path, handler: &mut H) -> Result<FileI
                             dbgd!("NOT found ch_id {}", packet.ch_id);
                         }
 
+                        for stream in parser.streams.iter_mut() {
+                            stream.pop_jitter(false, &mut handler)?;
+                        }
+
                         // break;
                     },
                     _ => {
@@ -393,6 +474,12 @@ pub fn parse_tlv_file<H: Handler>(ipath: &Path, handler: &mut H) -> Result<FileI
         }
     }
 
+    if !handler.ctx.finished {
+        for stream in parser.streams.iter_mut() {
+            stream.pop_jitter(true, &mut handler)?;
+        }
+    }
+
     Ok(parser.into())
 }
 
@@ -442,7 +529,7 @@ struct ParserStream<H: Handler> {
 }
 
 impl<H: Handler> ParserStream<H> {
-    pub fn new(stream_index: usize, info: StreamInfo, handler: &mut HandlerMut<'_, H>) -> Result<Self> {
+    pub fn new(stream_index: usize, info: StreamInfo, srtp_keys: &SrtpKeyMap, handler: &mut HandlerMut<'_, H>) -> Result<Self> {
 
         // let sdp = sdp_rs::SessionDescription::from_str(&info.sdp)?; 
         let sdp = SdpMain::parse_from_str(&info.sdp)?;
@@ -464,9 +551,17 @@ impl<H: Handler> ParserStream<H> {
 
             handler.handler.on_add_track(ContextMut(&mut handler.ctx), index)?;
 
+            let has_fec = sdp_media_has_fec(&sdp_media);
+            let srtp = make_srtp_session(&sdp_media, srtp_keys)?;
+
             me.tracks.push(ParserTrack {
                 index,
                 flows: Default::default(),
+                reds: Default::default(),
+                fecs: has_fec.then(Default::default),
+                simulcast: SimulcastState::new(&sdp_media, handler.handler.simulcast_rid_order()),
+                srtp,
+                srtp_failed: 0,
                 // ext: track_ext,
                 info : TrackInfo {
                     // ch_id: (mindex << 1) as u64,
@@ -496,6 +591,11 @@ impl<H: Handler> ParserStream<H> {
         let track_index = (ch_id >> 1) as usize;
         
         if let Some(track) = self.tracks.get_mut(track_index) {
+            let ch_data = match track.decrypt(ch_data) {
+                Some(v) => v,
+                None => return Ok(()),
+            };
+
             if check_is_rtcp(&ch_data.data) {
                 handler.handler.on_track_rtcp(
                     ContextMut(&mut handler.ctx), 
@@ -508,69 +608,58 @@ impl<H: Handler> ParserStream<H> {
 
             dbgd!("handle_ch_data: track_index {track_index}, rtp {rtp:?}");
 
-            let found = track.flows.get_mut(&rtp.payload_type());
+            let payload_type = rtp.payload_type();
 
-            match found {
-                Some(flow) => {
-                    handler.handler.on_flow_rtp(ContextMut(&mut handler.ctx), &mut FlowMut(flow), &ch_data)?;
-                },
-                None => {
-                    match &track.info.sdp_media {
-                        SdpMedia::Video(mdesc) => {
-                            if let Some(codec) = mdesc.codecs.get(&rtp.payload_type()) {
-                                let flow_index = FlowIndex { 
-                                    track: track.index, 
-                                    flow: track.flows.len(), 
-                                };
-
-                                let ext = handler.handler.on_add_flow(
-                                    ContextMut(&mut handler.ctx),
-                                    flow_index,
-                                    codec
-                                )?;
-
-                                let mut flow = ParserFlow {
-                                    flow: Flow {
-                                        index: flow_index,
-                                        codec: codec.clone(),
-                                    },
-                                    ext,
-                                };
-
-                                handler.handler.on_flow_rtp(ContextMut(&mut handler.ctx), &mut FlowMut(&mut flow), &ch_data)?;
-
-                                track.flows.insert(rtp.payload_type(), flow);
-                            }
-                        },
-                        SdpMedia::Audio(mdesc) => {
-                            if let Some(codec) = mdesc.codecs.get(&rtp.payload_type()) {
-                                let flow_index = FlowIndex { 
-                                    track: track.index, 
-                                    flow: track.flows.len(), 
-                                };
-
-                                let ext = handler.handler.on_add_flow(
-                                    ContextMut(&mut handler.ctx),
-                                    flow_index,
-                                    codec
-                                )?;
-
-                                let mut flow = ParserFlow {
-                                    flow: Flow {
-                                        index: flow_index,
-                                        codec: codec.clone(),
-                                    },
-                                    ext,
-                                };
-
-                                handler.handler.on_flow_rtp(ContextMut(&mut handler.ctx), &mut FlowMut(&mut flow), &ch_data)?;
-
-                                track.flows.insert(rtp.payload_type(), flow);
-                            }
-                        },
-                        SdpMedia::Unknown => {},
-                    }
-                },
+            if media_codec(&track.info.sdp_media, payload_type).map(|x|x.codec_id) == Some(CodecId::RED) {
+                let red = track.reds.entry(payload_type).or_default();
+                if let Err(_e) = red.push_rtp_slice(&ch_data.data) {
+                    dbgd!("invalid red packet [{_e:?}]");
+                    return Ok(())
+                }
+
+                let packets: Vec<Bytes> = std::iter::from_fn(|| red.pull_rtp()).collect();
+                for data in packets {
+                    let payload_type = data[1] & 0x7F;
+                    let packet = ChPacket {
+                        ts: ch_data.ts,
+                        ch_id: ch_data.ch_id,
+                        data,
+                    };
+                    track.handle_flow_rtp(payload_type, &packet, handler)?;
+                }
+                return Ok(())
+            }
+
+            let apt = media_codec(&track.info.sdp_media, payload_type).and_then(|x|x.rtx_apt());
+            if let Some(apt) = apt {
+                // RFC 4588, merged into the primary flow, dropped if primary not started yet
+                let ssrc = rtp.ssrc();
+                let layer = match &mut track.simulcast {
+                    Some(simulcast) => {
+                        let primary_ssrc = media_av(&track.info.sdp_media).and_then(|x|x.rtx_primary_ssrc(ssrc));
+                        match simulcast.rank_of(&ch_data.data, primary_ssrc.unwrap_or(ssrc)) {
+                            Some(rank) => Some(rank),
+                            None => return Ok(()),
+                        }
+                    },
+                    None => None,
+                };
+                if let Some(flow) = track.flows.get_mut(&(apt, layer)) {
+                    flow.handle_rtx(&track.info.sdp_media, ssrc, &ch_data, handler)?;
+                }
+                return Ok(())
+            }
+
+            track.handle_flow_rtp(payload_type, &ch_data, handler)?;
+        }
+        Ok(())
+    }
+
+    // release packets due by now of the parser, all remaining if flush
+    fn pop_jitter(&mut self, flush: bool, handler: &mut HandlerMut<'_, H>) -> Result<()> {
+        for track in self.tracks.iter_mut() {
+            for flow in track.flows.values_mut() {
+                flow.pop_jitter(flush, handler)?;
             }
         }
         Ok(())
@@ -580,10 +669,257 @@ impl<H: Handler> ParserStream<H> {
 struct ParserTrack<H: Handler> {
     index: TrackIndex,
     info: TrackInfo,
-    flows: HashMap<u8, ParserFlow<H::Flow>>, 
+    flows: HashMap<FlowKey, ParserFlow<H::Flow>>, 
+
+    // RFC 2198, unwrapped packets go to the flows of block payload type
+    reds: HashMap<u8, RtpRedUnwrapper>,
+
+    // receivers by protected ssrc if FEC is negotiated
+    fecs: Option<HashMap<u32, RtpFecReceiver>>,
+
+    // layers of simulcast if negotiated
+    simulcast: Option<SimulcastState>,
+
+    // decrypts packets before anything else if keys are given
+    srtp: Option<SrtpSession>,
+    srtp_failed: u64,
     // ext: H::Track,
 }
 
+impl<H: Handler> ParserTrack<H> {
+    // None if failed to decrypt
+    fn decrypt(&mut self, ch_data: ChPacket) -> Option<ChPacket> {
+        let srtp = match &mut self.srtp {
+            Some(srtp) => srtp,
+            None => return Some(ch_data),
+        };
+
+        let r = if check_is_rtcp(&ch_data.data) {
+            srtp.decrypt_rtcp(&ch_data.data)
+        } else {
+            srtp.decrypt_rtp(&ch_data.data)
+        };
+
+        match r {
+            Ok(data) => Some(ChPacket { data, ..ch_data }),
+            Err(_e) => {
+                dbgd!("drop srtp packet [{_e:?}]");
+                self.srtp_failed += 1;
+                None
+            }
+        }
+    }
+
+    fn handle_flow_rtp(&mut self, payload_type: u8, ch_data: &ChPacket, handler: &mut HandlerMut<'_, H>) -> Result<()> {
+        let fecs = match &mut self.fecs {
+            Some(fecs) => fecs,
+            None => return self.dispatch_flow_rtp(payload_type, ch_data, handler),
+        };
+
+        let codec_id = media_codec(&self.info.sdp_media, payload_type).map(|x|x.codec_id);
+        let fec = match codec_id {
+            Some(CodecId::ULPFEC) => Some(parse_ulpfec(&ch_data.data)),
+            Some(CodecId::FlexFEC) => Some(parse_flexfec(&ch_data.data)),
+            _ => None,
+        };
+
+        let (seq, receiver) = match fec {
+            Some(Ok(fec)) => {
+                let receiver = fecs.entry(fec.ssrc).or_default();
+                receiver.push_fec(fec);
+                (None, receiver)
+            },
+            Some(Err(_e)) => {
+                dbgd!("invalid fec packet [{_e:?}]");
+                return Ok(())
+            },
+            None => {
+                let rtp = RtpReader::new(&ch_data.data).map_err(|e|anyhow!("invalid rtp {e:?}"))?;
+                let receiver = fecs.entry(rtp.ssrc()).or_default();
+                if !receiver.push_media(ch_data.data.clone()) {
+                    // received or recovered already
+                    return Ok(())
+                }
+                (Some(u16::from(rtp.sequence_number())), receiver)
+            },
+        };
+
+        let mut packets: Vec<ChPacket> = std::iter::from_fn(|| receiver.pull_rtp())
+            .map(|data| ChPacket {
+                ts: ch_data.ts,
+                ch_id: ch_data.ch_id,
+                data,
+            })
+            .collect();
+
+        // deliver in seq order with the current media packet
+        if let Some(seq) = seq {
+            packets.push(ch_data.clone());
+            packets.sort_by_key(|x| u16::from_be_bytes([x.data[2], x.data[3]]).wrapping_sub(seq) as i16);
+        }
+
+        for packet in packets.iter() {
+            self.dispatch_flow_rtp(packet.data[1] & 0x7F, packet, handler)?;
+        }
+        Ok(())
+    }
+
+    fn dispatch_flow_rtp(&mut self, payload_type: u8, ch_data: &ChPacket, handler: &mut HandlerMut<'_, H>) -> Result<()> {
+        let rank = match &mut self.simulcast {
+            Some(simulcast) => {
+                let rtp = RtpReader::new(&ch_data.data).map_err(|e|anyhow!("invalid rtp {e:?}"))?;
+                match simulcast.rank_of(&ch_data.data, rtp.ssrc()) {
+                    Some(rank) => Some(rank),
+                    None => {
+                        dbgd!("unknown simulcast layer of ssrc {}", rtp.ssrc());
+                        return Ok(())
+                    }
+                }
+            },
+            None => None,
+        };
+
+        let key = (payload_type, rank);
+        if let Some(flow) = self.flows.get_mut(&key) {
+            return flow.handle_rtp(ch_data, handler)
+        }
+
+        let codec = match media_codec(&self.info.sdp_media, payload_type) {
+            Some(codec) => codec,
+            None => return Ok(()),
+        };
+
+        let flow_index = FlowIndex { 
+            track: self.index, 
+            flow: self.flows.len(), 
+        };
+
+        let ext = handler.handler.on_add_flow(
+            ContextMut(&mut handler.ctx),
+            flow_index,
+            codec
+        )?;
+
+        let rtx = media_av(&self.info.sdp_media)
+            .and_then(|x|x.rtx_payload_type(payload_type))
+            .is_some();
+
+        let extmap = media_av(&self.info.sdp_media)
+            .map(|x|RtpExtMap::new(x.extmaps.iter().map(|x|(x.id, x.uri.as_str()))))
+            .unwrap_or_default();
+
+        let layer = match (rank, &self.simulcast) {
+            (Some(rank), Some(simulcast)) => Some(SimulcastLayer {
+                rank,
+                rid: match &simulcast.layers[rank] {
+                    SdpSimulcastLayer::Rid(rid) => Some(rid.clone()),
+                    SdpSimulcastLayer::Ssrc(_) => None,
+                },
+                source: self.flows.iter()
+                    .find(|(k, _v)| k.0 == payload_type)
+                    .map(|(_k, v)| v.flow.source_index())
+                    .unwrap_or(flow_index),
+            }),
+            _ => None,
+        };
+
+        let mut flow = ParserFlow::new(flow_index, codec, ext, rtx, extmap, layer);
+
+        flow.handle_rtp(ch_data, handler)?;
+
+        self.flows.insert(key, flow);
+        Ok(())
+    }
+}
+
+// payload type and rank of simulcast layer
+type FlowKey = (u8, Option<usize>);
+
+// maps packets to layers of simulcast by ssrc,
+// which is learned from rid extension or given by a=ssrc-group:SIM
+struct SimulcastState {
+    layers: Vec<SdpSimulcastLayer>,
+    extmap: RtpExtMap,
+    ranks: HashMap<u32, usize>,
+}
+
+impl SimulcastState {
+    fn new(media: &SdpMedia, rid_order: SimulcastRidOrder) -> Option<Self> {
+        let av = media_av(media)?;
+        let layers = av.simulcast_layers(rid_order);
+        if layers.len() < 2 {
+            return None
+        }
+
+        let ranks = layers.iter().enumerate()
+            .filter_map(|(rank, x)| match x {
+                SdpSimulcastLayer::Ssrc(ssrc) => Some((*ssrc, rank)),
+                SdpSimulcastLayer::Rid(_) => None,
+            })
+            .collect();
+
+        Some(Self {
+            extmap: RtpExtMap::new(av.extmaps.iter().map(|x|(x.id, x.uri.as_str()))),
+            layers,
+            ranks,
+        })
+    }
+
+    // None if the ssrc is not known yet and the packet has no rid
+    fn rank_of(&mut self, data: &[u8], ssrc: u32) -> Option<usize> {
+        if let Some(rank) = self.ranks.get(&ssrc) {
+            return Some(*rank)
+        }
+
+        let ext = self.extmap.parse(data).ok()?;
+        let rid = ext.rid.or(ext.repaired_rid)?;
+        let rank = self.layers.iter().position(|x| *x == SdpSimulcastLayer::Rid(rid.clone()))?;
+        self.ranks.insert(ssrc, rank);
+        Some(rank)
+    }
+}
+
+fn media_codec(media: &SdpMedia, payload_type: u8) -> Option<&SdpCodec> {
+    media_av(media).and_then(|x|x.codecs.get(&payload_type))
+}
+
+fn sdp_media_has_fec(media: &SdpMedia) -> bool {
+    media_av(media)
+    .map(|x| x.codecs.values().any(|x| matches!(x.codec_id, CodecId::ULPFEC | CodecId::FlexFEC)))
+    .unwrap_or(false)
+}
+
+// a=crypto lines of unsupported suite or invalid key are skipped,
+// fails if none of them is usable and no key is supplied
+fn make_srtp_session(media: &SdpMedia, srtp_keys: &SrtpKeyMap) -> Result<Option<SrtpSession>> {
+    let mut srtp = SrtpSession::new();
+    let cryptos = media_av(media).map(|x| &x.cryptos[..]).unwrap_or_default();
+    for crypto in cryptos.iter() {
+        let key = SrtpMasterKey::from_sdes(&crypto.suite, &crypto.key_params)
+            .map(|x| x.with_session_params(&crypto.session_params));
+        match key {
+            Ok(key) => srtp.add_key(&key)?,
+            Err(_e) => {
+                dbgd!("skip crypto [{}], {_e:?}", crypto.tag);
+            },
+        }
+    }
+    srtp.add_key_map(srtp_keys)?;
+
+    if !cryptos.is_empty() && srtp.is_empty() {
+        bail!("no usable crypto in [{}]", cryptos.iter().map(|x| x.suite.as_str()).collect::<Vec<_>>().join(", "))
+    }
+    Ok((!srtp.is_empty()).then_some(srtp))
+}
+
+fn media_av(media: &SdpMedia) -> Option<&SdpAV> {
+    match media {
+        SdpMedia::Video(mdesc) => Some(mdesc),
+        SdpMedia::Audio(mdesc) => Some(mdesc),
+        SdpMedia::Unknown => None,
+    }
+}
+
 struct TrackInfo {
     // ch_id: u64,
     sdp_media: SdpMedia,
@@ -591,11 +927,17 @@ struct TrackInfo {
 
 impl <H: Handler> From<ParserTrack<H>> for Track {
     fn from(from: ParserTrack<H>) -> Self {
+        // handlers look up flows by index
+        let mut flows: Vec<Flow> = from.flows.into_values().map(Flow::from).collect();
+        flows.sort_by_key(|x| x.index);
+
         Self {
-            flows: from.flows
-                .into_iter()
-                .map(|(_k, v)| Flow::from(v))
-                .collect(),
+            stats: TrackStats {
+                red_recovered: from.reds.values().map(|x|x.recovered()).sum(),
+                fec_recovered: from.fecs.iter().flat_map(|x|x.values()).map(|x|x.recovered()).sum(),
+                srtp_failed: from.srtp_failed,
+            },
+            flows,
         }
     }
 }
@@ -611,6 +953,191 @@ impl <H: Handler> From<ParserTrack<H>> for Track {
 struct ParserFlow<T> {
     flow: Flow,
     ext: T,
+    dtmf: Option<DtmfState>,
+    jitter: JitterState,
+    rtx: Option<RtxState>,
+    extmap: RtpExtMap,
+    rtp_ext: RtpExtensions,
+}
+
+// max waiting time for retransmission in milliseconds
+const RTX_WAIT_MS: i64 = 500;
+
+// packets of the flow are held in a jitter buffer and delivered in seq order when due,
+// that is checked by the clock of the parser at each record of any track,
+// gaps not filled by then are reported as lost, delivered packets keep their arrival ts
+struct JitterState {
+    buf: RtpJitterBuf<ChPacket>,
+}
+
+impl JitterState {
+    fn new(clock_rate: u32, rtx: bool) -> Self {
+        // flows paired with rtx wait for retransmissions
+        let config = if rtx {
+            JitterConfig {
+                min_delay_ms: RTX_WAIT_MS,
+                init_delay_ms: RTX_WAIT_MS,
+                ..Default::default()
+            }
+        } else {
+            Default::default()
+        };
+
+        Self {
+            buf: RtpJitterBuf::new(clock_rate.max(1), config),
+        }
+    }
+
+    fn push(&mut self, now: i64, ch_data: ChPacket) {
+        let (seq, ts) = match RtpReader::new(&ch_data.data) {
+            Ok(rtp) => (u16::from(rtp.sequence_number()), rtp.timestamp()),
+            Err(_e) => {
+                dbgd!("invalid rtp [{_e:?}]");
+                return
+            }
+        };
+
+        if !self.buf.push(now, seq, ts, ch_data) {
+            dbgd!("drop duplicated or late packet, seq {seq}");
+        }
+    }
+
+    // all remaining if flush
+    fn pop(&mut self, now: i64, flush: bool) -> Option<JitterOutput<ChPacket>> {
+        if flush {
+            self.buf.flush()
+        } else {
+            self.buf.pop(now)
+        }
+    }
+}
+
+// the flow is paired with rtx, packets are restored by the ssrc of the flow
+struct RtxState {
+    ssrc: Option<u32>,
+}
+
+struct DtmfState {
+    parser: RtpDtmfParser,
+    begin_ts: i64,
+}
+
+impl<T> ParserFlow<T> {
+    fn new(index: FlowIndex, codec: &SdpCodec, ext: T, rtx: bool, extmap: RtpExtMap, layer: Option<SimulcastLayer>) -> Self {
+        let dtmf = match codec.codec_id {
+            CodecId::TelephoneEvent => Some(DtmfState {
+                parser: RtpDtmfParser::new(codec.clock_rate),
+                begin_ts: 0,
+            }),
+            _ => None,
+        };
+
+        Self {
+            flow: Flow {
+                index,
+                codec: codec.clone(),
+                layer,
+            },
+            ext,
+            dtmf,
+            jitter: JitterState::new(codec.clock_rate, rtx),
+            rtx: rtx.then_some(RtxState {
+                ssrc: None,
+            }),
+            extmap,
+            rtp_ext: Default::default(),
+        }
+    }
+
+    fn handle_rtp<H: Handler<Flow = T>>(&mut self, ch_data: &ChPacket, handler: &mut HandlerMut<'_, H>) -> Result<()> {
+        if let Some(rtx) = &mut self.rtx {
+            if rtx.ssrc.is_none() {
+                rtx.ssrc = RtpReader::new(&ch_data.data).ok().map(|x|x.ssrc());
+            }
+        }
+
+        self.jitter.push(handler.ctx.now, ch_data.clone());
+        Ok(())
+    }
+
+    fn pop_jitter<H: Handler<Flow = T>>(&mut self, flush: bool, handler: &mut HandlerMut<'_, H>) -> Result<()> {
+        while let Some(item) = self.jitter.pop(handler.ctx.now, flush) {
+            match item {
+                JitterOutput::Packet(packet) => {
+                    self.deliver_rtp(&packet.data, handler)?;
+                },
+                JitterOutput::Lost(lost) => {
+                    dbgd!("flow {:?} lost {lost:?}", self.flow.index);
+                    handler.handler.on_flow_lost(ContextMut(&mut handler.ctx), &self.flow, &lost)?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_rtx<H: Handler<Flow = T>>(&mut self, media: &SdpMedia, rtx_ssrc: u32, ch_data: &ChPacket, handler: &mut HandlerMut<'_, H>) -> Result<()> {
+        let rtx = match &mut self.rtx {
+            Some(rtx) => rtx,
+            None => return Ok(()),
+        };
+
+        let ssrc = match rtx.ssrc {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        // a=ssrc-group:FID pairs the rtx ssrc with the primary one
+        let primary_ssrc = media_av(media).and_then(|x|x.rtx_primary_ssrc(rtx_ssrc));
+        if primary_ssrc.is_some() && primary_ssrc != Some(ssrc) {
+            return Ok(())
+        }
+
+        let data = match unwrap_rtx_packet(&ch_data.data, self.flow.codec.payload_type, ssrc) {
+            Ok(Some(v)) => v,
+            Ok(None) => return Ok(()),
+            Err(_e) => {
+                dbgd!("invalid rtx packet [{_e:?}]");
+                return Ok(())
+            }
+        };
+
+        let packet = ChPacket {
+            ts: ch_data.ts,
+            ch_id: ch_data.ch_id,
+            data,
+        };
+
+        self.jitter.push(handler.ctx.now, packet);
+        Ok(())
+    }
+
+    fn deliver_rtp<H: Handler<Flow = T>>(&mut self, ch_data: &ChPacket, handler: &mut HandlerMut<'_, H>) -> Result<()> {
+        if !self.extmap.is_empty() {
+            self.rtp_ext = self.extmap.parse(&ch_data.data).unwrap_or_else(|_e| {
+                dbgd!("invalid rtp extensions [{_e:?}]");
+                Default::default()
+            });
+        }
+
+        handler.handler.on_flow_rtp(ContextMut(&mut handler.ctx), &mut FlowMut(self), ch_data)?;
+
+        if let Some(dtmf) = &mut self.dtmf {
+            if let Err(_e) = dtmf.parser.push_rtp_slice(&ch_data.data) {
+                dbgd!("invalid telephone-event packet [{_e:?}]");
+                return Ok(())
+            }
+
+            while let Some(event) = dtmf.parser.pull_event() {
+                if !event.end {
+                    dtmf.begin_ts = ch_data.ts;
+                    handler.handler.on_flow_dtmf_begin(ContextMut(&mut handler.ctx), &self.flow, ch_data.ts, &event)?;
+                } else {
+                    handler.handler.on_flow_dtmf_end(ContextMut(&mut handler.ctx), &self.flow, dtmf.begin_ts, &event)?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl <T> From<ParserFlow<T>> for Flow {
@@ -621,3 +1148,45 @@ impl <T> From<ParserFlow<T>> for Flow {
 
 
 
+
+#[test]
+fn test_simulcast_state() {
+    let sdp = indoc::indoc!{
+        "v=0
+        o=- 0 0 IN IP4 127.0.0.1
+        s=-
+        t=0 0
+        m=video 9 UDP/TLS/RTP/SAVPF 96
+        a=rtpmap:96 VP8/90000
+        a=extmap:4 urn:ietf:params:rtp-hdrext:sdes:rtp-stream-id
+        a=extmap:5 urn:ietf:params:rtp-hdrext:sdes:repaired-rtp-stream-id
+        a=rid:q send
+        a=rid:f send
+        a=simulcast:send q;f
+        "
+    };
+    let sdp = SdpMain::parse_from_str(sdp).unwrap();
+    let mut simulcast = SimulcastState::new(&sdp.medias[0], SimulcastRidOrder::LowToHigh).unwrap();
+
+    // one-byte header extension with rid if any
+    let make_rtp = |ssrc: u32, ext: Option<(u8, &str)>| {
+        let mut buf = vec![if ext.is_some() { 0x90 } else { 0x80 }, 96, 0, 1, 0, 0, 0, 0];
+        buf.extend_from_slice(&ssrc.to_be_bytes());
+        if let Some((id, rid)) = ext {
+            let mut elements = vec![(id << 4) | (rid.len() as u8 - 1)];
+            elements.extend_from_slice(rid.as_bytes());
+            elements.resize(elements.len().div_ceil(4) * 4, 0);
+            buf.extend_from_slice(&[0xBE, 0xDE]);
+            buf.extend_from_slice(&(elements.len() as u16 / 4).to_be_bytes());
+            buf.extend_from_slice(&elements);
+        }
+        buf.extend_from_slice(&[0x90, 0x80]);
+        buf
+    };
+
+    assert_eq!(simulcast.rank_of(&make_rtp(1, None), 1), None);
+    assert_eq!(simulcast.rank_of(&make_rtp(1, Some((4, "f"))), 1), Some(1));
+    assert_eq!(simulcast.rank_of(&make_rtp(1, None), 1), Some(1));
+    assert_eq!(simulcast.rank_of(&make_rtp(2, Some((5, "q"))), 2), Some(0));
+    assert_eq!(simulcast.rank_of(&make_rtp(3, Some((4, "x"))), 3), None);
+}